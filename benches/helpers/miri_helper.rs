@@ -4,7 +4,7 @@ extern crate rustc;
 extern crate rustc_driver;
 extern crate test;
 
-use self::miri::eval_main;
+use self::miri::{eval_main, MiriConfig};
 use self::rustc_driver::{driver, Compilation};
 use std::cell::RefCell;
 use std::rc::Rc;
@@ -50,7 +50,7 @@ pub fn run(filename: &str, bencher: &mut Bencher) {
         let entry_def_id = tcx.hir().local_def_id(entry_node_id);
 
         bencher.borrow_mut().iter(|| {
-            eval_main(tcx, entry_def_id, false);
+            eval_main(tcx, entry_def_id, MiriConfig { validate: false, ..MiriConfig::default() });
         });
 
         state.session.abort_if_errors();
@@ -0,0 +1,178 @@
+//! Emulation of the LLVM intrinsics that `core::arch::aarch64` uses for the NEON intrinsics
+//! that do not map to a portable `simd_*` intrinsic.  Loads and stores are plain memory
+//! accesses in `core::arch`, so they need no support here.
+//! The names carry the vector types as suffixes (`neon.addp.v4i32`), which we ignore: the
+//! operand layouts tell us everything we need.
+
+use rustc::ty::layout::Size;
+
+use crate::*;
+use crate::simd::{saturate, sign_extend};
+use crate::x86::round_ties_even;
+
+impl<'a, 'mir, 'tcx> EvalContextExt<'a, 'mir, 'tcx> for crate::MiriEvalContext<'a, 'mir, 'tcx> {}
+pub trait EvalContextExt<'a, 'mir, 'tcx: 'a+'mir>: crate::MiriEvalContextExt<'a, 'mir, 'tcx> {
+    /// Emulate an `llvm.aarch64.*` intrinsic.  `link_name` is without the `llvm.aarch64.` prefix.
+    fn call_aarch64_intrinsic(
+        &mut self,
+        link_name: &str,
+        args: &[OpTy<'tcx, Borrow>],
+        dest: PlaceTy<'tcx, Borrow>,
+    ) -> EvalResult<'tcx> {
+        let this = self.eval_context_mut();
+        let op = if link_name.starts_with("neon.") {
+            let name = &link_name["neon.".len()..];
+            name.split('.').next().unwrap()
+        } else {
+            ""
+        };
+        match op {
+            // Table lookups: `tbl1` to `tbl4` take 1 to 4 tables of 16 bytes, followed by the
+            // indices.  `tbx` additionally takes the fallback vector first.
+            "tbl1" | "tbl2" | "tbl3" | "tbl4" | "tbx1" | "tbx2" | "tbx3" | "tbx4" => {
+                let (fallback, tables) = if op.starts_with("tbx") {
+                    (Some(this.read_int_lanes(args[0])?), &args[1..args.len() - 1])
+                } else {
+                    (None, &args[..args.len() - 1])
+                };
+                let mut table = Vec::new();
+                for &t in tables {
+                    table.extend(this.read_int_lanes(t)?);
+                }
+                let indices = this.read_int_lanes(args[args.len() - 1])?;
+                let values: Vec<i128> = indices.iter().enumerate().map(|(i, &index)| {
+                    // Indices past the end of the table yield 0, or the fallback lane.
+                    match table.get(index as u8 as usize) {
+                        Some(&v) => v,
+                        None => fallback.as_ref().map_or(0, |fallback| fallback[i]),
+                    }
+                }).collect();
+                this.write_int_lanes(&values, dest)?;
+            }
+
+            // Pairwise operations: concatenate both inputs, and combine adjacent lanes.
+            "addp" | "smaxp" | "umaxp" | "sminp" | "uminp" => {
+                let mut lanes = this.read_int_lanes(args[0])?;
+                lanes.extend(this.read_int_lanes(args[1])?);
+                let lane_size = Size::from_bytes(dest.layout.size.bytes() / (lanes.len() / 2) as u64);
+                let lanes: Vec<i128> = lanes.into_iter().map(|v| normalize(op, v, lane_size)).collect();
+                let values: Vec<i128> = lanes.chunks(2).map(|pair| {
+                    combine_int(op, pair[0], pair[1])
+                }).collect();
+                this.write_int_lanes(&values, dest)?;
+            }
+            "faddp" | "fmaxp" | "fminp" | "fmaxnmp" | "fminnmp" => {
+                let mut lanes = this.read_float_lanes(args[0])?;
+                lanes.extend(this.read_float_lanes(args[1])?);
+                let values: Vec<f64> = lanes.chunks(2).map(|pair| {
+                    combine_float(op, pair[0], pair[1])
+                }).collect();
+                this.write_float_lanes(&values, dest)?;
+            }
+
+            // Reductions across the vector, into a scalar.  The `l` variants widen the lanes,
+            // which only matters for the size of `dest`.
+            "saddv" | "uaddv" | "smaxv" | "umaxv" | "sminv" | "uminv" | "saddlv" | "uaddlv" => {
+                let lanes = this.simd_lanes(args[0])?;
+                let lane_size = lanes[0].layout.size;
+                let lanes: Vec<i128> = this.read_int_lanes(args[0])?.into_iter()
+                    .map(|v| normalize(op, v, lane_size))
+                    .collect();
+                let combine_op = match op {
+                    "saddv" | "uaddv" | "saddlv" | "uaddlv" => "addp".to_string(),
+                    _ => format!("{}p", &op[..op.len() - 1]),
+                };
+                let res = lanes[1..].iter().fold(lanes[0], |acc, &v| combine_int(&combine_op, acc, v));
+                // The result wraps around, like the register would.
+                let size = dest.layout.size;
+                let res = res as u128 & (u128::max_value() >> (128 - size.bits()));
+                this.write_scalar(Scalar::from_uint(res, size), dest)?;
+            }
+            "faddv" | "fmaxv" | "fminv" | "fmaxnmv" | "fminnmv" => {
+                let lanes = this.read_float_lanes(args[0])?;
+                let combine_op = format!("{}p", &op[..op.len() - 1]);
+                let res = lanes[1..].iter().fold(lanes[0], |acc, &v| combine_float(&combine_op, acc, v));
+                let res = match dest.layout.size.bytes() {
+                    4 => Scalar::from_f32(res as f32),
+                    8 => Scalar::from_f64(res),
+                    _ => bug!("unsupported float size"),
+                };
+                this.write_scalar(res, dest)?;
+            }
+
+            // Lane-wise integer operations
+            "sqadd" | "uqadd" | "sqsub" | "uqsub" | "sabd" | "uabd" |
+            "shadd" | "uhadd" | "srhadd" | "urhadd" | "abs" | "sqabs" | "sqneg" => {
+                let left = this.read_int_lanes(args[0])?;
+                let right = if args.len() > 1 { this.read_int_lanes(args[1])? } else { vec![0; left.len()] };
+                let lane_size = Size::from_bytes(dest.layout.size.bytes() / left.len() as u64);
+                let values: Vec<i128> = left.iter().zip(&right).map(|(&l, &r)| {
+                    let (l, r) = (normalize(op, l, lane_size), normalize(op, r, lane_size));
+                    let signed = !op.starts_with('u');
+                    match op {
+                        "sqadd" | "uqadd" => saturate(l + r, lane_size, signed),
+                        "sqsub" | "uqsub" => saturate(l - r, lane_size, signed),
+                        "sabd" | "uabd" => (l - r).abs(),
+                        "shadd" | "uhadd" => (l + r) >> 1,
+                        "srhadd" | "urhadd" => (l + r + 1) >> 1,
+                        // `abs` of the minimum value wraps around to itself.
+                        "abs" => l.abs(),
+                        "sqabs" => saturate(l.abs(), lane_size, true),
+                        "sqneg" => saturate(-l, lane_size, true),
+                        _ => bug!(),
+                    }
+                }).collect();
+                this.write_int_lanes(&values, dest)?;
+            }
+
+            // Lane-wise float operations
+            "fmax" | "fmin" | "fmaxnm" | "fminnm" => {
+                let left = this.read_float_lanes(args[0])?;
+                let right = this.read_float_lanes(args[1])?;
+                let combine_op = format!("{}p", op);
+                let values: Vec<f64> = left.iter().zip(&right)
+                    .map(|(&l, &r)| combine_float(&combine_op, l, r))
+                    .collect();
+                this.write_float_lanes(&values, dest)?;
+            }
+            "frintn" => {
+                let values: Vec<f64> = this.read_float_lanes(args[0])?.into_iter().map(round_ties_even).collect();
+                this.write_float_lanes(&values, dest)?;
+            }
+
+            _ => return err!(Unimplemented(format!("can't call foreign function: llvm.aarch64.{}", link_name))),
+        }
+        Ok(())
+    }
+}
+
+/// Reinterpret a lane according to the signedness in the name of the operation: operations
+/// starting with `u` are unsigned, all others are signed.
+fn normalize(op: &str, v: i128, size: Size) -> i128 {
+    let bits = v as u128 & (u128::max_value() >> (128 - size.bits()));
+    if op.starts_with('u') {
+        bits as i128
+    } else {
+        sign_extend(bits, size)
+    }
+}
+
+fn combine_int(op: &str, l: i128, r: i128) -> i128 {
+    match op {
+        "addp" => l + r,
+        "smaxp" | "umaxp" => l.max(r),
+        "sminp" | "uminp" => l.min(r),
+        _ => bug!("unexpected pairwise operation {}", op),
+    }
+}
+
+fn combine_float(op: &str, l: f64, r: f64) -> f64 {
+    match op {
+        "faddp" => l + r,
+        // `fmax` and `fmin` propagate NaNs, the `nm` variants prefer numbers.
+        "fmaxp" | "fminp" if l.is_nan() || r.is_nan() => std::f64::NAN,
+        "fmaxp" | "fmaxnmp" => l.max(r),
+        "fminp" | "fminnmp" => l.min(r),
+        _ => bug!("unexpected pairwise operation {}", op),
+    }
+}
@@ -0,0 +1,299 @@
+//! Where heap allocations were created and freed, so that a use-after-free can point at both,
+//! and a leak at where the leaked memory came from.
+//! We capture a backtrace whenever the program allocates or frees heap memory.  The
+//! backtraces of live allocations are kept until they are freed; for freed allocations we
+//! only remember the most recent ones, to bound the memory use of long-running programs.
+//! With `-Zmiri-track-alloc-id` and `-Zmiri-track-alloc-site`, we also report when
+//! particular allocations are created and freed.
+//! We also count the bytes on the heap, so that `-Zmiri-max-heap` can make allocations fail
+//! once the program uses more than that.
+
+use std::cell::Cell;
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use rustc::mir;
+use syntax::errors::{DiagnosticBuilder, Level};
+use syntax::source_map::Span;
+
+use crate::*;
+
+/// Where in the program we are: the depth of the stack, and the block and statement of the
+/// innermost frame
+pub type Location = (usize, mir::BasicBlock, usize);
+
+/// How many freed allocations we keep the backtraces of
+const RECENTLY_FREED: usize = 1024;
+
+/// The backtraces of a freed allocation
+pub struct FreedAlloc<'tcx> {
+    pub created: Vec<FrameInfo<'tcx>>,
+    pub freed: Vec<FrameInfo<'tcx>>,
+}
+
+/// Leaked allocations that were created by the same backtrace
+pub struct LeakGroup<'a, 'tcx: 'a> {
+    pub frames: &'a [FrameInfo<'tcx>],
+    /// How many allocations leaked
+    pub count: usize,
+    /// How many bytes leaked, in total
+    pub bytes: u64,
+}
+
+/// The heap allocations whose creation and deallocation we report
+#[derive(Clone, Debug, Default)]
+pub struct TrackedAllocs {
+    /// Ranges of allocation ids, the end is exclusive
+    ids: Vec<(u64, u64)>,
+    /// Source locations as `(file, line)`; the allocations created there are tracked
+    sites: Vec<(String, usize)>,
+}
+
+impl TrackedAllocs {
+    /// Track the ids in `list`, which is comma-separated ids and ranges like `1200..1300`
+    /// or `1200..=1299`.
+    pub fn add_ids(&mut self, list: &str) -> Result<(), String> {
+        for item in list.split(',') {
+            let item = item.trim();
+            let parse = |id: &str| id.trim().parse::<u64>()
+                .map_err(|_| format!("invalid allocation id `{}`", id));
+            let range = if let Some(split) = item.find("..=") {
+                (parse(&item[..split])?, parse(&item[split + 3..])? + 1)
+            } else if let Some(split) = item.find("..") {
+                (parse(&item[..split])?, parse(&item[split + 2..])?)
+            } else {
+                let id = parse(item)?;
+                (id, id + 1)
+            };
+            if range.0 >= range.1 {
+                return Err(format!("empty range of allocation ids `{}`", item));
+            }
+            self.ids.push(range);
+        }
+        Ok(())
+    }
+
+    /// Track the allocations created at `site`, which is `<file>:<line>`.  The file only
+    /// has to be a suffix of the path of the source file.
+    pub fn add_site(&mut self, site: &str) -> Result<(), String> {
+        let split = site.rfind(':')
+            .ok_or_else(|| format!("expected `<file>:<line>`, got `{}`", site))?;
+        let line = site[split + 1..].parse()
+            .map_err(|_| format!("invalid line number in `{}`", site))?;
+        self.sites.push((site[..split].to_owned(), line));
+        Ok(())
+    }
+
+    fn tracks_id(&self, id: AllocId) -> bool {
+        self.ids.iter().any(|&(start, end)| start <= id.0 && id.0 < end)
+    }
+}
+
+/// A live heap allocation
+struct LiveAlloc<'tcx> {
+    /// Where it was created
+    created: Vec<FrameInfo<'tcx>>,
+    size: u64,
+}
+
+#[derive(Default)]
+pub struct AllocHistory<'tcx> {
+    /// The live heap allocations
+    live: HashMap<AllocId, LiveAlloc<'tcx>>,
+    /// How many bytes the live heap allocations have, in total
+    heap_size: u64,
+    /// How many bytes there may be on the heap
+    max_heap: Option<u64>,
+    /// Backtraces of the recently freed heap allocations
+    freed: HashMap<AllocId, FreedAlloc<'tcx>>,
+    /// The keys of `freed`, oldest first
+    freed_order: VecDeque<AllocId>,
+    /// The last freed allocation the program dereferenced a pointer to, and where: the depth
+    /// of the stack, the block and the statement.  This is set from `tag_dereference`, which
+    /// only has shared access to the machine.
+    dangling: Cell<Option<(AllocId, Location)>>,
+    /// Which allocations to report
+    tracked: TrackedAllocs,
+    /// The live allocations we report the deallocation of
+    tracked_live: HashSet<AllocId>,
+}
+
+impl<'tcx> AllocHistory<'tcx> {
+    pub fn new(tracked: TrackedAllocs, max_heap: Option<u64>) -> Self {
+        AllocHistory { tracked, max_heap, ..AllocHistory::default() }
+    }
+
+    /// How many heap allocations are live
+    pub fn live_count(&self) -> usize {
+        self.live.len()
+    }
+
+    /// The live heap allocations
+    pub fn live_ids(&self) -> Vec<AllocId> {
+        self.live.keys().cloned().collect()
+    }
+
+    pub fn created(&self, id: AllocId) -> Option<&[FrameInfo<'tcx>]> {
+        self.live.get(&id).map(|alloc| &alloc.created[..])
+    }
+
+    pub fn freed(&self, id: AllocId) -> Option<&FreedAlloc<'tcx>> {
+        self.freed.get(&id)
+    }
+
+    /// Remember that the program dereferenced a pointer to `id` at `location`, if it is freed.
+    pub fn note_dereference(&self, id: AllocId, location: Location) {
+        if self.freed.contains_key(&id) {
+            self.dangling.set(Some((id, location)));
+        }
+    }
+
+    /// The freed allocation that was dereferenced at `location`, where the program stopped
+    /// because of a dangling pointer.  The error comes right after the dereference; if the
+    /// last one was somewhere else, the error is about a pointer we did not see.
+    pub fn dangling_at(&self, location: Location) -> Option<AllocId> {
+        match self.dangling.get() {
+            Some((id, at)) if at == location => Some(id),
+            _ => None,
+        }
+    }
+}
+
+impl<'a, 'mir, 'tcx> EvalContextExt<'a, 'mir, 'tcx> for crate::MiriEvalContext<'a, 'mir, 'tcx> {}
+pub trait EvalContextExt<'a, 'mir, 'tcx: 'a+'mir>: crate::MiriEvalContextExt<'a, 'mir, 'tcx> {
+    /// Record where the heap allocation `id` was created.
+    fn record_allocation(&mut self, id: AllocId) {
+        let this = self.eval_context_mut();
+        this.machine.stats.allocations += 1;
+        let frames = this.generate_stacktrace(None);
+        let tracked = this.machine.alloc_history.tracked.tracks_id(id) ||
+            this.created_at_tracked_site(&frames);
+        if tracked {
+            this.machine.alloc_history.tracked_live.insert(id);
+            this.report_tracked(id, "created", &frames);
+        }
+        let size = this.memory().get(id).map_or(0, |alloc| alloc.bytes.len() as u64);
+        this.trace_allocation("allocate", id, size);
+        let history = &mut this.machine.alloc_history;
+        history.heap_size += size;
+        history.live.insert(id, LiveAlloc { created: frames, size });
+    }
+
+    /// Record where the heap allocation `id` was freed.
+    fn record_deallocation(&mut self, id: AllocId) {
+        let this = self.eval_context_mut();
+        this.machine.stats.deallocations += 1;
+        let frames = this.generate_stacktrace(None);
+        if this.machine.alloc_history.tracked_live.remove(&id) {
+            this.report_tracked(id, "freed", &frames);
+        }
+        let size = this.machine.alloc_history.live.get(&id).map_or(0, |alloc| alloc.size);
+        this.trace_allocation("deallocate", id, size);
+        let history = &mut this.machine.alloc_history;
+        let created = match history.live.remove(&id) {
+            Some(alloc) => {
+                history.heap_size -= alloc.size;
+                alloc.created
+            }
+            None => Vec::new(),
+        };
+        if history.freed_order.len() == RECENTLY_FREED {
+            let oldest = history.freed_order.pop_front().unwrap();
+            history.freed.remove(&oldest);
+        }
+        history.freed.insert(id, FreedAlloc { created, freed: frames });
+        history.freed_order.push_back(id);
+    }
+
+    /// Whether one of the frames is at a tracked allocation site.
+    fn created_at_tracked_site(&self, frames: &[FrameInfo<'tcx>]) -> bool {
+        let this = self.eval_context_ref();
+        let sites = &this.machine.alloc_history.tracked.sites;
+        !sites.is_empty() && frames.iter().any(|frame| {
+            let loc = this.tcx.sess.source_map().lookup_char_pos(frame.call_site.lo());
+            let file = loc.file.name.to_string();
+            sites.iter().any(|&(ref site_file, site_line)| {
+                loc.line == site_line && file.ends_with(&**site_file)
+            })
+        })
+    }
+
+    /// Whether the heap has room for `size` more bytes.  If not, the allocation fails, and
+    /// we report where the program tried to make it.
+    fn heap_has_room(&self, size: u64) -> bool {
+        let this = self.eval_context_ref();
+        let history = &this.machine.alloc_history;
+        let max = match history.max_heap {
+            Some(max) => max,
+            None => return true,
+        };
+        if history.heap_size.saturating_add(size) <= max {
+            return true;
+        }
+        let msg = format!(
+            "allocating {} bytes failed: {} of the {} bytes of -Zmiri-max-heap are in use",
+            size, history.heap_size, max,
+        );
+        let frames = this.generate_stacktrace(None);
+        let mut note = DiagnosticBuilder::new(this.tcx.sess.diagnostic(), Level::Note, &msg);
+        if let Some(site) = local_call_site(&frames) {
+            note.set_span(site);
+        }
+        this.note_stacktrace(&mut note, &frames);
+        note.emit();
+        false
+    }
+
+    /// Report that a tracked allocation was created or freed, with the backtrace.
+    fn report_tracked(&self, id: AllocId, event: &str, frames: &[FrameInfo<'tcx>]) {
+        let this = self.eval_context_ref();
+        let msg = format!("tracked allocation alloc{} was {}", id.0, event);
+        let mut note = DiagnosticBuilder::new(this.tcx.sess.diagnostic(), Level::Note, &msg);
+        if let Some(site) = local_call_site(frames) {
+            note.set_span(site);
+        }
+        this.note_stacktrace(&mut note, frames);
+        note.emit();
+    }
+
+    /// The heap allocations that are still live, grouped by the backtrace that created
+    /// them, most bytes first.  A leak in a loop thus shows up once, not once per iteration.
+    fn leaked_allocations(&self) -> Vec<LeakGroup<'_, 'tcx>> {
+        let this = self.eval_context_ref();
+        let mut leaked: Vec<_> = this.machine.alloc_history.live.iter().collect();
+        leaked.sort_by_key(|&(id, _)| *id);
+        // Group by the call sites, in the order of the first allocation of each group.
+        let mut groups: Vec<LeakGroup<'_, 'tcx>> = Vec::new();
+        let mut group_of: HashMap<Vec<Span>, usize> = HashMap::new();
+        for (_, alloc) in leaked {
+            let frames = &alloc.created;
+            let call_sites = frames.iter().map(|frame| frame.call_site).collect();
+            let group = *group_of.entry(call_sites).or_insert_with(|| {
+                groups.push(LeakGroup { frames, count: 0, bytes: 0 });
+                groups.len() - 1
+            });
+            groups[group].count += 1;
+            groups[group].bytes += alloc.size;
+        }
+        groups.sort_by_key(|group| ::std::cmp::Reverse(group.bytes));
+        groups
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn track_ids() {
+        let mut tracked = TrackedAllocs::default();
+        tracked.add_ids("3, 10..12,20..=21").unwrap();
+        let tracks = |id| tracked.tracks_id(AllocId(id));
+        assert!(tracks(3) && tracks(10) && tracks(11) && tracks(20) && tracks(21));
+        assert!(!tracks(4) && !tracks(12) && !tracks(22));
+
+        assert!(tracked.add_ids("12..12").is_err());
+        assert!(tracked.add_ids("x").is_err());
+        assert!(tracked.add_site("src/main.rs:12").is_ok());
+        assert!(tracked.add_site("src/main.rs").is_err());
+    }
+}
@@ -0,0 +1,152 @@
+//! The backtraces printed along with errors.
+
+use rustc::mir;
+use rustc::ty::{self, layout::Size};
+use syntax::ast::{FloatTy, Name};
+use syntax::errors::DiagnosticBuilder;
+use syntax::source_map::Span;
+
+use crate::*;
+
+/// Locals larger than this are not shown
+const MAX_LOCAL_SIZE: u64 = 16;
+
+/// The crates whose frames `BacktraceStyle::Short` collapses
+const STD_CRATES: &[&str] = &["std", "core", "alloc"];
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum BacktraceStyle {
+    /// Like `Full`, but runs of frames in the standard library are collapsed into one line
+    Short,
+    /// Every frame, with the location of the call
+    Full,
+    /// Like `Full`, and the values of the named locals of each frame
+    FullLocals,
+}
+
+impl<'a, 'mir, 'tcx> EvalContextExt<'a, 'mir, 'tcx> for crate::MiriEvalContext<'a, 'mir, 'tcx> {}
+pub trait EvalContextExt<'a, 'mir, 'tcx: 'a+'mir>: crate::MiriEvalContextExt<'a, 'mir, 'tcx> {
+    /// Add a note for each frame of `frames`, innermost frame first.  Calls from local code
+    /// point at the call site.
+    fn note_stacktrace(&self, err: &mut DiagnosticBuilder, frames: &[FrameInfo<'tcx>]) {
+        let this = self.eval_context_ref();
+        let in_std = |frame: &FrameInfo<'tcx>| {
+            let krate = frame.instance.def_id().krate;
+            STD_CRATES.contains(&&*this.tcx.crate_name(krate).as_str())
+        };
+        let mut idx = 0;
+        while idx < frames.len() {
+            if this.machine.backtrace == BacktraceStyle::Short {
+                let run = frames[idx..].iter().take_while(|frame| in_std(frame)).count();
+                if run > 1 {
+                    err.note(&format!(
+                        "inside {} calls in the standard library \
+                         (`-Zmiri-backtrace=full` shows them)",
+                        run,
+                    ));
+                    idx += run;
+                    continue;
+                }
+            }
+            // we need to look at the next frame (the caller) to know where the call is
+            let frame_info = &frames[idx];
+            let call_site_is_local = frames.get(idx+1).map_or(false,
+                |caller_info| caller_info.instance.def_id().is_local());
+            if call_site_is_local {
+                err.span_note(frame_info.call_site, &frame_info.to_string());
+            } else {
+                err.note(&frame_info.to_string());
+            }
+            idx += 1;
+        }
+    }
+
+    /// Add a note with the named locals of each frame, innermost frame first.
+    /// Only scalars and small aggregates of them are shown.
+    fn note_locals(&self, err: &mut DiagnosticBuilder) {
+        let this = self.eval_context_ref();
+        for (idx, frame) in this.stack().iter().enumerate().rev() {
+            let locals: Vec<String> = this.frame_locals(idx).into_iter()
+                .filter_map(|(_, name, op)| Some(format!("{} = {}", name?, this.format_value(op)?)))
+                .collect();
+            if !locals.is_empty() {
+                err.note(&format!("locals of `{}`: {}", frame.instance, locals.join(", ")));
+            }
+        }
+    }
+
+    /// The locals of the frame at `idx` in the stack that can be accessed, with their names
+    /// if they have one.  Dead locals and locals of unsized type cannot be accessed.
+    fn frame_locals(&self, idx: usize) -> Vec<(mir::Local, Option<Name>, OpTy<'tcx, Borrow>)> {
+        let this = self.eval_context_ref();
+        let frame = &this.stack()[idx];
+        frame.mir.local_decls.iter_enumerated()
+            .filter_map(|(local, decl)| {
+                let op = this.access_local(frame, local, None).ok()?;
+                Some((local, decl.name, op))
+            })
+            .collect()
+    }
+
+    /// Render a value the way the user would write it, if it is small enough and made only
+    /// of scalars.
+    fn format_value(&self, op: OpTy<'tcx, Borrow>) -> Option<String> {
+        let this = self.eval_context_ref();
+        if op.layout.size.bytes() > MAX_LOCAL_SIZE {
+            return None;
+        }
+        let fields = || -> Option<Vec<String>> {
+            (0..op.layout.fields.count())
+                .map(|i| this.format_value(this.operand_field(op, i as u64).ok()?))
+                .collect()
+        };
+        match op.layout.ty.sty {
+            ty::Bool | ty::Char | ty::Int(_) | ty::Uint(_) | ty::Float(_) |
+            ty::RawPtr(_) | ty::Ref(..) | ty::FnPtr(_) => {
+                let value = this.read_scalar(op).ok()?;
+                Some(format_scalar(value, op.layout.ty))
+            }
+            ty::Tuple(_) => Some(format!("({})", fields()?.join(", "))),
+            ty::Array(..) => Some(format!("[{}]", fields()?.join(", "))),
+            ty::Adt(adt_def, _) if adt_def.is_struct() => {
+                let values = fields()?;
+                let variant = adt_def.non_enum_variant();
+                let fields = variant.fields.iter().zip(values)
+                    .map(|(field, value)| format!("{}: {}", field.ident, value))
+                    .collect::<Vec<_>>();
+                Some(format!("{} {{ {} }}", this.tcx.item_path_str(adt_def.did), fields.join(", ")))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// The innermost location in local code, which is where an editor should point.
+pub fn local_call_site(frames: &[FrameInfo]) -> Option<Span> {
+    // Like in the backtrace, the call site of a frame is in local code if its caller is.
+    (0..frames.len())
+        .find(|&idx| frames.get(idx+1).map_or(false,
+            |caller_info| caller_info.instance.def_id().is_local()))
+        .map(|idx| frames[idx].call_site)
+}
+
+fn format_scalar(value: ScalarMaybeUndef<Borrow>, ty: ty::Ty) -> String {
+    let (bits, size) = match value {
+        ScalarMaybeUndef::Undef => return "<uninit>".to_owned(),
+        ScalarMaybeUndef::Scalar(Scalar::Ptr(ptr)) =>
+            return format!("alloc{}+{}", ptr.alloc_id.0, ptr.offset.bytes()),
+        ScalarMaybeUndef::Scalar(Scalar::Bits { bits, size }) => (bits, size),
+    };
+    match ty.sty {
+        ty::Bool => (bits != 0).to_string(),
+        ty::Char => match ::std::char::from_u32(bits as u32) {
+            Some(c) => format!("{:?}", c),
+            None => format!("{:#x}", bits),
+        },
+        ty::Int(_) => crate::simd::sign_extend(bits, Size::from_bytes(size as u64)).to_string(),
+        ty::Uint(_) => bits.to_string(),
+        ty::Float(FloatTy::F32) => f32::from_bits(bits as u32).to_string(),
+        ty::Float(FloatTy::F64) => f64::from_bits(bits as u64).to_string(),
+        _ => format!("{:#x}", bits),
+    }
+}
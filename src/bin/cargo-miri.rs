@@ -2,27 +2,36 @@
 
 extern crate cargo_metadata;
 
+use std::ffi::OsString;
 use std::path::{PathBuf, Path};
 use std::io::{self, Write};
 use std::process::Command;
 use std::fs::{self, File};
+use std::thread;
 
 const CARGO_MIRI_HELP: &str = r#"Interprets bin crates
 
 Usage:
-    cargo miri [subcommand] [options] [--] [<opts>...]
+    cargo miri [subcommand] [options] [--] [<opts>...] [-- <program args>...]
 
 Subcommands:
     run                      Run binaries (default)
-    test                     Run tests
+    test                     Run tests, of all workspace members with `--workspace`
+    bench                    Run each benchmark once, without measuring it
     setup                    Only perform automatic setup, but without asking questions (for getting a proper libstd)
 
 Common options:
     -h, --help               Print this message
     --features               Features to compile for the package
     -V, --version            Print version info and exit
+    -j, --jobs <N>           Run the tests of each crate in N miri processes in parallel
+    --miri-sysroot <PATH>    Use this pre-built sysroot, like MIRI_SYSROOT, instead of building one
+    --rust-src <PATH>        Build the sysroot from this rust-src, like MIRI_RUST_SRC
+    --offline                Do not install xargo or rust-src if they are missing
+    --target <TRIPLE>        Interpret for this target; may be given several times
 
-Other options are the same as `cargo rustc`.
+Other options are the same as `cargo rustc`.  The options after the first `--` are
+passed to miri, those after a second `--` to the interpreted program.
 
 The feature `cargo-miri` is automatically defined for convenience. You can use
 it to configure the resource limits
@@ -36,6 +45,7 @@ available resource limits are `memory_size`, `step_limit`, `stack_limit`
 enum MiriCommand {
     Run,
     Test,
+    Bench,
     Setup,
 }
 
@@ -73,20 +83,53 @@ fn get_arg_flag_value(name: &str) -> Option<String> {
     }
 }
 
-fn list_targets() -> impl Iterator<Item=cargo_metadata::Target> {
-    // We need to get the manifest, and then the metadata, to enumerate targets.
-    let manifest_path = get_arg_flag_value("--manifest-path").map(|m|
-        Path::new(&m).canonicalize().unwrap()
-    );
+/// All values of a flag that may be given several times, like `--target`
+fn get_arg_flag_values(name: &str) -> Vec<String> {
+    let mut values = Vec::new();
+    let mut args = std::env::args().take_while(|arg| arg != "--");
+    while let Some(arg) = args.next() {
+        if arg == name {
+            values.extend(args.next());
+        } else if arg.starts_with(name) && arg[name.len()..].starts_with('=') {
+            values.push(arg[name.len() + 1..].to_owned());
+        }
+    }
+    values
+}
 
-    let mut metadata = if let Ok(metadata) = cargo_metadata::metadata(
-        manifest_path.as_ref().map(AsRef::as_ref),
-    )
-    {
+/// The number of jobs, from `-j N`, `-jN` or `--jobs N`, with or without `=`
+fn get_jobs() -> Option<String> {
+    get_arg_flag_value("-j").or_else(|| get_arg_flag_value("--jobs")).or_else(|| {
+        std::env::args().take_while(|arg| arg != "--")
+            .find(|arg| arg.len() > 2 && arg.starts_with("-j") && arg[2..].bytes().all(|b| b.is_ascii_digit()))
+            .map(|arg| arg[2..].to_owned())
+    })
+}
+
+fn manifest_path() -> Option<PathBuf> {
+    get_arg_flag_value("--manifest-path").map(|m| Path::new(&m).canonicalize().unwrap())
+}
+
+fn metadata() -> cargo_metadata::Metadata {
+    let manifest_path = manifest_path();
+    if let Ok(metadata) = cargo_metadata::metadata(manifest_path.as_ref().map(AsRef::as_ref)) {
         metadata
     } else {
         show_error(format!("error: Could not obtain cargo metadata."));
-    };
+    }
+}
+
+/// The members of the workspace, for `--workspace`
+fn workspace_members() -> Vec<cargo_metadata::Package> {
+    let metadata = metadata();
+    let members = metadata.workspace_members;
+    metadata.packages.into_iter().filter(|package| members.contains(&package.id)).collect()
+}
+
+fn current_package() -> cargo_metadata::Package {
+    // We need to get the manifest, and then the metadata, to find the package.
+    let manifest_path = manifest_path();
+    let mut metadata = metadata();
 
     let current_dir = std::env::current_dir();
 
@@ -108,10 +151,63 @@ fn list_targets() -> impl Iterator<Item=cargo_metadata::Target> {
             }
         })
         .expect("could not find matching package");
-    let package = metadata.packages.remove(package_index);
+    metadata.packages.remove(package_index)
+}
 
-    // Finally we got the list of targets to build
-    package.targets.into_iter()
+/// What `[package.metadata.miri]` in a manifest declares for a target
+#[derive(Debug, Default)]
+struct TargetConfig {
+    /// The features to build the package with
+    features: Vec<String>,
+    /// The miri flags, first those for all targets, then those for the target
+    flags: Vec<String>,
+}
+
+/// Read the settings for `target` from `[package.metadata.miri]` in the manifest of
+/// `package`, and from `[package.metadata.miri.targets.<target>]`.
+fn target_config(package: &cargo_metadata::Package, target: &str) -> TargetConfig {
+    let config = &package.metadata["miri"];
+    let sections = [
+        (config, "package.metadata.miri".to_owned()),
+        (&config["targets"][target], format!("package.metadata.miri.targets.{}", target)),
+    ];
+    let mut config = TargetConfig::default();
+    for &(section, ref name) in &sections {
+        let section = match section.as_object() {
+            Some(section) => section,
+            None if section.is_null() => continue,
+            None => show_error(format!("`{}` should be a table", name)),
+        };
+        for (key, value) in section {
+            match key.as_str() {
+                "flags" | "forward-env" | "features" | "skip-tests" => {
+                    let items = value.as_array()
+                        .and_then(|items| items.iter().map(|item| item.as_str()).collect::<Option<Vec<_>>>())
+                        .unwrap_or_else(|| show_error(format!("`{}.{}` should be an array of strings", name, key)));
+                    for item in items {
+                        match key.as_str() {
+                            "flags" => config.flags.push(item.to_owned()),
+                            "features" => config.features.push(item.to_owned()),
+                            "skip-tests" => config.flags.push(format!("-Zmiri-skip-tests={}", item)),
+                            _ => config.flags.push(format!("-Zmiri-env-forward={}", item)),
+                        }
+                    }
+                }
+                "env" => {
+                    let vars = value.as_object()
+                        .unwrap_or_else(|| show_error(format!("`{}.env` should be a table", name)));
+                    for (var, value) in vars {
+                        let value = value.as_str()
+                            .unwrap_or_else(|| show_error(format!("`{}.env.{}` should be a string", name, var)));
+                        config.flags.push(format!("-Zmiri-env-set={}={}", var, value));
+                    }
+                }
+                "targets" if name == "package.metadata.miri" => {}
+                _ => show_error(format!("unknown key `{}` in `{}`", key, name)),
+            }
+        }
+    }
+    config
 }
 
 fn ask(question: &str) {
@@ -126,16 +222,93 @@ fn ask(question: &str) {
     };
 }
 
-/// Perform the setup requires to make `cargo miri` work: Getting a custom-built libstd. Then sets MIRI_SYSROOT.
+/// The arguments after the subcommand, without the options of cargo-miri itself, which cargo
+/// does not know.  `cargo rustc` does not know `--workspace` either, it builds a single package,
+/// and we pass it the target triples one at a time.
+fn cargo_args(skip: usize, workspace: bool) -> Vec<OsString> {
+    let mut args = Vec::new();
+    let mut os_args = std::env::args_os().skip(skip);
+    while let Some(arg) = os_args.next() {
+        if arg == "--" {
+            args.push(arg);
+            break;
+        }
+        if arg == "--miri-sysroot" || arg == "--rust-src" || arg == "--target" {
+            // Skip the value, too.
+            os_args.next();
+            continue;
+        }
+        let own = arg == "--offline" || (workspace && (arg == "--workspace" || arg == "--all")) ||
+            arg.to_str().map_or(false, |arg| {
+                arg.starts_with("--miri-sysroot=") || arg.starts_with("--rust-src=") ||
+                    arg.starts_with("--target=")
+            });
+        if !own {
+            args.push(arg);
+        }
+    }
+    args.extend(os_args);
+    args
+}
+
+/// The Xargo.toml of the sysroot we build
+const XARGO_TOML: &str = r#"
+[dependencies.std]
+default_features = false
+# We need the `panic_unwind` feature because we use the `unwind` panic strategy.
+# Using `abort` works for libstd, but then libtest will not compile.
+features = ["panic_unwind"]
+
+[dependencies.test]
+stage = 1
+"#;
+
+/// Perform the setup requires to make `cargo miri` work: Getting a custom-built libstd for
+/// `target` (the host if `None`).  Returns the sysroot.
 /// Skipped if MIRI_SYSROOT is already set, in that case we expect the user has done all this already.
-fn setup(ask_user: bool) {
-    if std::env::var("MIRI_SYSROOT").is_ok() {
-        return;
+/// When `offline`, we do not install anything, so the setup works without network access
+/// given xargo, and rust-src from rustup or `--rust-src`.
+fn setup(ask_user: bool, offline: bool, target: Option<&str>) -> PathBuf {
+    if let Ok(sysroot) = std::env::var("MIRI_SYSROOT") {
+        if !Path::new(&sysroot).is_dir() {
+            show_error(format!("the miri sysroot `{}` does not exist", sysroot));
+        }
+        return PathBuf::from(sysroot);
+    }
+
+    // We build the sysroot in whatever is a good cache dir for this platform.  If we already
+    // built it with the same compiler, sources and flags, we are done; that saves running
+    // xargo, which checks all of libstd for changes, on every `cargo miri` invocation.
+    let dirs = directories::ProjectDirs::from("miri", "miri", "miri").unwrap();
+    let dir = dirs.cache_dir();
+    let rust_src = get_arg_flag_value("--rust-src").or_else(|| std::env::var("MIRI_RUST_SRC").ok());
+    let is_host = match target {
+        None => true,
+        Some(target) => target == rustc_version::version_meta().unwrap().host,
+    };
+    // xargo puts things into a different directory when the architecture matches the host.
+    let sysroot = if is_host { dir.join("HOST") } else { PathBuf::from(dir) };
+    let stamp_path = dir.join(format!("sysroot-{}.stamp", target.filter(|_| !is_host).unwrap_or("HOST")));
+    let stamp = format!(
+        "{}\nrust-src: {:?}\nflags: {}\n{}",
+        rustc_version::version_meta().unwrap().short_version_string,
+        rust_src,
+        miri::miri_default_args().join(" "),
+        XARGO_TOML,
+    );
+    if sysroot.is_dir() && fs::read_to_string(&stamp_path).ok().as_ref() == Some(&stamp) {
+        if !ask_user {
+            println!("A libstd for miri is already available in `{}`", sysroot.display());
+        }
+        return sysroot;
     }
 
     // First, we need xargo
     if Command::new("xargo").arg("--version").output().is_err()
     {
+        if offline {
+            show_error(format!("xargo is not installed, and we cannot install it offline"));
+        }
         if ask_user {
             ask("It seems you do not have xargo installed. I will run `cargo install xargo`. Proceed?");
         } else {
@@ -146,11 +319,20 @@ fn setup(ask_user: bool) {
         }
     }
 
-    // Then, we also need rust-src.  Let's see if it is already installed.
-    let sysroot = Command::new("rustc").args(&["--print", "sysroot"]).output().unwrap().stdout;
-    let sysroot = std::str::from_utf8(&sysroot[..]).unwrap();
-    let src = Path::new(sysroot.trim_end_matches('\n')).join("lib").join("rustlib").join("src");
-    if !src.exists() {
+    // Then, we also need rust-src.  A vendored copy can be given instead of the rustup component.
+    let rustc_sysroot = Command::new("rustc").args(&["--print", "sysroot"]).output().unwrap().stdout;
+    let rustc_sysroot = std::str::from_utf8(&rustc_sysroot[..]).unwrap();
+    let src = Path::new(rustc_sysroot.trim_end_matches('\n')).join("lib").join("rustlib").join("src");
+    if let Some(ref rust_src) = rust_src {
+        // xargo wants the directory that contains `libstd`.
+        if !Path::new(rust_src).join("libstd").is_dir() {
+            show_error(format!("`{}` does not contain the rust source (no `libstd` in it)", rust_src));
+        }
+    } else if !src.exists() {
+        if offline {
+            show_error(format!("the rust-src component is not installed, and we cannot install it \
+                                offline; use `--rust-src <path>` to build from a copy of it"));
+        }
         if ask_user {
             ask("It seems you do not have the rust-src component installed. I will run `rustup component add rust-src`. Proceed?");
         } else {
@@ -161,24 +343,13 @@ fn setup(ask_user: bool) {
         }
     }
 
-    // Next, we need our own libstd. We will do this work in whatever is a good cache dir for this platform.
-    let dirs = directories::ProjectDirs::from("miri", "miri", "miri").unwrap();
-    let dir = dirs.cache_dir();
+    // Next, we need our own libstd.
     if !dir.exists() {
         fs::create_dir_all(&dir).unwrap();
     }
     // The interesting bit: Xargo.toml
     File::create(dir.join("Xargo.toml")).unwrap()
-        .write_all(br#"
-[dependencies.std]
-default_features = false
-# We need the `panic_unwind` feature because we use the `unwind` panic strategy.
-# Using `abort` works for libstd, but then libtest will not compile.
-features = ["panic_unwind"]
-
-[dependencies.test]
-stage = 1
-        "#).unwrap();
+        .write_all(XARGO_TOML.as_bytes()).unwrap();
     // The boring bits: A dummy project for xargo
     File::create(dir.join("Cargo.toml")).unwrap()
         .write_all(br#"
@@ -192,41 +363,38 @@ path = "lib.rs"
         "#).unwrap();
     File::create(dir.join("lib.rs")).unwrap();
     // Run xargo
-    let target = get_arg_flag_value("--target");
     let mut command = Command::new("xargo");
     command.arg("build").arg("-q")
         .current_dir(&dir)
         .env("RUSTFLAGS", miri::miri_default_args().join(" "))
         .env("XARGO_HOME", dir.to_str().unwrap());
-    if let Some(ref target) = target {
-        command.arg("--target").arg(&target);
+    if let Some(ref rust_src) = rust_src {
+        command.env("XARGO_RUST_SRC", rust_src);
+    }
+    if let Some(target) = target {
+        command.arg("--target").arg(target);
     }
     if !command.status().unwrap().success()
     {
         show_error(format!("Failed to run xargo"));
     }
 
-    // That should be it!  But we need to figure out where xargo built stuff.
-    // Unfortunately, it puts things into a different directory when the
-    // architecture matches the host.
-    let is_host = match target {
-        None => true,
-        Some(target) => target == rustc_version::version_meta().unwrap().host,
-    };
-    let sysroot = if is_host { dir.join("HOST") } else { PathBuf::from(dir) };
-    std::env::set_var("MIRI_SYSROOT", &sysroot);
+    // That should be it!  Remember what we built it from.
+    fs::write(&stamp_path, stamp).unwrap();
     if !ask_user {
         println!("A libstd for miri is now available in `{}`", sysroot.display());
     }
+    sysroot
 }
 
 fn main() {
     // Check for version and help flags even when invoked as 'cargo-miri'
-    if std::env::args().any(|a| a == "--help" || a == "-h") {
+    // The arguments after `--` are not for us.
+    if std::env::args().take_while(|a| a != "--").any(|a| a == "--help" || a == "-h") {
         show_help();
         return;
     }
-    if std::env::args().any(|a| a == "--version" || a == "-V") {
+    if std::env::args().take_while(|a| a != "--").any(|a| a == "--version" || a == "-V") {
         show_version();
         return;
     }
@@ -239,6 +407,7 @@ fn main() {
 
         let (subcommand, skip) = match std::env::args().nth(2).deref() {
             Some("test") => (MiriCommand::Test, 3),
+            Some("bench") => (MiriCommand::Bench, 3),
             Some("run") => (MiriCommand::Run, 3),
             Some("setup") => (MiriCommand::Setup, 3),
             // Default command, if there is an option or nothing
@@ -251,60 +420,159 @@ fn main() {
         };
 
         // We always setup
+        if let Some(sysroot) = get_arg_flag_value("--miri-sysroot") {
+            std::env::set_var("MIRI_SYSROOT", sysroot);
+        }
+        let offline = std::env::args().skip(skip).take_while(|arg| arg != "--").any(|arg| arg == "--offline");
         let ask = subcommand != MiriCommand::Setup;
-        setup(ask);
+        // `--target` may be given several times, then we run everything for each target in
+        // turn, with its own sysroot.
+        let triples: Vec<Option<String>> = match get_arg_flag_values("--target") {
+            ref triples if triples.is_empty() => vec![None],
+            triples => triples.into_iter().map(Some).collect(),
+        };
+        let sysroots: Vec<PathBuf> = triples.iter().map(|triple| setup(ask, offline, triple.deref())).collect();
         if subcommand == MiriCommand::Setup {
             // Stop here.
             return;
         }
 
-        // Now run the command.
-        for target in list_targets() {
-            let args = std::env::args().skip(skip);
-            let kind = target.kind.get(0).expect(
-                "badly formatted cargo metadata: target::kind is an empty array",
-            );
-            match (subcommand, &kind[..]) {
-                (MiriCommand::Test, "test") => {
-                    // For test binaries we call `cargo rustc --test target -- <rustc args>`
-                    if let Err(code) = process(
-                        vec!["--test".to_string(), target.name].into_iter().chain(
-                            args,
-                        ),
-                    )
-                    {
-                        std::process::exit(code);
+        // With `--workspace` (or `--all`), we run the targets of all members of the workspace,
+        // each with its own settings, instead of those of the current package.
+        let workspace = std::env::args().skip(skip).take_while(|arg| arg != "--")
+            .any(|arg| arg == "--workspace" || arg == "--all");
+        let packages = if workspace { workspace_members() } else { vec![current_package()] };
+
+        // Doctests are compiled and run by rustdoc itself, in-process, so there is no rustc
+        // invocation we could redirect to miri.  Say so, rather than skipping them silently.
+        if subcommand == MiriCommand::Test {
+            if std::env::args().skip(skip).take_while(|arg| arg != "--").any(|arg| arg == "--doc") {
+                show_error(format!("`cargo miri test --doc` is not supported: rustdoc runs doctests \
+                                    itself, without a way to run them in miri"));
+            }
+            let mut targets = packages.iter().flat_map(|package| &package.targets);
+            if targets.any(|target| target.kind.iter().any(|kind| kind == "lib")) {
+                eprintln!("warning: doctests are not run by `cargo miri test`, \
+                           so they are not checked for undefined behavior");
+            }
+        }
+
+        // With `-j`, the wrapper below splits the tests of each crate between several miri
+        // processes.  It is called by cargo, so we tell it through the environment.
+        if subcommand == MiriCommand::Test || subcommand == MiriCommand::Bench {
+            if let Some(jobs) = get_jobs() {
+                std::env::set_var("MIRI_TEST_JOBS", jobs);
+            }
+        }
+
+        // Now run the command.  In a workspace, and for several target triples, we carry on
+        // after a target failed, and sum up at the end.
+        let keep_going = workspace || triples.len() > 1;
+        let mut targets_run = 0;
+        let mut failed = Vec::new();
+        let mut exit_code = 0;
+        for (triple, sysroot) in triples.iter().zip(&sysroots) {
+            // The wrapper below gets the sysroot from the environment.
+            std::env::set_var("MIRI_SYSROOT", sysroot);
+            for package in &packages {
+                for target in &package.targets {
+                    // `cargo rustc` does not know `--workspace`, it builds a single package.
+                    let user_args = cargo_args(skip, workspace);
+                    let config = target_config(package, &target.name);
+                    let mut args: Vec<OsString> = Vec::new();
+                    if workspace {
+                        args.push("--package".into());
+                        args.push(OsString::from(&package.name));
                     }
-                }
-                (MiriCommand::Test, "lib") => {
-                    // For libraries we call `cargo rustc -- --test <rustc args>`
-                    // Notice now that `--test` is a rustc arg rather than a cargo arg. This tells
-                    // rustc to build a test harness which calls all #[test] functions. We don't
-                    // use the harness since we execute each #[test] function's MIR ourselves before
-                    // compilation even completes, but this option is necessary to build the library.
-                    if let Err(code) = process(
-                        vec!["--".to_string(), "--test".to_string()].into_iter().chain(
-                            args,
-                        ),
-                    )
-                    {
-                        std::process::exit(code);
+                    if let Some(triple) = triple {
+                        args.push("--target".into());
+                        args.push(triple.into());
                     }
-                }
-                (MiriCommand::Run, "bin") => {
-                    // For ordinary binaries we call `cargo rustc --bin target -- <rustc args>`
-                    if let Err(code) = process(
-                        vec!["--bin".to_string(), target.name].into_iter().chain(
-                            args,
-                        ),
-                    )
-                    {
-                        std::process::exit(code);
+                    if !config.features.is_empty() {
+                        args.push("--features".into());
+                        args.push(config.features.join(" ").into());
+                    }
+                    let args = args.into_iter().chain(user_args);
+                    // The flags from the manifest go first, so that those on the command line win.
+                    let flags = config.flags;
+                    let kind = target.kind.get(0).expect(
+                        "badly formatted cargo metadata: target::kind is an empty array",
+                    );
+                    let result = match (subcommand, &kind[..]) {
+                        (MiriCommand::Test, "test") => {
+                            // For test binaries we call `cargo rustc --test target -- <rustc args>`
+                            process(
+                                vec![OsString::from("--test"), OsString::from(&target.name)].into_iter().chain(
+                                    args,
+                                ),
+                                flags,
+                            )
+                        }
+                        (MiriCommand::Test, "lib") => {
+                            // For libraries we call `cargo rustc -- --test <rustc args>`
+                            // Notice now that `--test` is a rustc arg rather than a cargo arg. This tells
+                            // rustc to build a test harness which calls all #[test] functions. We don't
+                            // use the harness since we execute each #[test] function's MIR ourselves before
+                            // compilation even completes, but this option is necessary to build the library.
+                            let flags = Some("--test".to_owned()).into_iter().chain(flags).collect();
+                            process(args, flags)
+                        }
+                        (MiriCommand::Bench, "bench") => {
+                            // Benchmarks are built like tests, with `cargo rustc --bench target`.
+                            // `-Zmiri-bench` makes miri run their `#[bench]` functions once each.
+                            let flags = Some("-Zmiri-bench".to_owned()).into_iter().chain(flags).collect();
+                            process(
+                                vec![OsString::from("--bench"), OsString::from(&target.name)].into_iter().chain(
+                                    args,
+                                ),
+                                flags,
+                            )
+                        }
+                        (MiriCommand::Bench, "lib") => {
+                            // Like for tests, `--test` builds the library with its benchmarks.
+                            let flags = vec!["--test".to_owned(), "-Zmiri-bench".to_owned()].into_iter()
+                                .chain(flags)
+                                .collect();
+                            process(args, flags)
+                        }
+                        (MiriCommand::Run, "bin") => {
+                            // For ordinary binaries we call `cargo rustc --bin target -- <rustc args>`
+                            process(
+                                vec![OsString::from("--bin"), OsString::from(&target.name)].into_iter().chain(
+                                    args,
+                                ),
+                                flags,
+                            )
+                        }
+                        _ => continue,
+                    };
+                    targets_run += 1;
+                    if let Err(code) = result {
+                        if !keep_going {
+                            std::process::exit(code);
+                        }
+                        failed.push(match triple {
+                            Some(triple) => format!("{} ({}) on {}", package.name, target.name, triple),
+                            None => format!("{} ({})", package.name, target.name),
+                        });
+                        // Like miri, exit with the code of the most serious failure, which is the lowest.
+                        if exit_code == 0 || code < exit_code {
+                            exit_code = code;
+                        }
                     }
                 }
-                _ => {}
             }
         }
+        if keep_going {
+            eprintln!(
+                "miri ran {} target(s) in {} workspace member(s) for {} target triple(s), {} failed",
+                targets_run, packages.len(), triples.len(), failed.len(),
+            );
+            for target in &failed {
+                eprintln!("    {}", target);
+            }
+            std::process::exit(exit_code);
+        }
     } else if let Some("rustc") = std::env::args().nth(1).as_ref().map(AsRef::as_ref) {
         // This arm is executed when cargo-miri runs `cargo rustc` with the `RUSTC_WRAPPER` env var set to itself:
         // Dependencies get dispatched to rustc, the final test/binary to miri.
@@ -332,24 +600,38 @@ fn main() {
 
         // this conditional check for the --sysroot flag is there so users can call `cargo-miri` directly
         // without having to pass --sysroot or anything
-        let rustc_args = std::env::args().skip(2);
-        let mut args: Vec<String> = if std::env::args().any(|s| s == "--sysroot") {
-            rustc_args.collect()
-        } else {
-            rustc_args
-                .chain(Some("--sysroot".to_owned()))
-                .chain(Some(sys_root))
-                .collect()
+        let mut args: Vec<OsString> = std::env::args_os().skip(2).collect();
+        // The arguments of the interpreted program come last, after a `--`.
+        let program_args = match args.iter().position(|arg| arg == "--") {
+            Some(dashes) => args.split_off(dashes),
+            None => Vec::new(),
         };
-        args.splice(0..0, miri::miri_default_args().iter().map(ToString::to_string));
-        args.extend_from_slice(&["--cfg".to_owned(), r#"feature="cargo-miri""#.to_owned()]);
+        if !args.iter().any(|arg| arg == "--sysroot") {
+            args.push("--sysroot".into());
+            args.push(sys_root.into());
+        }
+        args.splice(0..0, miri::miri_default_args().iter().map(OsString::from));
+        args.push("--cfg".into());
+        args.push(r#"feature="cargo-miri""#.into());
 
         // this check ensures that dependencies are built but not interpreted and the final crate is
         // interpreted but not built
-        let miri_enabled = std::env::args().any(|s| s == "--emit=dep-info,metadata");
+        let miri_enabled = args.iter().any(|arg| arg == "--emit=dep-info,metadata");
+        args.extend(program_args);
         let mut command = if miri_enabled {
             let mut path = std::env::current_exe().expect("current executable path invalid");
             path.set_file_name("miri");
+            let jobs = std::env::var("MIRI_TEST_JOBS").ok().map(|jobs| match jobs.parse::<usize>() {
+                Ok(n) if n > 0 => n,
+                _ => show_error(format!("-j should be a positive integer, got {:?}", jobs)),
+            });
+            let listing = args.iter().any(|arg| arg == "-Zmiri-list-tests");
+            match jobs {
+                Some(jobs) if jobs > 1 && !listing && args.iter().any(|arg| arg == "--test") => {
+                    run_test_shards(&path, &args, jobs)
+                }
+                _ => {}
+            }
             Command::new(path)
         } else {
             Command::new("rustc")
@@ -370,23 +652,48 @@ fn main() {
     }
 }
 
-fn process<I>(old_args: I) -> Result<(), i32>
+/// Run the tests of a crate in `jobs` miri processes, each of which runs every `jobs`-th test.
+/// The output of each process is shown once it is done, so that the tests do not interleave.
+fn run_test_shards(miri: &Path, args: &[OsString], jobs: usize) -> ! {
+    let shards: Vec<_> = (0..jobs).map(|shard| {
+        let mut command = Command::new(miri);
+        command.arg(format!("-Zmiri-test-shard={}/{}", shard, jobs)).args(args);
+        thread::spawn(move || command.output())
+    }).collect();
+    let mut exit_code = 0;
+    for shard in shards {
+        let output = shard.join().unwrap().unwrap_or_else(|e| panic!("error during miri run: {:?}", e));
+        io::stdout().write_all(&output.stdout).unwrap();
+        io::stderr().write_all(&output.stderr).unwrap();
+        if !output.status.success() {
+            // Like miri, exit with the code of the most serious failure, which is the lowest.
+            let code = output.status.code().unwrap_or(42);
+            if exit_code == 0 || code < exit_code {
+                exit_code = code;
+            }
+        }
+    }
+    std::process::exit(exit_code)
+}
+
+fn process<I>(old_args: I, rustc_args: Vec<String>) -> Result<(), i32>
 where
-    I: Iterator<Item = String>,
+    I: Iterator<Item = OsString>,
 {
-    let mut args = vec!["rustc".to_owned()];
-
-    let mut found_dashes = false;
-    for arg in old_args {
-        found_dashes |= arg == "--";
-        args.push(arg);
-    }
-    if !found_dashes {
-        args.push("--".to_owned());
-    }
-    args.push("--emit=dep-info,metadata".to_owned());
-    args.push("--cfg".to_owned());
-    args.push(r#"feature="cargo-miri""#.to_owned());
+    let mut args: Vec<OsString> = vec!["rustc".into()];
+    args.extend(old_args);
+    // Our rustc flags go right after the first `--`, before the arguments of the interpreted
+    // program, which start at the second one.
+    let dashes = match args.iter().position(|arg| arg == "--") {
+        Some(dashes) => dashes,
+        None => {
+            args.push("--".into());
+            args.len() - 1
+        }
+    };
+    let flags = ["--emit=dep-info,metadata", "--cfg", r#"feature="cargo-miri""#];
+    let flags = rustc_args.into_iter().map(OsString::from).chain(flags.iter().map(OsString::from));
+    args.splice(dashes + 1..dashes + 1, flags);
 
     let path = std::env::current_exe().expect("current executable path invalid");
     let exit_status = Command::new("cargo")
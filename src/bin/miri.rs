@@ -10,6 +10,8 @@ extern crate rustc_codegen_utils;
 extern crate env_logger;
 extern crate log_settings;
 extern crate syntax;
+#[cfg(unix)]
+extern crate libc;
 
 #[macro_use]
 extern crate log;
@@ -17,6 +19,16 @@ extern crate log;
 use std::path::PathBuf;
 use std::str::FromStr;
 use std::env;
+use std::ffi::OsString;
+use std::cmp;
+use std::io::{self, Write};
+use std::ops::Range;
+use std::process::{Command, Output};
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::Duration;
 
 use rustc::session::Session;
 use rustc_metadata::cstore::CStore;
@@ -24,15 +36,82 @@ use rustc_driver::{Compilation, CompilerCalls, RustcDefaultCalls};
 use rustc_driver::driver::{CompileState, CompileController};
 use rustc::session::config::{self, Input, ErrorOutputType};
 use rustc::hir::{self, itemlikevisit};
-use rustc::ty::TyCtxt;
+use rustc::hir::def_id::DefId;
+use rustc::ty::{self, TyCtxt};
 use rustc_codegen_utils::codegen_backend::CodegenBackend;
 use syntax::ast;
 
+/// The exit code for the most serious class of findings that failed the run, or 0.
+/// rustc exits with 1 on errors; we replace that with the code of the class.
+static EXIT_CODE: AtomicUsize = AtomicUsize::new(0);
+
+/// Remember that a finding of `class` failed the run.
+fn record_failure(class: Option<miri::ErrorClass>) {
+    if let Some(class) = class {
+        let code = class.exit_code() as usize;
+        let old = EXIT_CODE.load(Ordering::Relaxed);
+        if old == 0 || code < old {
+            EXIT_CODE.store(code, Ordering::Relaxed);
+        }
+    }
+}
+
 struct MiriCompilerCalls {
     default: Box<RustcDefaultCalls>,
 
-    /// Whether to enforce the validity invariant.
-    validate: bool,
+    /// Configuration for the interpreter
+    miri_config: miri::MiriConfig,
+
+    /// How to run `#[test]` functions
+    test_options: TestOptions,
+
+    /// The path of the function to call instead of `main`, from `-Zmiri-call`
+    call: Option<String>,
+}
+
+/// Which tests to run, and how.  Listing and filtering follow libtest's `--list --format terse`
+/// and `--exact`, so that external test runners can discover the tests of a crate and run each
+/// of them in a process of its own.
+#[derive(Clone, Debug, Default)]
+struct TestOptions {
+    /// Whether to run the remaining tests after one of them failed.
+    keep_going: bool,
+    /// Only print the names of the tests, one `<name>: test` line each, and run none of them.
+    list: bool,
+    /// Only run the tests whose name contains this, or is this if `exact` is set.
+    filter: Option<String>,
+    exact: bool,
+    /// Only run every `n`-th of the selected tests, starting at the `i`-th, as `(i, n)`.
+    /// This splits the tests between several miri processes that run in parallel.
+    shard: Option<(usize, usize)>,
+    /// Run the `#[bench]` functions, once each, instead of the `#[test]` functions.
+    bench: bool,
+    /// Patterns of the names of tests that do not work in miri, which we skip
+    skip: Vec<String>,
+}
+
+impl TestOptions {
+    /// The attribute of the functions we run, which is also how we call them in messages.
+    fn kind(&self) -> &'static str {
+        if self.bench { "bench" } else { "test" }
+    }
+
+    fn selects(&self, name: &str) -> bool {
+        match self.filter {
+            Some(ref filter) if self.exact => name == filter,
+            Some(ref filter) => name.contains(&**filter),
+            None => true,
+        }
+    }
+
+    fn skips(&self, name: &str) -> bool {
+        self.skip.iter().any(|pattern| miri::matches_pattern(pattern, name))
+    }
+
+    /// Whether the test with the given index among the selected tests is in our shard.
+    fn in_shard(&self, index: usize) -> bool {
+        self.shard.map_or(true, |(shard, shards)| index % shards == shard)
+    }
 }
 
 impl<'a> CompilerCalls<'a> for MiriCompilerCalls {
@@ -90,9 +169,12 @@ impl<'a> CompilerCalls<'a> for MiriCompilerCalls {
         let this = *self;
         let mut control = this.default.build_controller(sess, matches);
         control.after_hir_lowering.callback = Box::new(after_hir_lowering);
-        let validate = this.validate;
-        control.after_analysis.callback =
-            Box::new(move |state| after_analysis(state, validate));
+        let miri_config = this.miri_config;
+        let test_options = this.test_options;
+        let call = this.call;
+        control.after_analysis.callback = Box::new(move |state| {
+            after_analysis(state, miri_config.clone(), test_options.clone(), call.clone())
+        });
         control.after_analysis.stop = Compilation::Stop;
         control
     }
@@ -108,52 +190,326 @@ fn after_hir_lowering(state: &mut CompileState) {
 
 fn after_analysis<'a, 'tcx>(
     state: &mut CompileState<'a, 'tcx>,
-    validate: bool,
+    miri_config: miri::MiriConfig,
+    test_options: TestOptions,
+    call: Option<String>,
 ) {
     init_late_loggers();
     state.session.abort_if_errors();
 
     let tcx = state.tcx.unwrap();
 
-    if std::env::args().any(|arg| arg == "--test") {
+    if let Some(path) = call {
+        let did = find_local_fn(tcx, &path).unwrap_or_else(|| {
+            state.session.fatal(&format!("-Zmiri-call: there is no function `{}` in this crate", path))
+        });
+        record_failure(miri::eval_main(tcx, did, miri_config.clone()));
+
+        write_report(state.session, &miri_config);
+        state.session.abort_if_errors();
+    } else if state.session.opts.test {
         struct Visitor<'a, 'tcx: 'a> {
             tcx: TyCtxt<'a, 'tcx, 'tcx>,
             state: &'a CompileState<'a, 'tcx>,
-            validate: bool,
+            miri_config: miri::MiriConfig,
+            test_options: TestOptions,
+            /// What the tests can share, so that each does not work it out again
+            cache: Rc<miri::SessionCache<'tcx>>,
+            /// How many tests passed the filter so far
+            selected: usize,
+            tests: usize,
+            /// The tests in our shard that are on the skip-list
+            skipped: Vec<String>,
+            failed: Vec<String>,
         };
         impl<'a, 'tcx: 'a, 'hir> itemlikevisit::ItemLikeVisitor<'hir> for Visitor<'a, 'tcx> {
             fn visit_item(&mut self, i: &'hir hir::Item) {
                 if let hir::ItemKind::Fn(.., body_id) = i.node {
+                    let kind = self.test_options.kind();
                     if i.attrs.iter().any(|attr| {
-                        attr.name() == "test"
+                        attr.name() == kind
                     })
                     {
                         let did = self.tcx.hir().body_owner_def_id(body_id);
-                        println!(
-                            "running test: {}",
-                            self.tcx.def_path_debug_str(did),
-                        );
-                        miri::eval_main(self.tcx, did, self.validate);
-                        self.state.session.abort_if_errors();
+                        let test_name = test_name(self.tcx, did);
+                        if self.test_options.list {
+                            println!("{}: {}", test_name, kind);
+                            return;
+                        }
+                        if !self.test_options.selects(&test_name) {
+                            return;
+                        }
+                        self.selected += 1;
+                        if !self.test_options.in_shard(self.selected - 1) {
+                            return;
+                        }
+                        let name = self.tcx.def_path_debug_str(did);
+                        if self.test_options.skips(&test_name) {
+                            println!("skipping {}: {}", kind, name);
+                            self.skipped.push(name);
+                            return;
+                        }
+                        println!("running {}: {}", kind, name);
+                        let errors_before = self.state.session.err_count();
+                        record_failure(miri::eval_main_cached(self.tcx, did, self.miri_config.clone(), &self.cache));
+                        self.tests += 1;
+                        if !self.test_options.keep_going {
+                            if self.state.session.has_errors() {
+                                write_report(self.state.session, &self.miri_config);
+                            }
+                            self.state.session.abort_if_errors();
+                        } else if self.state.session.err_count() > errors_before {
+                            // The test's own error has been reported; carry on with the
+                            // others, which do not share any state with it.
+                            self.failed.push(name);
+                        }
                     }
                 }
             }
             fn visit_trait_item(&mut self, _trait_item: &'hir hir::TraitItem) {}
             fn visit_impl_item(&mut self, _impl_item: &'hir hir::ImplItem) {}
         }
-        state.hir_crate.unwrap().visit_all_item_likes(
-            &mut Visitor { tcx, state, validate }
-        );
+        let mut visitor = Visitor {
+            tcx, state, miri_config, test_options, cache: Rc::default(),
+            selected: 0, tests: 0, skipped: vec![], failed: vec![],
+        };
+        state.hir_crate.unwrap().visit_all_item_likes(&mut visitor);
+        if !visitor.skipped.is_empty() {
+            // Skipped tests did not pass, so do not let them go unnoticed among those that did.
+            let mut report = format!(
+                "{} {}(s) skipped, as listed with `-Zmiri-skip-tests`:",
+                visitor.skipped.len(), visitor.test_options.kind(),
+            );
+            for name in &visitor.skipped {
+                report.push_str("\n    ");
+                report.push_str(name);
+            }
+            state.session.note_without_error(&report);
+        }
+        if !visitor.failed.is_empty() {
+            let mut report = format!(
+                "{} of {} {}s failed:", visitor.failed.len(), visitor.tests, visitor.test_options.kind(),
+            );
+            for name in &visitor.failed {
+                report.push_str("\n    ");
+                report.push_str(name);
+            }
+            state.session.note_without_error(&report);
+        }
+        write_report(state.session, &visitor.miri_config);
+        state.session.abort_if_errors();
     } else if let Some((entry_node_id, _, _)) = *state.session.entry_fn.borrow() {
         let entry_def_id = tcx.hir().local_def_id(entry_node_id);
-        miri::eval_main(tcx, entry_def_id, validate);
+        record_failure(miri::eval_main(tcx, entry_def_id, miri_config.clone()));
 
+        write_report(state.session, &miri_config);
         state.session.abort_if_errors();
     } else {
         println!("no main function found, assuming auxiliary build");
     }
 }
 
+/// Run miri once for each of the `seeds`, in parallel, until one of the runs fails.
+/// Each run is a process of its own, with the arguments we got, but with that seed.
+fn run_many_seeds(seeds: Range<u64>) -> ! {
+    let mut args = Vec::new();
+    let mut os_args = env::args_os().skip(1);
+    while let Some(arg) = os_args.next() {
+        if arg == "--" {
+            args.push(arg);
+            break;
+        }
+        let seed_flag = arg.to_str().map_or(false, |arg| {
+            arg.starts_with("-Zmiri-many-seeds=") || arg.starts_with("-Zmiri-seed=")
+        });
+        if !seed_flag {
+            args.push(arg);
+        }
+    }
+    args.extend(os_args);
+    let args = Arc::new(args);
+
+    let (start, end) = (seeds.start, seeds.end);
+    let total = end - start;
+    // How many seeds the workers took so far
+    let taken = Arc::new(AtomicUsize::new(0));
+    // The failed run with the lowest seed, with its output and exit code
+    let failure: Arc<Mutex<Option<(u64, Output)>>> = Arc::default();
+    let workers: Vec<_> = (0..cmp::min(parallelism(), total)).map(|_| {
+        let (args, taken, failure) = (args.clone(), taken.clone(), failure.clone());
+        thread::spawn(move || loop {
+            let seed = start + taken.fetch_add(1, Ordering::Relaxed) as u64;
+            if seed >= end || failure.lock().unwrap().is_some() {
+                return;
+            }
+            let output = Command::new(env::current_exe().unwrap())
+                .arg(format!("-Zmiri-seed={}", seed))
+                .args(&*args)
+                .output()
+                .unwrap_or_else(|err| panic!("could not run miri: {}", err));
+            if !output.status.success() {
+                let mut failure = failure.lock().unwrap();
+                if failure.as_ref().map_or(true, |&(failed_seed, _)| seed < failed_seed) {
+                    *failure = Some((seed, output));
+                }
+                return;
+            }
+        })
+    }).collect();
+    for worker in workers {
+        worker.join().unwrap();
+    }
+
+    let failure = failure.lock().unwrap().take();
+    match failure {
+        Some((seed, output)) => {
+            io::stdout().write_all(&output.stdout).unwrap();
+            io::stderr().write_all(&output.stderr).unwrap();
+            eprintln!("the run with -Zmiri-seed={} failed", seed);
+            std::process::exit(output.status.code().unwrap_or(1));
+        }
+        None => {
+            eprintln!("all {} runs with seeds {}..{} succeeded", total, start, end);
+            std::process::exit(0);
+        }
+    }
+}
+
+/// How many runs to do at the same time
+#[cfg(unix)]
+fn parallelism() -> u64 {
+    let cpus = unsafe { libc::sysconf(libc::_SC_NPROCESSORS_ONLN) };
+    cmp::max(cpus, 1) as u64
+}
+
+#[cfg(not(unix))]
+fn parallelism() -> u64 {
+    1
+}
+
+/// The bytes of an argument of the interpreted program.  Arguments on Windows are UTF-16,
+/// which we convert to UTF-8 like Rust programs do.
+#[cfg(unix)]
+fn into_bytes(arg: OsString) -> Vec<u8> {
+    use std::os::unix::ffi::OsStringExt;
+    arg.into_vec()
+}
+
+#[cfg(not(unix))]
+fn into_bytes(arg: OsString) -> Vec<u8> {
+    arg.to_string_lossy().into_owned().into_bytes()
+}
+
+/// Set an environment variable of the program, replacing an earlier value.
+fn set_env_var(miri_config: &mut miri::MiriConfig, name: &str, value: String) {
+    if name.is_empty() || name.contains('=') {
+        panic!("invalid environment variable name {:?}", name);
+    }
+    miri_config.env_vars.retain(|&(ref var, _)| var != name);
+    miri_config.env_vars.push((name.to_owned(), value));
+}
+
+fn parse_timeout(flag: &str, secs: &str) -> Duration {
+    match secs.parse() {
+        Ok(0) | Err(_) => panic!("{} should be a positive number of seconds, got {:?}", flag, secs),
+        Ok(secs) => Duration::from_secs(secs),
+    }
+}
+
+/// The name libtest gives a test: its path within the crate, like `tests::it_works`.
+fn test_name(tcx: TyCtxt, did: DefId) -> String {
+    tcx.def_path(did).data.iter()
+        .map(|component| component.data.as_interned_str().to_string())
+        .collect::<Vec<_>>()
+        .join("::")
+}
+
+/// The function at `path` in the local crate, named like `test_name` does, optionally
+/// starting with `crate::`.
+fn find_local_fn(tcx: TyCtxt, path: &str) -> Option<DefId> {
+    let path = path.trim_start_matches("crate::");
+    tcx.body_owners().find(|&did| {
+        match tcx.type_of(did).sty {
+            ty::FnDef(..) => test_name(tcx, did) == path,
+            _ => false,
+        }
+    })
+}
+
+/// Write the `-Zmiri-report`, if one was requested.  This has to happen before we abort
+/// because of errors.
+fn write_report(session: &Session, miri_config: &miri::MiriConfig) {
+    if let Some(ref report) = miri_config.report {
+        if let Err(err) = report.write() {
+            session.err(&format!("could not write the miri report: {}", err));
+        }
+    }
+}
+
+/// Make `SIGUSR1` and `SIGQUIT` (Ctrl-\) print where the interpreted program is.
+#[cfg(unix)]
+fn install_backtrace_handler() {
+    extern "C" fn request_backtrace(_signal: libc::c_int) {
+        miri::BACKTRACE_REQUESTED.store(true, std::sync::atomic::Ordering::Relaxed);
+    }
+    unsafe {
+        libc::signal(libc::SIGUSR1, request_backtrace as libc::sighandler_t);
+        libc::signal(libc::SIGQUIT, request_backtrace as libc::sighandler_t);
+    }
+}
+
+#[cfg(not(unix))]
+fn install_backtrace_handler() {
+    panic!("-Zmiri-backtrace-on-signal is only supported on Unix hosts");
+}
+
+/// Emulate the foreign functions of the shim plugin at `path`.  It exports `miri_shim_names`,
+/// a null-terminated array of the link names it emulates, and for each of them a
+/// `miri::PluginFn` called `miri_shim_<name>`.
+#[cfg(unix)]
+fn load_shim_plugin(miri_config: &mut miri::MiriConfig, path: &str) {
+    use std::ffi::{CStr, CString};
+
+    let c_path = CString::new(path).unwrap();
+    // We never unload the plugin, its functions are used until the end.
+    let lib = unsafe { libc::dlopen(c_path.as_ptr(), libc::RTLD_NOW) };
+    if lib.is_null() {
+        let err = unsafe { libc::dlerror() };
+        let err = if err.is_null() {
+            "unknown error".into()
+        } else {
+            unsafe { CStr::from_ptr(err) }.to_string_lossy()
+        };
+        panic!("-Zmiri-shim-plugin: cannot load {}: {}", path, err);
+    }
+    let symbol = |name: &str| {
+        let name = CString::new(name).unwrap();
+        unsafe { libc::dlsym(lib, name.as_ptr()) }
+    };
+    let names = symbol("miri_shim_names") as *const *const libc::c_char;
+    if names.is_null() {
+        panic!("-Zmiri-shim-plugin: {} does not export `miri_shim_names`", path);
+    }
+    for i in 0.. {
+        let name = unsafe { *names.add(i) };
+        if name.is_null() {
+            break;
+        }
+        let name = unsafe { CStr::from_ptr(name) }.to_string_lossy().into_owned();
+        let function = symbol(&format!("miri_shim_{}", name));
+        if function.is_null() {
+            panic!("-Zmiri-shim-plugin: {} does not export `miri_shim_{}`", path, name);
+        }
+        let function: miri::PluginFn = unsafe { std::mem::transmute(function) };
+        miri_config.foreign_shims.add(name.clone(), Arc::new(miri::PluginShim::new(name, function)));
+    }
+}
+
+#[cfg(not(unix))]
+fn load_shim_plugin(_miri_config: &mut miri::MiriConfig, _path: &str) {
+    panic!("-Zmiri-shim-plugin is only supported on Unix hosts");
+}
+
 fn init_early_loggers() {
     // Notice that our `extern crate log` is NOT the same as rustc's!  So we have to initialize
     // them both.  We always initialize miri early.
@@ -212,20 +568,406 @@ fn find_sysroot() -> String {
 
 fn main() {
     init_early_loggers();
-    let mut args: Vec<String> = std::env::args().collect();
+    let mut miri_config = miri::MiriConfig::default();
+
+    // The arguments after `--` are for the interpreted program, and need not be unicode.
+    let mut args: Vec<String> = Vec::new();
+    let mut os_args = std::env::args_os();
+    for arg in &mut os_args {
+        if arg == "--" {
+            break;
+        }
+        args.push(arg.into_string().unwrap_or_else(|arg| panic!("argument is not valid unicode: {:?}", arg)));
+    }
+    miri_config.args = os_args.map(into_bytes).collect();
 
     // Parse our own -Z flags and remove them before rustc gets their hand on them.
-    let mut validate = true;
+    let mut test_options = TestOptions::default();
+    let mut many_seeds = None;
+    let mut host_rng = false;
+    let mut call = None;
+    let mut call_args = None;
     args.retain(|arg| {
         match arg.as_str() {
             "-Zmiri-disable-validation" => {
-                validate = false;
+                miri_config.validate = false;
+                false
+            },
+            "-Zmiri-random-nans" => {
+                miri_config.random_nans = true;
+                false
+            },
+            "-Zmiri-float-imprecision" => {
+                miri_config.float_imprecision = true;
+                false
+            },
+            "-Zmiri-deterministic-floats" => {
+                miri_config.deterministic_floats = true;
+                false
+            },
+            "-Zmiri-keep-going" => {
+                test_options.keep_going = true;
+                false
+            },
+            "-Zmiri-list-tests" => {
+                test_options.list = true;
+                false
+            },
+            "-Zmiri-bench" => {
+                test_options.bench = true;
+                false
+            },
+            "-Zmiri-test-exact" => {
+                test_options.exact = true;
+                false
+            },
+            arg if arg.starts_with("-Zmiri-test-shard=") => {
+                let shard = &arg["-Zmiri-test-shard=".len()..];
+                let parsed = shard.find('/').and_then(|split| {
+                    Some((shard[..split].parse().ok()?, shard[split + 1..].parse().ok()?))
+                });
+                test_options.shard = match parsed {
+                    Some((shard, shards)) if shard < shards => Some((shard, shards)),
+                    _ => panic!("-Zmiri-test-shard should be `<i>/<n>` with `i < n`, got {:?}", shard),
+                };
+                false
+            },
+            arg if arg.starts_with("-Zmiri-skip-tests=") => {
+                let patterns = &arg["-Zmiri-skip-tests=".len()..];
+                test_options.skip.extend(patterns.split(',').map(|pattern| pattern.trim().to_owned()));
+                false
+            },
+            arg if arg.starts_with("-Zmiri-test-filter=") => {
+                test_options.filter = Some(arg["-Zmiri-test-filter=".len()..].to_owned());
+                false
+            },
+            arg if arg.starts_with("-Zmiri-backtrace=") => {
+                miri_config.backtrace = match &arg["-Zmiri-backtrace=".len()..] {
+                    "short" => miri::BacktraceStyle::Short,
+                    "full" => miri::BacktraceStyle::Full,
+                    "full-locals" => miri::BacktraceStyle::FullLocals,
+                    style => panic!("-Zmiri-backtrace should be `short`, `full` or `full-locals`, got {:?}", style),
+                };
+                false
+            },
+            arg if arg.starts_with("-Zmiri-suppressions=") => {
+                let path = &arg["-Zmiri-suppressions=".len()..];
+                let text = std::fs::read_to_string(path).unwrap_or_else(|err| {
+                    panic!("-Zmiri-suppressions: cannot read {:?}: {}", path, err)
+                });
+                miri_config.suppressions = miri::Suppressions::parse(&text).unwrap_or_else(|err| {
+                    panic!("-Zmiri-suppressions: {:?}: {}", path, err)
+                });
+                false
+            },
+            arg if arg.starts_with("-Zmiri-report=") => {
+                let report = &arg["-Zmiri-report=".len()..];
+                miri_config.report = Some(if report.starts_with("html=") {
+                    miri::Report::new(PathBuf::from(&report["html=".len()..]))
+                } else if report.starts_with("json=") {
+                    miri::Report::json(PathBuf::from(&report["json=".len()..]))
+                } else {
+                    panic!("-Zmiri-report should be `html=<dir>` or `json=<path>`, got {:?}", report);
+                });
+                false
+            },
+            "-Zmiri-stats" => {
+                miri_config.stats = true;
+                false
+            },
+            "-Zmiri-warn-int2ptr" => {
+                miri_config.warn_int2ptr = true;
+                false
+            },
+            "-Zmiri-backtrace-on-signal" => {
+                install_backtrace_handler();
+                false
+            },
+            arg if arg.starts_with("-Zmiri-call=") => {
+                call = Some(arg["-Zmiri-call=".len()..].to_owned());
+                false
+            },
+            arg if arg.starts_with("-Zmiri-call-args=") => {
+                let path = &arg["-Zmiri-call-args=".len()..];
+                let json = std::fs::read_to_string(path).unwrap_or_else(|err| {
+                    panic!("-Zmiri-call-args: cannot read {:?}: {}", path, err)
+                });
+                call_args = Some(miri::CallArg::parse_list(&json).unwrap_or_else(|err| {
+                    panic!("-Zmiri-call-args: {:?}: {}", path, err)
+                }));
+                false
+            },
+            "-Zmiri-interactive" => {
+                miri_config.interactive = true;
+                false
+            },
+            arg if arg.starts_with("-Zmiri-watch-alloc=") => {
+                let watch = &arg["-Zmiri-watch-alloc=".len()..];
+                miri_config.watches.push(miri::Watch::parse(watch).unwrap_or_else(|err| {
+                    panic!("-Zmiri-watch-alloc: {}", err)
+                }));
+                false
+            },
+            arg if arg.starts_with("-Zmiri-state-dump=") => {
+                miri_config.state_dump = Some(PathBuf::from(&arg["-Zmiri-state-dump=".len()..]));
+                false
+            },
+            arg if arg.starts_with("-Zmiri-gdb=") => {
+                let port = &arg["-Zmiri-gdb=".len()..];
+                miri_config.gdb_port = Some(port.parse().unwrap_or_else(|_| {
+                    panic!("-Zmiri-gdb should be a port number, got {:?}", port)
+                }));
+                false
+            },
+            arg if arg.starts_with("-Zmiri-shim-plugin=") => {
+                load_shim_plugin(&mut miri_config, &arg["-Zmiri-shim-plugin=".len()..]);
+                false
+            },
+            arg if arg.starts_with("-Zmiri-report-progress=") => {
+                let interval = &arg["-Zmiri-report-progress=".len()..];
+                miri_config.report_progress = match interval.parse() {
+                    Ok(0) | Err(_) =>
+                        panic!("-Zmiri-report-progress should be a positive integer, got {:?}", interval),
+                    Ok(interval) => Some(interval),
+                };
+                false
+            },
+            arg if arg.starts_with("-Zmiri-env-forward=") => {
+                let name = &arg["-Zmiri-env-forward=".len()..];
+                // A variable that is not set on the host is not set for the program either.
+                match env::var(name) {
+                    Ok(value) => set_env_var(&mut miri_config, name, value),
+                    Err(env::VarError::NotPresent) => {}
+                    Err(env::VarError::NotUnicode(_)) =>
+                        panic!("-Zmiri-env-forward: the value of {} is not valid unicode", name),
+                }
+                false
+            },
+            arg if arg.starts_with("-Zmiri-env-set=") => {
+                let var = &arg["-Zmiri-env-set=".len()..];
+                let split = var.find('=').unwrap_or_else(|| {
+                    panic!("-Zmiri-env-set should be `<var>=<value>`, got {:?}", var)
+                });
+                set_env_var(&mut miri_config, &var[..split], var[split + 1..].to_owned());
+                false
+            },
+            arg if arg.starts_with("-Zmiri-output=") => {
+                let output = &arg["-Zmiri-output=".len()..];
+                miri_config.output = match output {
+                    "stream" => miri::OutputMode::Stream,
+                    "capture" => miri::OutputMode::Capture,
+                    _ if output.starts_with("dir=") => miri::OutputMode::Files(PathBuf::from(&output["dir=".len()..])),
+                    _ => panic!("-Zmiri-output should be `stream`, `capture` or `dir=<dir>`, got {:?}", output),
+                };
+                false
+            },
+            "-Zmiri-stdin=-" => {
+                miri_config.stdin = miri::StdinSource::Host;
+                false
+            },
+            arg if arg.starts_with("-Zmiri-stdin=") => {
+                let path = &arg["-Zmiri-stdin=".len()..];
+                let data = std::fs::read(path).unwrap_or_else(|err| {
+                    panic!("-Zmiri-stdin: cannot read {:?}: {}", path, err)
+                });
+                miri_config.stdin = miri::StdinSource::Data(data);
+                false
+            },
+            arg if arg.starts_with("-Zmiri-stdin-data=") => {
+                let data = arg["-Zmiri-stdin-data=".len()..].as_bytes().to_vec();
+                miri_config.stdin = miri::StdinSource::Data(data);
+                false
+            },
+            arg if arg.starts_with("-Zmiri-isolation-error=") => {
+                let spec = &arg["-Zmiri-isolation-error=".len()..];
+                miri_config.isolation.set(spec).unwrap_or_else(|err| {
+                    panic!("-Zmiri-isolation-error: {}", err)
+                });
+                false
+            },
+            arg if arg.starts_with("-Zmiri-isolation-allow-path=") => {
+                let pattern = &arg["-Zmiri-isolation-allow-path=".len()..];
+                miri_config.allowed_paths.allow(pattern, false).unwrap_or_else(|err| {
+                    panic!("-Zmiri-isolation-allow-path: {}", err)
+                });
+                false
+            },
+            arg if arg.starts_with("-Zmiri-isolation-allow-path-rw=") => {
+                let pattern = &arg["-Zmiri-isolation-allow-path-rw=".len()..];
+                miri_config.allowed_paths.allow(pattern, true).unwrap_or_else(|err| {
+                    panic!("-Zmiri-isolation-allow-path-rw: {}", err)
+                });
+                false
+            },
+            arg if arg.starts_with("-Zmiri-clock=") => {
+                let mode = &arg["-Zmiri-clock=".len()..];
+                let tick = |ns: &str| match ns.parse() {
+                    Ok(0) | Err(_) => panic!("-Zmiri-clock should have a positive number of nanoseconds, got {:?}", ns),
+                    Ok(ns) => Duration::from_nanos(ns),
+                };
+                miri_config.clock = match mode {
+                    "wait" => miri::ClockMode::Wait,
+                    _ if mode.starts_with("block=") => miri::ClockMode::PerBlock(tick(&mode["block=".len()..])),
+                    _ if mode.starts_with("query=") => miri::ClockMode::PerQuery(tick(&mode["query=".len()..])),
+                    _ => panic!("-Zmiri-clock should be `wait`, `block=<ns>` or `query=<ns>`, got {:?}", mode),
+                };
+                false
+            },
+            arg if arg.starts_with("-Zmiri-self-profile=") => {
+                miri_config.self_profile = Some(PathBuf::from(&arg["-Zmiri-self-profile=".len()..]));
+                false
+            },
+            arg if arg.starts_with("-Zmiri-profile=") => {
+                let path = PathBuf::from(&arg["-Zmiri-profile=".len()..]);
+                // The runs append to the profile, start with an empty one.
+                if let Err(err) = std::fs::File::create(&path) {
+                    panic!("-Zmiri-profile: cannot create {}: {}", path.display(), err);
+                }
+                miri_config.profile = Some(path);
+                false
+            },
+            arg if arg.starts_with("-Zmiri-trace=") => {
+                let path = PathBuf::from(&arg["-Zmiri-trace=".len()..]);
+                // The runs append to the trace, start with an empty one.
+                if let Err(err) = std::fs::File::create(&path) {
+                    panic!("-Zmiri-trace: cannot create {}: {}", path.display(), err);
+                }
+                miri_config.trace = Some(path);
+                false
+            },
+            arg if arg.starts_with("-Zmiri-coverage=") => {
+                let path = PathBuf::from(&arg["-Zmiri-coverage=".len()..]);
+                // The runs append to the coverage, start with none.
+                if let Err(err) = std::fs::File::create(&path) {
+                    panic!("-Zmiri-coverage: cannot create {}: {}", path.display(), err);
+                }
+                miri_config.coverage = Some(path);
+                false
+            },
+            arg if arg.starts_with("-Zmiri-event-stream=") => {
+                let addr = &arg["-Zmiri-event-stream=".len()..];
+                miri_config.event_stream = Some(miri::EventStream::connect(addr).unwrap_or_else(|err| {
+                    panic!("-Zmiri-event-stream: cannot connect to {}: {}", addr, err)
+                }));
+                false
+            },
+            arg if arg.starts_with("-Zmiri-record=") => {
+                miri_config.replay = Some(miri::ReplayMode::Record(PathBuf::from(&arg["-Zmiri-record=".len()..])));
+                false
+            },
+            arg if arg.starts_with("-Zmiri-replay=") => {
+                miri_config.replay = Some(miri::ReplayMode::Replay(PathBuf::from(&arg["-Zmiri-replay=".len()..])));
+                false
+            },
+            arg if arg.starts_with("-Zmiri-timeout=") => {
+                miri_config.timeout = Some(parse_timeout("-Zmiri-timeout", &arg["-Zmiri-timeout=".len()..]));
+                false
+            },
+            arg if arg.starts_with("-Zmiri-max-steps=") => {
+                let steps = &arg["-Zmiri-max-steps=".len()..];
+                miri_config.max_steps = match steps.parse() {
+                    Ok(0) | Err(_) =>
+                        panic!("-Zmiri-max-steps should be a positive integer, got {:?}", steps),
+                    Ok(steps) => Some(steps),
+                };
+                false
+            },
+            arg if arg.starts_with("-Zmiri-test-timeout=") => {
+                let timeout = &arg["-Zmiri-test-timeout=".len()..];
+                miri_config.test_timeout = Some(parse_timeout("-Zmiri-test-timeout", timeout));
+                false
+            },
+            arg if arg.starts_with("-Zmiri-track-alloc-id=") => {
+                let ids = &arg["-Zmiri-track-alloc-id=".len()..];
+                miri_config.tracked_allocs.add_ids(ids).unwrap_or_else(|err| {
+                    panic!("-Zmiri-track-alloc-id: {}", err)
+                });
+                false
+            },
+            arg if arg.starts_with("-Zmiri-track-alloc-site=") => {
+                let site = &arg["-Zmiri-track-alloc-site=".len()..];
+                miri_config.tracked_allocs.add_site(site).unwrap_or_else(|err| {
+                    panic!("-Zmiri-track-alloc-site: {}", err)
+                });
+                false
+            },
+            arg if arg.starts_with("-Zmiri-max-heap=") => {
+                let bytes = &arg["-Zmiri-max-heap=".len()..];
+                miri_config.max_heap = Some(bytes.parse().unwrap_or_else(|_| {
+                    panic!("-Zmiri-max-heap should be a number of bytes, got {:?}", bytes)
+                }));
+                false
+            },
+            arg if arg.starts_with("-Zmiri-severity=") => {
+                let spec = &arg["-Zmiri-severity=".len()..];
+                miri_config.severities.set(spec).unwrap_or_else(|err| {
+                    panic!("-Zmiri-severity: {}", err)
+                });
+                false
+            },
+            arg if arg.starts_with("-Zmiri-shim-report=") => {
+                let count = &arg["-Zmiri-shim-report=".len()..];
+                miri_config.shim_report = Some(count.parse().unwrap_or_else(|_| {
+                    panic!("-Zmiri-shim-report should be a number, got {:?}", count)
+                }));
+                false
+            },
+            arg if arg.starts_with("-Zmiri-many-seeds=") => {
+                let seeds = &arg["-Zmiri-many-seeds=".len()..];
+                let parse = |seed: &str| seed.parse::<u64>().unwrap_or_else(|_| {
+                    panic!("-Zmiri-many-seeds should be a range like `0..64`, got {:?}", seeds)
+                });
+                let range = if let Some(split) = seeds.find("..=") {
+                    parse(&seeds[..split])..parse(&seeds[split + 3..]) + 1
+                } else if let Some(split) = seeds.find("..") {
+                    parse(&seeds[..split])..parse(&seeds[split + 2..])
+                } else {
+                    panic!("-Zmiri-many-seeds should be a range like `0..64`, got {:?}", seeds)
+                };
+                if range.start >= range.end {
+                    panic!("-Zmiri-many-seeds: the range {:?} is empty", seeds);
+                }
+                many_seeds = Some(range);
+                false
+            },
+            "-Zmiri-host-rng" => {
+                host_rng = true;
+                false
+            },
+            arg if arg.starts_with("-Zmiri-seed=") => {
+                let seed = &arg["-Zmiri-seed=".len()..];
+                miri_config.seed = seed.parse().unwrap_or_else(|_| {
+                    panic!("-Zmiri-seed should be an unsigned 64-bit integer, got {:?}", seed)
+                });
                 false
             },
             _ => true
         }
     });
 
+    if miri_config.interactive && miri_config.gdb_port.is_some() {
+        panic!("-Zmiri-interactive cannot be combined with -Zmiri-gdb");
+    }
+    match (&call, call_args) {
+        (Some(_), args) => miri_config.call = Some(miri::Call::new(args.unwrap_or_default())),
+        (None, Some(_)) => panic!("-Zmiri-call-args needs -Zmiri-call"),
+        (None, None) => {}
+    }
+
+    if let Some(seeds) = many_seeds {
+        if host_rng {
+            panic!("-Zmiri-host-rng cannot be combined with -Zmiri-many-seeds");
+        }
+        run_many_seeds(seeds);
+    }
+    if host_rng {
+        miri_config.seed = miri::host_seed();
+        eprintln!(
+            "note: -Zmiri-host-rng picked the seed {0}, use -Zmiri-seed={0} to reproduce this run",
+            miri_config.seed,
+        );
+    }
+
     // Determine sysroot and let rustc know about it
     let sysroot_flag = String::from("--sysroot");
     if !args.contains(&sysroot_flag) {
@@ -239,8 +981,14 @@ fn main() {
     let result = rustc_driver::run(move || {
         rustc_driver::run_compiler(&args, Box::new(MiriCompilerCalls {
             default: Box::new(RustcDefaultCalls),
-            validate,
+            miri_config,
+            test_options,
+            call,
         }), None, None)
     });
+    let exit_code = EXIT_CODE.load(Ordering::Relaxed);
+    if result != 0 && exit_code != 0 {
+        std::process::exit(exit_code as i32);
+    }
     std::process::exit(result as i32);
 }
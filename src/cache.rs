@@ -0,0 +1,24 @@
+//! What the runs of one session, like the tests of a crate, can share, so that each run does
+//! not have to work it out again.  Layouts and the values of constants are queries, which
+//! the compiler already caches for the whole session; this is the work miri does on top of
+//! them: stripping inline assembly from function bodies, resolving paths, and reading the
+//! `libc` constants out of their allocations.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+use rustc::hir::def_id::DefId;
+use rustc::mir;
+use rustc::ty;
+
+#[derive(Default)]
+pub struct SessionCache<'tcx> {
+    /// Function bodies, with trivial inline assembly removed
+    pub(crate) asm_free_mir: RefCell<HashMap<ty::InstanceDef<'tcx>, &'tcx mir::Mir<'tcx>>>,
+    /// The items resolved by `resolve_path`
+    pub(crate) paths: RefCell<HashMap<Vec<String>, ty::Instance<'tcx>>>,
+    /// The values of `libc` constants, by name
+    pub(crate) libc_consts: RefCell<HashMap<String, i32>>,
+    /// `core::slice::memchr::{memchr, memrchr}`, once we looked for them
+    pub(crate) core_memchr: RefCell<Option<(Option<DefId>, Option<DefId>)>>,
+}
@@ -0,0 +1,484 @@
+//! `-Zmiri-call=<path>`: evaluate a single function of the crate instead of a whole program,
+//! for property-testing and fuzzing harnesses that target individual unsafe functions.  The
+//! arguments come from `-Zmiri-call-args=<file>`, a JSON array with one element per argument:
+//! numbers for integers and floats, `true` and `false` for `bool`, strings for `char` and
+//! `&str`, arrays for arrays and slices, and `null` for `()`.  The function may not be
+//! generic.  Undefined behavior is reported as always; if there is none, we report the
+//! return value, in the same notation if its type allows.
+
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use rustc::hir::def_id::DefId;
+use rustc::mir;
+use rustc::ty::{self, layout::{LayoutOf, Size}};
+use syntax::ast::FloatTy;
+use syntax::source_map::DUMMY_SP;
+
+use crate::*;
+use crate::state_dump::json_string;
+
+/// An argument or a return value
+#[derive(Clone, Debug, PartialEq)]
+pub enum CallArg {
+    Null,
+    Bool(bool),
+    Int(i128),
+    Float(f64),
+    Str(String),
+    Array(Vec<CallArg>),
+}
+
+impl CallArg {
+    /// Parse the JSON array of the arguments of a call.
+    pub fn parse_list(json: &str) -> Result<Vec<CallArg>, String> {
+        let mut parser = Parser { json, pos: 0 };
+        let value = parser.value()?;
+        parser.skip_whitespace();
+        if parser.pos != json.len() {
+            return Err(parser.error("trailing characters"));
+        }
+        match value {
+            CallArg::Array(args) => Ok(args),
+            _ => Err("the arguments should be a JSON array".to_owned()),
+        }
+    }
+}
+
+impl fmt::Display for CallArg {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CallArg::Null => write!(f, "null"),
+            CallArg::Bool(b) => write!(f, "{}", b),
+            CallArg::Int(i) => write!(f, "{}", i),
+            CallArg::Float(x) => write!(f, "{:?}", x),
+            CallArg::Str(ref s) => write!(f, "{}", json_string(s)),
+            CallArg::Array(ref elems) => {
+                write!(f, "[")?;
+                for (i, elem) in elems.iter().enumerate() {
+                    if i != 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", elem)?;
+                }
+                write!(f, "]")
+            }
+        }
+    }
+}
+
+/// Just enough JSON for the values above; objects are not supported.
+struct Parser<'s> {
+    json: &'s str,
+    pos: usize,
+}
+
+impl<'s> Parser<'s> {
+    fn error(&self, msg: &str) -> String {
+        format!("{} at byte {} of the arguments", msg, self.pos)
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.json[self.pos..].chars().next()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += c.len_utf8();
+        Some(c)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(c) = self.peek() {
+            match c {
+                ' ' | '\t' | '\n' | '\r' => self.pos += 1,
+                _ => break,
+            }
+        }
+    }
+
+    fn keyword(&mut self, word: &str, value: CallArg) -> Result<CallArg, String> {
+        if self.json[self.pos..].starts_with(word) {
+            self.pos += word.len();
+            Ok(value)
+        } else {
+            Err(self.error("unexpected characters"))
+        }
+    }
+
+    fn value(&mut self) -> Result<CallArg, String> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('n') => self.keyword("null", CallArg::Null),
+            Some('t') => self.keyword("true", CallArg::Bool(true)),
+            Some('f') => self.keyword("false", CallArg::Bool(false)),
+            Some('"') => self.string().map(CallArg::Str),
+            Some('[') => self.array(),
+            Some('-') | Some('0'..='9') => self.number(),
+            Some('{') => Err(self.error("objects are not supported")),
+            Some(_) => Err(self.error("unexpected character")),
+            None => Err(self.error("unexpected end")),
+        }
+    }
+
+    fn array(&mut self) -> Result<CallArg, String> {
+        self.next(); // `[`
+        let mut elems = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some(']') {
+            self.next();
+            return Ok(CallArg::Array(elems));
+        }
+        loop {
+            elems.push(self.value()?);
+            self.skip_whitespace();
+            match self.next() {
+                Some(',') => {}
+                Some(']') => return Ok(CallArg::Array(elems)),
+                _ => return Err(self.error("expected `,` or `]`")),
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.next(); // `"`
+        let mut s = String::new();
+        loop {
+            match self.next() {
+                Some('"') => return Ok(s),
+                Some('\\') => {
+                    let c = match self.next() {
+                        Some('"') => '"',
+                        Some('\\') => '\\',
+                        Some('/') => '/',
+                        Some('b') => '\u{8}',
+                        Some('f') => '\u{c}',
+                        Some('n') => '\n',
+                        Some('r') => '\r',
+                        Some('t') => '\t',
+                        Some('u') => self.unicode_escape()?,
+                        _ => return Err(self.error("invalid escape")),
+                    };
+                    s.push(c);
+                }
+                Some(c) if (c as u32) < 0x20 => return Err(self.error("control character in string")),
+                Some(c) => s.push(c),
+                None => return Err(self.error("unterminated string")),
+            }
+        }
+    }
+
+    /// The rest of a `\uXXXX` escape, which may be the first half of a surrogate pair.
+    fn unicode_escape(&mut self) -> Result<char, String> {
+        let first = self.hex4()?;
+        let code = if first >= 0xD800 && first < 0xDC00 {
+            if !self.json[self.pos..].starts_with("\\u") {
+                return Err(self.error("unpaired surrogate"));
+            }
+            self.pos += 2;
+            let second = self.hex4()?;
+            if second < 0xDC00 || second >= 0xE000 {
+                return Err(self.error("unpaired surrogate"));
+            }
+            0x10000 + ((first - 0xD800) << 10) + (second - 0xDC00)
+        } else {
+            first
+        };
+        ::std::char::from_u32(code).ok_or_else(|| self.error("invalid unicode escape"))
+    }
+
+    fn hex4(&mut self) -> Result<u32, String> {
+        let digits = self.json.get(self.pos..self.pos + 4)
+            .ok_or_else(|| self.error("invalid unicode escape"))?;
+        let code = u32::from_str_radix(digits, 16).map_err(|_| self.error("invalid unicode escape"))?;
+        self.pos += 4;
+        Ok(code)
+    }
+
+    /// Numbers with a fraction or an exponent are floats, all others integers.
+    fn number(&mut self) -> Result<CallArg, String> {
+        let start = self.pos;
+        let mut float = false;
+        while let Some(c) = self.peek() {
+            match c {
+                '0'..='9' | '-' | '+' => {}
+                '.' | 'e' | 'E' => float = true,
+                _ => break,
+            }
+            self.pos += 1;
+        }
+        let text = &self.json[start..self.pos];
+        if float {
+            text.parse().map(CallArg::Float).map_err(|_| self.error("invalid number"))
+        } else {
+            text.parse().map(CallArg::Int).map_err(|_| self.error("invalid or too large integer"))
+        }
+    }
+}
+
+/// A call of a single function.  Clones share the return value, so an embedder can read it
+/// from its copy of the configuration after the run.
+#[derive(Clone, Debug)]
+pub struct Call {
+    pub args: Vec<CallArg>,
+    returned: Arc<Mutex<Option<CallArg>>>,
+}
+
+impl Call {
+    pub fn new(args: Vec<CallArg>) -> Self {
+        Call { args, returned: Arc::default() }
+    }
+
+    /// What the function returned, if it did return, and its type is one we can express
+    pub fn returned(&self) -> Option<CallArg> {
+        self.returned.lock().unwrap().clone()
+    }
+}
+
+impl<'a, 'mir, 'tcx> EvalContextExt<'a, 'mir, 'tcx> for crate::MiriEvalContext<'a, 'mir, 'tcx> {}
+pub trait EvalContextExt<'a, 'mir, 'tcx: 'a+'mir>: crate::MiriEvalContextExt<'a, 'mir, 'tcx> {
+    /// Push the frame of a call of `fn_id` with `args`.  Returns the place of the return value.
+    fn push_call_frame(
+        &mut self,
+        fn_id: DefId,
+        args: &[CallArg],
+    ) -> EvalResult<'tcx, MPlaceTy<'tcx, Borrow>> {
+        let this = self.eval_context_mut();
+        let name = this.tcx.item_path_str(fn_id);
+        if this.tcx.generics_of(fn_id).requires_monomorphization(this.tcx.tcx) {
+            return err!(Unimplemented(format!("cannot call `{}`, it is generic", name)));
+        }
+        let instance = ty::Instance::mono(this.tcx.tcx, fn_id);
+        let mir = this.load_mir(instance.def)?;
+        if mir.arg_count != args.len() {
+            return err!(Unimplemented(format!(
+                "`{}` takes {} argument(s), but {} were given", name, mir.arg_count, args.len(),
+            )));
+        }
+
+        // Return value (in static memory so that it does not count as leak)
+        let ret = this.layout_of(mir.return_ty())?;
+        let ret_place = this.allocate(ret, MiriMemoryKind::MutStatic.into())?;
+
+        this.push_stack_frame(
+            instance,
+            DUMMY_SP, // there is no call site, we want no span
+            mir,
+            Some(ret_place.into()),
+            StackPopCleanup::None { cleanup: true },
+        )?;
+
+        let locals: Vec<mir::Local> = this.frame().mir.args_iter().collect();
+        for (local, arg) in locals.into_iter().zip(args) {
+            let dest = this.eval_place(&mir::Place::Local(local))?;
+            this.write_call_arg(arg, dest)?;
+        }
+        Ok(ret_place)
+    }
+
+    /// Write `arg` to `dest`, allocating the memory that references point to.  That memory
+    /// belongs to the environment, so it is never a leak.
+    fn write_call_arg(&mut self, arg: &CallArg, dest: PlaceTy<'tcx, Borrow>) -> EvalResult<'tcx> {
+        let this = self.eval_context_mut();
+        let ty = dest.layout.ty;
+        let size = dest.layout.size;
+        let mismatch = || err!(Unimplemented(format!(
+            "cannot pass {} as an argument of type `{}`", arg, ty,
+        )));
+        match (arg, &ty.sty) {
+            (CallArg::Null, ty::Tuple(tys)) if tys.is_empty() => Ok(()),
+            (CallArg::Bool(b), ty::Bool) => this.write_scalar(Scalar::from_bool(*b), dest),
+            (CallArg::Int(i), ty::Int(_)) | (CallArg::Int(i), ty::Uint(_)) => {
+                let bits = this.truncate(*i as u128, dest.layout);
+                // Values that do not fit are an error in the harness, do not wrap them around.
+                let fits = match ty.sty {
+                    ty::Int(_) => crate::simd::sign_extend(bits, size) == *i,
+                    _ => *i >= 0 && bits == *i as u128,
+                };
+                if !fits {
+                    return err!(Unimplemented(format!("{} does not fit in `{}`", i, ty)));
+                }
+                this.write_scalar(Scalar::from_uint(bits, size), dest)
+            }
+            (CallArg::Int(i), ty::Float(float_ty)) => write_float(this, *i as f64, *float_ty, dest),
+            (CallArg::Float(x), ty::Float(float_ty)) => write_float(this, *x, *float_ty, dest),
+            (CallArg::Str(s), ty::Char) => {
+                let mut chars = s.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) => this.write_scalar(Scalar::from_uint(c as u32 as u128, size), dest),
+                    _ => mismatch(),
+                }
+            }
+            (CallArg::Array(elems), ty::Array(..)) => {
+                if elems.len() as u64 != dest.layout.fields.count() as u64 {
+                    return mismatch();
+                }
+                for (i, elem) in elems.iter().enumerate() {
+                    let field = this.place_field(dest, i as u64)?;
+                    this.write_call_arg(elem, field)?;
+                }
+                Ok(())
+            }
+            (_, ty::Ref(_, pointee, _)) => {
+                let (elem_ty, len) = match (arg, &pointee.sty) {
+                    (CallArg::Str(s), ty::Str) => (this.tcx.types.u8, s.len()),
+                    (CallArg::Array(elems), ty::Slice(elem_ty)) => (*elem_ty, elems.len()),
+                    _ => return mismatch(),
+                };
+                let layout = this.layout_of(this.tcx.mk_array(elem_ty, len as u64))?;
+                let place = this.allocate(layout, MiriMemoryKind::Env.into())?;
+                match arg {
+                    CallArg::Str(s) => {
+                        let ptr = place.ptr.to_ptr()?;
+                        let tcx = &{this.tcx.tcx};
+                        this.memory_mut().get_mut(ptr.alloc_id)?.write_bytes(tcx, ptr, s.as_bytes())?;
+                    }
+                    CallArg::Array(elems) => {
+                        for (i, elem) in elems.iter().enumerate() {
+                            let field = this.mplace_field(place, i as u64)?;
+                            this.write_call_arg(elem, field.into())?;
+                        }
+                    }
+                    _ => unreachable!(),
+                }
+                let len = Scalar::from_uint(len as u128, this.memory().pointer_size());
+                this.write_immediate(Immediate::ScalarPair(place.ptr.into(), len.into()), dest)
+            }
+            _ => mismatch(),
+        }
+    }
+
+    /// The value at `place`, if its type is one we can express
+    fn read_call_result(&self, place: MPlaceTy<'tcx, Borrow>) -> EvalResult<'tcx, Option<CallArg>> {
+        let this = self.eval_context_ref();
+        let size = place.layout.size;
+        Ok(Some(match place.layout.ty.sty {
+            ty::Tuple(tys) if tys.is_empty() => CallArg::Null,
+            ty::Bool => CallArg::Bool(this.read_scalar(place.into())?.to_bool()?),
+            ty::Int(_) => {
+                let bits = this.read_scalar(place.into())?.to_bits(size)?;
+                CallArg::Int(crate::simd::sign_extend(bits, size))
+            }
+            ty::Uint(_) => {
+                let bits = this.read_scalar(place.into())?.to_bits(size)?;
+                if bits > i128::max_value() as u128 {
+                    return Ok(None);
+                }
+                CallArg::Int(bits as i128)
+            }
+            ty::Float(FloatTy::F32) => {
+                let bits = this.read_scalar(place.into())?.to_bits(size)?;
+                CallArg::Float(f32::from_bits(bits as u32) as f64)
+            }
+            ty::Float(FloatTy::F64) => {
+                let bits = this.read_scalar(place.into())?.to_bits(size)?;
+                CallArg::Float(f64::from_bits(bits as u64))
+            }
+            ty::Char => CallArg::Str(this.read_scalar(place.into())?.to_char()?.to_string()),
+            ty::Array(..) => return this.read_call_elems(place),
+            ty::Ref(_, pointee, _) => {
+                let target = this.deref_operand(place.into())?;
+                match pointee.sty {
+                    ty::Str => {
+                        let len = target.len(this)?;
+                        let bytes = this.memory().read_bytes(target.ptr.not_undef()?, Size::from_bytes(len))?;
+                        CallArg::Str(String::from_utf8_lossy(bytes).into_owned())
+                    }
+                    ty::Slice(_) => return this.read_call_elems(target),
+                    _ => return Ok(None),
+                }
+            }
+            _ => return Ok(None),
+        }))
+    }
+
+    /// The elements of the array or slice at `place`, if their type is one we can express
+    fn read_call_elems(&self, place: MPlaceTy<'tcx, Borrow>) -> EvalResult<'tcx, Option<CallArg>> {
+        let this = self.eval_context_ref();
+        let mut elems = Vec::new();
+        for i in 0..place.len(this)? {
+            match this.read_call_result(this.mplace_field(place, i)?)? {
+                Some(elem) => elems.push(elem),
+                None => return Ok(None),
+            }
+        }
+        Ok(Some(CallArg::Array(elems)))
+    }
+
+    /// Tell the user, and the embedder, what the function `fn_id` returned to `ret`.
+    fn report_call_result(&self, fn_id: DefId, ret: MPlaceTy<'tcx, Borrow>) {
+        let this = self.eval_context_ref();
+        let value = match this.read_call_result(ret) {
+            Ok(value) => value,
+            Err(_) => None,
+        };
+        let description = match value {
+            Some(ref value) => value.to_string(),
+            None => format!("a value of type `{}`", ret.layout.ty),
+        };
+        this.tcx.sess.note_without_error(
+            &format!("`{}` returned {}", this.tcx.item_path_str(fn_id), description),
+        );
+        if let Some(ref call) = this.machine.call {
+            *call.returned.lock().unwrap() = value;
+        }
+    }
+}
+
+fn write_float<'a, 'mir, 'tcx>(
+    ecx: &mut MiriEvalContext<'a, 'mir, 'tcx>,
+    value: f64,
+    float_ty: FloatTy,
+    dest: PlaceTy<'tcx, Borrow>,
+) -> EvalResult<'tcx> {
+    let bits = match float_ty {
+        FloatTy::F32 => (value as f32).to_bits() as u128,
+        FloatTy::F64 => value.to_bits() as u128,
+    };
+    ecx.write_scalar(Scalar::from_uint(bits, dest.layout.size), dest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_values() {
+        assert_eq!(
+            CallArg::parse_list(r#" [null, true, false, -12, 3.5, 1e3, "a\"\n\u00e9\ud83d\ude00", [1, []]] "#),
+            Ok(vec![
+                CallArg::Null,
+                CallArg::Bool(true),
+                CallArg::Bool(false),
+                CallArg::Int(-12),
+                CallArg::Float(3.5),
+                CallArg::Float(1000.0),
+                CallArg::Str("a\"\n\u{e9}\u{1f600}".to_owned()),
+                CallArg::Array(vec![CallArg::Int(1), CallArg::Array(vec![])]),
+            ]),
+        );
+        assert_eq!(CallArg::parse_list("[]"), Ok(vec![]));
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert!(CallArg::parse_list("1").is_err());
+        assert!(CallArg::parse_list("[1,]").is_err());
+        assert!(CallArg::parse_list("[1] 2").is_err());
+        assert!(CallArg::parse_list("[{}]").is_err());
+        assert!(CallArg::parse_list("[\"abc]").is_err());
+        assert!(CallArg::parse_list("[\"\\ud83d\"]").is_err());
+        assert!(CallArg::parse_list("[99999999999999999999999999999999999999999]").is_err());
+    }
+
+    #[test]
+    fn display_roundtrips() {
+        let args = vec![
+            CallArg::Str("tab\tquote\" \u{1}".to_owned()),
+            CallArg::Array(vec![CallArg::Float(0.5), CallArg::Null]),
+            CallArg::Int(-7),
+        ];
+        let json = CallArg::Array(args.clone()).to_string();
+        assert_eq!(CallArg::parse_list(&json), Ok(args));
+    }
+}
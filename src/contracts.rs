@@ -0,0 +1,33 @@
+//! Violated preconditions of unsafe functions, as an error of their own.  Contract checks,
+//! be it of a contracts crate or of a library checking its callers, can tell miri about a
+//! violation by calling `miri_precondition_failed(msg, len)`, declared as
+//! `extern "Rust" { fn miri_precondition_failed(msg: *const u8, len: usize) -> !; }`, with
+//! the UTF-8 description of the precondition.  Rather than a panic deep inside the checked
+//! function, the error then names the precondition and points at the call in local code
+//! that violated it.  The checks in the standard library can use this too once it has them.
+
+use rustc::ty::{self, layout::Size};
+
+use crate::*;
+
+impl<'a, 'mir, 'tcx> EvalContextExt<'a, 'mir, 'tcx> for crate::MiriEvalContext<'a, 'mir, 'tcx> {}
+pub trait EvalContextExt<'a, 'mir, 'tcx: 'a+'mir>: crate::MiriEvalContextExt<'a, 'mir, 'tcx> {
+    /// Whether `instance` is the `miri_precondition_failed` hook
+    fn is_precondition_hook(&self, instance: ty::Instance<'tcx>) -> bool {
+        let this = self.eval_context_ref();
+        let def_id = instance.def_id();
+        this.tcx.is_foreign_item(def_id) && &*this.tcx.item_name(def_id).as_str() == "miri_precondition_failed"
+    }
+
+    /// `miri_precondition_failed(msg, len)`: stop with an error that names the precondition.
+    /// The function it is called from is the one whose precondition was violated.
+    fn precondition_failed(&mut self, args: &[OpTy<'tcx, Borrow>]) -> EvalResult<'tcx> {
+        let this = self.eval_context_mut();
+        let ptr = this.read_scalar(args[0])?.not_undef()?;
+        let len = this.read_scalar(args[1])?.to_usize(this)?;
+        let msg = String::from_utf8_lossy(this.memory().read_bytes(ptr, Size::from_bytes(len))?)
+            .into_owned();
+        this.machine.violated_precondition = Some(this.frame().instance.to_string());
+        err!(MachineError(format!("unsafe precondition violated: {}", msg)))
+    }
+}
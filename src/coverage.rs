@@ -0,0 +1,132 @@
+//! `-Zmiri-coverage=<path>`: which lines of the local crate the run executed, in the lcov
+//! format that `genhtml`, codecov and editors read.  Code that runs under miri is not
+//! instrumented, so we count how often each basic block of the local functions runs, before
+//! its terminator, and at the end map the statements of the blocks to lines.  Functions that
+//! never ran are in the report too, with all their lines at zero.
+//!
+//! Like the profile, the runs append to the file, each as a test of its own, and the driver
+//! truncates it at startup.  lcov adds up the records of the same file.
+
+use std::cmp;
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+use rustc::hir::def_id::DefId;
+use rustc::mir;
+use rustc::ty;
+use syntax::source_map::{FileName, Span};
+
+use crate::*;
+
+#[derive(Clone, Debug)]
+pub struct Coverage {
+    path: PathBuf,
+    /// How often each basic block of the local functions was executed
+    blocks: HashMap<(DefId, mir::BasicBlock), u64>,
+}
+
+impl Coverage {
+    pub fn new(path: PathBuf) -> Self {
+        Coverage { path, blocks: HashMap::new() }
+    }
+}
+
+/// The coverage of one source file
+#[derive(Default)]
+struct FileCoverage {
+    /// How often each line ran: the most any block with code on it ran
+    lines: BTreeMap<usize, u64>,
+    /// The functions, as their first line, name and how often they were called
+    functions: Vec<(usize, String, u64)>,
+}
+
+impl<'a, 'mir, 'tcx> EvalContextExt<'a, 'mir, 'tcx> for crate::MiriEvalContext<'a, 'mir, 'tcx> {}
+pub trait EvalContextExt<'a, 'mir, 'tcx: 'a+'mir>: crate::MiriEvalContextExt<'a, 'mir, 'tcx> {
+    /// Called before every terminator: the current block ran.
+    fn record_coverage(&mut self) {
+        let this = self.eval_context_mut();
+        if this.machine.coverage.is_none() {
+            return;
+        }
+        let frame = this.frame();
+        let def_id = match frame.instance.def {
+            ty::InstanceDef::Item(def_id) if def_id.is_local() => def_id,
+            // Shims have no source of their own.
+            _ => return,
+        };
+        let block = frame.block;
+        let coverage = this.machine.coverage.as_mut().unwrap();
+        *coverage.blocks.entry((def_id, block)).or_insert(0) += 1;
+    }
+
+    /// Append the coverage of the local functions to the report, as the test `program`.
+    fn write_coverage(&self, program: &str) -> io::Result<()> {
+        let this = self.eval_context_ref();
+        let coverage = match this.machine.coverage {
+            Some(ref coverage) => coverage,
+            None => return Ok(()),
+        };
+        let tcx = this.tcx.tcx;
+        let source_map = tcx.sess.source_map();
+        // The file and line of `span`, if it is in a file; code from macros counts for the
+        // line that uses the macro.
+        let line_of = |span: Span| {
+            let loc = source_map.lookup_char_pos(span.source_callsite().lo());
+            match loc.file.name {
+                FileName::Real(ref path) => Some((path.clone(), loc.line)),
+                _ => None,
+            }
+        };
+        let mut files: BTreeMap<PathBuf, FileCoverage> = BTreeMap::new();
+        for def_id in tcx.body_owners() {
+            match tcx.type_of(def_id).sty {
+                ty::FnDef(..) | ty::Closure(..) => {}
+                // Constants and statics are evaluated by the compiler.
+                _ => continue,
+            }
+            let mir = tcx.optimized_mir(def_id);
+            for (block, data) in mir.basic_blocks().iter_enumerated() {
+                let hits = coverage.blocks.get(&(def_id, block)).cloned().unwrap_or(0);
+                let spans = data.statements.iter().map(|stmt| stmt.source_info.span)
+                    .chain(Some(data.terminator().source_info.span));
+                for (path, line) in spans.filter_map(|span| line_of(span)) {
+                    let count = files.entry(path).or_default().lines.entry(line).or_insert(0);
+                    *count = cmp::max(*count, hits);
+                }
+            }
+            if let Some((path, line)) = line_of(mir.span) {
+                let calls = coverage.blocks.get(&(def_id, mir::START_BLOCK)).cloned().unwrap_or(0);
+                files.entry(path).or_default().functions.push((line, tcx.item_path_str(def_id), calls));
+            }
+        }
+
+        // Test names may only have letters, digits and underscores.
+        let test: String = program.chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect();
+        let mut report = String::new();
+        for (path, mut file) in files {
+            // Tools find the files more reliably by their absolute paths.
+            let path = fs::canonicalize(&path).unwrap_or(path);
+            report.push_str(&format!("TN:{}\nSF:{}\n", test, path.display()));
+            file.functions.sort();
+            for &(line, ref name, _) in &file.functions {
+                report.push_str(&format!("FN:{},{}\n", line, name));
+            }
+            for &(_, ref name, calls) in &file.functions {
+                report.push_str(&format!("FNDA:{},{}\n", calls, name));
+            }
+            let functions_hit = file.functions.iter().filter(|&&(_, _, calls)| calls > 0).count();
+            report.push_str(&format!("FNF:{}\nFNH:{}\n", file.functions.len(), functions_hit));
+            for (line, hits) in &file.lines {
+                report.push_str(&format!("DA:{},{}\n", line, hits));
+            }
+            let lines_hit = file.lines.values().filter(|&&hits| hits > 0).count();
+            report.push_str(&format!("LF:{}\nLH:{}\nend_of_record\n", file.lines.len(), lines_hit));
+        }
+        let mut file = OpenOptions::new().create(true).append(true).open(&coverage.path)?;
+        file.write_all(report.as_bytes())
+    }
+}
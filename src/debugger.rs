@@ -0,0 +1,469 @@
+//! Stopping the interpreted program to inspect it, for `-Zmiri-gdb`, `-Zmiri-interactive`
+//! and `miri_breakpoint()`.  While there is a debugger, we run the program one MIR statement
+//! at a time, and stop when it gets to a new line of local code with a breakpoint, or to any
+//! new line of local code when stepping.  Breakpoints are set, and the program is inspected,
+//! with the commands in `execute`.  Without gdb, the user types them at a prompt on miri's
+//! terminal, which reads miri's own standard input.
+
+use std::io::{self, Write};
+use std::net::TcpListener;
+
+use rustc::ty::layout::Size;
+use syntax::source_map::Span;
+
+use crate::*;
+use crate::gdb_stub::GdbStub;
+
+/// The commands of the prompt, besides those of `execute`
+const PROMPT_HELP: &str = "\
+continue, c               continue until the next breakpoint
+step, s                   continue until the next line of local code
+kill                      end the program";
+
+/// How often to look for Ctrl-C while the program runs, in steps
+const INTERRUPT_INTERVAL: u64 = 4096;
+
+const HELP: &str = "\
+break <file>:<line>       stop when the program gets to that line of local code
+break                     list the breakpoints
+delete [<file>:<line>]    delete one or all breakpoints
+backtrace                 the call stack, innermost call first
+locals                    the named locals of the current function
+print <local>             the value of a local of the current function
+dump <alloc> [<offset>]   the bytes of an allocation around an offset, like `dump alloc5 16`";
+
+/// Why the program stopped
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum Stop {
+    /// Before the first statement, so that the user can set breakpoints
+    Start,
+    Breakpoint,
+    Step,
+    Interrupt,
+    /// The program wrote to watched memory, see `watch.rs`
+    Watch(String),
+    /// The program has undefined behavior, or miri does not support what it does.  It cannot
+    /// go on, but the user can still look at it.
+    Error(String),
+}
+
+/// What to do after a stop
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) enum Resume {
+    Continue,
+    /// Stop at the next line of local code
+    Step,
+    Kill,
+    /// Continue without the debugger
+    Detach,
+}
+
+/// A line of source code, as file name and line number
+type Line = (String, usize);
+
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Breakpoints(Vec<Line>);
+
+impl Breakpoints {
+    /// Whether there is a breakpoint at `line`.  Breakpoints may leave out leading directories.
+    fn hits(&self, line: &Line) -> bool {
+        self.0.iter().any(|(file, number)| {
+            *number == line.1 && (line.0 == *file || line.0.ends_with(&format!("/{}", file)))
+        })
+    }
+}
+
+/// How the user controls the program
+pub(crate) enum Frontend {
+    Gdb(GdbStub),
+    /// Commands typed at a prompt on miri's terminal
+    Prompt,
+}
+
+impl Frontend {
+    /// The program stopped at step `steps`.  Let the user inspect it until they resume it.
+    fn serve<'a, 'mir, 'tcx>(
+        &mut self,
+        ecx: &MiriEvalContext<'a, 'mir, 'tcx>,
+        breakpoints: &mut Breakpoints,
+        stop: &Stop,
+        steps: u64,
+    ) -> io::Result<Resume> {
+        match self {
+            Frontend::Gdb(stub) => stub.serve(ecx, breakpoints, stop, steps),
+            Frontend::Prompt => prompt(ecx, breakpoints, stop),
+        }
+    }
+
+    /// Whether the user asked to stop the running program.  At the prompt, Ctrl-C ends miri.
+    fn interrupted(&mut self) -> bool {
+        match self {
+            Frontend::Gdb(stub) => stub.interrupted(),
+            Frontend::Prompt => false,
+        }
+    }
+
+    /// Tell the user that the program ended, normally or with an error.  At the prompt, miri
+    /// does that anyway.
+    fn finished(&mut self, failed: bool) -> io::Result<()> {
+        match self {
+            Frontend::Gdb(stub) => if failed { stub.terminated() } else { stub.exited() },
+            Frontend::Prompt => Ok(()),
+        }
+    }
+}
+
+pub(crate) struct Debugger {
+    frontend: Frontend,
+    breakpoints: Breakpoints,
+    /// Whether we stopped at the start already
+    started: bool,
+    /// Whether to stop at the next line of local code
+    stepping: bool,
+    /// The line of local code the program is at, or was at last
+    line: Option<Line>,
+    /// How many statements we executed, which is the program counter the debugger sees
+    steps: u64,
+}
+
+impl Debugger {
+    fn new(frontend: Frontend) -> Self {
+        Debugger {
+            frontend,
+            breakpoints: Breakpoints::default(),
+            started: false,
+            stepping: false,
+            line: None,
+            steps: 0,
+        }
+    }
+}
+
+/// Parse `<file>:<line>`.
+fn parse_line(location: &str) -> Option<Line> {
+    let mut parts = location.rsplitn(2, ':');
+    let number = parts.next()?.parse().ok()?;
+    let file = parts.next()?;
+    Some((file.to_owned(), number))
+}
+
+/// Read commands from the terminal until the user resumes the program.  The end of the
+/// input lets the program go on without the prompt.
+fn prompt<'a, 'mir, 'tcx>(
+    ecx: &MiriEvalContext<'a, 'mir, 'tcx>,
+    breakpoints: &mut Breakpoints,
+    stop: &Stop,
+) -> io::Result<Resume> {
+    let mut stderr = io::stderr();
+    writeln!(stderr, "{}", describe_stop(ecx, stop))?;
+    loop {
+        write!(stderr, "(miri) ")?;
+        stderr.flush()?;
+        let mut line = String::new();
+        if io::stdin().read_line(&mut line)? == 0 {
+            writeln!(stderr)?;
+            return Ok(Resume::Detach);
+        }
+        match line.trim() {
+            "" => {}
+            "continue" | "c" => return Ok(Resume::Continue),
+            "step" | "s" => return Ok(Resume::Step),
+            "kill" => return Ok(Resume::Kill),
+            "help" => writeln!(stderr, "{}\n{}", PROMPT_HELP, HELP)?,
+            command => writeln!(stderr, "{}", execute(ecx, breakpoints, command))?,
+        }
+    }
+}
+
+/// Run a command of the user, and return what to show them.
+pub(crate) fn execute<'a, 'mir, 'tcx>(
+    ecx: &MiriEvalContext<'a, 'mir, 'tcx>,
+    breakpoints: &mut Breakpoints,
+    command: &str,
+) -> String {
+    let words: Vec<&str> = command.split_whitespace().collect();
+    match *words.as_slice() {
+        ["break"] | ["delete"] if breakpoints.0.is_empty() => "there are no breakpoints".to_owned(),
+        ["break"] => breakpoints.0.iter()
+            .map(|(file, number)| format!("breakpoint at {}:{}", file, number))
+            .collect::<Vec<_>>()
+            .join("\n"),
+        ["break", location] => match parse_line(location) {
+            Some(line) => {
+                let msg = format!("breakpoint at {}:{}", line.0, line.1);
+                breakpoints.0.push(line);
+                msg
+            }
+            None => format!("expected `<file>:<line>`, got `{}`", location),
+        },
+        ["delete"] => {
+            breakpoints.0.clear();
+            "deleted all breakpoints".to_owned()
+        }
+        ["delete", location] => {
+            let count = breakpoints.0.len();
+            breakpoints.0.retain(|line| Some(line) != parse_line(location).as_ref());
+            if breakpoints.0.len() == count {
+                format!("there is no breakpoint at `{}`", location)
+            } else {
+                format!("deleted the breakpoint at {}", location)
+            }
+        }
+        ["backtrace"] | ["bt"] => ecx.debug_backtrace(),
+        ["locals"] => ecx.debug_locals(),
+        ["print", name] | ["p", name] => ecx.debug_print(name),
+        ["dump", alloc] => ecx.debug_dump(alloc, "0"),
+        ["dump", alloc, offset] => ecx.debug_dump(alloc, offset),
+        ["help"] => HELP.to_owned(),
+        _ => format!("unknown command `{}`, the commands are:\n{}", command.trim(), HELP),
+    }
+}
+
+/// Tell the user why the program stopped, and where.
+pub(crate) fn describe_stop<'a, 'mir, 'tcx>(ecx: &MiriEvalContext<'a, 'mir, 'tcx>, stop: &Stop) -> String {
+    let what = match *stop {
+        Stop::Start => "the program is about to start".to_owned(),
+        Stop::Breakpoint => "breakpoint".to_owned(),
+        Stop::Step => "stepped".to_owned(),
+        Stop::Interrupt => "interrupted".to_owned(),
+        Stop::Watch(ref msg) => msg.clone(),
+        Stop::Error(ref msg) => format!("error: {}", msg),
+    };
+    match ecx.stack().len() {
+        0 => what,
+        depth => format!("{}, {}", what, ecx.debug_frame(depth - 1)),
+    }
+}
+
+impl<'a, 'mir, 'tcx> EvalContextExt<'a, 'mir, 'tcx> for crate::MiriEvalContext<'a, 'mir, 'tcx> {}
+pub trait EvalContextExt<'a, 'mir, 'tcx: 'a+'mir>: crate::MiriEvalContextExt<'a, 'mir, 'tcx> {
+    /// `run`, under the control of the debugger, if the user asked for one, and reporting the
+    /// writes to watched memory.
+    fn run_debugged(&mut self) -> EvalResult<'tcx> {
+        let this = self.eval_context_mut();
+        if this.machine.interactive {
+            this.machine.debugger = Some(Debugger::new(Frontend::Prompt));
+        } else if let Some(port) = this.machine.gdb_port.take() {
+            let error = |err: io::Error| EvalErrorKind::MachineError(
+                format!("-Zmiri-gdb: cannot wait for a debugger on port {}: {}", port, err),
+            );
+            let listener = TcpListener::bind(("127.0.0.1", port)).map_err(error)?;
+            this.tcx.sess.note_without_error(&format!(
+                "waiting for a debugger on port {0}, connect with `target remote :{0}` in gdb", port,
+            ));
+            let stub = GdbStub::accept(&listener).map_err(error)?;
+            this.machine.debugger = Some(Debugger::new(Frontend::Gdb(stub)));
+        }
+        // `miri_breakpoint()` can bring up the debugger at any time, so we cannot just `run`.
+        loop {
+            if let Some(stop) = this.debugger_should_stop() {
+                this.debugger_wait(stop)?;
+            }
+            // The location of the statement, for the writes it does
+            let watching = this.watching();
+            let span = if watching { this.current_span() } else { None };
+            match this.observed_step() {
+                Ok(true) => if watching {
+                    this.report_watch_hits(span)?;
+                },
+                Ok(false) => {
+                    this.debugger_finished(false);
+                    return Ok(());
+                }
+                Err(err) => {
+                    // Killing the program now changes nothing.
+                    let _ = this.debugger_wait(Stop::Error(err.to_string()));
+                    this.debugger_finished(true);
+                    return Err(err);
+                }
+            }
+        }
+    }
+
+    /// Called before every statement.  Whether to stop there, and why.
+    fn debugger_should_stop(&mut self) -> Option<Stop> {
+        let this = self.eval_context_mut();
+        if this.machine.debugger.is_none() {
+            return None;
+        }
+        let line = this.current_line();
+        let debugger = this.machine.debugger.as_mut()?;
+        debugger.steps += 1;
+        if !debugger.started {
+            debugger.started = true;
+            return Some(Stop::Start);
+        }
+        if debugger.steps % INTERRUPT_INTERVAL == 0 && debugger.frontend.interrupted() {
+            return Some(Stop::Interrupt);
+        }
+        // A line takes several statements, we stop at the first.
+        let line = line?;
+        if debugger.line.as_ref() == Some(&line) {
+            return None;
+        }
+        let stop = if debugger.stepping {
+            Some(Stop::Step)
+        } else if debugger.breakpoints.hits(&line) {
+            Some(Stop::Breakpoint)
+        } else {
+            None
+        };
+        debugger.line = Some(line);
+        stop
+    }
+
+    /// Let the debugger inspect the program until it resumes it.
+    fn debugger_wait(&mut self, stop: Stop) -> EvalResult<'tcx> {
+        let this = self.eval_context_mut();
+        let mut debugger = match this.machine.debugger.take() {
+            Some(debugger) => debugger,
+            None => return Ok(()),
+        };
+        let resume = debugger.frontend.serve(this, &mut debugger.breakpoints, &stop, debugger.steps);
+        debugger.stepping = false;
+        match resume {
+            Ok(Resume::Continue) => {}
+            Ok(Resume::Step) => debugger.stepping = true,
+            Ok(Resume::Detach) => return Ok(()),
+            Ok(Resume::Kill) => return err!(MachineError("the debugger killed the program".to_owned())),
+            Err(err) => {
+                this.tcx.sess.warn(&format!("lost the connection to the debugger: {}", err));
+                return Ok(());
+            }
+        }
+        this.machine.debugger = Some(debugger);
+        Ok(())
+    }
+
+    /// Tell the debugger that the program ended, normally or with an error.
+    fn debugger_finished(&mut self, failed: bool) {
+        let this = self.eval_context_mut();
+        if let Some(mut debugger) = this.machine.debugger.take() {
+            // The debugger may be gone already, which is fine.
+            let _ = debugger.frontend.finished(failed);
+        }
+    }
+
+    /// `miri_breakpoint()`: stop, in the debugger if there is one, and at the prompt if not.
+    fn debugger_break(&mut self) -> EvalResult<'tcx> {
+        let this = self.eval_context_mut();
+        if this.machine.debugger.is_none() {
+            let mut debugger = Debugger::new(Frontend::Prompt);
+            debugger.started = true;
+            debugger.line = this.current_line();
+            this.machine.debugger = Some(debugger);
+        }
+        this.debugger_wait(Stop::Breakpoint)
+    }
+
+    /// The line the program is at, if it is in local code
+    fn current_line(&self) -> Option<Line> {
+        let this = self.eval_context_ref();
+        if !this.stack().last()?.instance.def_id().is_local() {
+            return None;
+        }
+        let span = this.current_span()?.source_callsite();
+        if span.is_dummy() {
+            return None;
+        }
+        let loc = this.tcx.sess.source_map().lookup_char_pos(span.lo());
+        Some((loc.file.name.to_string(), loc.line))
+    }
+
+    /// The function of the frame at `idx` in the stack, and where in it we are
+    fn debug_frame(&self, idx: usize) -> String {
+        let this = self.eval_context_ref();
+        let frame = &this.stack()[idx];
+        let block = &frame.mir.basic_blocks()[frame.block];
+        let span = if frame.stmt < block.statements.len() {
+            block.statements[frame.stmt].source_info.span
+        } else {
+            block.terminator().source_info.span
+        };
+        format!("in `{}` at {}", frame.instance, this.debug_span(span))
+    }
+
+    fn debug_span(&self, span: Span) -> String {
+        let this = self.eval_context_ref();
+        let loc = this.tcx.sess.source_map().lookup_char_pos(span.source_callsite().lo());
+        format!("{}:{}:{}", loc.file.name, loc.line, loc.col.0 + 1)
+    }
+
+    fn debug_backtrace(&self) -> String {
+        let this = self.eval_context_ref();
+        (0..this.stack().len()).rev()
+            .map(|idx| format!("#{} {}", this.stack().len() - 1 - idx, this.debug_frame(idx)))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// The live named locals of the current function, innermost scope last
+    fn debug_named_locals(&self) -> Vec<(String, OpTy<'tcx, Borrow>)> {
+        let this = self.eval_context_ref();
+        let idx = match this.stack().len().checked_sub(1) {
+            Some(idx) => idx,
+            None => return Vec::new(),
+        };
+        this.frame_locals(idx).into_iter()
+            .filter_map(|(_, name, op)| Some((name?.to_string(), op)))
+            .collect()
+    }
+
+    fn debug_locals(&self) -> String {
+        let this = self.eval_context_ref();
+        let locals: Vec<String> = this.debug_named_locals().into_iter()
+            .map(|(name, op)| match this.format_value(op) {
+                Some(value) => format!("{} = {}", name, value),
+                None => format!("{}: {}", name, op.layout.ty),
+            })
+            .collect();
+        if locals.is_empty() {
+            "there are no named locals here".to_owned()
+        } else {
+            locals.join("\n")
+        }
+    }
+
+    fn debug_print(&self, name: &str) -> String {
+        let this = self.eval_context_ref();
+        // Of several locals with the same name, the innermost one is the one in scope.
+        match this.debug_named_locals().into_iter().rev().find(|(local, _)| local == name) {
+            Some((_, op)) => match this.format_value(op) {
+                Some(value) => format!("{} = {}", name, value),
+                None => format!("`{}` has type `{}`, which cannot be shown", name, op.layout.ty),
+            },
+            None => format!("there is no local `{}` here", name),
+        }
+    }
+
+    /// Dump the allocation `alloc`, given as `alloc5` or `5`, around `offset`.
+    fn debug_dump(&self, alloc: &str, offset: &str) -> String {
+        let this = self.eval_context_ref();
+        let id = match alloc.trim_start_matches("alloc").parse() {
+            Ok(id) => AllocId(id),
+            Err(_) => return format!("expected an allocation like `alloc5`, got `{}`", alloc),
+        };
+        let offset = match offset.parse() {
+            Ok(offset) => Size::from_bytes(offset),
+            Err(_) => return format!("expected an offset in bytes, got `{}`", offset),
+        };
+        this.memory_dump(Pointer::new(id, offset))
+            .unwrap_or_else(|| format!("there is no memory in alloc{}", id.0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn breakpoints() {
+        let breakpoints = Breakpoints(vec![parse_line("main.rs:12").unwrap()]);
+        assert!(breakpoints.hits(&("src/main.rs".to_owned(), 12)));
+        assert!(breakpoints.hits(&("main.rs".to_owned(), 12)));
+        assert!(!breakpoints.hits(&("src/domain.rs".to_owned(), 12)));
+        assert!(!breakpoints.hits(&("src/main.rs".to_owned(), 13)));
+        assert_eq!(parse_line("C:/src/main.rs:3"), Some(("C:/src/main.rs".to_owned(), 3)));
+        assert_eq!(parse_line("main.rs"), None);
+    }
+}
@@ -0,0 +1,78 @@
+//! Emulation of libdispatch (Grand Central Dispatch) objects on macOS.
+//! Every object is backed by a small allocation, so that the program gets a unique pointer
+//! for it and we can detect leaks and use-after-free.  The object state lives here, keyed
+//! by the allocation.
+
+use std::collections::HashMap;
+
+use rustc::mir::interpret::AllocId;
+
+use crate::{EvalResult, EvalErrorKind};
+
+#[derive(Debug)]
+pub enum DispatchObject {
+    /// A counting semaphore, see `dispatch_semaphore_create`.
+    Semaphore {
+        value: i64,
+        /// The value the semaphore was created with.  libdispatch refuses to destroy
+        /// a semaphore whose value is below this.
+        initial: i64,
+    },
+}
+
+#[derive(Debug)]
+struct Entry {
+    object: DispatchObject,
+    ref_count: u64,
+}
+
+#[derive(Debug, Default)]
+pub struct DispatchObjects {
+    objects: HashMap<AllocId, Entry>,
+}
+
+impl DispatchObjects {
+    pub fn insert(&mut self, id: AllocId, object: DispatchObject) {
+        trace!("New dispatch object {:?}: {:?}", id, object);
+        self.objects.insert(id, Entry { object, ref_count: 1 });
+    }
+
+    pub fn get_mut<'tcx>(&mut self, id: AllocId) -> EvalResult<'tcx, &mut DispatchObject> {
+        match self.objects.get_mut(&id) {
+            Some(entry) => Ok(&mut entry.object),
+            None => err!(MachineError(format!("{:?} is not a dispatch object", id))),
+        }
+    }
+
+    pub fn retain<'tcx>(&mut self, id: AllocId) -> EvalResult<'tcx> {
+        match self.objects.get_mut(&id) {
+            Some(entry) => {
+                entry.ref_count += 1;
+                Ok(())
+            }
+            None => err!(MachineError(format!("{:?} is not a dispatch object", id))),
+        }
+    }
+
+    /// Drop a reference to the object.  Returns whether this was the last one, in which case
+    /// the caller has to free the allocation.
+    pub fn release<'tcx>(&mut self, id: AllocId) -> EvalResult<'tcx, bool> {
+        let entry = self.objects.get_mut(&id).ok_or_else(|| EvalErrorKind::MachineError(
+            format!("{:?} is not a dispatch object", id),
+        ))?;
+        entry.ref_count -= 1;
+        if entry.ref_count > 0 {
+            return Ok(false);
+        }
+        let entry = self.objects.remove(&id).unwrap();
+        trace!("Dispatch object {:?} destroyed: {:?}", id, entry.object);
+        match entry.object {
+            DispatchObject::Semaphore { value, initial } if value < initial =>
+                err!(MachineError(format!(
+                    "semaphore destroyed while in use: its value {} is below its initial value {}",
+                    value, initial,
+                ))),
+            _ => Ok(true),
+        }
+    }
+}
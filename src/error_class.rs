@@ -0,0 +1,141 @@
+//! The classes of findings, how seriously to take each of them, and the exit code of a run
+//! that fails because of them.  CI pipelines can thus gate differently on, say, an
+//! unsupported shim than on undefined behavior.
+
+use crate::*;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ErrorClass {
+    /// Including Stacked Borrows violations
+    UndefinedBehavior,
+    /// The validity invariant was violated
+    InvalidValue,
+    /// Panics, failed assertions like overflow and bounds checks, deadlocks, misuse of the
+    /// emulated APIs, and runs that took too long
+    ProgramError,
+    /// Miri cannot run this code, or failed itself
+    Unsupported,
+    MemoryLeak,
+}
+
+const ERROR_CLASSES: [ErrorClass; 5] = [
+    ErrorClass::UndefinedBehavior,
+    ErrorClass::InvalidValue,
+    ErrorClass::ProgramError,
+    ErrorClass::Unsupported,
+    ErrorClass::MemoryLeak,
+];
+
+impl ErrorClass {
+    /// The class of an error of kind `kind`.  `stacked_borrows` says whether it is a Stacked
+    /// Borrows violation, which is a `MachineError` like those of the shims.
+    pub fn of(kind: &EvalErrorKind<'_, u64>, stacked_borrows: bool) -> Self {
+        use rustc::mir::interpret::EvalErrorKind::*;
+        match *kind {
+            MachineError(_) if stacked_borrows => ErrorClass::UndefinedBehavior,
+            // Panics, and the `Assert` terminators that panic at run time
+            MachineError(_) | Panic { .. } | BoundsCheck { .. } | Overflow(_) | OverflowNeg |
+            DivisionByZero | RemainderByZero |
+            GeneratorResumedAfterReturn | GeneratorResumedAfterPanic |
+            StackFrameLimitReached => ErrorClass::ProgramError,
+            ValidationFailure(_) => ErrorClass::InvalidValue,
+            // What miri does not support, and its own failures
+            Unimplemented(_) | NoMirFor(_) | InlineAsm | PathNotFound(_) | TooGeneric |
+            TypeckError | ReferencedConstant | UnimplementedTraitSelection | Layout(_) |
+            TypeNotPrimitive(_) | OutOfTls | InfiniteLoop => ErrorClass::Unsupported,
+            _ => ErrorClass::UndefinedBehavior,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ErrorClass::UndefinedBehavior => "undefined_behavior",
+            ErrorClass::InvalidValue => "invalid_value",
+            ErrorClass::ProgramError => "program_error",
+            ErrorClass::Unsupported => "unsupported",
+            ErrorClass::MemoryLeak => "memory_leak",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        ERROR_CLASSES.iter().cloned().find(|class| class.name() == name)
+    }
+
+    /// A stable name for the class, so that tools reading `--error-format=json` output do
+    /// not have to match on the message.
+    pub fn code(self) -> String {
+        format!("miri::{}", self.name())
+    }
+
+    /// The exit code of a run that failed because of a finding of this class.  If several
+    /// findings failed it, the most serious class, i.e., the lowest code, wins.
+    pub fn exit_code(self) -> i32 {
+        match self {
+            ErrorClass::UndefinedBehavior => 2,
+            ErrorClass::InvalidValue => 3,
+            ErrorClass::ProgramError => 4,
+            ErrorClass::Unsupported => 5,
+            ErrorClass::MemoryLeak => 6,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Severity {
+    /// Report the finding as an error, failing the run
+    Error,
+    /// Report the finding as a warning
+    Warn,
+    /// Do not report the finding
+    Allow,
+}
+
+/// The severity of each class
+#[derive(Clone, Debug)]
+pub struct Severities([Severity; 5]);
+
+impl Default for Severities {
+    fn default() -> Self {
+        Severities([Severity::Error; 5])
+    }
+}
+
+impl Severities {
+    pub fn get(&self, class: ErrorClass) -> Severity {
+        self.0[class as usize]
+    }
+
+    /// Set the severity of a class from `<class>=<error|warn|allow>`.
+    pub fn set(&mut self, spec: &str) -> Result<(), String> {
+        let mut parts = spec.splitn(2, '=');
+        let class = parts.next().unwrap();
+        let class = ErrorClass::from_name(class)
+            .ok_or_else(|| format!("unknown class `{}`", class))?;
+        self.0[class as usize] = match parts.next() {
+            Some("error") => Severity::Error,
+            Some("warn") => Severity::Warn,
+            Some("allow") => Severity::Allow,
+            _ => return Err(format!("expected `<class>=<error|warn|allow>`, got `{}`", spec)),
+        };
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn set_severities() {
+        let mut severities = Severities::default();
+        severities.set("unsupported=warn").unwrap();
+        severities.set("memory_leak=allow").unwrap();
+        assert_eq!(severities.get(ErrorClass::Unsupported), Severity::Warn);
+        assert_eq!(severities.get(ErrorClass::MemoryLeak), Severity::Allow);
+        assert_eq!(severities.get(ErrorClass::UndefinedBehavior), Severity::Error);
+
+        assert!(severities.set("leak=allow").is_err());
+        assert!(severities.set("unsupported").is_err());
+        assert!(severities.set("unsupported=ignore").is_err());
+    }
+}
@@ -0,0 +1,191 @@
+//! `-Zmiri-event-stream=<addr>`: send what the program does with memory, and what miri
+//! reports, to a live visualizer while the program runs.  The stream is a `MemoryObserver`,
+//! so it sees every allocation, deallocation, read, write and retag.
+//!
+//! Every event is a frame: its length as a little-endian `u32`, not counting the length
+//! itself, and then the kind of event as a `u8`, followed by the fields of that kind.
+//! Integers are little-endian, strings are a `u32` length and UTF-8.
+//!
+//! * `0` start of a run: the program (string)
+//! * `1` allocate, `2` deallocate, `3` read, `4` write, `5` retag: the allocation (`u64`),
+//!   the offset and size of the range (`u64` each), the kind of tag (`u8`: `0` untagged
+//!   shared, `1` shared, `2` unique) and its timestamp (`u64`)
+//! * `6` diagnostic: whether it is an error (`u8`), the message and the location (strings)
+//! * `7` end of a run: the program finished, the diagnostics about it follow
+//!
+//! Miri runs a single thread, so there are no thread switches.
+
+use std::fmt;
+use std::fs::OpenOptions;
+use std::io::{self, BufWriter, Write};
+use std::net::TcpStream;
+use std::sync::{Arc, Mutex};
+
+use rustc::ty::TyCtxt;
+use syntax::errors::{Diagnostic, Level};
+
+use crate::*;
+
+const START: u8 = 0;
+const DIAGNOSTIC: u8 = 6;
+const END: u8 = 7;
+
+struct Connection {
+    out: BufWriter<Box<dyn Write + Send>>,
+    /// Whether the other side went away, after which we stop sending
+    closed: bool,
+}
+
+/// The stream of events.  Clones share the connection, so that all runs of a session, like
+/// the tests of a crate, go to the same place.
+#[derive(Clone)]
+pub struct EventStream(Arc<Mutex<Connection>>);
+
+impl EventStream {
+    /// Connect to `addr`, which is `<host>:<port>` for TCP, or else the path of a Unix
+    /// socket, a named pipe or a file.
+    pub fn connect(addr: &str) -> io::Result<Self> {
+        let is_path = addr.contains('/') || addr.contains('\\') || !addr.contains(':');
+        let out: Box<dyn Write + Send> = match connect_unix(addr)? {
+            Some(socket) => socket,
+            None if is_path =>
+                Box::new(OpenOptions::new().write(true).create(true).truncate(true).open(addr)?),
+            None => {
+                let stream = TcpStream::connect(addr)?;
+                stream.set_nodelay(true)?;
+                Box::new(stream)
+            }
+        };
+        Ok(EventStream(Arc::new(Mutex::new(Connection { out: BufWriter::new(out), closed: false }))))
+    }
+
+    fn send(&self, frame: &[u8], flush: bool) {
+        let mut connection = self.0.lock().unwrap();
+        if connection.closed {
+            return;
+        }
+        let mut res = connection.out.write_all(frame);
+        if flush && res.is_ok() {
+            res = connection.out.flush();
+        }
+        if res.is_err() {
+            connection.closed = true;
+        }
+    }
+
+    pub(crate) fn start(&self, program: &str) {
+        let mut payload = Vec::new();
+        put_str(&mut payload, program);
+        self.send(&frame(START, &payload), true);
+    }
+
+    pub(crate) fn diagnostic(&self, tcx: TyCtxt, diag: &Diagnostic) {
+        let mut payload = vec![(diag.level != Level::Warning) as u8];
+        put_str(&mut payload, &diag.message());
+        let location = diag.span.primary_span()
+            .map_or(String::new(), |span| tcx.sess.source_map().span_to_string(span));
+        put_str(&mut payload, &location);
+        self.send(&frame(DIAGNOSTIC, &payload), true);
+    }
+
+    pub(crate) fn end(&self) {
+        self.send(&frame(END, &[]), true);
+    }
+}
+
+impl MemoryObserver for EventStream {
+    fn observe(&self, event: &MemoryEvent) {
+        self.send(&encode_memory_event(event), false);
+    }
+}
+
+impl fmt::Debug for EventStream {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "EventStream")
+    }
+}
+
+/// A connection to `addr`, if it is a Unix socket
+#[cfg(unix)]
+fn connect_unix(addr: &str) -> io::Result<Option<Box<dyn Write + Send>>> {
+    use std::os::unix::fs::FileTypeExt;
+    use std::os::unix::net::UnixStream;
+    let is_socket = ::std::fs::metadata(addr).map_or(false, |metadata| metadata.file_type().is_socket());
+    if !is_socket {
+        return Ok(None);
+    }
+    Ok(Some(Box::new(UnixStream::connect(addr)?)))
+}
+
+#[cfg(not(unix))]
+fn connect_unix(_addr: &str) -> io::Result<Option<Box<dyn Write + Send>>> {
+    Ok(None)
+}
+
+fn encode_memory_event(event: &MemoryEvent) -> Vec<u8> {
+    let kind = match event.kind {
+        MemoryEventKind::Allocate => 1,
+        MemoryEventKind::Deallocate => 2,
+        MemoryEventKind::Read => 3,
+        MemoryEventKind::Write => 4,
+        MemoryEventKind::Retag => 5,
+    };
+    let (tag, timestamp) = match event.tag {
+        Borrow::Shr(None) => (0, 0),
+        Borrow::Shr(Some(timestamp)) => (1, timestamp),
+        Borrow::Uniq(timestamp) => (2, timestamp),
+    };
+    let mut payload = Vec::with_capacity(33);
+    put_u64(&mut payload, event.alloc_id.0);
+    put_u64(&mut payload, event.offset.bytes());
+    put_u64(&mut payload, event.size.bytes());
+    payload.push(tag);
+    put_u64(&mut payload, timestamp);
+    frame(kind, &payload)
+}
+
+fn frame(kind: u8, payload: &[u8]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(payload.len() + 5);
+    put_u32(&mut frame, payload.len() as u32 + 1);
+    frame.push(kind);
+    frame.extend_from_slice(payload);
+    frame
+}
+
+fn put_u32(buf: &mut Vec<u8>, value: u32) {
+    buf.extend((0..4).map(|i| (value >> (i * 8)) as u8));
+}
+
+fn put_u64(buf: &mut Vec<u8>, value: u64) {
+    buf.extend((0..8).map(|i| (value >> (i * 8)) as u8));
+}
+
+fn put_str(buf: &mut Vec<u8>, s: &str) {
+    put_u32(buf, s.len() as u32);
+    buf.extend_from_slice(s.as_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustc::ty::layout::Size;
+
+    #[test]
+    fn frames() {
+        let mut payload = Vec::new();
+        put_str(&mut payload, "main");
+        assert_eq!(frame(START, &payload), b"\x09\0\0\0\0\x04\0\0\0main");
+        let event = MemoryEvent {
+            kind: MemoryEventKind::Write,
+            alloc_id: AllocId(5),
+            offset: Size::from_bytes(8),
+            size: Size::from_bytes(4),
+            tag: Borrow::Uniq(0x102),
+            span: None,
+        };
+        let encoded = encode_memory_event(&event);
+        assert_eq!(encoded.len(), 4 + 1 + 8 + 8 + 8 + 1 + 8);
+        assert_eq!(&encoded[..6], b"\x22\0\0\0\x04\x05");
+        assert_eq!(&encoded[29..], b"\x02\x02\x01\0\0\0\0\0\0");
+    }
+}
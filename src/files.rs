@@ -0,0 +1,252 @@
+//! Access to the files of the host.  The interpreted program is isolated from the host, so
+//! that its runs are reproducible: it can only open the files that
+//! `-Zmiri-isolation-allow-path` (for reading) and `-Zmiri-isolation-allow-path-rw` (for
+//! reading and writing) allow.  Opening any other file is an error, or fails in the program,
+//! see `-Zmiri-isolation-error`.
+//! We support the Unix functions `std::fs::File` uses to open, read, write and close a file.
+
+use std::cmp;
+use std::collections::HashMap;
+use std::env;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write};
+use std::path::{Component, Path, PathBuf};
+
+use rustc::ty::layout::{LayoutOf, Size};
+
+use crate::*;
+
+#[derive(Clone, Debug)]
+struct PathRule {
+    /// The absolute path, in which `*` matches anything
+    pattern: String,
+    write: bool,
+}
+
+/// The host paths the program may access
+#[derive(Clone, Debug, Default)]
+pub struct AllowedPaths {
+    rules: Vec<PathRule>,
+}
+
+impl AllowedPaths {
+    /// Allow access to the paths matching `pattern`, for writing if `write` is set.
+    /// `*` matches any sequence of characters, including `/`, so `fixtures/*` allows the
+    /// whole directory tree.  Relative patterns are relative to the current directory.
+    pub fn allow(&mut self, pattern: &str, write: bool) -> Result<(), String> {
+        let pattern = absolute(Path::new(pattern))
+            .ok_or_else(|| format!("`{}` is not a valid path", pattern))?;
+        self.rules.push(PathRule { pattern: pattern.to_string_lossy().into_owned(), write });
+        Ok(())
+    }
+
+    fn allows(&self, path: &Path, write: bool) -> bool {
+        let path = match absolute(path) {
+            Some(path) => path,
+            None => return false,
+        };
+        let path = path.to_string_lossy();
+        self.rules.iter().any(|rule| (rule.write || !write) && matches_pattern(&rule.pattern, &path))
+    }
+}
+
+/// `path` relative to the current directory, without `.` and `..` components, so that
+/// `fixtures/*` does not allow `fixtures/../secret`.  Symbolic links are not resolved.
+fn absolute(path: &Path) -> Option<PathBuf> {
+    let mut absolute = env::current_dir().ok()?;
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => if !absolute.pop() {
+                return None;
+            },
+            component => absolute.push(component.as_os_str()),
+        }
+    }
+    Some(absolute)
+}
+
+#[cfg(unix)]
+fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+    PathBuf::from(OsStr::from_bytes(bytes))
+}
+
+#[cfg(not(unix))]
+fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    PathBuf::from(String::from_utf8_lossy(bytes).into_owned())
+}
+
+/// The open files of the program, by file descriptor
+#[derive(Debug)]
+pub struct FileTable {
+    next_fd: i32,
+    /// `None` for the files of a replayed run, which are not open on the host
+    files: HashMap<i32, Option<File>>,
+    allowed: AllowedPaths,
+    /// Where `errno` is, once the program asked for it
+    errno: Option<Pointer<Borrow>>,
+}
+
+impl FileTable {
+    pub fn new(allowed: AllowedPaths) -> Self {
+        FileTable {
+            // Do not overlap with the standard streams.
+            next_fd: 3,
+            files: HashMap::new(),
+            allowed,
+            errno: None,
+        }
+    }
+
+    /// A new file descriptor, for a file or anything else that has one, like a `kqueue`.
+    pub fn new_fd(&mut self) -> i32 {
+        let fd = self.next_fd;
+        self.next_fd += 1;
+        fd
+    }
+
+    /// Open a file of a replayed run, with the fd it had when the run was recorded.
+    fn open_replayed(&mut self, fd: i32) {
+        self.files.insert(fd, None);
+        self.next_fd = cmp::max(self.next_fd, fd + 1);
+    }
+
+    fn host_file(&mut self, fd: i32) -> &mut File {
+        self.files.get_mut(&fd).and_then(Option::as_mut)
+            .expect("only the files of replayed runs are not open on the host")
+    }
+
+    pub fn is_open(&self, fd: i32) -> bool {
+        self.files.contains_key(&fd)
+    }
+
+    /// Close the file with the given fd.  Returns `false` if there is no such file.
+    pub fn close(&mut self, fd: i32) -> bool {
+        self.files.remove(&fd).is_some()
+    }
+}
+
+impl<'a, 'mir, 'tcx> EvalContextExt<'a, 'mir, 'tcx> for crate::MiriEvalContext<'a, 'mir, 'tcx> {}
+pub trait EvalContextExt<'a, 'mir, 'tcx: 'a+'mir>: crate::MiriEvalContextExt<'a, 'mir, 'tcx> {
+    /// `open`: Open the file at the C string `path`.  Returns the fd, or -1 and sets `errno`.
+    fn open(&mut self, path: Scalar<Borrow>, flags: i32) -> EvalResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+        let tcx = &{this.tcx.tcx};
+        let path = path.to_ptr()?;
+        let path_bytes = this.memory().get(path.alloc_id)?.read_c_str(tcx, path)?.to_owned();
+        let path = path_from_bytes(&path_bytes);
+
+        let access = flags & this.eval_libc_i32("O_ACCMODE")?;
+        let read = access != this.eval_libc_i32("O_WRONLY")?;
+        let write = access != this.eval_libc_i32("O_RDONLY")?;
+        let has = |name: &str| this.eval_libc_i32(name).map(|flag| flags & flag != 0);
+        let create = has("O_CREAT")?;
+        let exclusive = has("O_EXCL")?;
+        let truncate = has("O_TRUNC")?;
+        let append = has("O_APPEND")?;
+
+        // A replayed run does not access the host, so it needs no access.
+        let allowed = this.machine.replay.replaying() ||
+            this.machine.files.allowed.allows(&path, write || create || truncate);
+        if !allowed {
+            let (access, flag) = if write || create || truncate {
+                ("writing", "-Zmiri-isolation-allow-path-rw")
+            } else {
+                ("reading", "-Zmiri-isolation-allow-path")
+            };
+            let op = format!("{} `{}` outside of `{}`", access, path.display(), flag);
+            this.reject_in_isolation(IsolationCategory::Filesystem, op)?;
+            this.set_errno("EACCES")?;
+            return Ok(-1);
+        }
+
+        let mut options = OpenOptions::new();
+        options.read(read).write(write).append(append).truncate(truncate);
+        if exclusive {
+            options.create_new(create);
+        } else {
+            options.create(create);
+        }
+        let result = this.replayed_open(&path_bytes, |this| {
+            let file = options.open(&path)?;
+            let fd = this.machine.files.new_fd();
+            this.machine.files.files.insert(fd, Some(file));
+            Ok(fd)
+        })?;
+        match result {
+            Ok(fd) => {
+                if !this.machine.files.is_open(fd) {
+                    this.machine.files.open_replayed(fd);
+                }
+                trace!("Opened {} with fd {}", path.display(), fd);
+                Ok(fd)
+            }
+            Err(errno) => {
+                this.set_errno(&errno)?;
+                Ok(-1)
+            }
+        }
+    }
+
+    /// `read` from an open file.  Returns how many bytes were read, or -1 and sets `errno`.
+    fn read_file(&mut self, fd: i32, buf: Scalar<Borrow>, len: u64) -> EvalResult<'tcx, i64> {
+        let this = self.eval_context_mut();
+        let result = this.replayed_read(fd, |this| {
+            // Do not allocate huge buffers just because the program asked for much.
+            let mut bytes = vec![0; cmp::min(len, 1 << 16) as usize];
+            let read = this.machine.files.host_file(fd).read(&mut bytes)?;
+            bytes.truncate(read);
+            Ok(bytes)
+        })?;
+        match result {
+            Ok(bytes) => {
+                this.memory_mut().write_bytes(buf, &bytes)?;
+                Ok(bytes.len() as i64)
+            }
+            Err(errno) => {
+                this.set_errno(&errno)?;
+                Ok(-1)
+            }
+        }
+    }
+
+    /// `write` to an open file.  Returns how many bytes were written, or -1 and sets `errno`.
+    fn write_file(&mut self, fd: i32, buf: Scalar<Borrow>, len: u64) -> EvalResult<'tcx, i64> {
+        let this = self.eval_context_mut();
+        let bytes = this.memory().read_bytes(buf, Size::from_bytes(len))?.to_vec();
+        let result = this.replayed_write(fd, |this| {
+            Ok(this.machine.files.host_file(fd).write(&bytes)? as u64)
+        })?;
+        match result {
+            Ok(written) => Ok(written as i64),
+            Err(errno) => {
+                this.set_errno(&errno)?;
+                Ok(-1)
+            }
+        }
+    }
+
+    /// The place of `errno`, as returned by `__errno_location`.
+    fn errno_place(&mut self) -> EvalResult<'tcx, MPlaceTy<'tcx, Borrow>> {
+        let this = self.eval_context_mut();
+        let layout = this.layout_of(this.tcx.types.i32)?;
+        if let Some(ptr) = this.machine.files.errno {
+            return Ok(MPlaceTy::from_aligned_ptr(ptr, layout));
+        }
+        // In static memory so that it does not count as leak
+        let place = this.allocate(layout, MiriMemoryKind::MutStatic.into())?;
+        this.write_scalar(Scalar::from_int(0, layout.size), place.into())?;
+        this.machine.files.errno = Some(place.ptr.to_ptr()?);
+        Ok(place)
+    }
+
+    /// Set `errno` to the `libc` constant `name`.
+    fn set_errno(&mut self, name: &str) -> EvalResult<'tcx> {
+        let this = self.eval_context_mut();
+        let errno = this.eval_libc_i32(name)?;
+        let place = this.errno_place()?;
+        this.write_scalar(Scalar::from_int(errno, place.layout.size), place.into())
+    }
+}
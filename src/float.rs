@@ -0,0 +1,134 @@
+//! Nondeterminism in float results that Rust leaves unspecified.
+//! The sign and payload of a NaN produced by an operation are not guaranteed, so with
+//! `-Zmiri-random-nans` we pick them at random for the operations that miri implements itself.
+//! Plain arithmetic (`+`, `-`, ...) is evaluated by the rustc interpreter core, which does
+//! not give us a chance to intervene, so those NaNs are unaffected.
+//! Similarly, the transcendental functions (`sin`, `exp`, `powf`, ...) are not required to be
+//! correctly rounded, and their results differ between platforms.  With
+//! `-Zmiri-float-imprecision` we move them by a few ULPs.
+//! `-Zmiri-deterministic-floats` turns all of this off, no matter what other flags say, so
+//! that the results are reproducible.
+
+use crate::*;
+
+/// A small xorshift generator.  Its only job is to make the "random" choices reproducible
+/// for a given seed.
+#[derive(Clone, Debug)]
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        // The state must never be zero.
+        Rng(seed ^ 0x9E37_79B9_7F4A_7C15)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        let mut x = self.0;
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.0 = x;
+        x.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct FloatState {
+    /// The generator for NaN payloads, if they are randomized
+    nan_rng: Option<Rng>,
+    /// The generator for the error of transcendental functions, if they are imprecise
+    imprecision_rng: Option<Rng>,
+}
+
+impl FloatState {
+    pub fn new(config: &MiriConfig) -> Self {
+        let nondeterministic = !config.deterministic_floats;
+        FloatState {
+            nan_rng: if nondeterministic && config.random_nans {
+                Some(Rng::new(config.seed))
+            } else {
+                None
+            },
+            // Use a different stream, so that enabling one mode does not change the other.
+            imprecision_rng: if nondeterministic && config.float_imprecision {
+                Some(Rng::new(!config.seed))
+            } else {
+                None
+            },
+        }
+    }
+}
+
+impl<'a, 'mir, 'tcx> EvalContextExt<'a, 'mir, 'tcx> for crate::MiriEvalContext<'a, 'mir, 'tcx> {}
+pub trait EvalContextExt<'a, 'mir, 'tcx: 'a+'mir>: crate::MiriEvalContextExt<'a, 'mir, 'tcx> {
+    /// Adjust the result of an `f32` operation.  A NaN gets a random sign and payload; it
+    /// stays a quiet NaN, like the ones the hardware produces.
+    fn float_result_f32(&mut self, f: f32) -> f32 {
+        let this = self.eval_context_mut();
+        match this.machine.floats.nan_rng {
+            Some(ref mut rng) if f.is_nan() => {
+                let random = rng.next_u64() as u32;
+                f32::from_bits(0x7FC0_0000 | (random & 0x803F_FFFF))
+            }
+            _ => f,
+        }
+    }
+
+    /// Adjust the result of an `f64` operation, like `float_result_f32`.
+    fn float_result_f64(&mut self, f: f64) -> f64 {
+        let this = self.eval_context_mut();
+        match this.machine.floats.nan_rng {
+            Some(ref mut rng) if f.is_nan() => {
+                let random = rng.next_u64();
+                f64::from_bits(0x7FF8_0000_0000_0000 | (random & 0x8007_FFFF_FFFF_FFFF))
+            }
+            _ => f,
+        }
+    }
+
+    /// Move the result of an `f32` transcendental function by a few ULPs.  Integral results
+    /// are left alone: they are exact on every platform we know of (`exp(0) == 1`,
+    /// `powf(2, 10) == 1024`, ...), and code relying on that is not fragile.
+    fn imprecise_f32(&mut self, f: f32) -> f32 {
+        let this = self.eval_context_mut();
+        match this.machine.floats.imprecision_rng {
+            Some(ref mut rng) if f.is_finite() && f.fract() != 0.0 => {
+                let bits = f.to_bits();
+                let ulps = ulp_error(rng) as i32;
+                // Never cross zero, so that the sign stays the same.
+                if ((bits & 0x7FFF_FFFF) as i32) <= -ulps {
+                    return f;
+                }
+                let res = f32::from_bits((bits as i32 + ulps) as u32);
+                if res.is_finite() { res } else { f }
+            }
+            _ => f,
+        }
+    }
+
+    /// Move the result of an `f64` transcendental function by a few ULPs, like
+    /// `imprecise_f32`.
+    fn imprecise_f64(&mut self, f: f64) -> f64 {
+        let this = self.eval_context_mut();
+        match this.machine.floats.imprecision_rng {
+            Some(ref mut rng) if f.is_finite() && f.fract() != 0.0 => {
+                let bits = f.to_bits();
+                let ulps = ulp_error(rng);
+                if ((bits & 0x7FFF_FFFF_FFFF_FFFF) as i64) <= -ulps {
+                    return f;
+                }
+                let res = f64::from_bits((bits as i64 + ulps) as u64);
+                if res.is_finite() { res } else { f }
+            }
+            _ => f,
+        }
+    }
+}
+
+/// An error of 1 to 4 ULPs, in either direction.  Adding it to the bits of a float moves
+/// the magnitude, so for negative numbers the direction flips, which does not matter.
+fn ulp_error(rng: &mut Rng) -> i64 {
+    let random = rng.next_u64();
+    let ulps = (random % 4 + 1) as i64;
+    if random >> 63 == 0 { ulps } else { -ulps }
+}
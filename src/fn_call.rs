@@ -1,10 +1,20 @@
+use std::time::{Duration, Instant};
+
 use rustc::ty;
-use rustc::ty::layout::{Align, LayoutOf, Size};
+use rustc::ty::layout::{Align, LayoutOf, Size, VariantIdx};
 use rustc::hir::def_id::DefId;
 use rustc::mir;
+use rustc_data_structures::indexed_vec::Idx;
 use syntax::attr;
 
 use crate::*;
+use crate::kqueue;
+use crate::self_profile::{SelfProfile, Phase};
+use crate::dispatch::DispatchObject;
+use crate::handles::{
+    HandleObject, CURRENT_PROCESS_HANDLE, CURRENT_THREAD_HANDLE,
+    STD_INPUT_HANDLE, STD_OUTPUT_HANDLE, STD_ERROR_HANDLE,
+};
 
 impl<'a, 'mir, 'tcx> EvalContextExt<'a, 'mir, 'tcx> for crate::MiriEvalContext<'a, 'mir, 'tcx> {}
 pub trait EvalContextExt<'a, 'mir, 'tcx: 'a+'mir>: crate::MiriEvalContextExt<'a, 'mir, 'tcx> {
@@ -35,22 +45,132 @@ pub trait EvalContextExt<'a, 'mir, 'tcx: 'a+'mir>: crate::MiriEvalContextExt<'a,
             return Ok(None);
         }
 
+        // `core`'s portable `memchr` and `memrchr`, which `std` uses on some targets, never
+        // get past their byte-by-byte loops in miri, because `align_offset` always fails.
+        if let Some(reverse) = this.core_memchr(instance.def_id()) {
+            let needle = this.read_scalar(args[0])?.to_u8()?;
+            let text = this.deref_operand(args[1])?;
+            let len = text.len(this)?;
+            let ptr = text.ptr.not_undef()?;
+            let idx = if reverse {
+                this.memory().read_bytes(ptr, Size::from_bytes(len))?
+                    .iter().rposition(|&c| c == needle).map(|idx| idx as u64)
+            } else {
+                this.find_byte(ptr, needle, len)?
+            };
+            this.write_option_usize(idx, dest.unwrap())?;
+            this.goto_block(ret)?;
+            return Ok(None);
+        }
+
+        // A contract check failed.  The hook never returns, so there is no `dest`, which the
+        // other foreign items need.
+        if this.is_precondition_hook(instance) {
+            return this.precondition_failed(args).map(|()| None);
+        }
+
         // Try to see if we can do something about foreign items
         if this.tcx.is_foreign_item(instance.def_id()) {
             // An external function that we cannot find MIR for, but we can still run enough
             // of them to make miri viable.
-            this.emulate_foreign_item(
+            let timed = this.machine.stats.shims.is_some() || this.machine.self_profile.is_some();
+            let start = if timed { Some(Instant::now()) } else { None };
+            let res = this.emulate_foreign_item(
                 instance.def_id(),
                 args,
                 dest.unwrap(),
                 ret.unwrap(),
-            )?;
+            );
+            SelfProfile::record(&mut this.machine.self_profile, Phase::Shim, start);
+            if let Some(start) = start {
+                this.record_shim_call(instance.def_id(), start.elapsed());
+            }
+            res?;
             // `goto_block` already handled
             return Ok(None);
         }
 
         // Otherwise, load the MIR
-        Ok(Some(this.load_mir(instance.def)?))
+        let mir = this.load_mir(instance.def)?;
+        Ok(Some(this.strip_trivial_asm(instance.def, mir)))
+    }
+
+    /// Whether `def_id` is `core::slice::memchr::memchr` (`Some(false)`) or
+    /// `core::slice::memchr::memrchr` (`Some(true)`).
+    fn core_memchr(&mut self, def_id: DefId) -> Option<bool> {
+        let this = self.eval_context_mut();
+        let cached = *this.machine.cache.core_memchr.borrow();
+        let fns = match cached {
+            Some(fns) => fns,
+            None => {
+                let memchr = this.resolve_path(&["core", "slice", "memchr", "memchr"]).ok();
+                let memrchr = this.resolve_path(&["core", "slice", "memchr", "memrchr"]).ok();
+                let fns = (memchr.map(|i| i.def_id()), memrchr.map(|i| i.def_id()));
+                *this.machine.cache.core_memchr.borrow_mut() = Some(fns);
+                fns
+            }
+        };
+        match fns {
+            (Some(memchr), _) if memchr == def_id => Some(false),
+            (_, Some(memrchr)) if memrchr == def_id => Some(true),
+            _ => None,
+        }
+    }
+
+    /// Write `idx` as an `Option<usize>`.
+    fn write_option_usize(&mut self, idx: Option<u64>, dest: PlaceTy<'tcx, Borrow>) -> EvalResult<'tcx> {
+        let this = self.eval_context_mut();
+        match idx {
+            Some(idx) => {
+                let some = VariantIdx::new(1);
+                this.write_discriminant_index(some, dest)?;
+                let payload = this.place_downcast(dest, some)?;
+                let payload = this.place_field(payload, 0)?;
+                this.write_scalar(Scalar::from_uint(idx, payload.layout.size), payload)
+            }
+            None => this.write_discriminant_index(VariantIdx::new(0), dest),
+        }
+    }
+
+    /// Remove trivial inline assembly from a function body: empty `asm!` and `nop`, as used
+    /// for compiler barriers and to hide values from the optimizer (like `test::black_box`).
+    /// These have no effect on the abstract machine.  Any other assembly stays, and
+    /// evaluation stops with an error when it is reached.
+    fn strip_trivial_asm(
+        &mut self,
+        def: ty::InstanceDef<'tcx>,
+        mir: &'tcx mir::Mir<'tcx>,
+    ) -> &'tcx mir::Mir<'tcx> {
+        let this = self.eval_context_mut();
+        if let Some(&stripped) = this.machine.cache.asm_free_mir.borrow().get(&def) {
+            return stripped;
+        }
+        let is_trivial_asm = |stmt: &mir::Statement<'tcx>| match stmt.kind {
+            mir::StatementKind::InlineAsm { ref asm, ref outputs, .. } => {
+                outputs.is_empty() && asm.asm.as_str().lines().all(|line| {
+                    let line = line.trim();
+                    line.is_empty() || line == "nop"
+                })
+            }
+            _ => false,
+        };
+        let has_trivial_asm = mir.basic_blocks().iter()
+            .any(|block| block.statements.iter().any(is_trivial_asm));
+        let stripped = if has_trivial_asm {
+            let mut stripped = mir.clone();
+            for block in stripped.basic_blocks_mut() {
+                for stmt in &mut block.statements {
+                    if is_trivial_asm(stmt) {
+                        stmt.make_nop();
+                    }
+                }
+            }
+            this.tcx.tcx.alloc_mir(stripped)
+        } else {
+            mir
+        };
+        this.machine.cache.asm_free_mir.borrow_mut().insert(def, stripped);
+        stripped
     }
 
     /// Emulate calling a foreign item, fail if the item is not supported.
@@ -63,13 +183,23 @@ pub trait EvalContextExt<'a, 'mir, 'tcx: 'a+'mir>: crate::MiriEvalContextExt<'a,
         ret: mir::BasicBlock,
     ) -> EvalResult<'tcx> {
         let this = self.eval_context_mut();
+        this.machine.stats.shim_calls += 1;
         let attrs = this.tcx.get_attrs(def_id);
         let link_name = match attr::first_attr_value_str_by_name(&attrs, "link_name") {
             Some(name) => name.as_str(),
             None => this.tcx.item_name(def_id).as_str(),
         };
-        // Strip linker suffixes (seen on 32bit macOS)
-        let link_name = link_name.trim_end_matches("$UNIX2003");
+        // Strip linker suffixes (seen on 32bit macOS, and for the 64-bit inode variants
+        // of the filesystem functions on macOS)
+        let link_name = link_name.trim_end_matches("$UNIX2003").trim_end_matches("$INODE64");
+
+        // The embedder knows best.
+        if let Some(shim) = this.machine.foreign_shims.get(link_name) {
+            shim.call(this, args, dest)?;
+            this.goto_block(Some(ret))?;
+            this.dump_place(*dest);
+            return Ok(());
+        }
 
         let tcx = &{this.tcx.tcx};
 
@@ -80,11 +210,12 @@ pub trait EvalContextExt<'a, 'mir, 'tcx: 'a+'mir>: crate::MiriEvalContextExt<'a,
         match &link_name[..] {
             "malloc" => {
                 let size = this.read_scalar(args[0])?.to_usize(this)?;
-                if size == 0 {
+                if size == 0 || !this.heap_has_room(size) {
                     this.write_null(dest)?;
                 } else {
                     let align = this.tcx.data_layout.pointer_align.abi;
                     let ptr = this.memory_mut().allocate(Size::from_bytes(size), align, MiriMemoryKind::C.into())?;
+                    this.record_allocation(ptr.alloc_id);
                     this.write_scalar(Scalar::Ptr(ptr.with_default_tag()), dest)?;
                 }
             }
@@ -92,11 +223,13 @@ pub trait EvalContextExt<'a, 'mir, 'tcx: 'a+'mir>: crate::MiriEvalContextExt<'a,
             "free" => {
                 let ptr = this.read_scalar(args[0])?.not_undef()?;
                 if !ptr.is_null_ptr(this) {
+                    let ptr = ptr.to_ptr()?;
                     this.memory_mut().deallocate(
-                        ptr.to_ptr()?,
+                        ptr,
                         None,
                         MiriMemoryKind::C.into(),
                     )?;
+                    this.record_deallocation(ptr.alloc_id);
                 }
             }
 
@@ -109,14 +242,20 @@ pub trait EvalContextExt<'a, 'mir, 'tcx: 'a+'mir>: crate::MiriEvalContextExt<'a,
                 if !align.is_power_of_two() {
                     return err!(HeapAllocNonPowerOfTwoAlignment(align));
                 }
-                let ptr = this.memory_mut()
-                    .allocate(
-                        Size::from_bytes(size),
-                        Align::from_bytes(align).unwrap(),
-                        MiriMemoryKind::Rust.into()
-                    )?
-                    .with_default_tag();
-                this.write_scalar(Scalar::Ptr(ptr), dest)?;
+                if !this.heap_has_room(size) {
+                    // The caller calls `handle_alloc_error`, or reports the failure.
+                    this.write_null(dest)?;
+                } else {
+                    let ptr = this.memory_mut()
+                        .allocate(
+                            Size::from_bytes(size),
+                            Align::from_bytes(align).unwrap(),
+                            MiriMemoryKind::Rust.into()
+                        )?
+                        .with_default_tag();
+                    this.record_allocation(ptr.alloc_id);
+                    this.write_scalar(Scalar::Ptr(ptr), dest)?;
+                }
             }
             "__rust_alloc_zeroed" => {
                 let size = this.read_scalar(args[0])?.to_usize(this)?;
@@ -127,17 +266,22 @@ pub trait EvalContextExt<'a, 'mir, 'tcx: 'a+'mir>: crate::MiriEvalContextExt<'a,
                 if !align.is_power_of_two() {
                     return err!(HeapAllocNonPowerOfTwoAlignment(align));
                 }
-                let ptr = this.memory_mut()
-                    .allocate(
-                        Size::from_bytes(size),
-                        Align::from_bytes(align).unwrap(),
-                        MiriMemoryKind::Rust.into()
-                    )?
-                    .with_default_tag();
-                this.memory_mut()
-                    .get_mut(ptr.alloc_id)?
-                    .write_repeat(tcx, ptr, 0, Size::from_bytes(size))?;
-                this.write_scalar(Scalar::Ptr(ptr), dest)?;
+                if !this.heap_has_room(size) {
+                    this.write_null(dest)?;
+                } else {
+                    let ptr = this.memory_mut()
+                        .allocate(
+                            Size::from_bytes(size),
+                            Align::from_bytes(align).unwrap(),
+                            MiriMemoryKind::Rust.into()
+                        )?
+                        .with_default_tag();
+                    this.record_allocation(ptr.alloc_id);
+                    this.memory_mut()
+                        .get_mut(ptr.alloc_id)?
+                        .write_repeat(tcx, ptr, 0, Size::from_bytes(size))?;
+                    this.write_scalar(Scalar::Ptr(ptr), dest)?;
+                }
             }
             "__rust_dealloc" => {
                 let ptr = this.read_scalar(args[0])?.to_ptr()?;
@@ -154,6 +298,7 @@ pub trait EvalContextExt<'a, 'mir, 'tcx: 'a+'mir>: crate::MiriEvalContextExt<'a,
                     Some((Size::from_bytes(old_size), Align::from_bytes(align).unwrap())),
                     MiriMemoryKind::Rust.into(),
                 )?;
+                this.record_deallocation(ptr.alloc_id);
             }
             "__rust_realloc" => {
                 let ptr = this.read_scalar(args[0])?.to_ptr()?;
@@ -166,28 +311,36 @@ pub trait EvalContextExt<'a, 'mir, 'tcx: 'a+'mir>: crate::MiriEvalContextExt<'a,
                 if !align.is_power_of_two() {
                     return err!(HeapAllocNonPowerOfTwoAlignment(align));
                 }
-                let new_ptr = this.memory_mut().reallocate(
-                    ptr,
-                    Size::from_bytes(old_size),
-                    Align::from_bytes(align).unwrap(),
-                    Size::from_bytes(new_size),
-                    Align::from_bytes(align).unwrap(),
-                    MiriMemoryKind::Rust.into(),
-                )?;
-                this.write_scalar(Scalar::Ptr(new_ptr.with_default_tag()), dest)?;
+                if new_size > old_size && !this.heap_has_room(new_size - old_size) {
+                    // The old allocation stays as it is.
+                    this.write_null(dest)?;
+                } else {
+                    let new_ptr = this.memory_mut().reallocate(
+                        ptr,
+                        Size::from_bytes(old_size),
+                        Align::from_bytes(align).unwrap(),
+                        Size::from_bytes(new_size),
+                        Align::from_bytes(align).unwrap(),
+                        MiriMemoryKind::Rust.into(),
+                    )?;
+                    this.record_deallocation(ptr.alloc_id);
+                    this.record_allocation(new_ptr.alloc_id);
+                    this.write_scalar(Scalar::Ptr(new_ptr.with_default_tag()), dest)?;
+                }
             }
 
             "syscall" => {
                 // TODO: read `syscall` ids like `sysconf` ids and
-                // figure out some way to actually process some of them
+                // figure out some way to actually process more of them
                 //
                 // libc::syscall(NR_GETRANDOM, buf.as_mut_ptr(), buf.len(), GRND_NONBLOCK)
                 // is called if a `HashMap` is created the regular way.
                 match this.read_scalar(args[0])?.to_usize(this)? {
                     318 | 511 => {
-                        return err!(Unimplemented(
-                            "miri does not support random number generators".to_owned(),
-                        ))
+                        let buf = this.read_scalar(args[1])?.not_undef()?;
+                        let len = this.read_scalar(args[2])?.to_usize(this)?;
+                        this.fill_random(buf, len)?;
+                        this.write_scalar(Scalar::from_uint(len, dest.layout.size), dest)?;
                     }
                     id => {
                         return err!(Unimplemented(
@@ -197,16 +350,45 @@ pub trait EvalContextExt<'a, 'mir, 'tcx: 'a+'mir>: crate::MiriEvalContextExt<'a,
                 }
             }
 
+            "getrandom" => {
+                let buf = this.read_scalar(args[0])?.not_undef()?;
+                let len = this.read_scalar(args[1])?.to_usize(this)?;
+                this.fill_random(buf, len)?;
+                this.write_scalar(Scalar::from_uint(len, dest.layout.size), dest)?;
+            }
+            "getentropy" => {
+                let buf = this.read_scalar(args[0])?.not_undef()?;
+                let len = this.read_scalar(args[1])?.to_usize(this)?;
+                // At most 256 bytes at a time, as on the host.
+                let result = if len > 256 {
+                    this.set_errno("EIO")?;
+                    -1
+                } else {
+                    this.fill_random(buf, len)?;
+                    0
+                };
+                this.write_scalar(Scalar::from_int(result, dest.layout.size), dest)?;
+            }
+
             "dlsym" => {
                 let _handle = this.read_scalar(args[0])?;
                 let symbol = this.read_scalar(args[1])?.to_ptr()?;
                 let symbol_name = this.memory().get(symbol.alloc_id)?.read_c_str(tcx, symbol)?;
                 let err = format!("bad c unicode symbol: {:?}", symbol_name);
                 let symbol_name = ::std::str::from_utf8(symbol_name).unwrap_or(&err);
-                return err!(Unimplemented(format!(
-                    "miri does not support dynamically loading libraries (requested symbol: {})",
-                    symbol_name
-                )));
+                // libstd looks up some libc functions at runtime, e.g. `signal` on Android.
+                // If libc declares the symbol, we hand out a pointer to that declaration, so that
+                // calls to it end up in our shims like direct calls.
+                match this.resolve_path(&["libc", symbol_name]) {
+                    Ok(instance) if this.tcx.is_foreign_item(instance.def_id()) => {
+                        let ptr = this.memory_mut().create_fn_alloc(instance).with_default_tag();
+                        this.write_scalar(Scalar::Ptr(ptr), dest)?;
+                    }
+                    _ => return err!(Unimplemented(format!(
+                        "miri does not support dynamically loading libraries (requested symbol: {})",
+                        symbol_name
+                    ))),
+                }
             }
 
             "__rust_maybe_catch_panic" => {
@@ -221,6 +403,7 @@ pub trait EvalContextExt<'a, 'mir, 'tcx: 'a+'mir>: crate::MiriEvalContextExt<'a,
                 // Now we make a function call.  TODO: Consider making this re-usable?  EvalContext::step does sth. similar for the TLS dtors,
                 // and of course eval_main.
                 let mir = this.load_mir(f_instance.def)?;
+                let mir = this.strip_trivial_asm(f_instance.def, mir);
                 let ret_place = MPlaceTy::dangling(this.layout_of(this.tcx.mk_unit())?, this).into();
                 this.push_stack_frame(
                     f_instance,
@@ -275,6 +458,26 @@ pub trait EvalContextExt<'a, 'mir, 'tcx: 'a+'mir>: crate::MiriEvalContextExt<'a,
                 )?;
             }
 
+            "memcpy" | "memmove" => {
+                let dest_ptr = this.read_scalar(args[0])?.not_undef()?;
+                let src = this.read_scalar(args[1])?.not_undef()?;
+                let n = Size::from_bytes(this.read_scalar(args[2])?.to_usize(this)?);
+                let align = Align::from_bytes(1).unwrap();
+                this.copy_bytes(src, align, dest_ptr, align, n, link_name == "memcpy")?;
+                this.write_scalar(dest_ptr, dest)?;
+            }
+
+            "memset" => {
+                let ptr = this.read_scalar(args[0])?.not_undef()?;
+                let val = this.read_scalar(args[1])?.to_i32()? as u8;
+                let n = Size::from_bytes(this.read_scalar(args[2])?.to_usize(this)?);
+                if n.bytes() != 0 {
+                    let alloc_ptr = ptr.to_ptr()?;
+                    this.memory_mut().get_mut(alloc_ptr.alloc_id)?.write_repeat(tcx, alloc_ptr, val, n)?;
+                }
+                this.write_scalar(ptr, dest)?;
+            }
+
             "memrchr" => {
                 let ptr = this.read_scalar(args[0])?.not_undef()?;
                 let val = this.read_scalar(args[1])?.to_i32()? as u8;
@@ -293,11 +496,8 @@ pub trait EvalContextExt<'a, 'mir, 'tcx: 'a+'mir>: crate::MiriEvalContextExt<'a,
                 let ptr = this.read_scalar(args[0])?.not_undef()?;
                 let val = this.read_scalar(args[1])?.to_i32()? as u8;
                 let num = this.read_scalar(args[2])?.to_usize(this)?;
-                if let Some(idx) = this.memory().read_bytes(ptr, Size::from_bytes(num))?.iter().position(
-                    |&c| c == val,
-                )
-                {
-                    let new_ptr = ptr.ptr_offset(Size::from_bytes(idx as u64), this)?;
+                if let Some(idx) = this.find_byte(ptr, val, num)? {
+                    let new_ptr = ptr.ptr_offset(Size::from_bytes(idx), this)?;
                     this.write_scalar(new_ptr, dest)?;
                 } else {
                     this.write_null(dest)?;
@@ -305,12 +505,14 @@ pub trait EvalContextExt<'a, 'mir, 'tcx: 'a+'mir>: crate::MiriEvalContextExt<'a,
             }
 
             "getenv" => {
-                let result = {
-                    let name_ptr = this.read_scalar(args[0])?.to_ptr()?;
-                    let name = this.memory().get(name_ptr.alloc_id)?.read_c_str(tcx, name_ptr)?;
-                    match this.machine.env_vars.get(name) {
-                        Some(&var) => Scalar::Ptr(var),
-                        None => Scalar::ptr_null(&*this.tcx),
+                let name_ptr = this.read_scalar(args[0])?.to_ptr()?;
+                let name = this.memory().get(name_ptr.alloc_id)?.read_c_str(tcx, name_ptr)?.to_owned();
+                let result = match this.machine.env_vars.get(&name) {
+                    Some(&var) => Scalar::Ptr(var),
+                    None => {
+                        let op = format!("reading the environment variable `{}`", String::from_utf8_lossy(&name));
+                        this.reject_in_isolation(IsolationCategory::Env, op)?;
+                        Scalar::ptr_null(&*this.tcx)
                     }
                 };
                 this.write_scalar(result, dest)?;
@@ -385,18 +587,13 @@ pub trait EvalContextExt<'a, 'mir, 'tcx: 'a+'mir>: crate::MiriEvalContextExt<'a,
                 trace!("Called write({:?}, {:?}, {:?})", fd, buf, n);
                 let result = if fd == 1 || fd == 2 {
                     // stdout/stderr
-                    use std::io::{self, Write};
-
-                    let buf_cont = this.memory().read_bytes(buf, Size::from_bytes(n))?;
-                    let res = if fd == 1 {
-                        io::stdout().write(buf_cont)
-                    } else {
-                        io::stderr().write(buf_cont)
-                    };
-                    match res {
+                    let buf_cont = this.memory().read_bytes(buf, Size::from_bytes(n))?.to_vec();
+                    match this.machine.output.write(fd == 2, &buf_cont) {
                         Ok(n) => n as i64,
                         Err(_) => -1,
                     }
+                } else if this.machine.files.is_open(fd) {
+                    this.write_file(fd, buf, n)?
                 } else {
                     warn!("Ignored output to FD {}", fd);
                     n as i64 // pretend it all went well
@@ -407,6 +604,85 @@ pub trait EvalContextExt<'a, 'mir, 'tcx: 'a+'mir>: crate::MiriEvalContextExt<'a,
                 )?;
             }
 
+            "read" => {
+                let fd = this.read_scalar(args[0])?.to_i32()?;
+                let buf = this.read_scalar(args[1])?.not_undef()?;
+                let n = this.read_scalar(args[2])?.to_usize(&*this.tcx)?;
+                trace!("Called read({:?}, {:?}, {:?})", fd, buf, n);
+                let result = if fd == 0 {
+                    match this.replayed_read(0, |this| this.machine.stdin.read(n as usize))? {
+                        Ok(bytes) => {
+                            this.memory_mut().write_bytes(buf, &bytes)?;
+                            bytes.len() as i64
+                        }
+                        Err(_) => -1,
+                    }
+                } else if this.machine.files.is_open(fd) {
+                    this.read_file(fd, buf, n)?
+                } else {
+                    return err!(Unimplemented(format!("reading from FD {} is not supported", fd)));
+                };
+                this.write_scalar(Scalar::from_int(result, dest.layout.size), dest)?;
+            }
+
+            "open" | "open64" => {
+                // The mode, if any, is a variadic argument.  We leave permissions to the host.
+                let path = this.read_scalar(args[0])?.not_undef()?;
+                let flags = this.read_scalar(args[1])?.to_i32()?;
+                let fd = this.open(path, flags)?;
+                this.write_scalar(Scalar::from_int(fd, dest.layout.size), dest)?;
+            }
+
+            "fcntl" => {
+                let fd = this.read_scalar(args[0])?.to_i32()?;
+                let cmd = this.read_scalar(args[1])?.to_i32()?;
+                if !this.machine.files.is_open(fd) {
+                    return err!(Unimplemented(format!("fcntl on FD {} is not supported", fd)));
+                }
+                // `File::open` checks that the file is close-on-exec, which all our files are,
+                // since the program cannot exec anything anyway.
+                let result = if cmd == this.eval_libc_i32("F_GETFD")? {
+                    this.eval_libc_i32("FD_CLOEXEC")?
+                } else if cmd == this.eval_libc_i32("F_SETFD")? {
+                    0
+                } else {
+                    return err!(Unimplemented(format!("fcntl command {} is not supported", cmd)));
+                };
+                this.write_scalar(Scalar::from_int(result, dest.layout.size), dest)?;
+            }
+
+            "fstat" | "fstat64" => {
+                // We do not support file metadata.  `fs::read` only uses it to size its buffer,
+                // and goes on without it.
+                this.set_errno("ENOSYS")?;
+                this.write_scalar(Scalar::from_int(-1, dest.layout.size), dest)?;
+            }
+
+            "socket" => {
+                this.reject_in_isolation(IsolationCategory::Network, "creating a socket".to_owned())?;
+                this.set_errno("EPERM")?;
+                this.write_scalar(Scalar::from_int(-1, dest.layout.size), dest)?;
+            }
+
+            "fork" | "execv" | "execve" | "execvp" => {
+                let op = format!("starting a process with `{}`", link_name);
+                this.reject_in_isolation(IsolationCategory::Process, op)?;
+                this.set_errno("EPERM")?;
+                this.write_scalar(Scalar::from_int(-1, dest.layout.size), dest)?;
+            }
+            "posix_spawn" | "posix_spawnp" => {
+                let op = format!("starting a process with `{}`", link_name);
+                this.reject_in_isolation(IsolationCategory::Process, op)?;
+                // These return the error instead of setting `errno`.
+                let eperm = this.eval_libc_i32("EPERM")?;
+                this.write_scalar(Scalar::from_int(eperm, dest.layout.size), dest)?;
+            }
+
+            "__errno_location" | "__error" => {
+                let errno = this.errno_place()?;
+                this.write_scalar(errno.ptr, dest)?;
+            }
+
             "strlen" => {
                 let ptr = this.read_scalar(args[0])?.to_ptr()?;
                 let n = this.memory().get(ptr.alloc_id)?.read_c_str(tcx, ptr)?.len();
@@ -424,7 +700,8 @@ pub trait EvalContextExt<'a, 'mir, 'tcx: 'a+'mir>: crate::MiriEvalContextExt<'a,
                 trace!("sysconf() called with name {}", name);
                 // cache the sysconf integers via miri's global cache
                 let paths = &[
-                    (&["libc", "_SC_PAGESIZE"], Scalar::from_int(4096, dest.layout.size)),
+                    (&["libc", "_SC_PAGESIZE"], Scalar::from_int(PAGE_SIZE, dest.layout.size)),
+                    (&["libc", "_SC_NPROCESSORS_ONLN"], Scalar::from_int(NUM_CPUS, dest.layout.size)),
                     (&["libc", "_SC_GETPW_R_SIZE_MAX"], Scalar::from_int(-1, dest.layout.size)),
                 ];
                 let mut result = None;
@@ -509,9 +786,35 @@ pub trait EvalContextExt<'a, 'mir, 'tcx: 'a+'mir>: crate::MiriEvalContextExt<'a,
             }
 
             "_tlv_atexit" => {
-                // FIXME: Register the dtor
+                let dtor = this.read_scalar(args[0])?.to_ptr()?;
+                let dtor = this.memory().get_fn(dtor)?;
+                let data = this.read_scalar(args[1])?.not_undef()?;
+                this.machine.tls.add_macos_thread_dtor(dtor, data);
             },
 
+            // Linux and Android system information
+            "getauxval" => {
+                let ty = this.read_scalar(args[0])?.to_usize(this)?;
+                let value = match ty {
+                    AT_PAGESZ => PAGE_SIZE as u64,
+                    // Other entries are not present, for which libc returns 0.  In particular,
+                    // `AT_HWCAP` reports no optional CPU features.
+                    _ => 0,
+                };
+                this.write_scalar(Scalar::from_uint(value, dest.layout.size), dest)?;
+            }
+            "__system_property_get" => {
+                // fn __system_property_get(name: *const c_char, value: *mut c_char) -> c_int
+                // No Android system properties are set, which libc reports as an empty value.
+                let name = this.read_scalar(args[0])?.to_ptr()?;
+                let name = this.memory().get(name.alloc_id)?.read_c_str(tcx, name)?;
+                trace!("__system_property_get({:?})", String::from_utf8_lossy(name));
+                let value = this.read_scalar(args[1])?.to_ptr()?;
+                this.memory_mut().get_mut(value.alloc_id)?.write_bytes(tcx, value, &[0])?;
+                // Return the length of the value.
+                this.write_null(dest)?;
+            }
+
             // Determining stack base address
             "pthread_attr_init" | "pthread_attr_destroy" | "pthread_attr_get_np" |
             "pthread_getattr_np" | "pthread_self" | "pthread_get_stacksize_np" => {
@@ -529,6 +832,24 @@ pub trait EvalContextExt<'a, 'mir, 'tcx: 'a+'mir>: crate::MiriEvalContextExt<'a,
                 let stackaddr = Scalar::from_int(0x80000, dest.layout.size); // just any address
                 this.write_scalar(stackaddr, dest)?;
             }
+            "stack_getbounds" => {
+                // illumos and Solaris: fill in the `stack_t` for the current thread.
+                let stack = this.deref_operand(args[0])?;
+                let ss_sp = this.mplace_field(stack, 0)?;
+                let stackaddr = Scalar::from_int(0x80000, ss_sp.layout.size); // just any address
+                this.write_scalar(stackaddr, ss_sp.into())?;
+                for field in 1..3 {
+                    // `ss_size` and `ss_flags`
+                    let field = this.mplace_field(stack, field)?;
+                    this.write_scalar(Scalar::from_int(0, field.layout.size), field.into())?;
+                }
+                this.write_null(dest)?;
+            }
+
+            // illumos and Solaris thread IDs
+            "_lwp_self" | "thr_self" => {
+                this.write_scalar(Scalar::from_uint(MAIN_THREAD_ID, dest.layout.size), dest)?;
+            }
 
             // Stub out calls for condvar, mutex and rwlock to just return 0
             "pthread_mutexattr_init" | "pthread_mutexattr_settype" | "pthread_mutex_init" |
@@ -549,6 +870,540 @@ pub trait EvalContextExt<'a, 'mir, 'tcx: 'a+'mir>: crate::MiriEvalContextExt<'a,
                 this.write_null(dest)?;
             }
 
+            // macOS time
+            "mach_absolute_time" => {
+                // Our timebase is 1/1, so the ticks are nanoseconds.
+                let now = this.machine.clock.query();
+                let ticks = now.as_secs() as u128 * 1_000_000_000 + now.subsec_nanos() as u128;
+                this.write_scalar(Scalar::from_uint(ticks, dest.layout.size), dest)?;
+            }
+            "mach_timebase_info" => {
+                let info = this.deref_operand(args[0])?;
+                for field in 0..2 {
+                    // Both `numer` and `denom` are 1.
+                    let field = this.mplace_field(info, field)?;
+                    this.write_scalar(Scalar::from_uint(1u32, field.layout.size), field.into())?;
+                }
+                // Return success (`KERN_SUCCESS`)
+                this.write_null(dest)?;
+            }
+
+            // Unix time
+            "clock_gettime" => {
+                let clock_id = this.read_scalar(args[0])?.to_i32()?;
+                // Figure out which kind of clock this is.  The available clocks, and their IDs,
+                // depend on the target.
+                let clocks: &[(&str, bool)] = &[
+                    ("CLOCK_MONOTONIC", false),
+                    ("CLOCK_MONOTONIC_RAW", false),
+                    ("CLOCK_MONOTONIC_COARSE", false),
+                    ("CLOCK_MONOTONIC_FAST", false),
+                    ("CLOCK_MONOTONIC_PRECISE", false),
+                    ("CLOCK_BOOTTIME", false),
+                    ("CLOCK_UPTIME", false),
+                    ("CLOCK_UPTIME_FAST", false),
+                    ("CLOCK_UPTIME_PRECISE", false),
+                    ("CLOCK_REALTIME", true),
+                    ("CLOCK_REALTIME_COARSE", true),
+                    ("CLOCK_REALTIME_FAST", true),
+                    ("CLOCK_REALTIME_PRECISE", true),
+                ];
+                let mut realtime = None;
+                for &(name, is_realtime) in clocks {
+                    let path = ["libc", name];
+                    if this.resolve_path(&path).is_err() {
+                        continue;
+                    }
+                    if this.eval_path_scalar(&path)?.to_i32()? == clock_id {
+                        realtime = Some(is_realtime);
+                        break;
+                    }
+                }
+                let realtime = match realtime {
+                    Some(realtime) => realtime,
+                    None => return err!(Unimplemented(
+                        format!("clock_gettime: unsupported clock {}", clock_id),
+                    )),
+                };
+                // All clocks run on the virtual clock.  The wall clock starts at the epoch, as
+                // the host's wall clock is not for the program to see.
+                if realtime {
+                    this.reject_in_isolation(IsolationCategory::Clock, "reading the wall clock".to_owned())?;
+                }
+                let now = this.machine.clock.query();
+                trace!("clock_gettime({}) = {:?} (realtime: {})", clock_id, now, realtime);
+                let tp = this.deref_operand(args[1])?;
+                let tv_sec = this.mplace_field(tp, 0)?;
+                this.write_scalar(Scalar::from_uint(now.as_secs(), tv_sec.layout.size), tv_sec.into())?;
+                let tv_nsec = this.mplace_field(tp, 1)?;
+                this.write_scalar(Scalar::from_uint(now.subsec_nanos(), tv_nsec.layout.size), tv_nsec.into())?;
+                this.write_null(dest)?;
+            }
+
+            // illumos and Solaris time
+            "gethrtime" => {
+                let now = this.machine.clock.query();
+                let nanos = now.as_secs() as i128 * 1_000_000_000 + now.subsec_nanos() as i128;
+                this.write_scalar(Scalar::from_int(nanos, dest.layout.size), dest)?;
+            }
+
+            // FreeBSD thread parking
+            "_umtx_op" => {
+                // fn _umtx_op(obj: *mut c_void, op: c_int, val: c_ulong,
+                //     uaddr: *mut c_void, uaddr2: *mut c_void) -> c_int
+                let op = this.read_scalar(args[1])?.to_i32()?;
+                match op {
+                    UMTX_OP_WAIT | UMTX_OP_WAIT_UINT | UMTX_OP_WAIT_UINT_PRIVATE => {
+                        let obj = this.read_scalar(args[0])?.to_ptr()?;
+                        let val = this.read_scalar(args[2])?.to_bits(args[2].layout.size)?;
+                        let size = if op == UMTX_OP_WAIT {
+                            this.memory().pointer_size()
+                        } else {
+                            Size::from_bytes(4)
+                        };
+                        this.memory().check_align(obj.into(), Align::from_bytes(size.bytes()).unwrap())?;
+                        let current = this.memory().get(obj.alloc_id)?.read_scalar(tcx, obj, size)?
+                            .to_bits(size)?;
+                        let expected = if op == UMTX_OP_WAIT { val } else { val as u32 as u128 };
+                        let result = if current != expected {
+                            // The value changed already, so we do not have to wait.
+                            0
+                        } else {
+                            // There is no other thread that could wake us up, so we have to time out.
+                            let size = this.read_scalar(args[3])?.to_usize(this)?;
+                            let timeout = this.read_scalar(args[4])?.not_undef()?;
+                            if timeout.is_null_ptr(this) {
+                                return err!(MachineError(
+                                    "deadlock: waiting forever in _umtx_op, with no other thread to wake us up".to_owned(),
+                                ));
+                            }
+                            // `timeout` points to either a `timespec`, or a `_umtx_time` which
+                            // starts with a `timespec`.  We only support relative timeouts.
+                            let timespec_size = this.memory().pointer_size() * 2;
+                            if size > timespec_size.bytes() {
+                                let flags = timeout.to_ptr()?.offset(timespec_size, this)?;
+                                let flags = this.memory().get(flags.alloc_id)?
+                                    .read_scalar(tcx, flags, Size::from_bytes(4))?.to_u32()?;
+                                if flags & UMTX_ABSTIME != 0 {
+                                    return err!(Unimplemented(
+                                        "_umtx_op with an absolute timeout is not supported".to_owned(),
+                                    ));
+                                }
+                            }
+                            let timeout = timeout.to_ptr()?;
+                            let ptr_size = this.memory().pointer_size();
+                            let sec = this.memory().get(timeout.alloc_id)?
+                                .read_scalar(tcx, timeout, ptr_size)?.to_bits(ptr_size)? as u64;
+                            let nsec_ptr = timeout.offset(ptr_size, this)?;
+                            let nsec = this.memory().get(nsec_ptr.alloc_id)?
+                                .read_scalar(tcx, nsec_ptr, ptr_size)?.to_bits(ptr_size)? as u32;
+                            this.machine.clock.advance(Duration::new(sec, nsec));
+                            this.set_errno("ETIMEDOUT")?;
+                            -1
+                        };
+                        this.write_scalar(Scalar::from_int(result, dest.layout.size), dest)?;
+                    }
+                    UMTX_OP_WAKE | UMTX_OP_WAKE_PRIVATE => {
+                        // There is nobody to wake up.
+                        this.write_null(dest)?;
+                    }
+                    _ => return err!(Unimplemented(format!("_umtx_op: unsupported operation {}", op))),
+                }
+            }
+
+            // Spawning threads, on FreeBSD, illumos and Solaris
+            "thr_new" | "_lwp_create" | "thr_create" => {
+                return err!(Unimplemented("Miri does not support threading".to_owned()));
+            }
+
+            // Grand Central Dispatch.  `dispatch_time_t` is measured in `mach_absolute_time` ticks.
+            "dispatch_time" => {
+                let when = this.read_scalar(args[0])?.to_u64()?;
+                let delta = this.read_scalar(args[1])?.to_i64()?;
+                let result = if when == DISPATCH_TIME_FOREVER {
+                    DISPATCH_TIME_FOREVER
+                } else {
+                    let when = if when == DISPATCH_TIME_NOW {
+                        let now = this.machine.clock.query();
+                        now.as_secs() * 1_000_000_000 + now.subsec_nanos() as u64
+                    } else {
+                        when
+                    };
+                    if delta >= 0 {
+                        // Saturate at `DISPATCH_TIME_FOREVER`.
+                        when.saturating_add(delta as u64).min(DISPATCH_TIME_FOREVER)
+                    } else {
+                        // Do not underflow into `DISPATCH_TIME_NOW`.
+                        when.saturating_sub(delta.wrapping_neg() as u64).max(1)
+                    }
+                };
+                this.write_scalar(Scalar::from_uint(result, dest.layout.size), dest)?;
+            }
+            "dispatch_semaphore_create" => {
+                let value = this.read_scalar(args[0])?.to_isize(this)?;
+                if value < 0 {
+                    // libdispatch returns `NULL` here.
+                    this.write_null(dest)?;
+                } else {
+                    let ptr = this.memory_mut().allocate(
+                        Size::from_bytes(1),
+                        Align::from_bytes(1).unwrap(),
+                        MiriMemoryKind::Dispatch.into(),
+                    )?.with_default_tag();
+                    this.machine.dispatch_objects.insert(
+                        ptr.alloc_id,
+                        DispatchObject::Semaphore { value, initial: value },
+                    );
+                    this.write_scalar(Scalar::Ptr(ptr), dest)?;
+                }
+            }
+            "dispatch_semaphore_signal" => {
+                let sema = this.read_scalar(args[0])?.to_ptr()?;
+                match this.machine.dispatch_objects.get_mut(sema.alloc_id)? {
+                    DispatchObject::Semaphore { value, .. } => *value += 1,
+                }
+                // There is no other thread that could be waiting, so we never wake one up.
+                this.write_null(dest)?;
+            }
+            "dispatch_semaphore_wait" => {
+                let sema = this.read_scalar(args[0])?.to_ptr()?;
+                let timeout = this.read_scalar(args[1])?.to_u64()?;
+                let acquired = match this.machine.dispatch_objects.get_mut(sema.alloc_id)? {
+                    DispatchObject::Semaphore { value, .. } => {
+                        if *value > 0 {
+                            *value -= 1;
+                            true
+                        } else {
+                            false
+                        }
+                    }
+                };
+                if !acquired {
+                    if timeout == DISPATCH_TIME_FOREVER {
+                        // There is no other thread that could signal this semaphore.
+                        return err!(MachineError(
+                            "deadlock: waiting forever for a dispatch semaphore that is never signaled".to_owned(),
+                        ));
+                    }
+                    // Nothing can change while we wait, so we just let the time pass
+                    // until the deadline.
+                    let now = this.machine.clock.now();
+                    let deadline = if timeout == DISPATCH_TIME_NOW {
+                        now
+                    } else {
+                        Duration::from_nanos(timeout)
+                    };
+                    if deadline > now {
+                        this.machine.clock.advance(deadline - now);
+                    }
+                }
+                // Return 0 on success, and non-zero on timeout.
+                let result = if acquired { 0 } else { 1 };
+                this.write_scalar(Scalar::from_int(result, dest.layout.size), dest)?;
+            }
+            "dispatch_retain" => {
+                let object = this.read_scalar(args[0])?.to_ptr()?;
+                this.machine.dispatch_objects.retain(object.alloc_id)?;
+            }
+            "dispatch_release" => {
+                let object = this.read_scalar(args[0])?.to_ptr()?;
+                if this.machine.dispatch_objects.release(object.alloc_id)? {
+                    this.memory_mut().deallocate(object, None, MiriMemoryKind::Dispatch.into())?;
+                }
+            }
+
+            // macOS locks.  The lock word is 0 when unlocked, and holds the owner's
+            // thread ID otherwise.
+            "os_unfair_lock_lock" | "os_unfair_lock_trylock" => {
+                let lock = this.deref_operand(args[0])?;
+                let lock = this.mplace_field(lock, 0)?;
+                let owner = this.read_scalar(lock.into())?.to_u32()?;
+                let acquired = if owner == 0 {
+                    this.write_scalar(Scalar::from_uint(MAIN_THREAD_ID, lock.layout.size), lock.into())?;
+                    true
+                } else if link_name == "os_unfair_lock_lock" {
+                    // There is no other thread that could release this lock.
+                    return err!(MachineError(format!(
+                        "deadlock: trying to acquire os_unfair_lock that is already held by thread {}",
+                        owner,
+                    )));
+                } else {
+                    false
+                };
+                if link_name == "os_unfair_lock_trylock" {
+                    this.write_scalar(Scalar::from_bool(acquired), dest)?;
+                }
+            }
+            "os_unfair_lock_unlock" => {
+                let lock = this.deref_operand(args[0])?;
+                let lock = this.mplace_field(lock, 0)?;
+                let owner = this.read_scalar(lock.into())?.to_u32()?;
+                if owner != MAIN_THREAD_ID {
+                    return err!(MachineError(format!(
+                        "unlocking os_unfair_lock that is not held by the current thread (owner: {})",
+                        owner,
+                    )));
+                }
+                this.write_scalar(Scalar::from_uint(0u32, lock.layout.size), lock.into())?;
+            }
+            "os_unfair_lock_assert_owner" | "os_unfair_lock_assert_not_owner" => {
+                let lock = this.deref_operand(args[0])?;
+                let lock = this.mplace_field(lock, 0)?;
+                let owned = this.read_scalar(lock.into())?.to_u32()? == MAIN_THREAD_ID;
+                if owned != (link_name == "os_unfair_lock_assert_owner") {
+                    return err!(MachineError(format!("{} failed", link_name)));
+                }
+            }
+
+            // macOS and BSD event queues
+            "kqueue" => {
+                let fd = this.machine.files.new_fd();
+                this.machine.kqueues.create(fd);
+                this.write_scalar(Scalar::from_int(fd, dest.layout.size), dest)?;
+            }
+            "kevent" => {
+                let kq = this.read_scalar(args[0])?.to_i32()?;
+                let nchanges = this.read_scalar(args[2])?.to_i32()?;
+                let nevents = this.read_scalar(args[4])?.to_i32()?;
+                if nchanges < 0 || nevents < 0 {
+                    return err!(MachineError(format!(
+                        "kevent: negative number of changes ({}) or events ({})",
+                        nchanges, nevents,
+                    )));
+                }
+                let kevent_type = args[1].layout.ty.builtin_deref(true)
+                    .ok_or_else(|| EvalErrorKind::AbiViolation("Wrong signature used for kevent: Second argument must be a raw pointer.".to_owned()))?.ty;
+                let kevent_layout = this.layout_of(kevent_type)?;
+                let evfilt_user = this.eval_path_scalar(&["libc", "EVFILT_USER"])?.not_undef()?
+                    .to_bits(Size::from_bytes(2))? as i16;
+                let evfilt_timer = this.eval_path_scalar(&["libc", "EVFILT_TIMER"])?.not_undef()?
+                    .to_bits(Size::from_bytes(2))? as i16;
+
+                // Read and apply the changes.
+                let mut receipts = Vec::new();
+                for i in 0..nchanges as u64 {
+                    let ptr = this.read_scalar(args[1])?.to_ptr()?.offset(kevent_layout.size * i, this)?;
+                    let change = MPlaceTy::from_aligned_ptr(ptr, kevent_layout);
+                    let mut fields = Vec::new();
+                    for field in 0..5 {
+                        let field = this.mplace_field(change, field)?;
+                        fields.push(this.read_scalar(field.into())?.to_bits(field.layout.size)?);
+                    }
+                    let udata = this.mplace_field(change, 5)?;
+                    let udata = this.read_scalar(udata.into())?.not_undef()?;
+                    let (ident, filter, flags, fflags, data) =
+                        (fields[0] as u64, fields[1] as i16, fields[2] as u16, fields[3] as u32, fields[4] as i64);
+                    let filter = if filter == evfilt_user {
+                        kqueue::Filter::User
+                    } else if filter == evfilt_timer {
+                        if fflags != 0 {
+                            return err!(Unimplemented(format!(
+                                "kevent: timers with flags {:#x} are not supported", fflags,
+                            )));
+                        }
+                        kqueue::Filter::Timer
+                    } else {
+                        // We have no file descriptors, processes or signals to watch.
+                        return err!(Unimplemented(format!(
+                            "kevent: filter {} is not supported", filter,
+                        )));
+                    };
+                    let change = kqueue::Change {
+                        ident,
+                        filter,
+                        flags: kqueue::EventFlags {
+                            add: flags & EV_ADD != 0,
+                            delete: flags & EV_DELETE != 0,
+                            enable: flags & EV_ENABLE != 0,
+                            disable: flags & EV_DISABLE != 0,
+                            oneshot: flags & EV_ONESHOT != 0,
+                            clear: flags & EV_CLEAR != 0,
+                            receipt: flags & EV_RECEIPT != 0,
+                        },
+                        trigger: fflags & NOTE_TRIGGER != 0,
+                        data,
+                        udata,
+                    };
+                    trace!("kevent: applying {:?} to kqueue {}", change, kq);
+                    let now = this.machine.clock.now();
+                    let queue = this.machine.kqueues.get_mut(kq).ok_or_else(|| EvalErrorKind::MachineError(
+                        format!("kevent: {} is not a kqueue", kq),
+                    ))?;
+                    queue.apply(change, now).map_err(EvalErrorKind::MachineError)?;
+                    if change.flags.receipt {
+                        receipts.push(kqueue::Event {
+                            ident,
+                            filter,
+                            flags: change.flags,
+                            data: 0, // success
+                            udata,
+                        });
+                    }
+                }
+
+                // Collect the events.  If we have receipts, those are all we report, and we
+                // do not wait.
+                let mut events = receipts;
+                events.truncate(nevents as usize);
+                if events.is_empty() && nevents > 0 {
+                    let timeout = this.read_scalar(args[5])?.not_undef()?;
+                    let timeout = if timeout.is_null_ptr(this) {
+                        None
+                    } else {
+                        let timeout = this.deref_operand(args[5])?;
+                        let sec = this.mplace_field(timeout, 0)?;
+                        let sec = this.read_scalar(sec.into())?.to_bits(sec.layout.size)? as u64;
+                        let nsec = this.mplace_field(timeout, 1)?;
+                        let nsec = this.read_scalar(nsec.into())?.to_bits(nsec.layout.size)? as u32;
+                        Some(Duration::new(sec, nsec))
+                    };
+                    let now = this.machine.clock.now();
+                    let queue = this.machine.kqueues.get_mut(kq).ok_or_else(|| EvalErrorKind::MachineError(
+                        format!("kevent: {} is not a kqueue", kq),
+                    ))?;
+                    events = queue.collect(nevents as usize, now);
+                    if events.is_empty() && timeout != Some(Duration::default()) {
+                        // Wait until the next timer fires, or the timeout expires.  Nothing else
+                        // can happen while we wait: there is no other thread that could trigger
+                        // a user event.
+                        let wake_up = match (queue.next_deadline(), timeout) {
+                            (Some(deadline), Some(timeout)) => deadline.min(now + timeout),
+                            (Some(deadline), None) => deadline,
+                            (None, Some(timeout)) => now + timeout,
+                            (None, None) => return err!(MachineError(format!(
+                                "deadlock: waiting forever for events on kqueue {}, which has no active timers",
+                                kq,
+                            ))),
+                        };
+                        events = queue.collect(nevents as usize, wake_up);
+                        this.machine.clock.advance(wake_up - now);
+                    }
+                }
+
+                // Report the events.
+                for (i, event) in events.iter().enumerate() {
+                    let ptr = this.read_scalar(args[3])?.to_ptr()?.offset(kevent_layout.size * i as u64, this)?;
+                    let place = MPlaceTy::from_aligned_ptr(ptr, kevent_layout);
+                    let filter = match event.filter {
+                        kqueue::Filter::User => evfilt_user,
+                        kqueue::Filter::Timer => evfilt_timer,
+                    };
+                    let mut flags = 0;
+                    if event.flags.receipt {
+                        flags |= EV_ERROR;
+                    }
+                    if event.flags.oneshot {
+                        flags |= EV_ONESHOT;
+                    }
+                    if event.flags.clear {
+                        flags |= EV_CLEAR;
+                    }
+                    let fields = [
+                        Scalar::from_uint(event.ident, this.mplace_field(place, 0)?.layout.size),
+                        Scalar::from_int(filter, this.mplace_field(place, 1)?.layout.size),
+                        Scalar::from_uint(flags, this.mplace_field(place, 2)?.layout.size),
+                        Scalar::from_uint(0u32, this.mplace_field(place, 3)?.layout.size),
+                        Scalar::from_int(event.data, this.mplace_field(place, 4)?.layout.size),
+                        event.udata,
+                    ];
+                    for (field, &value) in fields.iter().enumerate() {
+                        let field = this.mplace_field(place, field as u64)?;
+                        this.write_scalar(value, field.into())?;
+                    }
+                }
+                this.write_scalar(Scalar::from_int(events.len() as i32, dest.layout.size), dest)?;
+            }
+            "close" => {
+                let fd = this.read_scalar(args[0])?.to_i32()?;
+                if !this.machine.kqueues.close(fd) && !this.machine.files.close(fd) {
+                    return err!(Unimplemented(format!("close({}) is not supported", fd)));
+                }
+                this.write_null(dest)?;
+            }
+
+            // macOS and BSD system information
+            "sysctl" | "sysctlbyname" => {
+                let name = if link_name == "sysctl" {
+                    // fn sysctl(name: *mut c_int, namelen: c_uint, oldp: *mut c_void,
+                    //     oldlenp: *mut size_t, newp: *mut c_void, newlen: size_t) -> c_int
+                    let mib_ptr = this.read_scalar(args[0])?.to_ptr()?;
+                    let mib_len = this.read_scalar(args[1])?.to_u32()?;
+                    let int_size = Size::from_bytes(4);
+                    this.memory().check_align(mib_ptr.into(), Align::from_bytes(4).unwrap())?;
+                    let mut mib = Vec::new();
+                    for i in 0..mib_len as u64 {
+                        let ptr = mib_ptr.offset(int_size * i, this)?;
+                        mib.push(this.memory().get(ptr.alloc_id)?.read_scalar(tcx, ptr, int_size)?.to_i32()?);
+                    }
+                    match sysctl_mib_name(&mib) {
+                        Some(name) => name.to_owned(),
+                        None => return err!(Unimplemented(format!("sysctl MIB {:?} is not supported", mib))),
+                    }
+                } else {
+                    // fn sysctlbyname(name: *const c_char, oldp: *mut c_void,
+                    //     oldlenp: *mut size_t, newp: *mut c_void, newlen: size_t) -> c_int
+                    let name_ptr = this.read_scalar(args[0])?.to_ptr()?;
+                    let name = this.memory().get(name_ptr.alloc_id)?.read_c_str(tcx, name_ptr)?;
+                    String::from_utf8_lossy(name).into_owned()
+                };
+                // The remaining arguments are the same for both functions.
+                let args = &args[args.len() - 4..];
+                let (old_ptr, old_len_ptr, new_ptr) = (args[0], args[1], args[2]);
+
+                let target_os = this.tcx.tcx.sess.target.target.target_os.to_lowercase();
+                let value = match sysctl_value(&name, &target_os) {
+                    Some(value) => value,
+                    None => return err!(Unimplemented(format!("sysctl {} is not supported", name))),
+                };
+                trace!("sysctl {} = {:?}", name, value);
+                let len = match value {
+                    SysctlValue::Int(_) => 4,
+                    SysctlValue::Str(value) => value.len() as u64 + 1, // +1 for the null terminator
+                };
+
+                let result = if !this.read_scalar(new_ptr)?.not_undef()?.is_null_ptr(this) {
+                    // We do not let the program change the system configuration.
+                    this.set_errno("EPERM")?;
+                    -1
+                } else {
+                    let old_len_place = this.deref_operand(old_len_ptr)?;
+                    let old = this.read_scalar(old_ptr)?.not_undef()?;
+                    let result = if old.is_null_ptr(this) {
+                        // Just report the size.
+                        0
+                    } else {
+                        let old_len = this.read_scalar(old_len_place.into())?.to_usize(this)?;
+                        if old_len < len {
+                            // Not enough space.
+                            this.set_errno("ENOMEM")?;
+                            -1
+                        } else {
+                            let old = old.to_ptr()?;
+                            let alloc = this.memory_mut().get_mut(old.alloc_id)?;
+                            match value {
+                                SysctlValue::Int(value) => alloc.write_scalar(
+                                    tcx,
+                                    old,
+                                    Scalar::from_int(value, Size::from_bytes(4)).into(),
+                                    Size::from_bytes(4),
+                                )?,
+                                SysctlValue::Str(value) => {
+                                    alloc.write_bytes(tcx, old, value.as_bytes())?;
+                                    let end = old.offset(Size::from_bytes(value.len() as u64), tcx)?;
+                                    alloc.write_bytes(tcx, end, &[0])?;
+                                }
+                            }
+                            0
+                        }
+                    };
+                    if result == 0 {
+                        this.write_scalar(Scalar::from_uint(len, old_len_place.layout.size), old_len_place.into())?;
+                    }
+                    result
+                };
+                this.write_scalar(Scalar::from_int(result, dest.layout.size), dest)?;
+            }
+
             // Windows API subs
             "AddVectoredExceptionHandler" => {
                 // any non zero value works for the stdlib. This is just used for stackoverflows anyway
@@ -557,21 +1412,487 @@ pub trait EvalContextExt<'a, 'mir, 'tcx: 'a+'mir>: crate::MiriEvalContextExt<'a,
             "InitializeCriticalSection" |
             "EnterCriticalSection" |
             "LeaveCriticalSection" |
-            "DeleteCriticalSection" |
-            "SetLastError" => {
+            "DeleteCriticalSection" => {
                 // Function does not return anything, nothing to do
             },
-            "GetModuleHandleW" |
-            "GetProcAddress" |
+            "GetModuleHandleW" => {
+                // pretend the module does not exist, by returning zero
+                this.machine.tls.set_last_error(ERROR_MOD_NOT_FOUND);
+                this.write_null(dest)?;
+            },
+            "GetProcAddress" => {
+                // pretend the function does not exist, by returning zero
+                this.machine.tls.set_last_error(ERROR_PROC_NOT_FOUND);
+                this.write_null(dest)?;
+            },
             "TryEnterCriticalSection" => {
-                // pretend these do not exist/nothing happened, by returning zero
+                // pretend nothing happened, by returning zero
                 this.write_null(dest)?;
             },
             "GetLastError" => {
-                // this is c::ERROR_CALL_NOT_IMPLEMENTED
-                this.write_scalar(Scalar::from_int(120, dest.layout.size), dest)?;
+                let err = this.machine.tls.last_error();
+                this.write_scalar(Scalar::from_uint(err, dest.layout.size), dest)?;
+            },
+            "SetLastError" => {
+                let err = this.read_scalar(args[0])?.to_u32()?;
+                this.machine.tls.set_last_error(err);
+            },
+
+            // `RtlGenRandom`
+            "SystemFunction036" => {
+                let buf = this.read_scalar(args[0])?.not_undef()?;
+                let len = this.read_scalar(args[1])?.to_u32()?;
+                this.fill_random(buf, len as u64)?;
+                this.write_scalar(Scalar::from_bool(true), dest)?;
             },
 
+            // Windows handles
+            "GetCurrentProcess" => {
+                this.write_scalar(Scalar::from_int(CURRENT_PROCESS_HANDLE, dest.layout.size), dest)?;
+            }
+            "GetCurrentThread" => {
+                this.write_scalar(Scalar::from_int(CURRENT_THREAD_HANDLE, dest.layout.size), dest)?;
+            }
+            "CloseHandle" => {
+                let handle = this.read_scalar(args[0])?.to_isize(this)?;
+                this.machine.handles.close(handle)?;
+                // Return success (1)
+                this.write_scalar(Scalar::from_int(1, dest.layout.size), dest)?;
+            }
+            "DuplicateHandle" => {
+                // fn DuplicateHandle(hSourceProcessHandle: HANDLE, hSourceHandle: HANDLE,
+                //     hTargetProcessHandle: HANDLE, lpTargetHandle: LPHANDLE,
+                //     dwDesiredAccess: DWORD, bInheritHandle: BOOL, dwOptions: DWORD) -> BOOL
+                let source_process = this.read_scalar(args[0])?.to_isize(this)?;
+                let source = this.read_scalar(args[1])?.to_isize(this)?;
+                let target_process = this.read_scalar(args[2])?.to_isize(this)?;
+                let options = this.read_scalar(args[6])?.to_u32()?;
+                if source_process != CURRENT_PROCESS_HANDLE || target_process != CURRENT_PROCESS_HANDLE {
+                    return err!(Unimplemented(
+                        "DuplicateHandle only supports the current process".to_owned(),
+                    ));
+                }
+                let new_handle = this.machine.handles.duplicate(source)?;
+                if options & DUPLICATE_CLOSE_SOURCE != 0 {
+                    this.machine.handles.close(source)?;
+                }
+                let target = this.deref_operand(args[3])?;
+                this.write_scalar(Scalar::from_int(new_handle, target.layout.size), target.into())?;
+                // Return success (1)
+                this.write_scalar(Scalar::from_int(1, dest.layout.size), dest)?;
+            }
+
+            // Windows synchronization objects
+            "CreateEventW" | "CreateMutexW" => {
+                // fn CreateEventW(lpEventAttributes: LPSECURITY_ATTRIBUTES, bManualReset: BOOL,
+                //     bInitialState: BOOL, lpName: LPCWSTR) -> HANDLE
+                // fn CreateMutexW(lpMutexAttributes: LPSECURITY_ATTRIBUTES, bInitialOwner: BOOL,
+                //     lpName: LPCWSTR) -> HANDLE
+                let (object, name_arg) = if link_name == "CreateEventW" {
+                    let manual_reset = this.read_scalar(args[1])?.to_i32()? != 0;
+                    let signaled = this.read_scalar(args[2])?.to_i32()? != 0;
+                    (HandleObject::Event { manual_reset, signaled }, args[3])
+                } else {
+                    let initial_owner = this.read_scalar(args[1])?.to_i32()? != 0;
+                    (HandleObject::Mutex { lock_count: initial_owner as u32 }, args[2])
+                };
+                let name_ptr = this.read_scalar(name_arg)?.not_undef()?;
+                let handle = if name_ptr.is_null_ptr(this) {
+                    this.machine.tls.set_last_error(ERROR_SUCCESS);
+                    this.machine.handles.insert(object)
+                } else {
+                    let name = this.read_wide_str(name_ptr.to_ptr()?)?;
+                    match this.machine.handles.open_named(&name, &object) {
+                        Some(Some(handle)) => {
+                            // We did not create a new object.
+                            this.machine.tls.set_last_error(ERROR_ALREADY_EXISTS);
+                            handle
+                        }
+                        Some(None) => {
+                            // The name is used by an object of another kind.
+                            this.machine.tls.set_last_error(ERROR_INVALID_HANDLE);
+                            0
+                        }
+                        None => {
+                            this.machine.tls.set_last_error(ERROR_SUCCESS);
+                            this.machine.handles.insert_named(name, object)
+                        }
+                    }
+                };
+                this.write_scalar(Scalar::from_int(handle, dest.layout.size), dest)?;
+            }
+            "OpenEventW" | "OpenMutexW" => {
+                // fn OpenEventW(dwDesiredAccess: DWORD, bInheritHandle: BOOL, lpName: LPCWSTR) -> HANDLE
+                let name = this.read_wide_str(this.read_scalar(args[2])?.to_ptr()?)?;
+                let like = if link_name == "OpenEventW" {
+                    HandleObject::Event { manual_reset: false, signaled: false }
+                } else {
+                    HandleObject::Mutex { lock_count: 0 }
+                };
+                let handle = match this.machine.handles.open_named(&name, &like) {
+                    Some(Some(handle)) => handle,
+                    Some(None) => {
+                        this.machine.tls.set_last_error(ERROR_INVALID_HANDLE);
+                        0
+                    }
+                    None => {
+                        this.machine.tls.set_last_error(ERROR_FILE_NOT_FOUND);
+                        0
+                    }
+                };
+                this.write_scalar(Scalar::from_int(handle, dest.layout.size), dest)?;
+            }
+            "SetEvent" | "ResetEvent" => {
+                let handle = this.read_scalar(args[0])?.to_isize(this)?;
+                let object = this.machine.handles.get(handle)?;
+                let mut object = object.borrow_mut();
+                match *object {
+                    HandleObject::Event { ref mut signaled, .. } => {
+                        *signaled = link_name == "SetEvent";
+                    }
+                    _ => return err!(MachineError(
+                        format!("{} called on {:?}, which is not an event", link_name, *object),
+                    )),
+                }
+                // Return success (1)
+                this.write_scalar(Scalar::from_int(1, dest.layout.size), dest)?;
+            }
+            "ReleaseMutex" => {
+                let handle = this.read_scalar(args[0])?.to_isize(this)?;
+                let object = this.machine.handles.get(handle)?;
+                let mut object = object.borrow_mut();
+                let released = match *object {
+                    HandleObject::Mutex { ref mut lock_count } => {
+                        if *lock_count > 0 {
+                            *lock_count -= 1;
+                            true
+                        } else {
+                            false
+                        }
+                    }
+                    _ => return err!(MachineError(
+                        format!("ReleaseMutex called on {:?}, which is not a mutex", *object),
+                    )),
+                };
+                if released {
+                    this.write_scalar(Scalar::from_int(1, dest.layout.size), dest)?;
+                } else {
+                    this.machine.tls.set_last_error(ERROR_NOT_OWNER);
+                    this.write_null(dest)?;
+                }
+            }
+            "WaitForSingleObject" => {
+                let handle = this.read_scalar(args[0])?.to_isize(this)?;
+                let timeout = this.read_scalar(args[1])?.to_u32()?;
+                let object = this.machine.handles.get(handle)?;
+                let mut object = object.borrow_mut();
+                let result = if object.try_acquire() {
+                    WAIT_OBJECT_0
+                } else if timeout == INFINITE {
+                    // There is no other thread that could signal this object.
+                    return err!(MachineError(
+                        format!("deadlock: waiting forever for {:?}, which is not signaled", *object),
+                    ));
+                } else {
+                    // Nothing can change while we wait, so we just let the time pass.
+                    this.machine.clock.advance(Duration::from_millis(timeout as u64));
+                    WAIT_TIMEOUT
+                };
+                this.write_scalar(Scalar::from_uint(result, dest.layout.size), dest)?;
+            }
+            "WaitForMultipleObjects" => {
+                // fn WaitForMultipleObjects(nCount: DWORD, lpHandles: *const HANDLE,
+                //     bWaitAll: BOOL, dwMilliseconds: DWORD) -> DWORD
+                let count = this.read_scalar(args[0])?.to_u32()?;
+                let handles_ptr = this.read_scalar(args[1])?.not_undef()?;
+                let wait_all = this.read_scalar(args[2])?.to_i32()? != 0;
+                let timeout = this.read_scalar(args[3])?.to_u32()?;
+
+                // Check the count before we read the handles, it is what tells us how many
+                // there are.
+                let result = if count == 0 || count > MAXIMUM_WAIT_OBJECTS {
+                    this.machine.tls.set_last_error(ERROR_INVALID_PARAMETER);
+                    WAIT_FAILED
+                } else {
+                    let ptr_size = this.memory().pointer_size();
+                    let ptr_align = this.tcx.data_layout.pointer_align.abi;
+                    let handles_ptr = handles_ptr.to_ptr()?;
+                    this.memory().check_align(handles_ptr.into(), ptr_align)?;
+                    let mut objects = Vec::new();
+                    for i in 0..count as u64 {
+                        let ptr = handles_ptr.offset(ptr_size * i, this)?;
+                        let handle = this.memory().get(ptr.alloc_id)?
+                            .read_scalar(tcx, ptr, ptr_size)?
+                            .to_isize(this)?;
+                        objects.push((handle, this.machine.handles.get(handle)?));
+                    }
+                    let duplicates = objects.iter().enumerate().any(|(i, &(handle, _))| {
+                        objects[..i].iter().any(|&(other, _)| other == handle)
+                    });
+                    if duplicates {
+                        this.machine.tls.set_last_error(ERROR_INVALID_PARAMETER);
+                        WAIT_FAILED
+                    } else if wait_all {
+                        // Only acquire anything if we can acquire everything.
+                        if objects.iter().all(|(_, object)| object.borrow().is_signaled()) {
+                            for (_, object) in &objects {
+                                object.borrow_mut().acquire();
+                            }
+                            WAIT_OBJECT_0
+                        } else {
+                            WAIT_TIMEOUT
+                        }
+                    } else {
+                        // If several objects are signaled, the one with the lowest index wins.
+                        objects.iter()
+                            .position(|(_, object)| object.borrow_mut().try_acquire())
+                            .map(|idx| WAIT_OBJECT_0 + idx as u32)
+                            .unwrap_or(WAIT_TIMEOUT)
+                    }
+                };
+                if result == WAIT_TIMEOUT {
+                    if timeout == INFINITE {
+                        // There is no other thread that could signal these objects.
+                        return err!(MachineError(format!(
+                            "deadlock: waiting forever for {} objects, which are not signaled",
+                            count,
+                        )));
+                    }
+                    this.machine.clock.advance(Duration::from_millis(timeout as u64));
+                }
+                this.write_scalar(Scalar::from_uint(result, dest.layout.size), dest)?;
+            }
+
+            // Windows sleeping
+            "Sleep" | "SleepEx" => {
+                let millis = this.read_scalar(args[0])?.to_u32()?;
+                if millis == INFINITE {
+                    return err!(MachineError(
+                        "deadlock: sleeping forever, with no other thread to wake us up".to_owned(),
+                    ));
+                }
+                this.machine.clock.advance(Duration::from_millis(millis as u64));
+                if link_name == "SleepEx" {
+                    // There are no I/O completion callbacks or APCs that could interrupt us,
+                    // so we always return 0.
+                    this.write_null(dest)?;
+                }
+            }
+
+            // Windows console input and output
+            "GetStdHandle" => {
+                let which = this.read_scalar(args[0])?.to_i32()?;
+                // We just make this the identity function, so we know later in `WriteFile`
+                // and `WriteConsoleW` which one it is.
+                this.write_scalar(Scalar::from_int(which, dest.layout.size), dest)?;
+            }
+            "GetConsoleMode" => {
+                let handle = this.read_scalar(args[0])?.to_isize(this)?;
+                if handle == STD_OUTPUT_HANDLE || handle == STD_ERROR_HANDLE {
+                    // Claim to be a console, so that libstd uses `WriteConsoleW`.
+                    let mode = this.deref_operand(args[1])?;
+                    this.write_scalar(
+                        Scalar::from_uint(ENABLE_PROCESSED_OUTPUT, mode.layout.size),
+                        mode.into(),
+                    )?;
+                    this.write_scalar(Scalar::from_int(1, dest.layout.size), dest)?;
+                } else {
+                    // Make sure the handle is at least valid.
+                    this.machine.handles.get(handle)?;
+                    this.machine.tls.set_last_error(ERROR_INVALID_HANDLE);
+                    this.write_null(dest)?;
+                }
+            }
+            "WriteConsoleW" => {
+                // fn WriteConsoleW(hConsoleOutput: HANDLE, lpBuffer: LPCVOID,
+                //     nNumberOfCharsToWrite: DWORD, lpNumberOfCharsWritten: LPDWORD,
+                //     lpReserved: LPVOID) -> BOOL
+                let handle = this.read_scalar(args[0])?.to_isize(this)?;
+                let buf = this.read_scalar(args[1])?.not_undef()?;
+                let n = this.read_scalar(args[2])?.to_u32()? as u64;
+                trace!("Called WriteConsoleW({:?}, {:?}, {:?})", handle, buf, n);
+                let wchars: Vec<u16> = this.memory().read_bytes(buf, Size::from_bytes(n * 2))?
+                    .chunks(2)
+                    .map(|bytes| u16::from(bytes[0]) | (u16::from(bytes[1]) << 8))
+                    .collect();
+                let text = String::from_utf16_lossy(&wchars);
+                let res = if handle == STD_OUTPUT_HANDLE || handle == STD_ERROR_HANDLE {
+                    this.machine.output.write_all(handle == STD_ERROR_HANDLE, text.as_bytes())
+                } else {
+                    let object = this.machine.handles.get(handle)?;
+                    return err!(Unimplemented(
+                        format!("WriteConsoleW to {:?} is not supported", object.borrow()),
+                    ));
+                };
+                if res.is_ok() {
+                    let written = this.deref_operand(args[3])?;
+                    this.write_scalar(Scalar::from_uint(n, written.layout.size), written.into())?;
+                    this.write_scalar(Scalar::from_int(1, dest.layout.size), dest)?;
+                } else {
+                    this.machine.tls.set_last_error(ERROR_WRITE_FAULT);
+                    this.write_null(dest)?;
+                }
+            }
+            "ReadFile" => {
+                // fn ReadFile(hFile: HANDLE, lpBuffer: LPVOID, nNumberOfBytesToRead: DWORD,
+                //     lpNumberOfBytesRead: LPDWORD, lpOverlapped: LPOVERLAPPED) -> BOOL
+                let handle = this.read_scalar(args[0])?.to_isize(this)?;
+                let buf = this.read_scalar(args[1])?.not_undef()?;
+                let n = this.read_scalar(args[2])?.to_u32()?;
+                trace!("Called ReadFile({:?}, {:?}, {:?})", handle, buf, n);
+                if handle != STD_INPUT_HANDLE {
+                    let object = this.machine.handles.get(handle)?;
+                    return err!(Unimplemented(
+                        format!("ReadFile from {:?} is not supported", object.borrow()),
+                    ));
+                }
+                match this.replayed_read(0, |this| this.machine.stdin.read(n as usize))? {
+                    Ok(bytes) => {
+                        this.memory_mut().write_bytes(buf, &bytes)?;
+                        let read_ptr = this.read_scalar(args[3])?.not_undef()?;
+                        if !read_ptr.is_null_ptr(this) {
+                            let read_place = this.deref_operand(args[3])?;
+                            this.write_scalar(
+                                Scalar::from_uint(bytes.len() as u64, read_place.layout.size),
+                                read_place.into(),
+                            )?;
+                        }
+                        this.write_scalar(Scalar::from_int(1, dest.layout.size), dest)?;
+                    }
+                    Err(_) => {
+                        this.machine.tls.set_last_error(ERROR_READ_FAULT);
+                        this.write_null(dest)?;
+                    }
+                }
+            }
+            "WriteFile" => {
+                // fn WriteFile(hFile: HANDLE, lpBuffer: LPVOID, nNumberOfBytesToWrite: DWORD,
+                //     lpNumberOfBytesWritten: LPDWORD, lpOverlapped: LPOVERLAPPED) -> BOOL
+                let handle = this.read_scalar(args[0])?.to_isize(this)?;
+                let buf = this.read_scalar(args[1])?.not_undef()?;
+                let n = this.read_scalar(args[2])?.to_u32()?;
+                trace!("Called WriteFile({:?}, {:?}, {:?})", handle, buf, n);
+                let written = if handle == STD_OUTPUT_HANDLE || handle == STD_ERROR_HANDLE {
+                    let buf_cont = this.memory().read_bytes(buf, Size::from_bytes(n as u64))?.to_vec();
+                    this.machine.output.write(handle == STD_ERROR_HANDLE, &buf_cont).ok().map(|n| n as u32)
+                } else {
+                    // The handle has to be valid, but what is written to it goes nowhere.
+                    this.machine.handles.get(handle)?;
+                    warn!("Ignored output to handle {}", handle);
+                    Some(n) // pretend it all went well
+                };
+                match written {
+                    Some(written) => {
+                        let written_ptr = this.read_scalar(args[3])?.not_undef()?;
+                        if !written_ptr.is_null_ptr(this) {
+                            let written_place = this.deref_operand(args[3])?;
+                            this.write_scalar(
+                                Scalar::from_uint(written, written_place.layout.size),
+                                written_place.into(),
+                            )?;
+                        }
+                        this.write_scalar(Scalar::from_int(1, dest.layout.size), dest)?;
+                    }
+                    None => {
+                        this.machine.tls.set_last_error(ERROR_WRITE_FAULT);
+                        this.write_null(dest)?;
+                    }
+                }
+            }
+
+            // Windows environment variables
+            "GetEnvironmentVariableW" => {
+                // fn GetEnvironmentVariableW(lpName: LPCWSTR, lpBuffer: LPWSTR, nSize: DWORD) -> DWORD
+                let name = this.read_wide_str(this.read_scalar(args[0])?.to_ptr()?)?;
+                let buf = this.read_scalar(args[1])?.not_undef()?;
+                let buf_size = this.read_scalar(args[2])?.to_u32()? as u64;
+                let value = this.machine.wide_env_vars.iter()
+                    .find(|(var_name, _)| wide_eq_ignore_ascii_case(var_name, &name))
+                    .map(|(_, value)| value.clone());
+                let result = match value {
+                    None => {
+                        let op = format!("reading the environment variable `{}`", String::from_utf16_lossy(&name));
+                        this.reject_in_isolation(IsolationCategory::Env, op)?;
+                        this.machine.tls.set_last_error(ERROR_ENVVAR_NOT_FOUND);
+                        0
+                    }
+                    Some(value) => {
+                        let len = value.len() as u64;
+                        if len < buf_size {
+                            // The value fits, including the null terminator.
+                            this.write_wide_str(&value, buf.to_ptr()?)?;
+                            len
+                        } else {
+                            // Return the required buffer size, including the null terminator.
+                            len + 1
+                        }
+                    }
+                };
+                this.write_scalar(Scalar::from_uint(result, dest.layout.size), dest)?;
+            }
+            "SetEnvironmentVariableW" => {
+                // fn SetEnvironmentVariableW(lpName: LPCWSTR, lpValue: LPCWSTR) -> BOOL
+                let name_ptr = this.read_scalar(args[0])?.not_undef()?;
+                let value_ptr = this.read_scalar(args[1])?.not_undef()?;
+                let name = if name_ptr.is_null_ptr(this) {
+                    Vec::new()
+                } else {
+                    this.read_wide_str(name_ptr.to_ptr()?)?
+                };
+                if name.is_empty() || name.contains(&(b'=' as u16)) {
+                    this.machine.tls.set_last_error(ERROR_INVALID_PARAMETER);
+                    this.write_null(dest)?;
+                } else {
+                    // Remove the old value, matching the name case-insensitively.
+                    this.machine.wide_env_vars
+                        .retain(|var_name, _| !wide_eq_ignore_ascii_case(var_name, &name));
+                    // A null value means "delete this variable".
+                    if !value_ptr.is_null_ptr(this) {
+                        let value = this.read_wide_str(value_ptr.to_ptr()?)?;
+                        this.machine.wide_env_vars.insert(name, value);
+                    }
+                    this.write_scalar(Scalar::from_int(1, dest.layout.size), dest)?;
+                }
+            }
+            "GetEnvironmentStringsW" => {
+                // The result is a block of `name=value` strings, each null-terminated,
+                // and terminated by an extra null.  An empty block still has two nulls.
+                let mut block: Vec<u16> = Vec::new();
+                for (name, value) in this.machine.wide_env_vars.iter() {
+                    block.extend(name);
+                    block.push(b'=' as u16);
+                    block.extend(value);
+                    block.push(0);
+                }
+                if block.is_empty() {
+                    block.push(0);
+                }
+                // `write_wide_str` adds the final terminator.
+                let block_ptr = this.memory_mut().allocate(
+                    Size::from_bytes((block.len() as u64 + 1) * 2),
+                    Align::from_bytes(2).unwrap(),
+                    MiriMemoryKind::Env.into(),
+                )?.with_default_tag();
+                this.write_wide_str(&block, block_ptr)?;
+                this.write_scalar(Scalar::Ptr(block_ptr), dest)?;
+            }
+            "FreeEnvironmentStringsW" => {
+                let block_ptr = this.read_scalar(args[0])?.to_ptr()?;
+                this.memory_mut().deallocate(block_ptr, None, MiriMemoryKind::Env.into())?;
+                // Return success (1)
+                this.write_scalar(Scalar::from_int(1, dest.layout.size), dest)?;
+            }
+            "GetCommandLineW" => {
+                let command_line: Vec<u16> = windows_command_line(&this.machine.argv).encode_utf16().collect();
+                let command_line_ptr = this.memory_mut().allocate(
+                    Size::from_bytes((command_line.len() as u64 + 1) * 2),
+                    Align::from_bytes(2).unwrap(),
+                    MiriMemoryKind::Env.into(),
+                )?.with_default_tag();
+                this.write_wide_str(&command_line, command_line_ptr)?;
+                this.write_scalar(Scalar::Ptr(command_line_ptr), dest)?;
+            }
+
             // Windows TLS
             "TlsAlloc" => {
                 // This just creates a key; Windows does not natively support TLS dtors.
@@ -588,6 +1909,8 @@ pub trait EvalContextExt<'a, 'mir, 'tcx: 'a+'mir>: crate::MiriEvalContextExt<'a,
             "TlsGetValue" => {
                 let key = this.read_scalar(args[0])?.to_bits(args[0].layout.size)?;
                 let ptr = this.machine.tls.load_tls(key)?;
+                // A null result is ambiguous, so the last error is cleared on success.
+                this.machine.tls.set_last_error(ERROR_SUCCESS);
                 this.write_scalar(ptr, dest)?;
             }
             "TlsSetValue" => {
@@ -599,6 +1922,68 @@ pub trait EvalContextExt<'a, 'mir, 'tcx: 'a+'mir>: crate::MiriEvalContextExt<'a,
                 this.write_scalar(Scalar::from_int(1, dest.layout.size), dest)?;
             }
 
+            // Windows fiber-local storage.  We only have one fiber per thread, so this
+            // behaves like TLS, except that it supports dtors.
+            "FlsAlloc" => {
+                let dtor = match this.read_scalar(args[0])?.not_undef()? {
+                    Scalar::Ptr(dtor_ptr) => Some(this.memory().get_fn(dtor_ptr)?),
+                    Scalar::Bits { bits: 0, size } => {
+                        assert_eq!(size as u64, this.memory().pointer_size().bytes());
+                        None
+                    },
+                    Scalar::Bits { .. } => return err!(ReadBytesAsPointer),
+                };
+
+                // Create key and return it
+                let key = this.machine.tls.create_tls_key(dtor, tcx) as u128;
+
+                // Figure out how large a FLS key actually is. This is c::DWORD.
+                if dest.layout.size.bits() < 128 && key >= (1u128 << dest.layout.size.bits() as u128) {
+                    return err!(OutOfTls);
+                }
+                this.write_scalar(Scalar::from_uint(key, dest.layout.size), dest)?;
+            }
+            "FlsGetValue" => {
+                let key = this.read_scalar(args[0])?.to_bits(args[0].layout.size)?;
+                let ptr = this.machine.tls.load_tls(key)?;
+                // A null result is ambiguous, so the last error is cleared on success.
+                this.machine.tls.set_last_error(ERROR_SUCCESS);
+                this.write_scalar(ptr, dest)?;
+            }
+            "FlsSetValue" => {
+                let key = this.read_scalar(args[0])?.to_bits(args[0].layout.size)?;
+                let new_ptr = this.read_scalar(args[1])?.not_undef()?;
+                this.machine.tls.store_tls(key, new_ptr)?;
+
+                // Return success (1)
+                this.write_scalar(Scalar::from_int(1, dest.layout.size), dest)?;
+            }
+            "FlsFree" => {
+                let key = this.read_scalar(args[0])?.to_bits(args[0].layout.size)?;
+                this.machine.tls.delete_tls_key(key)?;
+
+                // Return success (1)
+                this.write_scalar(Scalar::from_int(1, dest.layout.size), dest)?;
+            }
+
+            // Miri's own functions
+            "miri_breakpoint" => {
+                this.debugger_break()?;
+            }
+            "miri_watch" => {
+                let ptr = this.read_scalar(args[0])?.to_ptr()?;
+                let len = this.read_scalar(args[1])?.to_usize(this)?;
+                this.watch_memory(ptr, len);
+            }
+
+            // LLVM intrinsics used by `core::arch`
+            name if name.starts_with("llvm.x86.") => {
+                this.call_x86_intrinsic(&name["llvm.x86.".len()..], args, dest)?;
+            }
+            name if name.starts_with("llvm.aarch64.") => {
+                this.call_aarch64_intrinsic(&name["llvm.aarch64.".len()..], args, dest)?;
+            }
+
             // We can't execute anything else
             _ => {
                 return err!(Unimplemented(
@@ -616,3 +2001,151 @@ pub trait EvalContextExt<'a, 'mir, 'tcx: 'a+'mir>: crate::MiriEvalContextExt<'a,
         self.eval_context_mut().write_scalar(Scalar::from_int(0, dest.layout.size), dest)
     }
 }
+
+/// The ID of the only thread, as far as functions that need one are concerned.
+const MAIN_THREAD_ID: u32 = 1;
+
+/// The machine we pretend to run on, as reported by `sysconf` and `sysctl`.
+const PAGE_SIZE: i32 = 4096;
+const NUM_CPUS: i32 = 1;
+
+// Windows error codes
+const ERROR_SUCCESS: u32 = 0;
+const ERROR_FILE_NOT_FOUND: u32 = 2;
+const ERROR_INVALID_HANDLE: u32 = 6;
+const ERROR_WRITE_FAULT: u32 = 29;
+const ERROR_READ_FAULT: u32 = 30;
+const ERROR_INVALID_PARAMETER: u32 = 87;
+const ERROR_MOD_NOT_FOUND: u32 = 126;
+const ERROR_PROC_NOT_FOUND: u32 = 127;
+const ERROR_ALREADY_EXISTS: u32 = 183;
+const ERROR_ENVVAR_NOT_FOUND: u32 = 203;
+const ERROR_NOT_OWNER: u32 = 288;
+
+// Windows wait results and timeouts
+const WAIT_OBJECT_0: u32 = 0;
+const WAIT_TIMEOUT: u32 = 0x102;
+const WAIT_FAILED: u32 = 0xFFFFFFFF;
+const INFINITE: u32 = 0xFFFFFFFF;
+const MAXIMUM_WAIT_OBJECTS: u32 = 64;
+
+// Windows `DuplicateHandle` options
+const DUPLICATE_CLOSE_SOURCE: u32 = 0x1;
+
+// Windows console modes
+const ENABLE_PROCESSED_OUTPUT: u32 = 0x1;
+
+// `getauxval` entry types
+const AT_PAGESZ: u64 = 6;
+
+// FreeBSD `_umtx_op` operations and flags
+const UMTX_OP_WAIT: i32 = 2;
+const UMTX_OP_WAKE: i32 = 3;
+const UMTX_OP_WAIT_UINT: i32 = 11;
+const UMTX_OP_WAIT_UINT_PRIVATE: i32 = 15;
+const UMTX_OP_WAKE_PRIVATE: i32 = 16;
+const UMTX_ABSTIME: u32 = 0x1;
+
+// libdispatch time values
+const DISPATCH_TIME_NOW: u64 = 0;
+const DISPATCH_TIME_FOREVER: u64 = !0;
+
+// `kevent` flags.  These are the same on macOS and the BSDs.
+const EV_ADD: u16 = 0x1;
+const EV_DELETE: u16 = 0x2;
+const EV_ENABLE: u16 = 0x4;
+const EV_DISABLE: u16 = 0x8;
+const EV_ONESHOT: u16 = 0x10;
+const EV_CLEAR: u16 = 0x20;
+const EV_RECEIPT: u16 = 0x40;
+const EV_ERROR: u16 = 0x4000;
+const NOTE_TRIGGER: u32 = 0x0100_0000;
+
+#[derive(Copy, Clone, Debug)]
+enum SysctlValue {
+    Int(i32),
+    Str(&'static str),
+}
+
+/// Map a numeric sysctl MIB to its name.  These numbers are the same on macOS and the BSDs.
+fn sysctl_mib_name(mib: &[i32]) -> Option<&'static str> {
+    // CTL_KERN = 1, CTL_HW = 6
+    Some(match mib {
+        [1, 1] => "kern.ostype",
+        [1, 2] => "kern.osrelease",
+        [6, 3] => "hw.ncpu",
+        [6, 7] => "hw.pagesize",
+        _ => return None,
+    })
+}
+
+/// The sysctl values we report.  They describe the machine Miri emulates, not the host.
+fn sysctl_value(name: &str, target_os: &str) -> Option<SysctlValue> {
+    Some(match name {
+        "kern.ostype" => SysctlValue::Str(match target_os {
+            "macos" | "ios" => "Darwin",
+            "freebsd" => "FreeBSD",
+            "netbsd" => "NetBSD",
+            "openbsd" => "OpenBSD",
+            _ => return None,
+        }),
+        "kern.osrelease" => SysctlValue::Str(match target_os {
+            "macos" | "ios" => "18.0.0",
+            "freebsd" => "12.0-RELEASE",
+            "netbsd" => "8.0",
+            "openbsd" => "6.4",
+            _ => return None,
+        }),
+        // We only ever run one thread at a time.
+        "hw.ncpu" | "hw.activecpu" | "hw.logicalcpu" | "hw.physicalcpu" => SysctlValue::Int(NUM_CPUS),
+        "hw.pagesize" => SysctlValue::Int(PAGE_SIZE),
+        _ => return None,
+    })
+}
+
+/// Join the arguments into a command line, quoted such that Windows programs split it into
+/// the same arguments again.  Arguments that are not UTF-8 are converted lossily.
+fn windows_command_line(argv: &[Vec<u8>]) -> String {
+    let mut command_line = String::new();
+    for arg in argv {
+        if !command_line.is_empty() {
+            command_line.push(' ');
+        }
+        let arg = String::from_utf8_lossy(arg);
+        if !arg.is_empty() && !arg.contains(|c| c == ' ' || c == '\t' || c == '"') {
+            command_line.push_str(&arg);
+            continue;
+        }
+        // Backslashes are only special in front of a quote, where they have to be doubled.
+        command_line.push('"');
+        let mut backslashes = 0;
+        for c in arg.chars() {
+            match c {
+                '\\' => backslashes += 1,
+                '"' => {
+                    command_line.extend((0..backslashes * 2 + 1).map(|_| '\\'));
+                    backslashes = 0;
+                }
+                _ => {
+                    command_line.extend((0..backslashes).map(|_| '\\'));
+                    backslashes = 0;
+                }
+            }
+            if c != '\\' {
+                command_line.push(c);
+            }
+        }
+        command_line.extend((0..backslashes * 2).map(|_| '\\'));
+        command_line.push('"');
+    }
+    command_line
+}
+
+/// Windows environment variable names are case-insensitive.
+fn wide_eq_ignore_ascii_case(left: &[u16], right: &[u16]) -> bool {
+    fn to_lower(c: u16) -> u16 {
+        if c >= b'A' as u16 && c <= b'Z' as u16 { c + (b'a' - b'A') as u16 } else { c }
+    }
+    left.len() == right.len() &&
+        left.iter().zip(right).all(|(&l, &r)| to_lower(l) == to_lower(r))
+}
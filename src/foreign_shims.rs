@@ -0,0 +1,87 @@
+//! Foreign functions that embedders emulate themselves, for projects with FFI layers of their
+//! own that miri knows nothing about.  The shims in `MiriConfig::foreign_shims` take
+//! precedence over miri's own, by link name.  `-Zmiri-shim-plugin` makes the driver load
+//! them from a dynamic library; those are `PluginShim`s, which only deal in integers.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+
+use crate::*;
+
+/// The driver moves the configuration to the compiler's thread, so shims have to be `Send`,
+/// and `Sync` to be shared by the tests.
+pub trait ForeignShim: Send + Sync {
+    /// Emulate a call with `args`, writing the return value to `dest`.
+    fn call<'a, 'mir, 'tcx: 'a + 'mir>(
+        &self,
+        ecx: &mut MiriEvalContext<'a, 'mir, 'tcx>,
+        args: &[OpTy<'tcx, Borrow>],
+        dest: PlaceTy<'tcx, Borrow>,
+    ) -> EvalResult<'tcx>;
+}
+
+#[derive(Clone, Default)]
+pub struct ForeignShims(HashMap<String, Arc<dyn ForeignShim>>);
+
+impl ForeignShims {
+    /// Emulate the foreign function called `link_name` with `shim`.
+    pub fn add(&mut self, link_name: String, shim: Arc<dyn ForeignShim>) {
+        self.0.insert(link_name, shim);
+    }
+
+    pub(crate) fn get(&self, link_name: &str) -> Option<Arc<dyn ForeignShim>> {
+        self.0.get(link_name).cloned()
+    }
+}
+
+impl fmt::Debug for ForeignShims {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut names: Vec<&str> = self.0.keys().map(|name| &**name).collect();
+        names.sort();
+        write!(f, "ForeignShims({:?})", names)
+    }
+}
+
+/// A function of a shim plugin.  It gets the arguments as integers, and returns the
+/// result as one, which we truncate to the return type.
+pub type PluginFn = extern "C" fn(args: *const u64, arg_count: usize) -> u64;
+
+/// A shim implemented by a function of a plugin.  Pointers cannot be passed to it, the
+/// plugin has no access to the memory of the program.
+pub struct PluginShim {
+    link_name: String,
+    function: PluginFn,
+}
+
+impl PluginShim {
+    pub fn new(link_name: String, function: PluginFn) -> Self {
+        PluginShim { link_name, function }
+    }
+}
+
+impl ForeignShim for PluginShim {
+    fn call<'a, 'mir, 'tcx: 'a + 'mir>(
+        &self,
+        ecx: &mut MiriEvalContext<'a, 'mir, 'tcx>,
+        args: &[OpTy<'tcx, Borrow>],
+        dest: PlaceTy<'tcx, Borrow>,
+    ) -> EvalResult<'tcx> {
+        let mut values = Vec::with_capacity(args.len());
+        for &arg in args {
+            let value = match ecx.read_scalar(arg)?.not_undef()? {
+                Scalar::Bits { bits, .. } => bits as u64,
+                Scalar::Ptr(_) => return err!(Unimplemented(format!(
+                    "the plugin shim for `{}` cannot be passed pointers", self.link_name,
+                ))),
+            };
+            values.push(value);
+        }
+        let result = (self.function)(values.as_ptr(), values.len());
+        if !dest.layout.is_zst() {
+            let result = ecx.truncate(u128::from(result), dest.layout);
+            ecx.write_scalar(Scalar::from_uint(result, dest.layout.size), dest)?;
+        }
+        Ok(())
+    }
+}
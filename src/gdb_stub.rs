@@ -0,0 +1,277 @@
+//! The server side of gdb's remote protocol, for `-Zmiri-gdb=<port>`.  gdb (or lldb) connects
+//! with `target remote :<port>` and controls the interpreted program: `continue`, `stepi`
+//! (which goes to the next line of local code), Ctrl-C, and reading memory.  Everything
+//! else goes through `monitor` commands, see `debugger::execute`, since the debugger has no
+//! debug info for a program that was never compiled to machine code.
+//!
+//! We pretend to be an x86-64 machine whose registers are all zero, except for the program
+//! counter, which counts the steps so that the debugger sees every stop as a new location.
+//! The memory of `allocN` is at address `N << 32`.
+
+use std::cmp;
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream};
+
+use crate::*;
+use crate::debugger::{Breakpoints, Resume, Stop, describe_stop, execute};
+
+const TARGET_XML: &str = "<?xml version=\"1.0\"?>\
+    <!DOCTYPE target SYSTEM \"gdb-target.dtd\">\
+    <target version=\"1.0\"><architecture>i386:x86-64</architecture></target>";
+
+pub(crate) struct GdbStub {
+    stream: TcpStream,
+    /// Whether the debugger turned off acknowledgments with `QStartNoAckMode`
+    no_ack: bool,
+}
+
+impl GdbStub {
+    /// Wait for a debugger to connect to `listener`.
+    pub(crate) fn accept(listener: &TcpListener) -> io::Result<Self> {
+        let (stream, _) = listener.accept()?;
+        stream.set_nodelay(true)?;
+        Ok(GdbStub { stream, no_ack: false })
+    }
+
+    fn read_byte(&mut self) -> io::Result<u8> {
+        let mut byte = [0];
+        self.stream.read_exact(&mut byte)?;
+        Ok(byte[0])
+    }
+
+    /// The next packet, or `None` for an interrupt.
+    fn receive(&mut self) -> io::Result<Option<String>> {
+        loop {
+            match self.read_byte()? {
+                b'$' => {}
+                0x03 => return Ok(None),
+                _ => continue, // acknowledgments, and noise between packets
+            }
+            let mut data = Vec::new();
+            let mut sum = 0u8;
+            loop {
+                match self.read_byte()? {
+                    b'#' => break,
+                    byte => {
+                        sum = sum.wrapping_add(byte);
+                        data.push(byte);
+                    }
+                }
+            }
+            let checksum = [self.read_byte()?, self.read_byte()?];
+            let checksum = ::std::str::from_utf8(&checksum).ok()
+                .and_then(|checksum| u8::from_str_radix(checksum, 16).ok());
+            if self.no_ack {
+                return Ok(Some(String::from_utf8_lossy(&data).into_owned()));
+            }
+            if checksum == Some(sum) {
+                self.stream.write_all(b"+")?;
+                return Ok(Some(String::from_utf8_lossy(&data).into_owned()));
+            }
+            self.stream.write_all(b"-")?;
+        }
+    }
+
+    fn send(&mut self, data: &str) -> io::Result<()> {
+        let mut packet = String::with_capacity(data.len() + 4);
+        for c in data.chars() {
+            // These characters have to be escaped, as `}` followed by the character xor 0x20.
+            match c {
+                '$' | '#' | '}' | '*' => {
+                    packet.push('}');
+                    packet.push((c as u8 ^ 0x20) as char);
+                }
+                c => packet.push(c),
+            }
+        }
+        let sum = packet.bytes().fold(0u8, |sum, byte| sum.wrapping_add(byte));
+        let packet = format!("${}#{:02x}", packet, sum);
+        loop {
+            self.stream.write_all(packet.as_bytes())?;
+            if self.no_ack {
+                return Ok(());
+            }
+            loop {
+                match self.read_byte()? {
+                    b'+' => return Ok(()),
+                    b'-' => break,
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    /// Print `text` on the console of the debugger.
+    fn send_output(&mut self, text: &str) -> io::Result<()> {
+        self.send(&format!("O{}", hex(text.as_bytes())))
+    }
+
+    /// Whether the user pressed Ctrl-C in the debugger since we last looked.
+    pub(crate) fn interrupted(&mut self) -> bool {
+        let mut byte = [0];
+        if self.stream.set_nonblocking(true).is_err() {
+            return false;
+        }
+        let res = self.stream.read(&mut byte);
+        let _ = self.stream.set_nonblocking(false);
+        match res {
+            Ok(1) => byte[0] == 0x03,
+            _ => false,
+        }
+    }
+
+    /// Tell the debugger that the program exited normally.
+    pub(crate) fn exited(&mut self) -> io::Result<()> {
+        self.send("W00")
+    }
+
+    /// Tell the debugger that the program was ended by an error.
+    pub(crate) fn terminated(&mut self) -> io::Result<()> {
+        self.send("X0b")
+    }
+
+    /// The program stopped, at step `steps`.  Tell the debugger why, unless it has yet to ask,
+    /// and serve its requests until it resumes the program.
+    pub(crate) fn serve<'a, 'mir, 'tcx>(
+        &mut self,
+        ecx: &MiriEvalContext<'a, 'mir, 'tcx>,
+        breakpoints: &mut Breakpoints,
+        stop: &Stop,
+        steps: u64,
+    ) -> io::Result<Resume> {
+        // SIGTRAP, SIGINT or SIGSEGV
+        let stop_reply = match *stop {
+            Stop::Interrupt => "T02thread:1;",
+            Stop::Error(_) => "T0bthread:1;",
+            _ => "T05thread:1;",
+        };
+        if *stop != Stop::Start {
+            self.send_output(&format!("{}\n", describe_stop(ecx, stop)))?;
+            self.send(stop_reply)?;
+        }
+        loop {
+            let packet = match self.receive()? {
+                Some(packet) => packet,
+                // We are stopped already.
+                None => continue,
+            };
+            let reply = match &*packet {
+                "?" => stop_reply.to_owned(),
+                "QStartNoAckMode" => {
+                    self.send("OK")?;
+                    self.no_ack = true;
+                    continue;
+                }
+                "qAttached" => "1".to_owned(),
+                "qC" => "QC1".to_owned(),
+                "qfThreadInfo" => "m1".to_owned(),
+                "qsThreadInfo" => "l".to_owned(),
+                "qSymbol::" => "OK".to_owned(),
+                "g" => {
+                    // 16 general purpose registers, the program counter and the flags
+                    let mut registers = hex(&[0; 16 * 8]);
+                    let pc: Vec<u8> = (0..8).map(|i| (steps >> (i * 8)) as u8).collect();
+                    registers.push_str(&hex(&pc));
+                    registers.push_str(&hex(&[0; 4]));
+                    registers
+                }
+                "k" => return Ok(Resume::Kill),
+                "D" => {
+                    self.send("OK")?;
+                    return Ok(Resume::Detach);
+                }
+                packet if packet.starts_with("qSupported") =>
+                    "PacketSize=4000;QStartNoAckMode+;qXfer:features:read+".to_owned(),
+                packet if packet.starts_with("qXfer:features:read:target.xml:") =>
+                    read_chunk(TARGET_XML, &packet["qXfer:features:read:target.xml:".len()..]),
+                packet if packet.starts_with('H') || packet.starts_with('T') => "OK".to_owned(),
+                packet if packet.starts_with('m') => match parse_range(&packet[1..]) {
+                    Some((addr, len)) => match read_memory(ecx, addr, len) {
+                        Some(bytes) => hex(&bytes),
+                        None => "E01".to_owned(),
+                    },
+                    None => "E01".to_owned(),
+                },
+                packet if packet.starts_with('M') || packet.starts_with('X') => "E01".to_owned(),
+                packet if packet.starts_with('c') || packet.starts_with('C') => return Ok(Resume::Continue),
+                packet if packet.starts_with('s') || packet.starts_with('S') => return Ok(Resume::Step),
+                packet if packet.starts_with("qRcmd,") => {
+                    let command = unhex(&packet["qRcmd,".len()..]).unwrap_or_default();
+                    let output = execute(ecx, breakpoints, &String::from_utf8_lossy(&command));
+                    if !output.is_empty() {
+                        self.send_output(&format!("{}\n", output))?;
+                    }
+                    "OK".to_owned()
+                }
+                // Everything else is not supported.
+                _ => String::new(),
+            };
+            self.send(&reply)?;
+        }
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn unhex(text: &str) -> Option<Vec<u8>> {
+    if text.len() % 2 != 0 {
+        return None;
+    }
+    (0..text.len()).step_by(2).map(|i| u8::from_str_radix(text.get(i..i + 2)?, 16).ok()).collect()
+}
+
+/// Parse `<addr>,<length>`, in hex.
+fn parse_range(text: &str) -> Option<(u64, u64)> {
+    let mut parts = text.splitn(2, ',');
+    let start = u64::from_str_radix(parts.next()?, 16).ok()?;
+    let len = u64::from_str_radix(parts.next()?, 16).ok()?;
+    Some((start, len))
+}
+
+/// The part of `document` that `qXfer` asks for with `<offset>,<length>`
+fn read_chunk(document: &str, range: &str) -> String {
+    let (offset, len) = match parse_range(range) {
+        Some(range) => range,
+        None => return "E01".to_owned(),
+    };
+    let start = cmp::min(offset as usize, document.len());
+    let end = cmp::min(start.saturating_add(len as usize), document.len());
+    let more = if end < document.len() { 'm' } else { 'l' };
+    format!("{}{}", more, &document[start..end])
+}
+
+/// The bytes at `addr`, in the allocation whose id is in the upper half of the address.
+/// The debugger may ask for bytes past the end, we give it those that exist.
+fn read_memory<'a, 'mir, 'tcx>(ecx: &MiriEvalContext<'a, 'mir, 'tcx>, addr: u64, len: u64) -> Option<Vec<u8>> {
+    let alloc = ecx.memory().get(AllocId(addr >> 32)).ok()?;
+    let offset = addr & 0xffff_ffff;
+    let size = alloc.bytes.len() as u64;
+    if offset >= size {
+        return None;
+    }
+    let end = cmp::min(offset.saturating_add(len), size);
+    Some(alloc.bytes[offset as usize..end as usize].to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hex_roundtrips() {
+        assert_eq!(hex(b"bt\n"), "62740a");
+        assert_eq!(unhex("62740a"), Some(b"bt\n".to_vec()));
+        assert_eq!(unhex("6"), None);
+        assert_eq!(unhex("zz"), None);
+    }
+
+    #[test]
+    fn chunks() {
+        assert_eq!(read_chunk("abcdef", "0,4"), "mabcd");
+        assert_eq!(read_chunk("abcdef", "4,4"), "lef");
+        assert_eq!(read_chunk("abcdef", "9,4"), "l");
+        assert_eq!(parse_range("500000000,10"), Some((0x5_0000_0000, 0x10)));
+    }
+}
@@ -0,0 +1,202 @@
+//! Emulation of Windows kernel object handles.
+//! Handles are opaque, pointer-sized values referring to kernel objects.  Several handles
+//! can refer to the same object (see `DuplicateHandle`); the object lives as long as
+//! any handle to it is open.
+
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::mem;
+use std::rc::{Rc, Weak};
+
+use crate::{EvalResult, EvalErrorKind};
+
+pub type Handle = i64;
+
+/// Pseudo-handle returned by `GetCurrentProcess`.  It does not need to be closed.
+pub const CURRENT_PROCESS_HANDLE: Handle = -1;
+/// Pseudo-handle returned by `GetCurrentThread`.  It does not need to be closed.
+pub const CURRENT_THREAD_HANDLE: Handle = -2;
+/// The standard streams, as returned by `GetStdHandle`.  We treat them like pseudo-handles.
+pub const STD_INPUT_HANDLE: Handle = -10;
+pub const STD_OUTPUT_HANDLE: Handle = -11;
+pub const STD_ERROR_HANDLE: Handle = -12;
+
+/// A kernel object a handle can refer to.
+#[derive(Debug)]
+pub enum HandleObject {
+    /// The process.  We only ever have the current one.
+    Process,
+    /// A thread.  We only ever have the current one.
+    Thread,
+    /// One of the standard streams, identified by its `GetStdHandle` id.
+    StdStream(Handle),
+    /// An event, see `CreateEventW`.
+    Event {
+        /// Whether the event stays signaled after a wait succeeded.
+        manual_reset: bool,
+        signaled: bool,
+    },
+    /// A mutex, see `CreateMutexW`.  Mutexes are recursive, so we count how often it is
+    /// locked.  There is only one thread, so if it is locked, that thread is the owner.
+    /// The owner cannot exit while the program is still running, so a mutex can never
+    /// be abandoned.
+    Mutex {
+        lock_count: u32,
+    },
+}
+
+impl HandleObject {
+    /// Whether a wait on this object would succeed right now.
+    pub fn is_signaled(&self) -> bool {
+        match *self {
+            HandleObject::Event { signaled, .. } => signaled,
+            // Either it is unlocked, or we already own it.
+            HandleObject::Mutex { .. } => true,
+            // Threads and processes are signaled when they exit, so waiting for the current
+            // one never succeeds.
+            HandleObject::Process | HandleObject::Thread => false,
+            HandleObject::StdStream(_) => false,
+        }
+    }
+
+    /// Perform the side-effects of a successful wait: Reset auto-reset events,
+    /// and acquire mutexes.  The object must be signaled.
+    pub fn acquire(&mut self) {
+        assert!(self.is_signaled(), "acquiring an object that is not signaled");
+        match *self {
+            HandleObject::Event { manual_reset, ref mut signaled } => {
+                if !manual_reset {
+                    *signaled = false;
+                }
+            }
+            HandleObject::Mutex { ref mut lock_count } => {
+                *lock_count += 1;
+            }
+            _ => {}
+        }
+    }
+
+    /// Try to satisfy a wait on this object.  Returns whether the object was signaled.
+    pub fn try_acquire(&mut self) -> bool {
+        if self.is_signaled() {
+            self.acquire();
+            true
+        } else {
+            false
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct HandleTable {
+    /// The next handle value to hand out.  Like Windows, we use multiples of 4.
+    /// Handle values are never reused, so any value below this one that is not in `handles`
+    /// is a closed handle.
+    next_handle: Handle,
+
+    /// The open handles, and the objects they refer to.
+    handles: BTreeMap<Handle, Rc<RefCell<HandleObject>>>,
+
+    /// Named objects.  A named object is destroyed when its last handle is closed,
+    /// so we do not keep it alive here.
+    names: HashMap<Vec<u16>, Weak<RefCell<HandleObject>>>,
+}
+
+impl Default for HandleTable {
+    fn default() -> Self {
+        HandleTable {
+            next_handle: 4, // 0 is `NULL`, which is never a valid handle
+            handles: BTreeMap::new(),
+            names: HashMap::new(),
+        }
+    }
+}
+
+impl HandleTable {
+    /// Create a new handle referring to a new object.
+    pub fn insert(&mut self, object: HandleObject) -> Handle {
+        self.insert_shared(Rc::new(RefCell::new(object)))
+    }
+
+    /// Create a new handle referring to a new object with the given name.
+    /// The name must not be in use yet, see `open_named`.
+    pub fn insert_named(&mut self, name: Vec<u16>, object: HandleObject) -> Handle {
+        let object = Rc::new(RefCell::new(object));
+        self.names.insert(name, Rc::downgrade(&object));
+        self.insert_shared(object)
+    }
+
+    /// Create a new handle referring to the existing object with the given name.
+    /// `like` is an object of the kind the caller expects; if the existing object is of
+    /// another kind, this returns `Some(None)`.
+    pub fn open_named(&mut self, name: &[u16], like: &HandleObject) -> Option<Option<Handle>> {
+        let object = self.names.get(name)?.upgrade()?;
+        if mem::discriminant(&*object.borrow()) != mem::discriminant(like) {
+            return Some(None);
+        }
+        Some(Some(self.insert_shared(object)))
+    }
+
+    fn insert_shared(&mut self, object: Rc<RefCell<HandleObject>>) -> Handle {
+        let handle = self.next_handle;
+        self.next_handle += 4;
+        trace!("New handle {} for {:?}", handle, object);
+        self.handles.insert(handle, object);
+        handle
+    }
+
+    /// Whether this is one of the pseudo-handles that do not have to be (and cannot be) closed.
+    pub fn is_pseudo(handle: Handle) -> bool {
+        match handle {
+            CURRENT_PROCESS_HANDLE | CURRENT_THREAD_HANDLE |
+            STD_INPUT_HANDLE | STD_OUTPUT_HANDLE | STD_ERROR_HANDLE => true,
+            _ => false,
+        }
+    }
+
+    /// Get the object referred to by `handle`.  Fails if the handle is not open.
+    pub fn get<'tcx>(&self, handle: Handle) -> EvalResult<'tcx, Rc<RefCell<HandleObject>>> {
+        match handle {
+            CURRENT_PROCESS_HANDLE => return Ok(Rc::new(RefCell::new(HandleObject::Process))),
+            CURRENT_THREAD_HANDLE => return Ok(Rc::new(RefCell::new(HandleObject::Thread))),
+            STD_INPUT_HANDLE | STD_OUTPUT_HANDLE | STD_ERROR_HANDLE =>
+                return Ok(Rc::new(RefCell::new(HandleObject::StdStream(handle)))),
+            _ => {}
+        }
+        match self.handles.get(&handle) {
+            Some(object) => Ok(Rc::clone(object)),
+            None => Err(self.invalid_handle(handle)),
+        }
+    }
+
+    /// Close `handle`.  The object is dropped when its last handle is closed.
+    pub fn close<'tcx>(&mut self, handle: Handle) -> EvalResult<'tcx> {
+        if Self::is_pseudo(handle) {
+            // Closing a pseudo-handle is allowed, and has no effect.
+            return Ok(());
+        }
+        match self.handles.remove(&handle) {
+            Some(object) => {
+                trace!("Handle {} for {:?} closed", handle, object);
+                Ok(())
+            }
+            None => Err(self.invalid_handle(handle)),
+        }
+    }
+
+    /// Create a new handle to the object `handle` refers to.
+    /// Duplicating a pseudo-handle creates a real handle to its object.
+    pub fn duplicate<'tcx>(&mut self, handle: Handle) -> EvalResult<'tcx, Handle> {
+        let object = self.get(handle)?;
+        Ok(self.insert_shared(object))
+    }
+
+    fn invalid_handle<'tcx>(&self, handle: Handle) -> crate::EvalError<'tcx> {
+        let msg = if handle > 0 && handle < self.next_handle && handle % 4 == 0 {
+            format!("use of closed handle {}", handle)
+        } else {
+            format!("use of invalid handle {}", handle)
+        };
+        EvalErrorKind::MachineError(msg).into()
+    }
+}
@@ -1,3 +1,4 @@
+use std::cmp;
 use std::mem;
 
 use rustc::ty::{self, layout};
@@ -5,12 +6,45 @@ use rustc::hir::def_id::{DefId, CRATE_DEF_INDEX};
 
 use crate::*;
 
+/// Whether `name` matches `pattern`, in which `*` stands for any sequence of characters,
+/// e.g. `*::fs::*` for all the tests in modules called `fs`, or `/tmp/*.txt` for the text
+/// files in `/tmp` and the directories below it.
+pub fn matches_pattern(pattern: &str, name: &str) -> bool {
+    let mut parts: Vec<&str> = pattern.split('*').collect();
+    let first = parts.remove(0);
+    if !name.starts_with(first) {
+        return false;
+    }
+    let mut rest = &name[first.len()..];
+    let last = match parts.pop() {
+        Some(last) => last,
+        None => return rest.is_empty(),
+    };
+    for part in parts {
+        match rest.find(part) {
+            Some(index) => rest = &rest[index + part.len()..],
+            None => return false,
+        }
+    }
+    rest.ends_with(last)
+}
+
+/// The name of the files we write for `program`, a path like `tests::it_works`.  `::` is
+/// not allowed in file names on Windows, so the parts are separated by `.`.
+pub(crate) fn program_file_stem(program: &str) -> String {
+    program.replace("::", ".")
+}
+
 impl<'a, 'mir, 'tcx> EvalContextExt<'a, 'mir, 'tcx> for crate::MiriEvalContext<'a, 'mir, 'tcx> {}
 pub trait EvalContextExt<'a, 'mir, 'tcx: 'a+'mir>: crate::MiriEvalContextExt<'a, 'mir, 'tcx> {
     /// Get an instance for a path.
     fn resolve_path(&self, path: &[&str]) -> EvalResult<'tcx, ty::Instance<'tcx>> {
         let this = self.eval_context_ref();
-        this.tcx
+        let key: Vec<String> = path.iter().map(|&s| s.to_owned()).collect();
+        if let Some(&instance) = this.machine.cache.paths.borrow().get(&key) {
+            return Ok(instance);
+        }
+        let instance = this.tcx
             .crates()
             .iter()
             .find(|&&krate| this.tcx.original_crate_name(krate) == path[0])
@@ -36,10 +70,157 @@ pub trait EvalContextExt<'a, 'mir, 'tcx: 'a+'mir>: crate::MiriEvalContextExt<'a,
                 }
                 None
             })
-            .ok_or_else(|| {
-                let path = path.iter().map(|&s| s.to_owned()).collect();
-                EvalErrorKind::PathNotFound(path).into()
-            })
+            .ok_or_else(|| EvalErrorKind::PathNotFound(key.clone()))?;
+        this.machine.cache.paths.borrow_mut().insert(key, instance);
+        Ok(instance)
+    }
+
+    /// Evaluate the `libc` constant `name`, e.g. an `errno` value, as it is for the target.
+    fn eval_libc_i32(&self, name: &str) -> EvalResult<'tcx, i32> {
+        let this = self.eval_context_ref();
+        if let Some(&value) = this.machine.cache.libc_consts.borrow().get(name) {
+            return Ok(value);
+        }
+        let value = this.eval_path_scalar(&["libc", name])?.to_i32()?;
+        this.machine.cache.libc_consts.borrow_mut().insert(name.to_owned(), value);
+        Ok(value)
+    }
+
+    /// Evaluate the static or constant at the given path, and read it as a scalar.
+    fn eval_path_scalar(&self, path: &[&str]) -> EvalResult<'tcx, ScalarMaybeUndef<Borrow>> {
+        let this = self.eval_context_ref();
+        let instance = this.resolve_path(path)?;
+        let cid = GlobalId {
+            instance,
+            promoted: None,
+        };
+        let const_val = this.const_eval_raw(cid)?;
+        this.read_scalar(const_val.into())
+    }
+
+    /// Read a null-terminated sequence of `u16`, as used for "wide" strings on Windows.
+    /// The terminator is not included in the result.
+    fn read_wide_str(&self, ptr: Pointer<Borrow>) -> EvalResult<'tcx, Vec<u16>> {
+        let this = self.eval_context_ref();
+        let mut wchars = Vec::new();
+        let mut ptr = ptr;
+        loop {
+            let bytes = this.memory().read_bytes(Scalar::Ptr(ptr), Size::from_bytes(2))?;
+            // Windows targets are always little-endian.
+            let wchar = u16::from(bytes[0]) | (u16::from(bytes[1]) << 8);
+            if wchar == 0 {
+                return Ok(wchars);
+            }
+            wchars.push(wchar);
+            ptr = ptr.offset(Size::from_bytes(2), this)?;
+        }
+    }
+
+    /// Write a sequence of `u16` followed by a null terminator, as used for "wide"
+    /// strings on Windows.
+    fn write_wide_str(&mut self, wchars: &[u16], ptr: Pointer<Borrow>) -> EvalResult<'tcx> {
+        let this = self.eval_context_mut();
+        let tcx = &{this.tcx.tcx};
+        let bytes: Vec<u8> = wchars.iter()
+            .chain(Some(&0))
+            .flat_map(|&wchar| vec![wchar as u8, (wchar >> 8) as u8])
+            .collect();
+        this.memory_mut().get_mut(ptr.alloc_id)?.write_bytes(tcx, ptr, &bytes)
+    }
+
+    /// Allocate a null-terminated copy of `bytes`, as used for C strings.
+    fn alloc_c_str(&mut self, bytes: &[u8], kind: MiriMemoryKind) -> EvalResult<'tcx, Pointer<Borrow>> {
+        let this = self.eval_context_mut();
+        let tcx = &{this.tcx.tcx};
+        let ptr = this.memory_mut().allocate(
+            Size::from_bytes(bytes.len() as u64 + 1),
+            layout::Align::from_bytes(1).unwrap(),
+            kind.into(),
+        )?.with_default_tag();
+        let alloc = this.memory_mut().get_mut(ptr.alloc_id)?;
+        alloc.write_bytes(tcx, ptr, bytes)?;
+        alloc.write_bytes(tcx, ptr.offset(Size::from_bytes(bytes.len() as u64), tcx)?, &[0])?;
+        Ok(ptr)
+    }
+
+    /// Copy `size` bytes, like `Memory::copy`.  That one transfers the definedness of every
+    /// byte on its own, looking up both allocations each time, which makes copying megabytes
+    /// slow.  If the source is plain initialized data, without pointers, we copy it in bulk
+    /// instead; everything else takes the precise path.
+    fn copy_bytes(
+        &mut self,
+        src: Scalar<Borrow>,
+        src_align: layout::Align,
+        dest: Scalar<Borrow>,
+        dest_align: layout::Align,
+        size: Size,
+        nonoverlapping: bool,
+    ) -> EvalResult<'tcx> {
+        let this = self.eval_context_mut();
+        if size.bytes() == 0 || !this.is_plain_data(src, size)? {
+            return this.memory_mut().copy(src, src_align, dest, dest_align, size, nonoverlapping);
+        }
+        this.memory().check_align(src, src_align)?;
+        this.memory().check_align(dest, dest_align)?;
+        if nonoverlapping {
+            let (src, dest) = (src.to_ptr()?, dest.to_ptr()?);
+            if src.alloc_id == dest.alloc_id &&
+                src.offset < dest.offset + size && dest.offset < src.offset + size
+            {
+                return err!(Intrinsic(
+                    "copy_nonoverlapping called on overlapping ranges".to_owned(),
+                ));
+            }
+        }
+        let bytes = this.memory().read_bytes(src, size)?.to_vec();
+        this.memory_mut().write_bytes(dest, &bytes)
+    }
+
+    /// Whether the `size` bytes at `ptr` are in bounds, initialized, and not part of a
+    /// pointer, so that copying their values is all there is to copying them.
+    fn is_plain_data(&self, ptr: Scalar<Borrow>, size: Size) -> EvalResult<'tcx, bool> {
+        let this = self.eval_context_ref();
+        let ptr = match ptr {
+            Scalar::Ptr(ptr) => ptr,
+            Scalar::Bits { .. } => return Ok(false),
+        };
+        let alloc = this.memory().get(ptr.alloc_id)?;
+        let end = ptr.offset + size;
+        if end.bytes() > alloc.bytes.len() as u64 {
+            // Let the precise path report this.
+            return Ok(false);
+        }
+        // A pointer that starts before `ptr` may reach into the range.
+        let pointer_size = this.memory().pointer_size().bytes();
+        let start = Size::from_bytes(ptr.offset.bytes().saturating_sub(pointer_size - 1));
+        Ok(alloc.undef_mask.is_range_defined(ptr.offset, end).is_ok() &&
+            alloc.relocations.range(start..end).is_empty())
+    }
+
+    /// The offset of the first byte `val` among the `len` bytes at `ptr`, like `memchr`.
+    /// C11 has `memchr` stop at the first match, so only the bytes up to there have to be
+    /// in bounds, initialized, and not part of a pointer.
+    fn find_byte(&self, ptr: Scalar<Borrow>, val: u8, len: u64) -> EvalResult<'tcx, Option<u64>> {
+        let this = self.eval_context_ref();
+        if len == 0 {
+            return Ok(None);
+        }
+        if let Scalar::Ptr(alloc_ptr) = ptr {
+            // Search the raw bytes, then check the ones we looked at.
+            let alloc = this.memory().get(alloc_ptr.alloc_id)?;
+            let size = alloc.bytes.len() as u64;
+            let start = cmp::min(alloc_ptr.offset.bytes(), size);
+            let end = cmp::min(alloc_ptr.offset.bytes().saturating_add(len), size);
+            let found = alloc.bytes[start as usize..end as usize].iter().position(|&c| c == val);
+            if let Some(idx) = found {
+                let idx = idx as u64;
+                this.memory().read_bytes(ptr, Size::from_bytes(idx + 1))?;
+                return Ok(Some(idx));
+            }
+        }
+        // No match, so all the bytes were read.
+        this.memory().read_bytes(ptr, Size::from_bytes(len))?;
+        Ok(None)
     }
 
     /// Visit the memory covered by `place`, sensitive to freezing:  The 3rd parameter
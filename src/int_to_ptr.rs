@@ -0,0 +1,68 @@
+//! `-Zmiri-warn-int2ptr`: a warning for every integer-to-pointer cast the program executes,
+//! so that crates can audit where pointers are made up from integers, even when that does
+//! not cause undefined behavior.  Every cast is reported once, with the backtrace of its
+//! first execution.
+
+use std::collections::HashSet;
+
+use rustc::mir;
+use syntax::source_map::Span;
+
+use crate::*;
+
+#[derive(Clone, Debug, Default)]
+pub struct IntToPtrCasts {
+    /// Whether to warn at all
+    enabled: bool,
+    /// The casts we already warned about
+    reported: HashSet<Span>,
+}
+
+impl IntToPtrCasts {
+    pub fn new(enabled: bool) -> Self {
+        IntToPtrCasts { enabled, reported: HashSet::new() }
+    }
+}
+
+impl<'a, 'mir, 'tcx> EvalContextExt<'a, 'mir, 'tcx> for crate::MiriEvalContext<'a, 'mir, 'tcx> {}
+pub trait EvalContextExt<'a, 'mir, 'tcx: 'a+'mir>: crate::MiriEvalContextExt<'a, 'mir, 'tcx> {
+    /// Warn about the integer-to-pointer casts in the current basic block.  This is called
+    /// before the terminator, so all statements of the block have been executed.
+    fn check_int_to_ptr_casts(&mut self) {
+        let this = self.eval_context_mut();
+        if !this.machine.int_to_ptr_casts.enabled {
+            return;
+        }
+        let tcx = this.tcx.tcx;
+        let casts: Vec<Span> = {
+            let frame = match this.stack().last() {
+                Some(frame) => frame,
+                None => return,
+            };
+            frame.mir.basic_blocks()[frame.block].statements.iter()
+                .filter(|stmt| match stmt.kind {
+                    mir::StatementKind::Assign(_, ref rvalue) => match **rvalue {
+                        mir::Rvalue::Cast(mir::CastKind::Misc, ref operand, ty) =>
+                            ty.is_unsafe_ptr() && operand.ty(frame.mir, tcx).is_integral(),
+                        _ => false,
+                    },
+                    _ => false,
+                })
+                .map(|stmt| stmt.source_info.span)
+                .collect()
+        };
+        for span in casts {
+            if !this.machine.int_to_ptr_casts.reported.insert(span) {
+                continue;
+            }
+            let frames = this.generate_stacktrace(None);
+            let mut warning = tcx.sess.struct_span_warn(span, "integer-to-pointer cast");
+            warning.note(
+                "the pointer has no provenance, so miri cannot check the accesses through it; \
+                 derive it from an existing pointer instead",
+            );
+            this.note_stacktrace(&mut warning, &frames);
+            warning.emit();
+        }
+    }
+}
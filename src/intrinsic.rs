@@ -1,12 +1,12 @@
 use rustc::mir;
-use rustc::ty::layout::{self, LayoutOf, Size};
+use rustc::ty::layout::{self, LayoutOf, Size, Align};
 use rustc::ty;
 
 use rustc::mir::interpret::{EvalResult, PointerArithmetic};
 
 use crate::{
-    PlaceTy, OpTy, Immediate, Scalar, ScalarMaybeUndef, Borrow,
-    OperatorEvalContextExt
+    PlaceTy, OpTy, MPlaceTy, Immediate, Scalar, ScalarMaybeUndef, Borrow,
+    OperatorEvalContextExt, SimdEvalContextExt, FloatEvalContextExt,
 };
 
 impl<'a, 'mir, 'tcx> EvalContextExt<'a, 'mir, 'tcx> for crate::MiriEvalContext<'a, 'mir, 'tcx> {}
@@ -29,6 +29,9 @@ pub trait EvalContextExt<'a, 'mir, 'tcx: 'a+'mir>: crate::MiriEvalContextExt<'a,
         // that might still hang around!
 
         let intrinsic_name = &this.tcx.item_name(instance.def_id()).as_str()[..];
+        if intrinsic_name.starts_with("simd_") {
+            return this.call_simd_intrinsic(intrinsic_name, args, dest);
+        }
         match intrinsic_name {
             "arith_offset" => {
                 let offset = this.read_scalar(args[1])?.to_isize(this)?;
@@ -52,7 +55,11 @@ pub trait EvalContextExt<'a, 'mir, 'tcx: 'a+'mir>: crate::MiriEvalContextExt<'a,
             "atomic_load_relaxed" |
             "atomic_load_acq" |
             "volatile_load" => {
-                let ptr = this.deref_operand(args[0])?;
+                let ptr = if intrinsic_name == "volatile_load" {
+                    this.deref_operand(args[0])?
+                } else {
+                    this.deref_atomic_operand(args[0])?
+                };
                 let val = this.read_scalar(ptr.into())?; // make sure it fits into a scalar; otherwise it cannot be atomic
                 this.write_scalar(val, dest)?;
             }
@@ -61,7 +68,11 @@ pub trait EvalContextExt<'a, 'mir, 'tcx: 'a+'mir>: crate::MiriEvalContextExt<'a,
             "atomic_store_relaxed" |
             "atomic_store_rel" |
             "volatile_store" => {
-                let ptr = this.deref_operand(args[0])?;
+                let ptr = if intrinsic_name == "volatile_store" {
+                    this.deref_operand(args[0])?
+                } else {
+                    this.deref_atomic_operand(args[0])?
+                };
                 let val = this.read_scalar(args[1])?; // make sure it fits into a scalar; otherwise it cannot be atomic
                 this.write_scalar(val, ptr.into())?;
             }
@@ -71,7 +82,7 @@ pub trait EvalContextExt<'a, 'mir, 'tcx: 'a+'mir>: crate::MiriEvalContextExt<'a,
             }
 
             _ if intrinsic_name.starts_with("atomic_xchg") => {
-                let ptr = this.deref_operand(args[0])?;
+                let ptr = this.deref_atomic_operand(args[0])?;
                 let new = this.read_scalar(args[1])?;
                 let old = this.read_scalar(ptr.into())?;
                 this.write_scalar(old, dest)?; // old value is returned
@@ -79,7 +90,7 @@ pub trait EvalContextExt<'a, 'mir, 'tcx: 'a+'mir>: crate::MiriEvalContextExt<'a,
             }
 
             _ if intrinsic_name.starts_with("atomic_cxchg") => {
-                let ptr = this.deref_operand(args[0])?;
+                let ptr = this.deref_atomic_operand(args[0])?;
                 let expect_old = this.read_immediate(args[1])?; // read as immediate for the sake of `binary_op_imm()`
                 let new = this.read_scalar(args[2])?;
                 let old = this.read_immediate(ptr.into())?; // read as immediate for the sake of `binary_op_imm()`
@@ -118,7 +129,7 @@ pub trait EvalContextExt<'a, 'mir, 'tcx: 'a+'mir>: crate::MiriEvalContextExt<'a,
             "atomic_xsub_rel" |
             "atomic_xsub_acqrel" |
             "atomic_xsub_relaxed" => {
-                let ptr = this.deref_operand(args[0])?;
+                let ptr = this.deref_atomic_operand(args[0])?;
                 if !ptr.layout.ty.is_integral() {
                     return err!(Unimplemented(format!("Atomic arithmetic operations only work on integer types")));
                 }
@@ -137,6 +148,34 @@ pub trait EvalContextExt<'a, 'mir, 'tcx: 'a+'mir>: crate::MiriEvalContextExt<'a,
                 this.binop_ignore_overflow(op, old, rhs, ptr.into())?;
             }
 
+            _ if intrinsic_name.starts_with("atomic_nand") ||
+                intrinsic_name.starts_with("atomic_max") ||
+                intrinsic_name.starts_with("atomic_min") ||
+                intrinsic_name.starts_with("atomic_umax") ||
+                intrinsic_name.starts_with("atomic_umin") => {
+                let ptr = this.deref_atomic_operand(args[0])?;
+                if !ptr.layout.ty.is_integral() {
+                    return err!(Unimplemented(format!("Atomic arithmetic operations only work on integer types")));
+                }
+                let rhs = this.read_immediate(args[1])?;
+                let old = this.read_immediate(ptr.into())?;
+                this.write_immediate(*old, dest)?; // old value is returned
+                let new = match intrinsic_name.split('_').nth(1).unwrap() {
+                    "nand" => {
+                        let (and, _) = this.binary_op_imm(mir::BinOp::BitAnd, old, rhs)?;
+                        this.unary_op(mir::UnOp::Not, and, ptr.layout)?
+                    }
+                    op => {
+                        // The signedness is that of the type: `umax` is only used on unsigned
+                        // integers, `max` only on signed ones.
+                        let cmp = if op.ends_with("max") { mir::BinOp::Gt } else { mir::BinOp::Lt };
+                        let (old_wins, _) = this.binary_op_imm(cmp, old, rhs)?;
+                        if old_wins.to_bool()? { old.to_scalar()? } else { rhs.to_scalar()? }
+                    }
+                };
+                this.write_scalar(new, ptr.into())?;
+            }
+
             "breakpoint" => unimplemented!(), // halt miri
 
             "copy" |
@@ -149,7 +188,7 @@ pub trait EvalContextExt<'a, 'mir, 'tcx: 'a+'mir>: crate::MiriEvalContextExt<'a,
                 // erase tags: this is a raw ptr operation
                 let src = this.read_scalar(args[0])?.not_undef()?;
                 let dest = this.read_scalar(args[1])?.not_undef()?;
-                this.memory_mut().copy(
+                this.copy_bytes(
                     src,
                     elem_align,
                     dest,
@@ -183,6 +222,16 @@ pub trait EvalContextExt<'a, 'mir, 'tcx: 'a+'mir>: crate::MiriEvalContextExt<'a,
                     "truncf32" => f.trunc(),
                     _ => bug!(),
                 };
+                let f = match intrinsic_name {
+                    // `fabs` only clears the sign bit, even of a NaN.
+                    "fabsf32" => f,
+                    // These are exact.
+                    "sqrtf32" | "floorf32" | "ceilf32" | "truncf32" => this.float_result_f32(f),
+                    _ => {
+                        let f = this.imprecise_f32(f);
+                        this.float_result_f32(f)
+                    }
+                };
                 this.write_scalar(Scalar::from_f32(f), dest)?;
             }
 
@@ -204,6 +253,16 @@ pub trait EvalContextExt<'a, 'mir, 'tcx: 'a+'mir>: crate::MiriEvalContextExt<'a,
                     "truncf64" => f.trunc(),
                     _ => bug!(),
                 };
+                let f = match intrinsic_name {
+                    // `fabs` only clears the sign bit, even of a NaN.
+                    "fabsf64" => f,
+                    // These are exact.
+                    "sqrtf64" | "floorf64" | "ceilf64" | "truncf64" => this.float_result_f64(f),
+                    _ => {
+                        let f = this.imprecise_f64(f);
+                        this.float_result_f64(f)
+                    }
+                };
                 this.write_scalar(Scalar::from_f64(f), dest)?;
             }
 
@@ -289,57 +348,47 @@ pub trait EvalContextExt<'a, 'mir, 'tcx: 'a+'mir>: crate::MiriEvalContextExt<'a,
             "powf32" => {
                 let f = this.read_scalar(args[0])?.to_f32()?;
                 let f2 = this.read_scalar(args[1])?.to_f32()?;
-                this.write_scalar(
-                    Scalar::from_f32(f.powf(f2)),
-                    dest,
-                )?;
+                let res = this.imprecise_f32(f.powf(f2));
+                let res = this.float_result_f32(res);
+                this.write_scalar(Scalar::from_f32(res), dest)?;
             }
 
             "powf64" => {
                 let f = this.read_scalar(args[0])?.to_f64()?;
                 let f2 = this.read_scalar(args[1])?.to_f64()?;
-                this.write_scalar(
-                    Scalar::from_f64(f.powf(f2)),
-                    dest,
-                )?;
+                let res = this.imprecise_f64(f.powf(f2));
+                let res = this.float_result_f64(res);
+                this.write_scalar(Scalar::from_f64(res), dest)?;
             }
 
             "fmaf32" => {
                 let a = this.read_scalar(args[0])?.to_f32()?;
                 let b = this.read_scalar(args[1])?.to_f32()?;
                 let c = this.read_scalar(args[2])?.to_f32()?;
-                this.write_scalar(
-                    Scalar::from_f32(a * b + c),
-                    dest,
-                )?;
+                let res = this.float_result_f32(a * b + c);
+                this.write_scalar(Scalar::from_f32(res), dest)?;
             }
 
             "fmaf64" => {
                 let a = this.read_scalar(args[0])?.to_f64()?;
                 let b = this.read_scalar(args[1])?.to_f64()?;
                 let c = this.read_scalar(args[2])?.to_f64()?;
-                this.write_scalar(
-                    Scalar::from_f64(a * b + c),
-                    dest,
-                )?;
+                let res = this.float_result_f64(a * b + c);
+                this.write_scalar(Scalar::from_f64(res), dest)?;
             }
 
             "powif32" => {
                 let f = this.read_scalar(args[0])?.to_f32()?;
                 let i = this.read_scalar(args[1])?.to_i32()?;
-                this.write_scalar(
-                    Scalar::from_f32(f.powi(i)),
-                    dest,
-                )?;
+                let res = this.float_result_f32(f.powi(i));
+                this.write_scalar(Scalar::from_f32(res), dest)?;
             }
 
             "powif64" => {
                 let f = this.read_scalar(args[0])?.to_f64()?;
                 let i = this.read_scalar(args[1])?.to_i32()?;
-                this.write_scalar(
-                    Scalar::from_f64(f.powi(i)),
-                    dest,
-                )?;
+                let res = this.float_result_f64(f.powi(i));
+                this.write_scalar(Scalar::from_f64(res), dest)?;
             }
 
             "size_of_val" => {
@@ -453,4 +502,17 @@ pub trait EvalContextExt<'a, 'mir, 'tcx: 'a+'mir>: crate::MiriEvalContextExt<'a,
 
         Ok(())
     }
+
+    /// Dereference the pointer argument of an atomic operation.  Unlike normal accesses,
+    /// atomic accesses must be aligned to their size, even if the type's alignment is smaller
+    /// (like `u64` on 32-bit x86, or `u128` on x86_64, where `cmpxchg16b` faults otherwise).
+    fn deref_atomic_operand(&self, op: OpTy<'tcx, Borrow>) -> EvalResult<'tcx, MPlaceTy<'tcx, Borrow>> {
+        let this = self.eval_context_ref();
+        let place = this.deref_operand(op)?;
+        let size = place.layout.size;
+        if size.bytes().is_power_of_two() {
+            this.memory().check_align(place.ptr, Align::from_bytes(size.bytes()).unwrap())?;
+        }
+        Ok(place)
+    }
 }
@@ -0,0 +1,183 @@
+//! What happens when the program tries to reach the host in a way isolation does not allow:
+//! opening a file outside of `-Zmiri-isolation-allow-path`, reading an environment variable
+//! that was not forwarded, creating a socket, starting a process or reading the wall clock.
+//! `-Zmiri-isolation-error` chooses, per category, whether this stops the run with an error,
+//! or makes the operation fail in the program, with or without a warning.  The wall clock
+//! does not fail: the program sees the virtual clock instead, which starts at the epoch.
+
+use std::collections::HashSet;
+
+use crate::*;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum IsolationCategory {
+    Filesystem,
+    Env,
+    Network,
+    Process,
+    Clock,
+}
+
+const CATEGORIES: [IsolationCategory; 5] = [
+    IsolationCategory::Filesystem,
+    IsolationCategory::Env,
+    IsolationCategory::Network,
+    IsolationCategory::Process,
+    IsolationCategory::Clock,
+];
+
+impl IsolationCategory {
+    pub fn name(self) -> &'static str {
+        match self {
+            IsolationCategory::Filesystem => "filesystem",
+            IsolationCategory::Env => "env",
+            IsolationCategory::Network => "network",
+            IsolationCategory::Process => "process",
+            IsolationCategory::Clock => "clock",
+        }
+    }
+
+    /// What `-Zmiri-isolation-error=<category>=warn` does instead of aborting
+    fn instead(self) -> &'static str {
+        match self {
+            IsolationCategory::Clock => "give the program the virtual clock",
+            _ => "make it fail in the program",
+        }
+    }
+
+    /// What happened in the program when the operation was rejected
+    fn rejected(self) -> &'static str {
+        match self {
+            IsolationCategory::Clock => "the program sees the virtual clock instead",
+            _ => "the operation failed",
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum IsolationAction {
+    /// Stop with an error
+    Abort,
+    /// Fail the operation, and warn about it
+    Warn,
+    /// Fail the operation silently
+    Hide,
+}
+
+/// The action for each category
+#[derive(Clone, Debug)]
+pub struct IsolationPolicy([IsolationAction; 5]);
+
+impl Default for IsolationPolicy {
+    fn default() -> Self {
+        // Programs look up environment variables all the time, e.g. std checks
+        // `RUST_BACKTRACE` when it panics, and cope with them not being set.  The virtual
+        // clock always was what programs see as the time.
+        let mut actions = [IsolationAction::Abort; 5];
+        actions[IsolationCategory::Env as usize] = IsolationAction::Hide;
+        actions[IsolationCategory::Clock as usize] = IsolationAction::Hide;
+        IsolationPolicy(actions)
+    }
+}
+
+impl IsolationPolicy {
+    pub fn get(&self, category: IsolationCategory) -> IsolationAction {
+        self.0[category as usize]
+    }
+
+    /// Set the action of all categories from `<action>`, or of some of them from a
+    /// comma-separated list of `<category>=<action>`.
+    pub fn set(&mut self, spec: &str) -> Result<(), String> {
+        let parse_action = |action| match action {
+            "abort" => Ok(IsolationAction::Abort),
+            "warn" => Ok(IsolationAction::Warn),
+            "hide" => Ok(IsolationAction::Hide),
+            _ => Err(format!("unknown action `{}`, expected `abort`, `warn` or `hide`", action)),
+        };
+        if !spec.contains('=') {
+            self.0 = [parse_action(spec)?; 5];
+            return Ok(());
+        }
+        for item in spec.split(',') {
+            let mut parts = item.splitn(2, '=');
+            let category = parts.next().unwrap().trim();
+            let category = CATEGORIES.iter().find(|c| c.name() == category)
+                .ok_or_else(|| format!("unknown category `{}`", category))?;
+            let action = parts.next()
+                .ok_or_else(|| format!("expected `<category>=<action>`, got `{}`", item))?;
+            self.0[*category as usize] = parse_action(action.trim())?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Isolation {
+    policy: IsolationPolicy,
+    /// The operations we already warned about
+    warned: HashSet<String>,
+}
+
+impl Isolation {
+    pub fn new(policy: IsolationPolicy) -> Self {
+        Isolation { policy, warned: HashSet::new() }
+    }
+}
+
+impl<'a, 'mir, 'tcx> EvalContextExt<'a, 'mir, 'tcx> for crate::MiriEvalContext<'a, 'mir, 'tcx> {}
+pub trait EvalContextExt<'a, 'mir, 'tcx: 'a+'mir>: crate::MiriEvalContextExt<'a, 'mir, 'tcx> {
+    /// The program tried `op`, which isolation does not allow.  Fails if the policy for
+    /// `category` is to abort; otherwise the caller makes the operation fail, or for the
+    /// clock, uses the virtual clock.
+    fn reject_in_isolation(&mut self, category: IsolationCategory, op: String) -> EvalResult<'tcx> {
+        let this = self.eval_context_mut();
+        match this.machine.isolation.policy.get(category) {
+            IsolationAction::Abort => err!(Unimplemented(format!(
+                "{} is not allowed in isolation, use `-Zmiri-isolation-error={}=warn` \
+                 to {} instead",
+                op, category.name(), category.instead(),
+            ))),
+            IsolationAction::Warn => {
+                let msg = format!("{} was rejected by isolation, {}", op, category.rejected());
+                if this.machine.isolation.warned.insert(op) {
+                    let frames = this.generate_stacktrace(None);
+                    let mut warning = match local_call_site(&frames) {
+                        Some(site) => this.tcx.sess.struct_span_warn(site, &msg),
+                        None => this.tcx.sess.struct_warn(&msg),
+                    };
+                    this.note_stacktrace(&mut warning, &frames);
+                    warning.emit();
+                }
+                Ok(())
+            }
+            IsolationAction::Hide => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn set_policy() {
+        let mut policy = IsolationPolicy::default();
+        assert_eq!(policy.get(IsolationCategory::Filesystem), IsolationAction::Abort);
+        assert_eq!(policy.get(IsolationCategory::Env), IsolationAction::Hide);
+        assert_eq!(policy.get(IsolationCategory::Clock), IsolationAction::Hide);
+
+        policy.set("warn").unwrap();
+        assert_eq!(policy.get(IsolationCategory::Process), IsolationAction::Warn);
+        policy.set("filesystem=abort, network=hide").unwrap();
+        assert_eq!(policy.get(IsolationCategory::Filesystem), IsolationAction::Abort);
+        assert_eq!(policy.get(IsolationCategory::Network), IsolationAction::Hide);
+        assert_eq!(policy.get(IsolationCategory::Env), IsolationAction::Warn);
+
+        policy.set("clock=abort").unwrap();
+        assert_eq!(policy.get(IsolationCategory::Clock), IsolationAction::Abort);
+
+        assert!(policy.set("time=warn").is_err());
+        assert!(policy.set("filesystem").is_err());
+        assert!(policy.set("env=ignore").is_err());
+    }
+}
@@ -0,0 +1,255 @@
+//! Emulation of `kqueue` on macOS and the BSDs.
+//! The only file descriptors we have are those of regular files, which never wait for
+//! anything, so we only support the filters that do not need file descriptors:
+//! user-triggered events (which is what async runtimes use to wake up their event loop)
+//! and timers (which run on the virtual clock).
+
+use std::collections::{BTreeMap, HashMap};
+use std::time::Duration;
+
+use crate::{Scalar, Borrow};
+
+/// The filters we support.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Filter {
+    /// `EVFILT_USER`
+    User,
+    /// `EVFILT_TIMER`, in milliseconds
+    Timer,
+}
+
+/// The flags of a `struct kevent`, decoded.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct EventFlags {
+    pub add: bool,
+    pub delete: bool,
+    pub enable: bool,
+    pub disable: bool,
+    pub oneshot: bool,
+    pub clear: bool,
+    pub receipt: bool,
+}
+
+/// A change to apply to the registered events, as passed in the `changelist` of `kevent`.
+#[derive(Copy, Clone, Debug)]
+pub struct Change {
+    pub ident: u64,
+    pub filter: Filter,
+    pub flags: EventFlags,
+    /// For user events: Whether to trigger the event (`NOTE_TRIGGER`).
+    pub trigger: bool,
+    /// For timers: The period in milliseconds.
+    pub data: i64,
+    pub udata: Scalar<Borrow>,
+}
+
+/// An event to report to the program, as returned in the `eventlist` of `kevent`.
+#[derive(Copy, Clone, Debug)]
+pub struct Event {
+    pub ident: u64,
+    pub filter: Filter,
+    /// `receipt` is set if this reports that a change was applied, rather than an actual event.
+    pub flags: EventFlags,
+    /// For timers: How often the timer expired since it was last reported.
+    pub data: i64,
+    pub udata: Scalar<Borrow>,
+}
+
+#[derive(Clone, Debug)]
+enum State {
+    User { triggered: bool },
+    Timer { period: Duration, deadline: Duration },
+}
+
+#[derive(Clone, Debug)]
+struct Registration {
+    state: State,
+    enabled: bool,
+    oneshot: bool,
+    clear: bool,
+    udata: Scalar<Borrow>,
+}
+
+/// A single `kqueue`.
+#[derive(Clone, Debug, Default)]
+pub struct Kqueue {
+    registrations: BTreeMap<(u64, Filter), Registration>,
+}
+
+impl Kqueue {
+    /// Apply a change.  `now` is the current time on the virtual clock.
+    pub fn apply(&mut self, change: Change, now: Duration) -> Result<(), String> {
+        let key = (change.ident, change.filter);
+        if change.flags.delete {
+            return match self.registrations.remove(&key) {
+                Some(_) => Ok(()),
+                None => Err(format!("kevent: cannot delete unregistered event {:?}", key)),
+            };
+        }
+        if change.flags.add && !self.registrations.contains_key(&key) {
+            let state = match change.filter {
+                Filter::User => State::User { triggered: false },
+                Filter::Timer => State::Timer { period: Duration::default(), deadline: now },
+            };
+            self.registrations.insert(key, Registration {
+                state,
+                enabled: true,
+                oneshot: false,
+                clear: false,
+                udata: change.udata,
+            });
+        }
+        let registration = match self.registrations.get_mut(&key) {
+            Some(registration) => registration,
+            None => return Err(format!("kevent: cannot modify unregistered event {:?}", key)),
+        };
+        registration.udata = change.udata;
+        // The other changes, like triggering a user event, keep how the event is reported.
+        if change.flags.add {
+            registration.oneshot = change.flags.oneshot;
+            registration.clear = change.flags.clear;
+        }
+        if change.flags.enable {
+            registration.enabled = true;
+        }
+        if change.flags.disable {
+            registration.enabled = false;
+        }
+        match registration.state {
+            State::User { ref mut triggered } => {
+                if change.trigger {
+                    *triggered = true;
+                }
+            }
+            // Adding a timer (again) starts it, enabling or disabling it does not.
+            State::Timer { ref mut period, ref mut deadline } if change.flags.add => {
+                if change.data <= 0 {
+                    return Err(format!("kevent: invalid timer period {}", change.data));
+                }
+                *period = Duration::from_millis(change.data as u64);
+                *deadline = now + *period;
+            }
+            State::Timer { .. } => {}
+        }
+        Ok(())
+    }
+
+    /// Collect up to `max` pending events.  `now` is the current time on the virtual clock.
+    pub fn collect(&mut self, max: usize, now: Duration) -> Vec<Event> {
+        let mut events = Vec::new();
+        let mut delivered_oneshot = Vec::new();
+        for (&(ident, filter), registration) in self.registrations.iter_mut() {
+            if events.len() >= max {
+                break;
+            }
+            if !registration.enabled {
+                continue;
+            }
+            let data = match registration.state {
+                State::User { ref mut triggered } => {
+                    if !*triggered {
+                        continue;
+                    }
+                    if registration.clear {
+                        *triggered = false;
+                    }
+                    0
+                }
+                State::Timer { period, ref mut deadline } => {
+                    if now < *deadline {
+                        continue;
+                    }
+                    let expirations = duration_div(now - *deadline, period) + 1;
+                    *deadline += period * expirations as u32;
+                    expirations as i64
+                }
+            };
+            let flags = EventFlags {
+                oneshot: registration.oneshot,
+                clear: registration.clear,
+                ..EventFlags::default()
+            };
+            events.push(Event { ident, filter, flags, data, udata: registration.udata });
+            if registration.oneshot {
+                delivered_oneshot.push((ident, filter));
+            }
+        }
+        for key in delivered_oneshot {
+            self.registrations.remove(&key);
+        }
+        events
+    }
+
+    /// The time at which the next timer expires, if any timer is active.
+    pub fn next_deadline(&self) -> Option<Duration> {
+        self.registrations.values()
+            .filter(|registration| registration.enabled)
+            .filter_map(|registration| match registration.state {
+                State::Timer { deadline, .. } => Some(deadline),
+                State::User { .. } => None,
+            })
+            .min()
+    }
+}
+
+fn duration_div(duration: Duration, period: Duration) -> u64 {
+    let nanos = |d: Duration| d.as_secs() as u128 * 1_000_000_000 + d.subsec_nanos() as u128;
+    (nanos(duration) / nanos(period)) as u64
+}
+
+/// All the `kqueue`s, by file descriptor.
+#[derive(Debug, Default)]
+pub struct Kqueues {
+    queues: HashMap<i32, Kqueue>,
+}
+
+impl Kqueues {
+    /// Create a kqueue with the fd `fd`, which the file table handed out.
+    pub fn create(&mut self, fd: i32) {
+        trace!("New kqueue with fd {}", fd);
+        self.queues.insert(fd, Kqueue::default());
+    }
+
+    pub fn get_mut(&mut self, fd: i32) -> Option<&mut Kqueue> {
+        self.queues.get_mut(&fd)
+    }
+
+    /// Close the kqueue with the given fd.  Returns `false` if there is no such kqueue.
+    pub fn close(&mut self, fd: i32) -> bool {
+        self.queues.remove(&fd).is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn change(ident: u64, filter: Filter, flags: EventFlags, trigger: bool, data: i64) -> Change {
+        Change { ident, filter, flags, trigger, data, udata: Scalar::Bits { bits: 0, size: 8 } }
+    }
+
+    #[test]
+    fn trigger_keeps_clear() {
+        let mut kq = Kqueue::default();
+        let now = Duration::default();
+        let add = EventFlags { add: true, clear: true, ..EventFlags::default() };
+        kq.apply(change(42, Filter::User, add, false, 0), now).unwrap();
+        kq.apply(change(42, Filter::User, EventFlags::default(), true, 0), now).unwrap();
+        assert_eq!(kq.collect(4, now).len(), 1);
+        assert_eq!(kq.collect(4, now).len(), 0);
+    }
+
+    #[test]
+    fn disable_keeps_timer() {
+        let mut kq = Kqueue::default();
+        let add = EventFlags { add: true, ..EventFlags::default() };
+        kq.apply(change(7, Filter::Timer, add, false, 10), Duration::from_millis(0)).unwrap();
+        let disable = EventFlags { disable: true, ..EventFlags::default() };
+        kq.apply(change(7, Filter::Timer, disable, false, 0), Duration::from_millis(5)).unwrap();
+        assert_eq!(kq.next_deadline(), None);
+        let enable = EventFlags { enable: true, ..EventFlags::default() };
+        kq.apply(change(7, Filter::Timer, enable, false, 0), Duration::from_millis(6)).unwrap();
+        assert_eq!(kq.next_deadline(), Some(Duration::from_millis(10)));
+        assert!(kq.apply(change(7, Filter::Timer, add, false, 0), Duration::from_millis(6)).is_err());
+    }
+}
@@ -1,3 +1,24 @@
+//! Miri, an interpreter for Rust's mid-level intermediate representation.
+//!
+//! Besides the `miri` and `cargo-miri` binaries, tools can run miri as a library, from
+//! within a rustc driver of their own:  Once the crate is analyzed, call `eval_main` with
+//! the `TyCtxt`, the `DefId` of the function to run (a `main` function, a `#[test]` or a
+//! `#[bench]`) and a `MiriConfig`.  The `-Zmiri-*` flags of the `miri` binary all map to
+//! fields of `MiriConfig`, see its `Default`.  For several runs in one compiler session,
+//! use `eval_main_cached` with a `SessionCache` shared by all of them.  To call any other
+//! function with arguments of your choosing, set `MiriConfig::call`; `Call::returned` has
+//! the return value after the run.
+//!
+//! Miri reports what it finds as compiler diagnostics.  To get them as values, set
+//! `MiriConfig::report` to `Report::in_memory()` and read `Report::findings` after the run;
+//! clones of a report share its findings.  `eval_main` returns the most serious
+//! `ErrorClass` that failed the run, if any.  To watch what the program does with memory,
+//! add a `MemoryObserver` to `MiriConfig::observers`.  To emulate foreign functions miri
+//! does not know, add a `ForeignShim` to `MiriConfig::foreign_shims`.
+//!
+//! These items are the interface for embedders; the rest of what is public here is so
+//! because the interpreter core needs it, and changes with the compiler.
+
 #![feature(rustc_private)]
 
 #![allow(clippy::cast_lossless)]
@@ -16,13 +37,18 @@ extern crate rustc_target;
 use std::collections::HashMap;
 use std::borrow::Cow;
 use std::env;
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use rustc::ty::{self, TyCtxt, query::TyCtxtAt};
 use rustc::ty::layout::{TyLayout, LayoutOf, Size};
-use rustc::hir::{self, def_id::DefId};
+use rustc::hir::{self, def_id::{DefId, LOCAL_CRATE}};
 use rustc::mir;
 
 use syntax::attr;
+use syntax::errors::DiagnosticId;
 use syntax::source_map::DUMMY_SP;
 
 pub use rustc_mir::interpret::*;
@@ -31,8 +57,45 @@ pub use rustc_mir::interpret::{self, AllocMap, PlaceTy}; // resolve ambiguity
 mod fn_call;
 mod operator;
 mod intrinsic;
+mod simd;
+mod x86;
+mod aarch64;
+mod float;
+mod alloc_history;
+mod backtrace;
+mod memory_dump;
+mod suppressions;
+mod report;
+mod progress;
+mod int_to_ptr;
+mod error_class;
+mod stats;
+mod self_profile;
+mod profile;
+mod observer;
+mod foreign_shims;
+mod call;
+mod debugger;
+mod gdb_stub;
+mod watch;
+mod state_dump;
+mod trace;
+mod coverage;
+mod event_stream;
+mod contracts;
 mod helpers;
 mod tls;
+mod handles;
+mod dispatch;
+mod kqueue;
+mod time;
+mod stdin;
+mod files;
+mod isolation;
+mod replay;
+mod random;
+mod cache;
+mod output;
 mod range_map;
 mod mono_hash_map;
 mod stacked_borrows;
@@ -40,11 +103,66 @@ mod stacked_borrows;
 pub use crate::fn_call::EvalContextExt as MissingFnsEvalContextExt;
 pub use crate::operator::EvalContextExt as OperatorEvalContextExt;
 pub use crate::intrinsic::EvalContextExt as IntrinsicEvalContextExt;
+pub use crate::simd::EvalContextExt as SimdEvalContextExt;
+pub use crate::x86::EvalContextExt as X86EvalContextExt;
+pub use crate::aarch64::EvalContextExt as Aarch64EvalContextExt;
+pub use crate::float::EvalContextExt as FloatEvalContextExt;
+pub use crate::alloc_history::EvalContextExt as AllocHistoryEvalContextExt;
+pub use crate::backtrace::{EvalContextExt as BacktraceEvalContextExt, BacktraceStyle, local_call_site};
+pub use crate::memory_dump::EvalContextExt as MemoryDumpEvalContextExt;
+pub use crate::suppressions::{EvalContextExt as SuppressionsEvalContextExt, Suppressions};
+pub use crate::report::{Report, Finding, Message, Location};
+pub use crate::progress::{EvalContextExt as ProgressEvalContextExt, BACKTRACE_REQUESTED};
+use crate::progress::Progress;
+pub use crate::int_to_ptr::EvalContextExt as IntToPtrEvalContextExt;
+use crate::int_to_ptr::IntToPtrCasts;
+pub use crate::error_class::{ErrorClass, Severity, Severities};
+pub use crate::stats::EvalContextExt as StatsEvalContextExt;
+use crate::stats::Stats;
+use crate::self_profile::{SelfProfile, Phase};
+pub use crate::profile::EvalContextExt as ProfileEvalContextExt;
+use crate::profile::Profile;
+pub use crate::foreign_shims::{ForeignShim, ForeignShims, PluginShim, PluginFn};
+pub use crate::call::{EvalContextExt as CallEvalContextExt, Call, CallArg};
+pub use crate::debugger::EvalContextExt as DebuggerEvalContextExt;
+use crate::debugger::Debugger;
+pub use crate::watch::{EvalContextExt as WatchEvalContextExt, Watch};
+use crate::watch::Watches;
+pub use crate::state_dump::EvalContextExt as StateDumpEvalContextExt;
+pub use crate::trace::EvalContextExt as TraceEvalContextExt;
+use crate::trace::Trace;
+pub use crate::coverage::EvalContextExt as CoverageEvalContextExt;
+use crate::coverage::Coverage;
+pub use crate::event_stream::EventStream;
+pub use crate::contracts::EvalContextExt as ContractsEvalContextExt;
+pub use crate::observer::{
+    EvalContextExt as ObserverEvalContextExt, MemoryObserver, MemoryEvent, MemoryEventKind, Observers,
+};
 pub use crate::tls::{EvalContextExt as TlsEvalContextExt, TlsData};
 use crate::range_map::RangeMap;
 #[allow(unused_imports)] // FIXME rustc bug https://github.com/rust-lang/rust/issues/53682
-pub use crate::helpers::{EvalContextExt as HelpersEvalContextExt};
+pub use crate::helpers::{EvalContextExt as HelpersEvalContextExt, matches_pattern};
 use crate::mono_hash_map::MonoHashMap;
+use crate::handles::HandleTable;
+use crate::dispatch::DispatchObjects;
+use crate::kqueue::Kqueues;
+use crate::time::VirtualClock;
+pub use crate::time::ClockMode;
+use crate::stdin::Stdin;
+pub use crate::files::{EvalContextExt as FilesEvalContextExt, AllowedPaths};
+use crate::files::FileTable;
+pub use crate::isolation::{EvalContextExt as IsolationEvalContextExt, IsolationCategory, IsolationPolicy};
+use crate::isolation::Isolation;
+pub use crate::replay::{EvalContextExt as ReplayEvalContextExt, ReplayMode};
+use crate::replay::Replay;
+pub use crate::random::{EvalContextExt as RandomEvalContextExt, host_seed};
+pub use crate::cache::SessionCache;
+pub use crate::stdin::StdinSource;
+use crate::output::Output;
+pub use crate::output::OutputMode;
+use crate::float::{FloatState, Rng};
+use crate::alloc_history::AllocHistory;
+pub use crate::alloc_history::TrackedAllocs;
 pub use crate::stacked_borrows::{EvalContextExt as StackedBorEvalContextExt};
 
 // Used by priroda
@@ -58,21 +176,195 @@ pub fn miri_default_args() -> &'static [&'static str] {
     &["-Zalways-encode-mir", "-Zmir-emit-retag", "-Zmir-opt-level=0"]
 }
 
+/// Configuration needed to spawn a Miri instance
+#[derive(Clone, Debug)]
+pub struct MiriConfig {
+    /// Whether to enforce the validity invariant
+    pub validate: bool,
+    /// Whether NaNs produced by float intrinsics get a random sign and payload
+    pub random_nans: bool,
+    /// Whether the results of transcendental float functions are off by a few ULPs
+    pub float_imprecision: bool,
+    /// Disables all float nondeterminism, overriding `random_nans` and `float_imprecision`
+    pub deterministic_floats: bool,
+    /// The seed for all randomized behavior
+    pub seed: u64,
+    /// The arguments of the program, after its name.  They are bytes, like on Unix.
+    pub args: Vec<Vec<u8>>,
+    /// The environment variables of the program, from `-Zmiri-env-forward` and `-Zmiri-env-set`.
+    /// No other variables of the host are visible.
+    pub env_vars: Vec<(String, String)>,
+    /// What the program reads from its standard input
+    pub stdin: StdinSource,
+    /// Where the standard output and error of the program go
+    pub output: OutputMode,
+    /// The host files the program may access
+    pub allowed_paths: AllowedPaths,
+    /// What to do when the program tries to reach the host in other ways
+    pub isolation: IsolationPolicy,
+    /// When the virtual clock advances
+    pub clock: ClockMode,
+    /// Whether to record what the program gets from the host, or to replay it
+    pub replay: Option<ReplayMode>,
+    /// How much to show in the backtraces of errors
+    pub backtrace: BacktraceStyle,
+    /// Known findings that do not fail the run
+    pub suppressions: Suppressions,
+    /// Where to record all findings, for `-Zmiri-report`
+    pub report: Option<Report>,
+    /// Report progress every this many basic blocks
+    pub report_progress: Option<u64>,
+    /// How long the whole run may take, counting from `start`
+    pub timeout: Option<Duration>,
+    /// How long each test, or the main function, may take
+    pub test_timeout: Option<Duration>,
+    /// How many basic blocks each test, or the main function, may execute
+    pub max_steps: Option<u64>,
+    /// When the run started
+    pub start: Instant,
+    /// The heap allocations to report the creation and deallocation of
+    pub tracked_allocs: TrackedAllocs,
+    /// How many bytes the program may have on the heap
+    pub max_heap: Option<u64>,
+    /// Whether to warn about integer-to-pointer casts
+    pub warn_int2ptr: bool,
+    /// How seriously to take each class of findings
+    pub severities: Severities,
+    /// Whether to print execution statistics at the end
+    pub stats: bool,
+    /// How many of the most called foreign functions to print at the end
+    pub shim_report: Option<usize>,
+    /// Where to write how long the phases of the interpreter took
+    pub self_profile: Option<PathBuf>,
+    /// Where to write the profile of the program
+    pub profile: Option<PathBuf>,
+    /// Where to append the timeline of the run
+    pub trace: Option<PathBuf>,
+    /// Where to append which lines of the local crate ran
+    pub coverage: Option<PathBuf>,
+    /// Where to send what the program does with memory, and the diagnostics, as it runs
+    pub event_stream: Option<EventStream>,
+    /// Who to tell about what the program does with memory
+    pub observers: Observers,
+    /// Foreign functions the embedder emulates
+    pub foreign_shims: ForeignShims,
+    /// Call the function directly with these arguments, rather than run it as a program
+    pub call: Option<Call>,
+    /// Wait for a debugger on this port, which then controls the program
+    pub gdb_port: Option<u16>,
+    /// Stop before the program starts, and let the user control it from a prompt
+    pub interactive: bool,
+    /// The memory whose writes to report
+    pub watches: Vec<Watch>,
+    /// Where to write the state of the machine when an error stops the program
+    pub state_dump: Option<PathBuf>,
+}
+
+impl Default for MiriConfig {
+    fn default() -> Self {
+        MiriConfig {
+            validate: true,
+            random_nans: false,
+            float_imprecision: false,
+            deterministic_floats: false,
+            seed: 0,
+            args: Vec::new(),
+            env_vars: Vec::new(),
+            stdin: StdinSource::default(),
+            output: OutputMode::Stream,
+            allowed_paths: AllowedPaths::default(),
+            isolation: IsolationPolicy::default(),
+            clock: ClockMode::default(),
+            replay: None,
+            backtrace: BacktraceStyle::Full,
+            suppressions: Suppressions::default(),
+            report: None,
+            report_progress: None,
+            timeout: None,
+            test_timeout: None,
+            max_steps: None,
+            start: Instant::now(),
+            tracked_allocs: TrackedAllocs::default(),
+            max_heap: None,
+            warn_int2ptr: false,
+            severities: Severities::default(),
+            stats: false,
+            shim_report: None,
+            self_profile: None,
+            profile: None,
+            trace: None,
+            coverage: None,
+            event_stream: None,
+            observers: Observers::default(),
+            foreign_shims: ForeignShims::default(),
+            call: None,
+            gdb_port: None,
+            interactive: false,
+            watches: Vec::new(),
+            state_dump: None,
+        }
+    }
+}
+
 // Used by priroda
 pub fn create_ecx<'a, 'mir: 'a, 'tcx: 'mir>(
     tcx: TyCtxt<'a, 'tcx, 'tcx>,
     main_id: DefId,
-    validate: bool,
+    config: MiriConfig,
 ) -> EvalResult<'tcx, EvalContext<'a, 'mir, 'tcx, Evaluator<'tcx>>> {
+    let env_vars = config.env_vars.clone();
+    let args = config.args.clone();
+    let mut observers = config.observers.clone();
+    if let Some(ref stream) = config.event_stream {
+        stream.start(&tcx.item_path_str(main_id));
+        observers.add(Arc::new(stream.clone()));
+    }
+    let watches = Watches::new(&config.watches);
+    let trace = match config.trace {
+        Some(ref path) => Some(Trace::new(path, &tcx.item_path_str(main_id)).map_err(|err| {
+            EvalErrorKind::MachineError(format!("could not create the trace: {}", err))
+        })?),
+        None => None,
+    };
+    let replay = Replay::new(config.replay.clone(), &tcx.item_path_str(main_id))
+        .map_err(EvalErrorKind::MachineError)?;
     let mut ecx = EvalContext::new(
         tcx.at(syntax::source_map::DUMMY_SP),
         ty::ParamEnv::reveal_all(),
-        Evaluator::new(validate),
+        Evaluator::new(config),
     );
+    ecx.machine.replay = replay;
+    ecx.machine.trace = trace;
+    ecx.memory().extra.borrow_mut().observers = observers;
+    ecx.memory().extra.borrow_mut().watches = watches;
+
+    // Set up the environment, both for the Unix and the Windows shims.  When replaying, the
+    // program gets the environment it was recorded with.
+    let env_vars = ecx.machine.replay.env_vars(env_vars);
+    for (name, value) in env_vars {
+        let value_ptr = ecx.alloc_c_str(value.as_bytes(), MiriMemoryKind::Env)?;
+        ecx.machine.env_vars.insert(name.clone().into_bytes(), value_ptr);
+        ecx.machine.wide_env_vars.insert(name.encode_utf16().collect(), value.encode_utf16().collect());
+    }
+
+    // The program is called like its crate.
+    let program_name = tcx.crate_name(LOCAL_CRATE).as_str().as_bytes().to_vec();
+    ecx.machine.argv = Some(program_name).into_iter().chain(args).collect();
 
     let main_instance = ty::Instance::mono(ecx.tcx.tcx, main_id);
     let main_mir = ecx.load_mir(main_instance.def)?;
 
+    if let Some(call) = ecx.machine.call.clone() {
+        let ret = ecx.push_call_frame(main_id, &call.args)?;
+        ecx.machine.call_return = Some(ret);
+        return Ok(ecx);
+    }
+
+    if tcx.has_attr(main_id, "bench") {
+        push_bench_frame(&mut ecx, main_id)?;
+        return Ok(ecx);
+    }
+
     if !main_mir.return_ty().is_unit() || main_mir.arg_count != 0 {
         return err!(Unimplemented(
             "miri does not support main functions without `fn()` type signatures"
@@ -119,32 +411,109 @@ pub fn create_ecx<'a, 'mir: 'a, 'tcx: 'mir>(
     let dest = ecx.eval_place(&mir::Place::Local(args.next().unwrap()))?;
     ecx.write_scalar(Scalar::Ptr(main_ptr), dest)?;
 
-    // Second argument (argc): 1
+    // Second argument (argc): the number of arguments, including the program name
+    let argv = ecx.machine.argv.clone();
     let dest = ecx.eval_place(&mir::Place::Local(args.next().unwrap()))?;
-    ecx.write_scalar(Scalar::from_int(1, dest.layout.size), dest)?;
+    ecx.write_scalar(Scalar::from_uint(argv.len() as u128, dest.layout.size), dest)?;
 
-    // FIXME: extract main source file path
-    // Third argument (argv): &[b"foo"]
+    // Third argument (argv): the arguments as C strings, followed by a null pointer
     let dest = ecx.eval_place(&mir::Place::Local(args.next().unwrap()))?;
-    let foo = ecx.memory_mut().allocate_static_bytes(b"foo\0").with_default_tag();
-    let foo_ty = ecx.tcx.mk_imm_ptr(ecx.tcx.types.u8);
-    let foo_layout = ecx.layout_of(foo_ty)?;
-    let foo_place = ecx.allocate(foo_layout, MiriMemoryKind::Env.into())?;
-    ecx.write_scalar(Scalar::Ptr(foo), foo_place.into())?;
-    ecx.memory_mut().mark_immutable(foo_place.to_ptr()?.alloc_id)?;
-    ecx.write_scalar(foo_place.ptr, dest)?;
+    let arg_ty = ecx.tcx.mk_imm_ptr(ecx.tcx.types.u8);
+    let argv_layout = ecx.layout_of(ecx.tcx.mk_array(arg_ty, argv.len() as u64 + 1))?;
+    let argv_place = ecx.allocate(argv_layout, MiriMemoryKind::Env.into())?;
+    for (i, arg) in argv.iter().enumerate() {
+        let arg_ptr = ecx.alloc_c_str(arg, MiriMemoryKind::Env)?;
+        let arg_place = ecx.mplace_field(argv_place, i as u64)?;
+        ecx.write_scalar(Scalar::Ptr(arg_ptr), arg_place.into())?;
+    }
+    let null = Scalar::ptr_null(&*ecx.tcx);
+    let arg_place = ecx.mplace_field(argv_place, argv.len() as u64)?;
+    ecx.write_scalar(null, arg_place.into())?;
+    ecx.write_scalar(argv_place.ptr, dest)?;
 
     assert!(args.next().is_none(), "start lang item has more arguments than expected");
 
     Ok(ecx)
 }
 
+/// Push the frame of libtest's `run_once` for the benchmark `bench_id`.  It calls the
+/// benchmark with a `Bencher` whose `iter` runs the benchmarked closure exactly once, so we
+/// check the code without any timing loops.  Like a test on libtest's threads, this does
+/// not go through the `start` lang item.
+fn push_bench_frame<'a, 'mir, 'tcx>(
+    ecx: &mut MiriEvalContext<'a, 'mir, 'tcx>,
+    bench_id: DefId,
+) -> EvalResult<'tcx> {
+    // `run_once` is generic over the benchmark, whose function item type we pass.
+    let run_once_id = ecx.resolve_path(&["test", "bench", "run_once"])?.def_id();
+    let bench_ty = ecx.tcx.type_of(bench_id);
+    let run_once_instance = ty::Instance::resolve(
+        ecx.tcx.tcx,
+        ty::ParamEnv::reveal_all(),
+        run_once_id,
+        ecx.tcx.mk_substs(::std::iter::once(ty::subst::Kind::from(bench_ty))),
+    ).unwrap();
+    let run_once_mir = ecx.load_mir(run_once_instance.def)?;
+
+    if run_once_mir.arg_count != 1 {
+        return err!(AbiViolation(format!(
+            "`test::bench::run_once` should have one argument, but has {}",
+            run_once_mir.arg_count
+        )));
+    }
+
+    // Return value (in static memory so that it does not count as leak)
+    let ret = ecx.layout_of(run_once_mir.return_ty())?;
+    let ret_ptr = ecx.allocate(ret, MiriMemoryKind::MutStatic.into())?;
+
+    ecx.push_stack_frame(
+        run_once_instance,
+        DUMMY_SP, // there is no call site, we want no span
+        run_once_mir,
+        Some(ret_ptr.into()),
+        StackPopCleanup::None { cleanup: true },
+    )?;
+
+    // The only argument is the benchmark function itself, which is zero-sized.
+    let arg = ecx.frame().mir.args_iter().next().unwrap();
+    let dest = ecx.eval_place(&mir::Place::Local(arg))?;
+    ecx.write_scalar(Scalar::zst(), dest)?;
+
+    Ok(())
+}
+
+/// Run the program, and report what went wrong.  Returns the most serious class of the
+/// findings that were reported as errors, if any.
 pub fn eval_main<'a, 'tcx: 'a>(
     tcx: TyCtxt<'a, 'tcx, 'tcx>,
     main_id: DefId,
-    validate: bool,
-) {
-    let mut ecx = create_ecx(tcx, main_id, validate).expect("Couldn't create ecx");
+    config: MiriConfig,
+) -> Option<ErrorClass> {
+    eval_main_cached(tcx, main_id, config, &Rc::default())
+}
+
+/// Like `eval_main`, for one of several runs in the same session, like the tests of a
+/// crate, which share `cache`.
+pub fn eval_main_cached<'a, 'tcx: 'a>(
+    tcx: TyCtxt<'a, 'tcx, 'tcx>,
+    main_id: DefId,
+    config: MiriConfig,
+    cache: &Rc<SessionCache<'tcx>>,
+) -> Option<ErrorClass> {
+    let start = Instant::now();
+    let mut ecx = match create_ecx(tcx, main_id, config) {
+        Ok(ecx) => ecx,
+        Err(e) => {
+            // The program never started, like when the replay log is missing or the trace
+            // cannot be created, so this is not the program's fault.
+            let mut err = tcx.sess.struct_err(&format!("could not start the program: {}", e));
+            err.code(DiagnosticId::Error(ErrorClass::Unsupported.code()));
+            err.emit();
+            return Some(ErrorClass::Unsupported);
+        }
+    };
+    ecx.machine.cache = Rc::clone(cache);
+    SelfProfile::record(&mut ecx.machine.self_profile, Phase::Startup, Some(start));
 
     // If MIRI_BACKTRACE is set and RUST_CTFE_BACKTRACE is not, set RUST_CTFE_BACKTRACE.
     // Do this late, so we really only apply this to miri's errors.
@@ -156,24 +525,119 @@ pub fn eval_main<'a, 'tcx: 'a>(
 
     // Run! The main execution.
     let res: EvalResult = (|| {
-        ecx.run()?;
-        ecx.run_tls_dtors()
+        let start = SelfProfile::start(&ecx.machine.self_profile);
+        let res = ecx.run_debugged();
+        SelfProfile::record(&mut ecx.machine.self_profile, Phase::Run, start);
+        res?;
+        let start = SelfProfile::start(&ecx.machine.self_profile);
+        let res = ecx.run_tls_dtors();
+        SelfProfile::record(&mut ecx.machine.self_profile, Phase::TlsDtors, start);
+        res
     })();
 
+    if ecx.machine.print_stats || ecx.machine.progress.out_of_steps() {
+        ecx.report_stats();
+    }
+    ecx.report_shims();
+    if let Err(err) = ecx.machine.output.write_files(&tcx.item_path_str(main_id)) {
+        tcx.sess.err(&format!("could not write the output of the program: {}", err));
+    }
+    if let Err(err) = ecx.machine.replay.finish() {
+        tcx.sess.err(&format!("could not write the replay log: {}", err));
+    }
+    if let Some(ref profile) = ecx.machine.self_profile {
+        if let Err(err) = profile.write(&tcx.item_path_str(main_id)) {
+            tcx.sess.err(&format!("could not write the self-profile: {}", err));
+        }
+    }
+    if let Err(err) = ecx.write_profile() {
+        tcx.sess.err(&format!("could not write the profile: {}", err));
+    }
+    if let Err(err) = ecx.finish_trace() {
+        tcx.sess.err(&format!("could not write the trace: {}", err));
+    }
+    if let Err(err) = ecx.write_coverage(&tcx.item_path_str(main_id)) {
+        tcx.sess.err(&format!("could not write the coverage: {}", err));
+    }
+    if let Some(ref stream) = ecx.machine.event_stream {
+        stream.end();
+    }
+
     // Process the result.
     match res {
         Ok(()) => {
+            if let Some(ret) = ecx.machine.call_return {
+                ecx.report_call_result(main_id, ret);
+            }
             let leaks = ecx.memory().leak_report();
-            // Disable the leak test on some platforms where we do not
-            // correctly implement TLS destructors.
-            let target_os = ecx.tcx.tcx.sess.target.target.target_os.to_lowercase();
-            let ignore_leaks = target_os == "windows" || target_os == "macos";
-            if !ignore_leaks && leaks != 0 {
-                tcx.sess.err("the evaluated program leaked memory");
+            let severity = ecx.machine.severities.get(ErrorClass::MemoryLeak);
+            if leaks != 0 && severity != Severity::Allow {
+                // Only heap allocations have a backtrace, so only those can be suppressed;
+                // other leaked memory, like that of Grand Central Dispatch, is always reported.
+                let groups = ecx.leaked_allocations();
+                let heap_leaks: usize = groups.iter().map(|group| group.count).sum();
+                let (suppressed, reported): (Vec<_>, Vec<_>) = groups.into_iter()
+                    .partition(|group| ecx.is_suppressed("memory_leak", group.frames));
+                let suppressed: usize = suppressed.iter().map(|group| group.count).sum();
+                if suppressed != 0 {
+                    tcx.sess.note_without_error(
+                        &format!("suppressed {} leaked allocation(s)", suppressed),
+                    );
+                }
+                if !reported.is_empty() || leaks > heap_leaks {
+                    // Point at where the leaked memory was allocated, so that editors show it.
+                    let msg = "the evaluated program leaked memory";
+                    let site = reported.iter().filter_map(|group| local_call_site(group.frames)).next();
+                    let mut err = match (site, severity) {
+                        (Some(site), Severity::Warn) => tcx.sess.struct_span_warn(site, msg),
+                        (None, Severity::Warn) => tcx.sess.struct_warn(msg),
+                        (Some(site), _) => tcx.sess.struct_span_err(site, msg),
+                        (None, _) => tcx.sess.struct_err(msg),
+                    };
+                    err.code(DiagnosticId::Error(ErrorClass::MemoryLeak.code()));
+                    for group in &reported {
+                        if let Some(site) = local_call_site(group.frames) {
+                            err.span_label(site, format!(
+                                "{} allocation(s) with {} bytes leaked from here",
+                                group.count, group.bytes,
+                            ));
+                        }
+                    }
+                    for group in reported {
+                        if group.count == 1 {
+                            err.note(&format!(
+                                "1 allocation with {} bytes was leaked, created here:", group.bytes,
+                            ));
+                        } else {
+                            err.note(&format!(
+                                "{} allocations with {} bytes in total were leaked, created here:",
+                                group.count, group.bytes,
+                            ));
+                        }
+                        ecx.note_stacktrace(&mut err, group.frames);
+                    }
+                    if let Some(output) = ecx.machine.output.captured() {
+                        err.note(&output);
+                    }
+                    if let Some(ref report) = ecx.machine.report {
+                        let mut leaked = ecx.machine.alloc_history.live_ids();
+                        leaked.sort();
+                        report.record(tcx, main_id, &err, &leaked, &[]);
+                    }
+                    if let Some(ref stream) = ecx.machine.event_stream {
+                        stream.diagnostic(tcx, &err);
+                    }
+                    err.emit();
+                    if severity == Severity::Error {
+                        return Some(ErrorClass::MemoryLeak);
+                    }
+                }
             }
+            None
         }
         Err(mut e) => {
             e.print_backtrace();
+            let violation = ecx.memory().extra.borrow_mut().violation.take();
             if let Some(frame) = ecx.stack().last() {
                 let block = &frame.mir.basic_blocks()[frame.block];
                 let span = if frame.stmt < block.statements.len() {
@@ -182,23 +646,93 @@ pub fn eval_main<'a, 'tcx: 'a>(
                     block.terminator().source_info.span
                 };
 
+                let class = ErrorClass::of(&e.kind, violation.is_some());
+                let severity = ecx.machine.severities.get(class);
+                if severity == Severity::Allow {
+                    return None;
+                }
+                let frames = ecx.generate_stacktrace(None);
+                if ecx.is_suppressed(class.name(), &frames) {
+                    tcx.sess.note_without_error(&format!("suppressed error: {}", e));
+                    return None;
+                }
+                // A violated precondition is the caller's fault, so we point at the call.
+                let precondition = ecx.machine.violated_precondition.clone();
+                let span = match precondition {
+                    Some(_) => local_call_site(&frames).unwrap_or(span),
+                    None => span,
+                };
+                let dangling = match e.kind {
+                    EvalErrorKind::DanglingPointerDeref => ecx.machine.alloc_history
+                        .dangling_at((ecx.stack().len(), frame.block, frame.stmt)),
+                    _ => None,
+                };
+                let out_of_bounds = match e.kind {
+                    EvalErrorKind::PointerOutOfBounds { ptr, allocation_size, .. } =>
+                        Some((ptr, allocation_size)),
+                    _ => None,
+                };
+                // What the error is about, for the report
+                let (alloc_ids, tags) = match (&e.kind, violation) {
+                    (_, Some((id, tag))) => (vec![id], vec![tag]),
+                    (EvalErrorKind::DanglingPointerDeref, _) => (dangling.into_iter().collect(), vec![]),
+                    (EvalErrorKind::PointerOutOfBounds { ptr, .. }, _) => (vec![ptr.alloc_id], vec![]),
+                    _ => (vec![], vec![]),
+                };
                 let e = e.to_string();
                 let msg = format!("constant evaluation error: {}", e);
-                let mut err = struct_error(ecx.tcx.tcx.at(span), msg.as_str());
-                let frames = ecx.generate_stacktrace(None);
+                let mut err = if severity == Severity::Warn {
+                    tcx.sess.struct_span_warn(span, &msg)
+                } else {
+                    struct_error(ecx.tcx.tcx.at(span), msg.as_str())
+                };
+                err.code(DiagnosticId::Error(class.code()));
                 err.span_label(span, e);
-                // we iterate with indices because we need to look at the next frame (the caller)
-                for idx in 0..frames.len() {
-                    let frame_info = &frames[idx];
-                    let call_site_is_local = frames.get(idx+1).map_or(false,
-                        |caller_info| caller_info.instance.def_id().is_local());
-                    if call_site_is_local {
-                        err.span_note(frame_info.call_site, &frame_info.to_string());
-                    } else {
-                        err.note(&frame_info.to_string());
+                if let Some(function) = precondition {
+                    err.note(&format!("this is a precondition of `{}`", function));
+                }
+                ecx.note_stacktrace(&mut err, &frames);
+                if ecx.machine.backtrace == BacktraceStyle::FullLocals {
+                    ecx.note_locals(&mut err);
+                }
+                if let Some(freed) = dangling.and_then(|id| ecx.machine.alloc_history.freed(id)) {
+                    if let Some(site) = local_call_site(&freed.created) {
+                        err.span_label(site, "the allocation was created here");
+                    }
+                    if let Some(site) = local_call_site(&freed.freed) {
+                        err.span_label(site, "the allocation was freed here");
                     }
+                    err.note("the allocation was created here:");
+                    ecx.note_stacktrace(&mut err, &freed.created);
+                    err.note("the allocation was freed here:");
+                    ecx.note_stacktrace(&mut err, &freed.freed);
+                }
+                if let Some((ptr, allocation_size)) = out_of_bounds {
+                    ecx.note_out_of_bounds(&mut err, ptr, allocation_size);
+                }
+                if severity == Severity::Error {
+                    match ecx.write_state_dump(&tcx.item_path_str(main_id), &e) {
+                        Ok(Some(path)) => {
+                            err.note(&format!("the state of the machine was written to {}", path.display()));
+                        }
+                        Ok(None) => {}
+                        Err(dump_err) =>
+                            tcx.sess.err(&format!("could not write the state dump: {}", dump_err)),
+                    }
+                }
+                if let Some(output) = ecx.machine.output.captured() {
+                    err.note(&output);
+                }
+                if let Some(ref report) = ecx.machine.report {
+                    report.record(tcx, main_id, &err, &alloc_ids, &tags);
+                }
+                if let Some(ref stream) = ecx.machine.event_stream {
+                    stream.diagnostic(tcx, &err);
                 }
                 err.emit();
+                if severity == Severity::Warn {
+                    return None;
+                }
             } else {
                 ecx.tcx.sess.err(&e.to_string());
             }
@@ -213,11 +747,11 @@ pub fn eval_main<'a, 'tcx: 'a>(
                     }
                 }
             }
+            Some(ErrorClass::of(&e.kind, violation.is_some()))
         }
     }
 }
 
-
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum MiriMemoryKind {
     /// `__rust_alloc` memory
@@ -226,6 +760,8 @@ pub enum MiriMemoryKind {
     C,
     /// Part of env var emulation
     Env,
+    /// libdispatch objects
+    Dispatch,
     /// mutable statics
     MutStatic,
 }
@@ -242,7 +778,7 @@ impl MayLeak for MiriMemoryKind {
     fn may_leak(self) -> bool {
         use self::MiriMemoryKind::*;
         match self {
-            Rust | C => false,
+            Rust | C | Dispatch => false,
             Env | MutStatic => true,
         }
     }
@@ -250,32 +786,173 @@ impl MayLeak for MiriMemoryKind {
 
 pub struct Evaluator<'tcx> {
     /// Environment variables set by `setenv`
-    /// Miri does not expose env vars from the host to the emulated program
+    /// Miri does not expose env vars from the host to the emulated program, except for those
+    /// the user asked for with `-Zmiri-env-forward`
     pub(crate) env_vars: HashMap<Vec<u8>, Pointer<Borrow>>,
 
+    /// Environment variables set by `SetEnvironmentVariableW`, on Windows targets.
+    /// Unlike `env_vars`, these are copied out on every access, so we store them as UTF-16
+    /// on the host.
+    pub(crate) wide_env_vars: HashMap<Vec<u16>, Vec<u16>>,
+
+    /// The program name and arguments, for `argv` and `GetCommandLineW`
+    pub(crate) argv: Vec<Vec<u8>>,
+
+    /// The standard input of the program
+    pub(crate) stdin: Stdin,
+
+    /// The standard output and error of the program
+    pub(crate) output: Output,
+
+    /// The files the program opened
+    pub(crate) files: FileTable,
+
+    /// What to do when the program tries to reach the host
+    pub(crate) isolation: Isolation,
+
+    /// The log of what the program got from the host
+    pub(crate) replay: Replay,
+
     /// TLS state
     pub(crate) tls: TlsData<'tcx>,
 
+    /// Open Windows handles
+    pub(crate) handles: HandleTable,
+
+    /// Open `kqueue`s, on macOS and the BSDs
+    pub(crate) kqueues: Kqueues,
+
+    /// libdispatch objects, on macOS
+    pub(crate) dispatch_objects: DispatchObjects,
+
+    /// The clock the program observes
+    pub(crate) clock: VirtualClock,
+
     /// Whether to enforce the validity invariant
     pub(crate) validate: bool,
 
+    /// Float nondeterminism
+    pub(crate) floats: FloatState,
+
+    /// The source of the program's random data
+    pub(crate) rng: Rng,
+
+    /// What this run shares with the other runs of the session
+    pub(crate) cache: Rc<SessionCache<'tcx>>,
+
+    /// Where heap allocations were created and freed
+    pub(crate) alloc_history: AllocHistory<'tcx>,
+
+    /// How much to show in the backtraces of errors
+    pub(crate) backtrace: BacktraceStyle,
+
+    /// Known findings that do not fail the run
+    pub(crate) suppressions: Suppressions,
+
+    /// Where to record all findings
+    pub(crate) report: Option<Report>,
+
+    /// How far the program got
+    pub(crate) progress: Progress,
+
+    /// The integer-to-pointer casts we warned about
+    pub(crate) int_to_ptr_casts: IntToPtrCasts,
+
+    /// How seriously to take each class of findings
+    pub(crate) severities: Severities,
+
+    /// Execution statistics, and whether to print them
+    pub(crate) stats: Stats,
+    pub(crate) print_stats: bool,
+
+    /// How long the phases of the interpreter took, if we profile it
+    pub(crate) self_profile: Option<SelfProfile>,
+
+    /// Where the program spends its time, if we profile it
+    pub(crate) profile: Option<Profile>,
+
+    /// The timeline of the run, if we write one
+    pub(crate) trace: Option<Trace<'tcx>>,
+
+    /// How often the blocks of the local functions ran, if we report coverage
+    pub(crate) coverage: Option<Coverage>,
+
+    /// Where to send the diagnostics as well, if anywhere
+    pub(crate) event_stream: Option<EventStream>,
+
+    /// The function whose precondition the program violated, if that is what stopped it
+    pub(crate) violated_precondition: Option<String>,
+
+    /// Foreign functions the embedder emulates
+    pub(crate) foreign_shims: ForeignShims,
+
+    /// The call of a single function, if that is what we run, and where it returns to
+    pub(crate) call: Option<Call>,
+    pub(crate) call_return: Option<MPlaceTy<'tcx, Borrow>>,
+
+    /// The port to wait for a debugger on, until it connected, whether to start at the prompt
+    /// instead, and the debugger
+    pub(crate) gdb_port: Option<u16>,
+    pub(crate) interactive: bool,
+    pub(crate) debugger: Option<Debugger>,
+
+    /// Where to write the state of the machine when an error stops the program
+    pub(crate) state_dump: Option<PathBuf>,
+
     /// Stacked Borrows state
     pub(crate) stacked_borrows: stacked_borrows::State,
 }
 
 impl<'tcx> Evaluator<'tcx> {
-    fn new(validate: bool) -> Self {
+    fn new(config: MiriConfig) -> Self {
         Evaluator {
             env_vars: HashMap::default(),
+            wide_env_vars: HashMap::default(),
+            argv: Vec::new(),
+            stdin: Stdin::new(config.stdin.clone()),
+            output: Output::new(config.output.clone()),
+            files: FileTable::new(config.allowed_paths.clone()),
+            isolation: Isolation::new(config.isolation.clone()),
+            replay: Replay::default(),
             tls: TlsData::default(),
-            validate,
+            handles: HandleTable::default(),
+            kqueues: Kqueues::default(),
+            dispatch_objects: DispatchObjects::default(),
+            clock: VirtualClock::new(config.clock),
+            validate: config.validate,
+            floats: FloatState::new(&config),
+            // Yet another stream, independent of the float ones.
+            rng: Rng::new(config.seed.rotate_left(32)),
+            progress: Progress::new(&config),
+            cache: Rc::default(),
+            alloc_history: AllocHistory::new(config.tracked_allocs, config.max_heap),
+            backtrace: config.backtrace,
+            suppressions: config.suppressions,
+            report: config.report,
+            int_to_ptr_casts: IntToPtrCasts::new(config.warn_int2ptr),
+            severities: config.severities,
+            stats: Stats::new(config.shim_report),
+            print_stats: config.stats,
+            self_profile: config.self_profile.map(SelfProfile::new),
+            profile: config.profile.map(Profile::new),
+            trace: None,
+            coverage: config.coverage.map(Coverage::new),
+            event_stream: config.event_stream,
+            violated_precondition: None,
+            foreign_shims: config.foreign_shims,
+            call: config.call,
+            call_return: None,
+            gdb_port: config.gdb_port,
+            interactive: config.interactive,
+            debugger: None,
+            state_dump: config.state_dump,
             stacked_borrows: stacked_borrows::State::default(),
         }
     }
 }
 
 #[allow(dead_code)] // FIXME https://github.com/rust-lang/rust/issues/47131
-type MiriEvalContext<'a, 'mir, 'tcx> = EvalContext<'a, 'mir, 'tcx, Evaluator<'tcx>>;
+pub type MiriEvalContext<'a, 'mir, 'tcx> = EvalContext<'a, 'mir, 'tcx, Evaluator<'tcx>>;
 
 // A little trait that's useful to be inherited by extension traits
 pub trait MiriEvalContextExt<'a, 'mir, 'tcx> {
@@ -410,9 +1087,15 @@ impl<'a, 'mir, 'tcx> Machine<'a, 'mir, 'tcx> for Evaluator<'tcx> {
     }
 
     #[inline(always)]
-    fn before_terminator(_ecx: &mut EvalContext<'a, 'mir, 'tcx, Self>) -> EvalResult<'tcx>
+    fn before_terminator(ecx: &mut EvalContext<'a, 'mir, 'tcx, Self>) -> EvalResult<'tcx>
     {
-        // We are not interested in detecting loops
+        // We are not interested in detecting loops, but in reporting progress
+        ecx.step_progress()?;
+        ecx.sample_profile();
+        ecx.trace_calls();
+        ecx.record_coverage();
+        ecx.machine.clock.step();
+        ecx.check_int_to_ptr_casts();
         Ok(())
     }
 
@@ -444,6 +1127,11 @@ impl<'a, 'mir, 'tcx> Machine<'a, 'mir, 'tcx> for Evaluator<'tcx> {
         place: MPlaceTy<'tcx, Borrow>,
         mutability: Option<hir::Mutability>,
     ) -> EvalResult<'tcx, Scalar<Borrow>> {
+        if let (Scalar::Ptr(ptr), Some(frame)) = (place.ptr, ecx.stack().last()) {
+            // Remember use-after-free, to explain the error we are about to raise.
+            let location = (ecx.stack().len(), frame.block, frame.stmt);
+            ecx.machine.alloc_history.note_dereference(ptr.alloc_id, location);
+        }
         let size = ecx.size_and_align_of_mplace(place)?.map(|(size, _)| size)
             // for extern types, just cover what we can
             .unwrap_or_else(|| place.layout.size);
@@ -465,13 +1153,16 @@ impl<'a, 'mir, 'tcx> Machine<'a, 'mir, 'tcx> for Evaluator<'tcx> {
         ptr: Pointer,
         kind: MemoryKind<Self::MemoryKinds>,
     ) -> EvalResult<'tcx, Pointer<Borrow>> {
-        if !ecx.machine.validate {
+        let ptr = if !ecx.machine.validate {
             // No tracking
-            Ok(ptr.with_default_tag())
+            ptr.with_default_tag()
         } else {
             let tag = ecx.tag_new_allocation(ptr.alloc_id, kind);
-            Ok(Pointer::new_with_tag(ptr.alloc_id, ptr.offset, tag))
-        }
+            Pointer::new_with_tag(ptr.alloc_id, ptr.offset, tag)
+        };
+        let size = ecx.memory().get(ptr.alloc_id)?.bytes.len() as u64;
+        ecx.observe_memory(MemoryEventKind::Allocate, ptr, Size::from_bytes(size));
+        Ok(ptr)
     }
 
     #[inline]
@@ -514,7 +1205,11 @@ impl<'a, 'mir, 'tcx> Machine<'a, 'mir, 'tcx> for Evaluator<'tcx> {
             // uninitialized data.
              Ok(())
         } else {
-            ecx.retag(fn_entry, two_phase, place)
+            ecx.machine.stats.retags += 1;
+            let start = SelfProfile::start(&ecx.machine.self_profile);
+            let res = ecx.retag(fn_entry, two_phase, place);
+            SelfProfile::record(&mut ecx.machine.self_profile, Phase::Retag, start);
+            res
         }
     }
 
@@ -0,0 +1,119 @@
+//! Details on the memory involved in a faulting access, added to some errors, and dumps of
+//! allocations for the debugger.
+
+use std::cmp;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use rustc::ty::layout::{HasDataLayout, Size};
+use syntax::errors::DiagnosticBuilder;
+
+use crate::*;
+
+/// How many bytes we show before and after the faulting offset
+const CONTEXT: u64 = 32;
+/// How many bytes we show per line
+const ROW: u64 = 16;
+
+impl<'a, 'mir, 'tcx> EvalContextExt<'a, 'mir, 'tcx> for crate::MiriEvalContext<'a, 'mir, 'tcx> {}
+pub trait EvalContextExt<'a, 'mir, 'tcx: 'a+'mir>: crate::MiriEvalContextExt<'a, 'mir, 'tcx> {
+    /// Explain an out-of-bounds access: how far outside the allocation it went, where the
+    /// allocation was created, and what memory is around it.  `ptr` is the end of the access.
+    fn note_out_of_bounds(&self, err: &mut DiagnosticBuilder, ptr: Pointer, allocation_size: Size) {
+        let this = self.eval_context_ref();
+        let offset = ptr.offset.bytes();
+        let pointer_bits = this.memory().pointer_size().bits();
+        // Offsets are unsigned, so a pointer before the allocation has wrapped around.
+        if offset >= 1 << (pointer_bits - 1) {
+            let before = (1u128 << pointer_bits) - offset as u128;
+            err.note(&format!(
+                "the access is {} bytes before the start of alloc{}, which has {} bytes",
+                before, ptr.alloc_id.0, allocation_size.bytes(),
+            ));
+        } else {
+            err.note(&format!(
+                "the access ends {} bytes past the end of alloc{}, which has {} bytes",
+                offset - allocation_size.bytes(), ptr.alloc_id.0, allocation_size.bytes(),
+            ));
+        }
+        if let Some(created) = this.machine.alloc_history.created(ptr.alloc_id) {
+            if let Some(site) = local_call_site(created) {
+                err.span_label(site, "the allocation was created here");
+            }
+            err.note("the allocation was created here:");
+            this.note_stacktrace(err, created);
+        }
+        this.note_memory_dump(err, ptr);
+    }
+
+    /// Add a note with the bytes of the allocation around `ptr`.
+    fn note_memory_dump(&self, err: &mut DiagnosticBuilder, ptr: Pointer) {
+        if let Some(dump) = self.memory_dump(ptr) {
+            err.note(&dump);
+        }
+    }
+
+    /// The bytes of the allocation around `ptr`, if it exists and is not empty.  Uninitialized
+    /// bytes are shown as `__`, the bytes of a pointer as `pp`, followed by where the pointers
+    /// point.
+    fn memory_dump(&self, ptr: Pointer) -> Option<String> {
+        let this = self.eval_context_ref();
+        let alloc = this.memory().get(ptr.alloc_id).ok()?;
+        let size = alloc.bytes.len() as u64;
+        if size == 0 {
+            return None;
+        }
+        // Out-of-bounds offsets can be far past the end, so stay within the allocation.
+        let focus = cmp::min(ptr.offset.bytes(), size);
+        let start = focus.saturating_sub(CONTEXT) / ROW * ROW;
+        let end = cmp::min(focus + CONTEXT, size);
+
+        // Where each pointer in the shown range starts, and what it points to
+        let data_layout = this.data_layout();
+        let pointer_size = data_layout.pointer_size.bytes();
+        let pointers: BTreeMap<u64, (AllocId, u128)> = alloc.relocations.iter()
+            .filter(|&&(offset, _)| offset.bytes() + pointer_size > start && offset.bytes() < end)
+            .map(|&(offset, (_, id))| {
+                // The bytes of a pointer hold the offset into the allocation it points to.
+                let bytes = &alloc.bytes[offset.bytes() as usize..(offset.bytes() + pointer_size) as usize];
+                let target = read_target_uint(data_layout.endian, bytes).unwrap();
+                (offset.bytes(), (id, target))
+            })
+            .collect();
+        let is_pointer = |i: u64| pointers.range(..=i).next_back()
+            .map_or(false, |(&offset, _)| i < offset + pointer_size);
+
+        let mut dump = format!(
+            "memory of alloc{} ({} bytes) around offset {:#x}, \
+             `__` is uninitialized, `pp` is part of a pointer:",
+            ptr.alloc_id.0, size, ptr.offset.bytes(),
+        );
+        let mut row = start;
+        while row < end {
+            let mut hex = String::new();
+            let mut ascii = String::new();
+            for i in row..row + ROW {
+                if i >= end {
+                    hex.push_str("   ");
+                    ascii.push(' ');
+                } else if is_pointer(i) {
+                    hex.push_str(" pp");
+                    ascii.push('.');
+                } else if !alloc.undef_mask.get(Size::from_bytes(i)) {
+                    hex.push_str(" __");
+                    ascii.push(' ');
+                } else {
+                    let byte = alloc.bytes[i as usize];
+                    write!(hex, " {:02x}", byte).unwrap();
+                    ascii.push(if byte.is_ascii_graphic() || byte == b' ' { byte as char } else { '.' });
+                }
+            }
+            write!(dump, "\n{:#06x}:{}  |{}|", row, hex, ascii).unwrap();
+            row += ROW;
+        }
+        for (offset, (id, target)) in pointers {
+            write!(dump, "\n{:#06x}: pointer to alloc{}+{:#x}", offset, id.0, target).unwrap();
+        }
+        Some(dump)
+    }
+}
@@ -0,0 +1,111 @@
+//! Observing what the program does with memory, for tools that embed miri, like analyzers
+//! and visualizers.  The observers in `MiriConfig::observers` are told about every read,
+//! write, allocation, deallocation and retag.  Reads, writes and deallocations are seen by
+//! the hooks of the allocations, which do not know where in the program we are, so we note
+//! the statement before every step; the steps of TLS destructors are noted the same way.
+
+use std::fmt;
+use std::sync::Arc;
+
+use rustc::ty::layout::Size;
+use syntax::source_map::Span;
+
+use crate::*;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MemoryEventKind {
+    Read,
+    Write,
+    Allocate,
+    Deallocate,
+    Retag,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct MemoryEvent {
+    pub kind: MemoryEventKind,
+    pub alloc_id: AllocId,
+    /// The range of the allocation, which is all of it for allocations and deallocations
+    pub offset: Size,
+    pub size: Size,
+    /// The tag of the pointer used for the access, or the new tag of a retag
+    pub tag: Borrow,
+    /// The statement or terminator that caused the event, if we know it
+    pub span: Option<Span>,
+}
+
+/// The driver moves the configuration to the compiler's thread, so observers have to be
+/// `Send`, and `Sync` to be shared by the tests.
+pub trait MemoryObserver: Send + Sync {
+    fn observe(&self, event: &MemoryEvent);
+}
+
+/// The observers of a run.  Clones share the observers, so every test tells the same ones.
+#[derive(Clone, Default)]
+pub struct Observers(Vec<Arc<dyn MemoryObserver>>);
+
+impl Observers {
+    pub fn add(&mut self, observer: Arc<dyn MemoryObserver>) {
+        self.0.push(observer);
+    }
+
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub(crate) fn notify(&self, event: &MemoryEvent) {
+        for observer in &self.0 {
+            observer.observe(event);
+        }
+    }
+}
+
+impl fmt::Debug for Observers {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Observers({})", self.0.len())
+    }
+}
+
+impl<'a, 'mir, 'tcx> EvalContextExt<'a, 'mir, 'tcx> for crate::MiriEvalContext<'a, 'mir, 'tcx> {}
+pub trait EvalContextExt<'a, 'mir, 'tcx: 'a+'mir>: crate::MiriEvalContextExt<'a, 'mir, 'tcx> {
+    /// Tell the observers that the program did `kind` to the `size` bytes at `ptr`.
+    fn observe_memory(&self, kind: MemoryEventKind, ptr: Pointer<Borrow>, size: Size) {
+        let this = self.eval_context_ref();
+        let global = this.memory().extra.borrow();
+        if global.observers.is_empty() {
+            return;
+        }
+        global.observers.notify(&MemoryEvent {
+            kind,
+            alloc_id: ptr.alloc_id,
+            offset: ptr.offset,
+            size,
+            tag: ptr.tag,
+            span: this.current_span(),
+        });
+    }
+
+    /// Execute one step, like `step`, noting its statement or terminator for the events of
+    /// the reads, writes and deallocations it does.
+    fn observed_step(&mut self) -> EvalResult<'tcx, bool> {
+        let this = self.eval_context_mut();
+        if !this.memory().extra.borrow().observers.is_empty() {
+            let span = this.current_span();
+            this.memory().extra.borrow_mut().span = span;
+        }
+        this.step()
+    }
+
+    /// The span of the statement or terminator that is being executed
+    fn current_span(&self) -> Option<Span> {
+        let this = self.eval_context_ref();
+        let frame = this.stack().last()?;
+        let block = &frame.mir.basic_blocks()[frame.block];
+        Some(if frame.stmt < block.statements.len() {
+            block.statements[frame.stmt].source_info.span
+        } else {
+            block.terminator().source_info.span
+        })
+    }
+}
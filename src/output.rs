@@ -0,0 +1,84 @@
+//! Where the standard output and error of the interpreted program go.  By default they are
+//! written to those of miri as they happen.  `-Zmiri-output=capture` keeps them and shows
+//! them with the error when the program fails, like libtest does for tests, which keeps the
+//! output of different tests apart.  `-Zmiri-output=dir=<dir>` writes them to files.
+
+use std::fs;
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum OutputMode {
+    Stream,
+    Capture,
+    /// Write the output to `<program>.stdout` and `<program>.stderr` in this directory
+    Files(PathBuf),
+}
+
+#[derive(Debug)]
+pub struct Output {
+    mode: OutputMode,
+    stdout: Vec<u8>,
+    stderr: Vec<u8>,
+}
+
+impl Output {
+    pub fn new(mode: OutputMode) -> Self {
+        Output { mode, stdout: Vec::new(), stderr: Vec::new() }
+    }
+
+    /// Write some of `bytes` to the standard error if `stderr` is set, otherwise to the
+    /// standard output, and return how many bytes were written.
+    pub fn write(&mut self, stderr: bool, bytes: &[u8]) -> io::Result<usize> {
+        match self.mode {
+            OutputMode::Stream if stderr => io::stderr().write(bytes),
+            OutputMode::Stream => io::stdout().write(bytes),
+            _ => {
+                self.buffer(stderr).extend_from_slice(bytes);
+                Ok(bytes.len())
+            }
+        }
+    }
+
+    /// Write all of `bytes`, like `write`.
+    pub fn write_all(&mut self, stderr: bool, bytes: &[u8]) -> io::Result<()> {
+        match self.mode {
+            OutputMode::Stream if stderr => io::stderr().write_all(bytes),
+            OutputMode::Stream => io::stdout().write_all(bytes),
+            _ => {
+                self.buffer(stderr).extend_from_slice(bytes);
+                Ok(())
+            }
+        }
+    }
+
+    fn buffer(&mut self, stderr: bool) -> &mut Vec<u8> {
+        if stderr { &mut self.stderr } else { &mut self.stdout }
+    }
+
+    /// The captured output, to be shown with an error, if we capture it and there is any.
+    pub fn captured(&self) -> Option<String> {
+        if self.mode != OutputMode::Capture || (self.stdout.is_empty() && self.stderr.is_empty()) {
+            return None;
+        }
+        let mut captured = String::from("the output of the program was:");
+        for &(name, ref bytes) in &[("stdout", &self.stdout), ("stderr", &self.stderr)] {
+            if !bytes.is_empty() {
+                captured.push_str(&format!("\n---- {} ----\n", name));
+                captured.push_str(String::from_utf8_lossy(bytes).trim_end());
+            }
+        }
+        Some(captured)
+    }
+
+    /// Write the output to files named after the program, if we are asked to.
+    pub fn write_files(&self, program: &str) -> io::Result<()> {
+        if let OutputMode::Files(ref dir) = self.mode {
+            let name = crate::helpers::program_file_stem(program);
+            fs::create_dir_all(dir)?;
+            fs::write(dir.join(format!("{}.stdout", name)), &self.stdout)?;
+            fs::write(dir.join(format!("{}.stderr", name)), &self.stderr)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,61 @@
+//! `-Zmiri-profile=<path>`: where the interpreted program spends its time, as the functions
+//! it is in.  Every `SAMPLE_INTERVAL` basic blocks we take the current call stack, and at
+//! the end of the run we append the stacks to `<path>`, in the "collapsed stack" format that
+//! flamegraph tools read: the functions from the outermost in, separated by `;`, and the
+//! number of basic blocks executed in there.  The driver truncates the file at startup, so
+//! that all runs of a session, like the tests of a crate, end up in one profile.
+
+use std::collections::HashMap;
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+use rustc::hir::def_id::DefId;
+
+use crate::*;
+
+/// How many basic blocks to execute between two samples
+const SAMPLE_INTERVAL: u64 = 64;
+
+#[derive(Clone, Debug)]
+pub struct Profile {
+    path: PathBuf,
+    /// How many basic blocks were executed in each call stack, outermost function first
+    samples: HashMap<Vec<DefId>, u64>,
+}
+
+impl Profile {
+    pub fn new(path: PathBuf) -> Self {
+        Profile { path, samples: HashMap::new() }
+    }
+}
+
+impl<'a, 'mir, 'tcx> EvalContextExt<'a, 'mir, 'tcx> for crate::MiriEvalContext<'a, 'mir, 'tcx> {}
+pub trait EvalContextExt<'a, 'mir, 'tcx: 'a+'mir>: crate::MiriEvalContextExt<'a, 'mir, 'tcx> {
+    /// Called before every terminator, after `step_progress`.
+    fn sample_profile(&mut self) {
+        let this = self.eval_context_mut();
+        if this.machine.profile.is_none() || this.machine.progress.blocks() % SAMPLE_INTERVAL != 0 {
+            return;
+        }
+        let stack: Vec<DefId> = this.stack().iter().map(|frame| frame.instance.def_id()).collect();
+        let profile = this.machine.profile.as_mut().unwrap();
+        *profile.samples.entry(stack).or_insert(0) += SAMPLE_INTERVAL;
+    }
+
+    /// Append the sampled stacks to the profile.
+    fn write_profile(&self) -> io::Result<()> {
+        let this = self.eval_context_ref();
+        let profile = match this.machine.profile {
+            Some(ref profile) => profile,
+            None => return Ok(()),
+        };
+        let mut lines: Vec<String> = profile.samples.iter().map(|(stack, blocks)| {
+            let names: Vec<String> = stack.iter().map(|&def_id| this.tcx.item_path_str(def_id)).collect();
+            format!("{} {}\n", names.join(";"), blocks)
+        }).collect();
+        lines.sort();
+        let mut file = OpenOptions::new().create(true).append(true).open(&profile.path)?;
+        file.write_all(lines.concat().as_bytes())
+    }
+}
@@ -0,0 +1,111 @@
+//! Reports for programs that run for a long time under interpretation, so that users can see
+//! that a run is still making progress, and where it is stuck if it seems to hang.
+//! `-Zmiri-report-progress=<N>` reports every `N` basic blocks; `-Zmiri-backtrace-on-signal`
+//! makes the driver set `BACKTRACE_REQUESTED` on `SIGUSR1` and `SIGQUIT` (Ctrl-\), upon which
+//! we print where the program currently is.
+//! `-Zmiri-timeout` and `-Zmiri-test-timeout` abort a run that takes too long, with the
+//! backtrace of where it was at that point, which an external `timeout` would lose.
+//! `-Zmiri-max-steps` does the same after a number of basic blocks, which, unlike time, does
+//! not depend on the machine, and also prints the execution statistics.
+
+use std::cmp;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use syntax::errors::{DiagnosticBuilder, Level};
+
+use crate::*;
+
+/// Set from a signal handler to ask for a backtrace of the running program
+pub static BACKTRACE_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+/// How many basic blocks to execute between two checks of the clock
+const DEADLINE_CHECK_INTERVAL: u64 = 1024;
+
+#[derive(Clone, Debug, Default)]
+pub struct Progress {
+    /// How many basic blocks to execute between two reports, if we report at all
+    interval: Option<u64>,
+    /// How many basic blocks have been executed so far
+    blocks: u64,
+    /// When to abort the program, and the timeout that this deadline enforces
+    deadline: Option<(Instant, Duration)>,
+    /// How many basic blocks the program may execute
+    max_blocks: Option<u64>,
+}
+
+impl Progress {
+    pub fn new(config: &MiriConfig) -> Self {
+        let run_deadline = config.timeout.map(|timeout| (config.start + timeout, timeout));
+        let test_deadline = config.test_timeout.map(|timeout| (Instant::now() + timeout, timeout));
+        let deadline = match (run_deadline, test_deadline) {
+            (Some(run), Some(test)) => Some(cmp::min(run, test)),
+            (run, test) => run.or(test),
+        };
+        Progress { interval: config.report_progress, blocks: 0, deadline, max_blocks: config.max_steps }
+    }
+
+    /// How many basic blocks have been executed so far
+    pub fn blocks(&self) -> u64 {
+        self.blocks
+    }
+
+    /// Whether the program was stopped because it executed too many basic blocks
+    pub fn out_of_steps(&self) -> bool {
+        self.max_blocks.map_or(false, |max| self.blocks > max)
+    }
+}
+
+impl<'a, 'mir, 'tcx> EvalContextExt<'a, 'mir, 'tcx> for crate::MiriEvalContext<'a, 'mir, 'tcx> {}
+pub trait EvalContextExt<'a, 'mir, 'tcx: 'a+'mir>: crate::MiriEvalContextExt<'a, 'mir, 'tcx> {
+    /// Called before every terminator, i.e., once per basic block.  Fails when the program
+    /// ran out of time.
+    fn step_progress(&mut self) -> EvalResult<'tcx> {
+        let this = self.eval_context_mut();
+        let progress = &mut this.machine.progress;
+        progress.blocks += 1;
+        if progress.out_of_steps() {
+            return err!(MachineError(format!(
+                "the program did not finish within {} basic blocks",
+                progress.blocks - 1,
+            )));
+        }
+        if let Some((deadline, timeout)) = progress.deadline {
+            if progress.blocks % DEADLINE_CHECK_INTERVAL == 0 && Instant::now() >= deadline {
+                return err!(MachineError(format!(
+                    "the program did not finish within the timeout of {} seconds",
+                    timeout.as_secs(),
+                )));
+            }
+        }
+        let report = progress.interval.map_or(false, |interval| progress.blocks % interval == 0);
+        // Only write to the flag when it is set, this runs very often.
+        let backtrace = BACKTRACE_REQUESTED.load(Ordering::Relaxed) &&
+            BACKTRACE_REQUESTED.swap(false, Ordering::Relaxed);
+        if report || backtrace {
+            this.report_progress(backtrace);
+        }
+        Ok(())
+    }
+
+    /// Print how far the program got, and where it is, with the whole backtrace if asked to.
+    fn report_progress(&self, backtrace: bool) {
+        let this = self.eval_context_ref();
+        let function = match this.stack().last() {
+            Some(frame) => frame.instance.to_string(),
+            None => return,
+        };
+        let msg = format!(
+            "progress: {} basic blocks executed, {} live heap allocations, now in `{}`",
+            this.machine.progress.blocks,
+            this.machine.alloc_history.live_count(),
+            function,
+        );
+        let mut note = DiagnosticBuilder::new(this.tcx.sess.diagnostic(), Level::Note, &msg);
+        if backtrace {
+            let frames = this.generate_stacktrace(None);
+            this.note_stacktrace(&mut note, &frames);
+        }
+        note.emit();
+    }
+}
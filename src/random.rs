@@ -0,0 +1,39 @@
+//! Random data for the program, from `getrandom` and its equivalents on other platforms.
+//! `std` asks for some whenever it creates a `HashMap` the regular way.  The data comes from
+//! a generator seeded with `-Zmiri-seed`, so that runs are reproducible.  With
+//! `-Zmiri-host-rng`, the driver picks the seed from host entropy instead, and prints it, so
+//! that a failing run can still be reproduced with `-Zmiri-seed`.
+//! Miri has no integer addresses, so there is no address randomization to seed.
+
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::time::SystemTime;
+
+use crate::*;
+
+/// A seed from host entropy, for `-Zmiri-host-rng`.
+pub fn host_seed() -> u64 {
+    // `RandomState` is seeded by the operating system, which saves us a dependency.
+    let mut hasher = RandomState::new().build_hasher();
+    if let Ok(now) = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH) {
+        hasher.write_u64(now.as_secs());
+        hasher.write_u32(now.subsec_nanos());
+    }
+    hasher.write_u32(::std::process::id());
+    hasher.finish()
+}
+
+impl<'a, 'mir, 'tcx> EvalContextExt<'a, 'mir, 'tcx> for crate::MiriEvalContext<'a, 'mir, 'tcx> {}
+pub trait EvalContextExt<'a, 'mir, 'tcx: 'a+'mir>: crate::MiriEvalContextExt<'a, 'mir, 'tcx> {
+    /// Fill the `len` bytes at `ptr` with random data.
+    fn fill_random(&mut self, ptr: Scalar<Borrow>, len: u64) -> EvalResult<'tcx> {
+        let this = self.eval_context_mut();
+        let mut bytes = Vec::with_capacity(len as usize);
+        while (bytes.len() as u64) < len {
+            let word = this.machine.rng.next_u64().to_le_bytes();
+            let missing = (len - bytes.len() as u64) as usize;
+            bytes.extend_from_slice(&word[..missing.min(word.len())]);
+        }
+        this.memory_mut().write_bytes(ptr, &bytes)
+    }
+}
@@ -101,6 +101,11 @@ impl<T> RangeMap<T> {
         self.iter_with_range(offset.bytes(), len.bytes()).map(|(_, data)| data)
     }
 
+    /// Provide read-only iteration over all the ranges and their data, in order.
+    pub fn iter_all<'a>(&'a self) -> impl Iterator<Item = (u64, u64, &'a T)> + 'a {
+        self.map.iter().map(|(range, data)| (range.start, range.end, data))
+    }
+
     pub fn iter_mut_all<'a>(&'a mut self) -> impl Iterator<Item = &'a mut T> + 'a {
         self.map.values_mut()
     }
@@ -0,0 +1,333 @@
+//! Recording and replaying what the program got from the host, so that a failure seen with
+//! access to the host can be reproduced without it, e.g. in CI.
+//! `-Zmiri-record=<dir>` logs the results of everything the program does on the host: the
+//! environment variables it was given, and opening, reading and writing files (including a
+//! standard input from the host) to `<dir>/<program>.replay`.  `-Zmiri-replay=<dir>` serves
+//! these results back instead of touching the host.  Everything else miri does is
+//! deterministic anyway, so the program runs exactly like it did when it was recorded.
+//!
+//! The log has one event per line, with fields separated by spaces.  Names, paths and data
+//! are hex-encoded, `-` if they are empty, so that they can contain spaces and newlines:
+//!
+//! ```text
+//! env 484f4d45 2f686f6d652f75736572
+//! open 666978747572652e747874 ok 3
+//! read 3 ok 68656c6c6f
+//! read 3 ok -
+//! write 4 err EACCES
+//! ```
+
+use std::collections::VecDeque;
+use std::fmt::Write;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use crate::*;
+
+#[derive(Clone, Debug)]
+pub enum ReplayMode {
+    /// Record the log into this directory
+    Record(PathBuf),
+    /// Replay the log from this directory
+    Replay(PathBuf),
+}
+
+/// The result of an operation, with the name of the `errno` value if it failed
+type OpResult<T> = Result<T, String>;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Event {
+    Env { name: String, value: String },
+    Open { path: Vec<u8>, result: OpResult<i32> },
+    Read { fd: i32, result: OpResult<Vec<u8>> },
+    Write { fd: i32, result: OpResult<u64> },
+}
+
+#[derive(Debug, Default)]
+pub struct Replay {
+    mode: Option<ReplayMode>,
+    /// The file of the log
+    path: PathBuf,
+    /// When recording, the events so far.  When replaying, those still to come.
+    events: VecDeque<Event>,
+}
+
+impl Replay {
+    /// Start recording or replaying the log of `program`, the path of the main or test function.
+    pub fn new(mode: Option<ReplayMode>, program: &str) -> Result<Self, String> {
+        let name = format!("{}.replay", crate::helpers::program_file_stem(program));
+        let (path, events) = match mode {
+            None => (PathBuf::new(), VecDeque::new()),
+            Some(ReplayMode::Record(ref dir)) => (dir.join(name), VecDeque::new()),
+            Some(ReplayMode::Replay(ref dir)) => {
+                let path = dir.join(name);
+                let log = fs::read_to_string(&path)
+                    .map_err(|err| format!("cannot read the replay log {}: {}", path.display(), err))?;
+                let events = parse(&log)
+                    .map_err(|err| format!("invalid replay log {}: {}", path.display(), err))?;
+                (path, events)
+            }
+        };
+        Ok(Replay { mode, path, events })
+    }
+
+    pub fn replaying(&self) -> bool {
+        match self.mode {
+            Some(ReplayMode::Replay(_)) => true,
+            _ => false,
+        }
+    }
+
+    fn record(&mut self, event: Event) {
+        if let Some(ReplayMode::Record(_)) = self.mode {
+            self.events.push_back(event);
+        }
+    }
+
+    /// The environment variables of the program: the recorded ones when replaying, `vars`
+    /// otherwise.
+    pub fn env_vars(&mut self, vars: Vec<(String, String)>) -> Vec<(String, String)> {
+        if !self.replaying() {
+            for (name, value) in &vars {
+                self.record(Event::Env { name: name.clone(), value: value.clone() });
+            }
+            return vars;
+        }
+        let mut vars = Vec::new();
+        while let Some(Event::Env { .. }) = self.events.front() {
+            if let Some(Event::Env { name, value }) = self.events.pop_front() {
+                vars.push((name, value));
+            }
+        }
+        vars
+    }
+
+    /// The next event, which has to be for `op`, for an operation the program does now.
+    fn next<'tcx>(&mut self, op: &str) -> EvalResult<'tcx, Event> {
+        match self.events.pop_front() {
+            Some(event) => Ok(event),
+            None => err!(MachineError(format!(
+                "the program did {}, but the replay log {} has no more events",
+                op, self.path.display(),
+            ))),
+        }
+    }
+
+    fn diverged<'tcx, T>(&self, op: &str, event: &Event) -> EvalResult<'tcx, T> {
+        err!(MachineError(format!(
+            "the program did {}, but the replay log {} has `{}` next, \
+             the program is not running like it did when it was recorded",
+            op, self.path.display(), format_event(event).trim_end(),
+        )))
+    }
+
+    /// Write the log, when recording.
+    pub fn finish(&self) -> io::Result<()> {
+        if let Some(ReplayMode::Record(ref dir)) = self.mode {
+            fs::create_dir_all(dir)?;
+            let log: String = self.events.iter().map(format_event).collect();
+            fs::write(&self.path, log)?;
+        }
+        Ok(())
+    }
+}
+
+/// The name of the `errno` value for an error of the host.  The numbers of the host may not
+/// be those of the target, so we go by the kind of the error.
+pub fn errno_name(err: &io::Error) -> &'static str {
+    match err.kind() {
+        io::ErrorKind::NotFound => "ENOENT",
+        io::ErrorKind::PermissionDenied => "EACCES",
+        io::ErrorKind::AlreadyExists => "EEXIST",
+        io::ErrorKind::InvalidInput => "EINVAL",
+        _ => "EIO",
+    }
+}
+
+impl<'a, 'mir, 'tcx> EvalContextExt<'a, 'mir, 'tcx> for crate::MiriEvalContext<'a, 'mir, 'tcx> {}
+pub trait EvalContextExt<'a, 'mir, 'tcx: 'a+'mir>: crate::MiriEvalContextExt<'a, 'mir, 'tcx> {
+    /// Open `path` on the host with `open`, or take the result from the replay log.
+    fn replayed_open(
+        &mut self,
+        path: &[u8],
+        open: impl FnOnce(&mut MiriEvalContext<'a, 'mir, 'tcx>) -> io::Result<i32>,
+    ) -> EvalResult<'tcx, OpResult<i32>> {
+        let this = self.eval_context_mut();
+        let result = if this.machine.replay.replaying() {
+            let op = format!("open `{}`", String::from_utf8_lossy(path));
+            match this.machine.replay.next(&op)? {
+                Event::Open { path: ref recorded, ref result } if recorded[..] == path[..] => result.clone(),
+                event => return this.machine.replay.diverged(&op, &event),
+            }
+        } else {
+            open(this).map_err(|err| errno_name(&err).to_owned())
+        };
+        this.machine.replay.record(Event::Open { path: path.to_owned(), result: result.clone() });
+        Ok(result)
+    }
+
+    /// Read from `fd` on the host with `read`, or take the result from the replay log.
+    fn replayed_read(
+        &mut self,
+        fd: i32,
+        read: impl FnOnce(&mut MiriEvalContext<'a, 'mir, 'tcx>) -> io::Result<Vec<u8>>,
+    ) -> EvalResult<'tcx, OpResult<Vec<u8>>> {
+        let this = self.eval_context_mut();
+        let result = if this.machine.replay.replaying() {
+            let op = format!("read from FD {}", fd);
+            match this.machine.replay.next(&op)? {
+                Event::Read { fd: recorded, ref result } if recorded == fd => result.clone(),
+                event => return this.machine.replay.diverged(&op, &event),
+            }
+        } else {
+            read(this).map_err(|err| errno_name(&err).to_owned())
+        };
+        this.machine.replay.record(Event::Read { fd, result: result.clone() });
+        Ok(result)
+    }
+
+    /// Write to `fd` on the host with `write`, or take the result from the replay log.
+    fn replayed_write(
+        &mut self,
+        fd: i32,
+        write: impl FnOnce(&mut MiriEvalContext<'a, 'mir, 'tcx>) -> io::Result<u64>,
+    ) -> EvalResult<'tcx, OpResult<u64>> {
+        let this = self.eval_context_mut();
+        let result = if this.machine.replay.replaying() {
+            let op = format!("write to FD {}", fd);
+            match this.machine.replay.next(&op)? {
+                Event::Write { fd: recorded, ref result } if recorded == fd => result.clone(),
+                event => return this.machine.replay.diverged(&op, &event),
+            }
+        } else {
+            write(this).map_err(|err| errno_name(&err).to_owned())
+        };
+        this.machine.replay.record(Event::Write { fd, result: result.clone() });
+        Ok(result)
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    if bytes.is_empty() {
+        return "-".to_owned();
+    }
+    let mut hex = String::with_capacity(bytes.len() * 2);
+    for byte in bytes {
+        write!(hex, "{:02x}", byte).unwrap();
+    }
+    hex
+}
+
+fn unhex(hex: &str) -> Result<Vec<u8>, String> {
+    if hex == "-" {
+        return Ok(Vec::new());
+    }
+    if hex.len() % 2 != 0 {
+        return Err(format!("`{}` is not hex-encoded data", hex));
+    }
+    (0..hex.len()).step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16)
+            .map_err(|_| format!("`{}` is not hex-encoded data", hex)))
+        .collect()
+}
+
+fn format_result<T>(result: &OpResult<T>, format: impl FnOnce(&T) -> String) -> String {
+    match *result {
+        Ok(ref value) => format!("ok {}", format(value)),
+        Err(ref errno) => format!("err {}", errno),
+    }
+}
+
+fn format_event(event: &Event) -> String {
+    match *event {
+        Event::Env { ref name, ref value } =>
+            format!("env {} {}\n", hex(name.as_bytes()), hex(value.as_bytes())),
+        Event::Open { ref path, ref result } =>
+            format!("open {} {}\n", hex(path), format_result(result, |fd| fd.to_string())),
+        Event::Read { fd, ref result } =>
+            format!("read {} {}\n", fd, format_result(result, |bytes| hex(bytes))),
+        Event::Write { fd, ref result } =>
+            format!("write {} {}\n", fd, format_result(result, |n| n.to_string())),
+    }
+}
+
+fn number<T: FromStr>(field: &str) -> Result<T, String> {
+    field.parse().map_err(|_| format!("`{}` is not a number", field))
+}
+
+fn parse(log: &str) -> Result<VecDeque<Event>, String> {
+    let mut events = VecDeque::new();
+    for (line_number, line) in log.lines().enumerate() {
+        let error = |msg: String| format!("line {}: {}", line_number + 1, msg);
+        let fields: Vec<&str> = line.split(' ').collect();
+        // The result of an operation is in the last two fields.
+        let result = || match (fields[2], fields[3]) {
+            ("ok", value) => Ok(Ok(value)),
+            ("err", errno) => Ok(Err(errno.to_owned())),
+            _ => Err(format!("expected `ok <value>` or `err <errno>`, got `{}`", line)),
+        };
+        let event = match (fields[0], fields.len()) {
+            ("env", 3) => {
+                let name = String::from_utf8(unhex(fields[1]).map_err(&error)?)
+                    .map_err(|_| error("the name of a variable is not UTF-8".to_owned()))?;
+                let value = String::from_utf8(unhex(fields[2]).map_err(&error)?)
+                    .map_err(|_| error(format!("the value of `{}` is not UTF-8", name)))?;
+                Event::Env { name, value }
+            }
+            ("open", 4) => Event::Open {
+                path: unhex(fields[1]).map_err(&error)?,
+                result: match result().map_err(&error)? {
+                    Ok(fd) => Ok(number(fd).map_err(&error)?),
+                    Err(errno) => Err(errno),
+                },
+            },
+            ("read", 4) => Event::Read {
+                fd: number(fields[1]).map_err(&error)?,
+                result: match result().map_err(&error)? {
+                    Ok(data) => Ok(unhex(data).map_err(&error)?),
+                    Err(errno) => Err(errno),
+                },
+            },
+            ("write", 4) => Event::Write {
+                fd: number(fields[1]).map_err(&error)?,
+                result: match result().map_err(&error)? {
+                    Ok(n) => Ok(number(n).map_err(&error)?),
+                    Err(errno) => Err(errno),
+                },
+            },
+            _ => return Err(error(format!("unknown event `{}`", line))),
+        };
+        events.push_back(event);
+    }
+    Ok(events)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_and_parse() {
+        let events = vec![
+            Event::Env { name: "HOME".to_owned(), value: "/home/user".to_owned() },
+            Event::Env { name: "A NAME".to_owned(), value: "a value\nwith spaces".to_owned() },
+            Event::Env { name: "EMPTY".to_owned(), value: String::new() },
+            Event::Open { path: b"fixture.txt".to_vec(), result: Ok(3) },
+            Event::Open { path: b"missing".to_vec(), result: Err("ENOENT".to_owned()) },
+            Event::Read { fd: 3, result: Ok(b"hello".to_vec()) },
+            Event::Read { fd: 3, result: Ok(Vec::new()) },
+            Event::Write { fd: 4, result: Ok(12) },
+            Event::Write { fd: 4, result: Err("EACCES".to_owned()) },
+        ];
+        let log: String = events.iter().map(format_event).collect();
+        assert_eq!(parse(&log).unwrap(), events.into_iter().collect::<VecDeque<_>>());
+
+        assert!(parse("env HOME 2f").is_err());
+        assert!(parse("read 3 ok 6").is_err());
+        assert!(parse("read x ok -").is_err());
+        assert!(parse("read 3 maybe -").is_err());
+        assert!(parse("seek 3 ok 0").is_err());
+    }
+}
@@ -0,0 +1,295 @@
+//! A report of all findings of a run, written with `-Zmiri-report=html=<dir>`, for reviewing
+//! the results of a big test suite outside a terminal, or with `-Zmiri-report=json=<path>`,
+//! for tools.  We record every diagnostic miri emits, with its backtrace and notes, and write
+//! them all out when the run is done.  Tools that embed miri use an in-memory report instead,
+//! and take the findings as values.
+//!
+//! The JSON report has what `--error-format=json` cannot have, as the format of the compiler
+//! is fixed: the allocations and borrow tags a finding is about, as fields of their own.
+
+use std::fmt::Write;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use rustc::hir::def_id::DefId;
+use rustc::ty::TyCtxt;
+use syntax::errors::{Diagnostic, DiagnosticId};
+use syntax::source_map::{FileName, Span, DUMMY_SP};
+
+use crate::{AllocId, Borrow};
+use crate::state_dump::json_string;
+
+/// Where in the source code a message points to
+#[derive(Clone, Debug)]
+pub struct Location {
+    pub file: String,
+    /// A `file://` URL of the source file, if it is on disk
+    pub url: Option<String>,
+    /// Both 1-based
+    pub line: usize,
+    pub column: usize,
+    /// The source line
+    pub snippet: Option<String>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Message {
+    pub text: String,
+    pub location: Option<Location>,
+}
+
+/// An error or warning miri reported
+#[derive(Clone, Debug)]
+pub struct Finding {
+    /// The test or main function that was running
+    pub program: String,
+    /// The class of the finding, like `miri::undefined_behavior`, see `ErrorClass::code`
+    pub code: Option<String>,
+    pub message: Message,
+    /// The backtrace and the other notes, in the order they were added
+    pub notes: Vec<Message>,
+    /// The allocations the finding is about, like the one accessed out of bounds, or the
+    /// leaked ones
+    pub alloc_ids: Vec<AllocId>,
+    /// The borrow tags the finding is about, like the one Stacked Borrows rejected
+    pub tags: Vec<Borrow>,
+}
+
+#[derive(Clone, Debug)]
+enum Output {
+    /// `index.html` in this directory
+    Html(PathBuf),
+    /// This JSON file
+    Json(PathBuf),
+}
+
+/// The findings of a run.  Clones share the findings, so every test can record into it.
+#[derive(Clone, Debug)]
+pub struct Report {
+    /// Where to write the report, if anywhere
+    output: Option<Output>,
+    findings: Arc<Mutex<Vec<Finding>>>,
+}
+
+impl Report {
+    /// A report that `write` writes to `dir` as HTML
+    pub fn new(dir: PathBuf) -> Self {
+        Report { output: Some(Output::Html(dir)), findings: Arc::default() }
+    }
+
+    /// A report that `write` writes to the file `path` as JSON
+    pub fn json(path: PathBuf) -> Self {
+        Report { output: Some(Output::Json(path)), findings: Arc::default() }
+    }
+
+    /// A report that only collects the findings, for `findings`
+    pub fn in_memory() -> Self {
+        Report { output: None, findings: Arc::default() }
+    }
+
+    /// The findings recorded so far
+    pub fn findings(&self) -> Vec<Finding> {
+        self.findings.lock().unwrap().clone()
+    }
+
+    /// Record a diagnostic that is about to be emitted while running `program`, and which is
+    /// about the allocations `alloc_ids` and the borrow tags `tags`.
+    pub fn record(
+        &self,
+        tcx: TyCtxt,
+        program: DefId,
+        diag: &Diagnostic,
+        alloc_ids: &[AllocId],
+        tags: &[Borrow],
+    ) {
+        let primary = diag.span.primary_span().unwrap_or(DUMMY_SP);
+        let finding = Finding {
+            program: tcx.item_path_str(program),
+            code: match diag.code {
+                Some(DiagnosticId::Error(ref code)) => Some(code.clone()),
+                _ => None,
+            },
+            message: Message { text: diag.message(), location: location(tcx, primary) },
+            notes: diag.children.iter().map(|child| Message {
+                text: child.message(),
+                location: location(tcx, child.span.primary_span().unwrap_or(DUMMY_SP)),
+            }).collect(),
+            alloc_ids: alloc_ids.to_vec(),
+            tags: tags.to_vec(),
+        };
+        self.findings.lock().unwrap().push(finding);
+    }
+
+    /// Write the report, if it is not an in-memory one.
+    pub fn write(&self) -> io::Result<()> {
+        match self.output {
+            Some(Output::Html(ref dir)) => self.write_html(dir),
+            Some(Output::Json(ref path)) => self.write_json(path),
+            None => Ok(()),
+        }
+    }
+
+    /// Write `index.html` into `dir`, grouping the findings by program.
+    fn write_html(&self, dir: &Path) -> io::Result<()> {
+        let findings = self.findings.lock().unwrap();
+        let mut programs: Vec<&str> = Vec::new();
+        for finding in findings.iter() {
+            if !programs.contains(&&*finding.program) {
+                programs.push(&finding.program);
+            }
+        }
+
+        let mut html = String::new();
+        html.push_str(HEADER);
+        write!(
+            html, "<h1>Miri report</h1>\n<p>{} finding(s) in {} program(s)</p>\n",
+            findings.len(), programs.len(),
+        ).unwrap();
+        for program in programs {
+            write!(html, "<h2><code>{}</code></h2>\n", escape(program)).unwrap();
+            for finding in findings.iter().filter(|finding| finding.program == program) {
+                html.push_str("<div class=\"finding\">\n<h3>");
+                if let Some(ref code) = finding.code {
+                    write!(html, "<span class=\"code\">{}</span> ", escape(code)).unwrap();
+                }
+                write!(html, "{}</h3>\n", escape(&finding.message.text)).unwrap();
+                write_location(&mut html, &finding.message.location);
+                if !finding.notes.is_empty() {
+                    write!(
+                        html, "<details>\n<summary>Backtrace and notes ({})</summary>\n<ul>\n",
+                        finding.notes.len(),
+                    ).unwrap();
+                    for note in &finding.notes {
+                        html.push_str("<li>");
+                        if note.text.contains('\n') {
+                            // Memory dumps and lists need their layout.
+                            write!(html, "<pre>{}</pre>", escape(&note.text)).unwrap();
+                        } else {
+                            html.push_str(&escape(&note.text));
+                        }
+                        write_location(&mut html, &note.location);
+                        html.push_str("</li>\n");
+                    }
+                    html.push_str("</ul>\n</details>\n");
+                }
+                html.push_str("</div>\n");
+            }
+        }
+        html.push_str("</body>\n</html>\n");
+
+        fs::create_dir_all(dir)?;
+        fs::write(dir.join("index.html"), html)
+    }
+
+    /// Write the findings to `path` as a JSON array, one finding per line.
+    fn write_json(&self, path: &Path) -> io::Result<()> {
+        let findings: Vec<String> = self.findings.lock().unwrap().iter()
+            .map(|finding| {
+                let code = match finding.code {
+                    Some(ref code) => json_string(code),
+                    None => "null".to_owned(),
+                };
+                let alloc_ids: Vec<String> = finding.alloc_ids.iter().map(|id| id.0.to_string()).collect();
+                let tags: Vec<String> = finding.tags.iter().map(|tag| json_tag(*tag)).collect();
+                let notes: Vec<String> = finding.notes.iter().map(json_message).collect();
+                format!(
+                    "{{\"program\":{},\"code\":{},\"message\":{},\
+                     \"alloc_ids\":[{}],\"tags\":[{}],\"notes\":[{}]}}",
+                    json_string(&finding.program), code, json_message(&finding.message),
+                    alloc_ids.join(","), tags.join(","), notes.join(","),
+                )
+            })
+            .collect();
+        fs::write(path, format!("[\n{}\n]\n", findings.join(",\n")))
+    }
+}
+
+fn json_message(message: &Message) -> String {
+    let location = match message.location {
+        Some(ref location) => format!(
+            "{{\"file\":{},\"line\":{},\"column\":{}}}",
+            json_string(&location.file), location.line, location.column,
+        ),
+        None => "null".to_owned(),
+    };
+    format!("{{\"text\":{},\"location\":{}}}", json_string(&message.text), location)
+}
+
+/// A borrow tag as JSON: `{"kind":"unique","time":5}`, and `"shared"` with the time it was
+/// frozen at, if it was, or `null`.
+fn json_tag(tag: Borrow) -> String {
+    let (kind, time) = match tag {
+        Borrow::Uniq(time) => ("unique", Some(time)),
+        Borrow::Shr(time) => ("shared", time),
+    };
+    let time = time.map_or("null".to_owned(), |time| time.to_string());
+    format!("{{\"kind\":\"{}\",\"time\":{}}}", kind, time)
+}
+
+const HEADER: &str = "<!DOCTYPE html>
+<html>
+<head>
+<meta charset=\"utf-8\">
+<title>Miri report</title>
+<style>
+body { font-family: sans-serif; margin: 2em; }
+.finding { border-left: 4px solid #c00; padding-left: 1em; margin-bottom: 2em; }
+.code { color: #c00; font-family: monospace; }
+.location { font-family: monospace; font-size: small; }
+pre { background: #f4f4f4; padding: 0.5em; overflow-x: auto; }
+</style>
+</head>
+<body>
+";
+
+fn location(tcx: TyCtxt, span: Span) -> Option<Location> {
+    if span == DUMMY_SP {
+        return None;
+    }
+    let loc = tcx.sess.source_map().lookup_char_pos(span.lo());
+    let url = match loc.file.name {
+        FileName::Real(ref path) => fs::canonicalize(path).ok()
+            .map(|path| format!("file://{}", path.display())),
+        _ => None,
+    };
+    Some(Location {
+        file: loc.file.name.to_string(),
+        url,
+        line: loc.line,
+        column: loc.col.0 + 1,
+        snippet: loc.file.get_line(loc.line - 1).map(|line| line.into_owned()),
+    })
+}
+
+fn write_location(html: &mut String, location: &Option<Location>) {
+    let location = match *location {
+        Some(ref location) => location,
+        None => return,
+    };
+    let text = format!("{}:{}:{}", location.file, location.line, location.column);
+    match location.url {
+        Some(ref url) => write!(
+            html, "<div class=\"location\"><a href=\"{}\">{}</a></div>\n", escape(url), escape(&text),
+        ).unwrap(),
+        None => write!(html, "<div class=\"location\">{}</div>\n", escape(&text)).unwrap(),
+    }
+    if let Some(ref snippet) = location.snippet {
+        write!(html, "<pre>{}</pre>\n", escape(snippet)).unwrap();
+    }
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '&' => escaped.push_str("&amp;"),
+            '"' => escaped.push_str("&quot;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
@@ -0,0 +1,80 @@
+//! `-Zmiri-self-profile=<dir>`: where the interpreter itself spends its time, so that a slow
+//! run can be attributed to the phases of miri rather than to the program.  At the end of
+//! each run we write, for each phase, how often it ran and how long it took in total, to
+//! `<dir>/<program>-<pid>.miri-profile`.
+//! Validating values happens inside the interpreter core, which gives us no way to time it;
+//! it is part of `run`, like everything else the program does.
+
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::process;
+use std::time::{Duration, Instant};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Phase {
+    /// Creating the interpreter and the first stack frames
+    Startup,
+    /// Running the program, including the phases below
+    Run,
+    /// Stacked Borrows retags, part of `Run`
+    Retag,
+    /// Emulating foreign functions, part of `Run`
+    Shim,
+    /// Running the destructors of thread-local storage
+    TlsDtors,
+}
+
+const PHASES: [Phase; 5] = [Phase::Startup, Phase::Run, Phase::Retag, Phase::Shim, Phase::TlsDtors];
+
+impl Phase {
+    fn name(self) -> &'static str {
+        match self {
+            Phase::Startup => "startup",
+            Phase::Run => "run",
+            Phase::Retag => "retag",
+            Phase::Shim => "shim",
+            Phase::TlsDtors => "tls-dtors",
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct SelfProfile {
+    dir: PathBuf,
+    /// How often each phase ran, and for how long in total
+    phases: [(u64, Duration); 5],
+}
+
+impl SelfProfile {
+    pub fn new(dir: PathBuf) -> Self {
+        SelfProfile { dir, phases: [(0, Duration::from_secs(0)); 5] }
+    }
+
+    /// Start timing a phase, if we profile at all.  Pass the result to `record`.
+    pub fn start(profile: &Option<SelfProfile>) -> Option<Instant> {
+        profile.as_ref().map(|_| Instant::now())
+    }
+
+    /// Record that `phase` ran from `start` until now.
+    pub fn record(profile: &mut Option<SelfProfile>, phase: Phase, start: Option<Instant>) {
+        if let (Some(profile), Some(start)) = (profile.as_mut(), start) {
+            let entry = &mut profile.phases[phase as usize];
+            entry.0 += 1;
+            entry.1 += start.elapsed();
+        }
+    }
+
+    /// Write the profile of the run of `program`.
+    pub fn write(&self, program: &str) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        let name = format!("{}-{}.miri-profile", crate::helpers::program_file_stem(program), process::id());
+        let mut profile = format!("# miri self-profile of `{}`\n# phase calls microseconds\n", program);
+        for &phase in PHASES.iter() {
+            let (calls, time) = self.phases[phase as usize];
+            let micros = time.as_secs() * 1_000_000 + u64::from(time.subsec_micros());
+            profile.push_str(&format!("{} {} {}\n", phase.name(), calls, micros));
+        }
+        fs::write(self.dir.join(name), profile)
+    }
+}
@@ -0,0 +1,606 @@
+//! Emulation of the platform-independent SIMD intrinsics (`simd_*`).
+//! They operate lane-wise on `#[repr(simd)]` types; the lanes are the fields of the vector.
+//! We check every lane for UB individually, so that errors point at the offending lane.
+
+use rustc::mir;
+use rustc::ty::layout::{Size, TyLayout};
+
+use crate::*;
+
+/// The kind of a lane, as far as the intrinsics care.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum LaneKind {
+    Int { signed: bool },
+    Float,
+    Ptr,
+}
+
+impl<'a, 'mir, 'tcx> EvalContextExt<'a, 'mir, 'tcx> for crate::MiriEvalContext<'a, 'mir, 'tcx> {}
+pub trait EvalContextExt<'a, 'mir, 'tcx: 'a+'mir>: crate::MiriEvalContextExt<'a, 'mir, 'tcx> {
+    fn call_simd_intrinsic(
+        &mut self,
+        intrinsic_name: &str,
+        args: &[OpTy<'tcx, Borrow>],
+        dest: PlaceTy<'tcx, Borrow>,
+    ) -> EvalResult<'tcx> {
+        let this = self.eval_context_mut();
+        match intrinsic_name {
+            "simd_add" | "simd_sub" | "simd_mul" | "simd_div" | "simd_rem" |
+            "simd_shl" | "simd_shr" | "simd_and" | "simd_or" | "simd_xor" |
+            "simd_fmin" | "simd_fmax" => {
+                let left = this.simd_lanes(args[0])?;
+                let right = this.simd_lanes(args[1])?;
+                let dest = this.simd_dest_lanes(dest)?;
+                assert_eq!(left.len(), dest.len());
+                assert_eq!(right.len(), dest.len());
+                for (lane, ((&l, &r), &d)) in left.iter().zip(&right).zip(&dest).enumerate() {
+                    let val = match intrinsic_name {
+                        "simd_fmin" | "simd_fmax" => {
+                            let l = this.read_scalar(l)?.not_undef()?;
+                            let r = this.read_scalar(r)?.not_undef()?;
+                            let min = intrinsic_name == "simd_fmin";
+                            float_min_max(l, r, d.layout.size, min)?
+                        }
+                        _ => {
+                            let op = match intrinsic_name {
+                                "simd_add" => mir::BinOp::Add,
+                                "simd_sub" => mir::BinOp::Sub,
+                                "simd_mul" => mir::BinOp::Mul,
+                                "simd_div" => mir::BinOp::Div,
+                                "simd_rem" => mir::BinOp::Rem,
+                                "simd_shl" => mir::BinOp::Shl,
+                                "simd_shr" => mir::BinOp::Shr,
+                                "simd_and" => mir::BinOp::BitAnd,
+                                "simd_or" => mir::BinOp::BitOr,
+                                "simd_xor" => mir::BinOp::BitXor,
+                                _ => bug!(),
+                            };
+                            let l_val = this.read_scalar(l)?.not_undef()?;
+                            let r_val = this.read_scalar(r)?.not_undef()?;
+                            let (val, overflowed) = this.binary_op(op, l_val, l.layout, r_val, r.layout)?;
+                            match op {
+                                mir::BinOp::Shl | mir::BinOp::Shr if overflowed => {
+                                    return err!(Intrinsic(format!(
+                                        "Overflowing shift by {:?} in {} in lane {}",
+                                        r_val, intrinsic_name, lane,
+                                    )));
+                                }
+                                mir::BinOp::Div | mir::BinOp::Rem if overflowed => {
+                                    return err!(Intrinsic(format!(
+                                        "Overflow in {} in lane {}", intrinsic_name, lane,
+                                    )));
+                                }
+                                // Everything else wraps around.
+                                _ => val,
+                            }
+                        }
+                    };
+                    this.write_scalar(val, d.into())?;
+                }
+            }
+
+            "simd_neg" | "simd_fabs" | "simd_fsqrt" | "simd_ceil" | "simd_floor" |
+            "simd_round" | "simd_trunc" => {
+                let lanes = this.simd_lanes(args[0])?;
+                let dest = this.simd_dest_lanes(dest)?;
+                assert_eq!(lanes.len(), dest.len());
+                for (&lane, &d) in lanes.iter().zip(&dest) {
+                    let val = this.read_scalar(lane)?.not_undef()?;
+                    let val = if intrinsic_name == "simd_neg" {
+                        this.unary_op(mir::UnOp::Neg, val, lane.layout)?
+                    } else {
+                        if lane_kind(lane.layout) != LaneKind::Float {
+                            return err!(Intrinsic(format!(
+                                "{} called on non-float lanes", intrinsic_name,
+                            )));
+                        }
+                        let f = |f: f64| match intrinsic_name {
+                            "simd_fabs" => f.abs(),
+                            "simd_fsqrt" => f.sqrt(),
+                            "simd_ceil" => f.ceil(),
+                            "simd_floor" => f.floor(),
+                            "simd_round" => f.round(),
+                            "simd_trunc" => f.trunc(),
+                            _ => bug!(),
+                        };
+                        // `fabs` only clears the sign bit, even of a NaN.
+                        let keep_nan = intrinsic_name == "simd_fabs";
+                        match lane.layout.size.bytes() {
+                            // Computing these in `f64` and rounding gives the correctly
+                            // rounded `f32` result.
+                            4 => {
+                                let res = f(val.to_f32()? as f64) as f32;
+                                Scalar::from_f32(if keep_nan { res } else { this.float_result_f32(res) })
+                            }
+                            8 => {
+                                let res = f(val.to_f64()?);
+                                Scalar::from_f64(if keep_nan { res } else { this.float_result_f64(res) })
+                            }
+                            _ => bug!("unsupported float size"),
+                        }
+                    };
+                    this.write_scalar(val, d.into())?;
+                }
+            }
+
+            "simd_fma" => {
+                let a = this.simd_lanes(args[0])?;
+                let b = this.simd_lanes(args[1])?;
+                let c = this.simd_lanes(args[2])?;
+                let dest = this.simd_dest_lanes(dest)?;
+                for (i, &d) in dest.iter().enumerate() {
+                    let a = this.read_scalar(a[i])?.not_undef()?;
+                    let b = this.read_scalar(b[i])?.not_undef()?;
+                    let c = this.read_scalar(c[i])?.not_undef()?;
+                    let val = match d.layout.size.bytes() {
+                        4 => {
+                            let res = a.to_f32()?.mul_add(b.to_f32()?, c.to_f32()?);
+                            Scalar::from_f32(this.float_result_f32(res))
+                        }
+                        8 => {
+                            let res = a.to_f64()?.mul_add(b.to_f64()?, c.to_f64()?);
+                            Scalar::from_f64(this.float_result_f64(res))
+                        }
+                        _ => bug!("unsupported float size"),
+                    };
+                    this.write_scalar(val, d.into())?;
+                }
+            }
+
+            "simd_eq" | "simd_ne" | "simd_lt" | "simd_le" | "simd_gt" | "simd_ge" => {
+                let op = match intrinsic_name {
+                    "simd_eq" => mir::BinOp::Eq,
+                    "simd_ne" => mir::BinOp::Ne,
+                    "simd_lt" => mir::BinOp::Lt,
+                    "simd_le" => mir::BinOp::Le,
+                    "simd_gt" => mir::BinOp::Gt,
+                    "simd_ge" => mir::BinOp::Ge,
+                    _ => bug!(),
+                };
+                let left = this.simd_lanes(args[0])?;
+                let right = this.simd_lanes(args[1])?;
+                let dest = this.simd_dest_lanes(dest)?;
+                assert_eq!(left.len(), dest.len());
+                for ((&l, &r), &d) in left.iter().zip(&right).zip(&dest) {
+                    let l_val = this.read_scalar(l)?.not_undef()?;
+                    let r_val = this.read_scalar(r)?.not_undef()?;
+                    let (res, _) = this.binary_op(op, l_val, l.layout, r_val, r.layout)?;
+                    let res = mask_lane(res.to_bool()?, d.layout.size);
+                    this.write_scalar(res, d.into())?;
+                }
+            }
+
+            "simd_extract" => {
+                let lanes = this.simd_lanes(args[0])?;
+                let index = this.read_scalar(args[1])?.to_u32()? as usize;
+                if index >= lanes.len() {
+                    return err!(Intrinsic(format!(
+                        "simd_extract: index {} is out of bounds for a vector of {} lanes",
+                        index, lanes.len(),
+                    )));
+                }
+                this.copy_op(lanes[index], dest)?;
+            }
+            "simd_insert" => {
+                let lanes = this.simd_lanes(args[0])?;
+                let index = this.read_scalar(args[1])?.to_u32()? as usize;
+                if index >= lanes.len() {
+                    return err!(Intrinsic(format!(
+                        "simd_insert: index {} is out of bounds for a vector of {} lanes",
+                        index, lanes.len(),
+                    )));
+                }
+                let dest = this.simd_dest_lanes(dest)?;
+                for (i, (&lane, &d)) in lanes.iter().zip(&dest).enumerate() {
+                    let src = if i == index { args[2] } else { lane };
+                    this.copy_op(src, d.into())?;
+                }
+            }
+
+            "simd_shuffle_dyn" => {
+                // fn simd_shuffle_dyn<T, I>(x: T, idx: I) -> T
+                // Like `simd_shuffle*`, but the indices are a vector known only at run time.
+                let lanes = this.simd_lanes(args[0])?;
+                let indices = this.simd_lanes(args[1])?;
+                let dest = this.simd_dest_lanes(dest)?;
+                assert_eq!(indices.len(), dest.len());
+                for (i, (&index, &d)) in indices.iter().zip(&dest).enumerate() {
+                    let index = this.read_scalar(index)?.to_bits(index.layout.size)?;
+                    if index >= lanes.len() as u128 {
+                        return err!(Intrinsic(format!(
+                            "simd_shuffle_dyn: index {} is out of bounds for a vector of {} lanes in lane {}",
+                            index, lanes.len(), i,
+                        )));
+                    }
+                    this.copy_op(lanes[index as usize], d.into())?;
+                }
+            }
+            name if name.starts_with("simd_shuffle") => {
+                let left = this.simd_lanes(args[0])?;
+                let right = this.simd_lanes(args[1])?;
+                let dest = this.simd_dest_lanes(dest)?;
+                let total = left.len() + right.len();
+                for (i, &d) in dest.iter().enumerate() {
+                    let index = this.operand_field(args[2], i as u64)?;
+                    let index = this.read_scalar(index)?.to_u32()? as usize;
+                    let src = if index < left.len() {
+                        left[index]
+                    } else if index < total {
+                        right[index - left.len()]
+                    } else {
+                        return err!(Intrinsic(format!(
+                            "{}: index {} is out of bounds for {} input lanes in lane {}",
+                            name, index, total, i,
+                        )));
+                    };
+                    this.copy_op(src, d.into())?;
+                }
+            }
+
+            "simd_select" => {
+                let mask = this.simd_lanes(args[0])?;
+                let yes = this.simd_lanes(args[1])?;
+                let no = this.simd_lanes(args[2])?;
+                let dest = this.simd_dest_lanes(dest)?;
+                assert_eq!(mask.len(), dest.len());
+                for (i, &d) in dest.iter().enumerate() {
+                    let src = if this.read_mask_lane(mask[i], intrinsic_name, i)? { yes[i] } else { no[i] };
+                    this.copy_op(src, d.into())?;
+                }
+            }
+            "simd_select_bitmask" => {
+                let mask = this.read_scalar(args[0])?.to_bits(args[0].layout.size)?;
+                let yes = this.simd_lanes(args[1])?;
+                let no = this.simd_lanes(args[2])?;
+                let dest = this.simd_dest_lanes(dest)?;
+                if dest.len() < args[0].layout.size.bits() as usize && mask >> dest.len() != 0 {
+                    return err!(Intrinsic(format!(
+                        "simd_select_bitmask: mask {:#b} has bits set beyond the {} lanes",
+                        mask, dest.len(),
+                    )));
+                }
+                for (i, &d) in dest.iter().enumerate() {
+                    let src = if mask & (1 << i) != 0 { yes[i] } else { no[i] };
+                    this.copy_op(src, d.into())?;
+                }
+            }
+
+            "simd_cast" | "simd_as" => {
+                let lanes = this.simd_lanes(args[0])?;
+                let dest = this.simd_dest_lanes(dest)?;
+                assert_eq!(lanes.len(), dest.len());
+                for (i, (&lane, &d)) in lanes.iter().zip(&dest).enumerate() {
+                    match (lane_kind(lane.layout), lane_kind(d.layout)) {
+                        (LaneKind::Float, LaneKind::Int { signed }) => {
+                            // `simd_as` saturates like `as`; for `simd_cast`, a value that does
+                            // not fit is UB.
+                            let val = this.read_scalar(lane)?.not_undef()?;
+                            let f = match lane.layout.size.bytes() {
+                                4 => val.to_f32()? as f64,
+                                8 => val.to_f64()?,
+                                _ => bug!("unsupported float size"),
+                            };
+                            let res = match float_to_int(f, d.layout.size, signed) {
+                                Some(res) => res,
+                                None if intrinsic_name == "simd_as" => saturate_float_to_int(f, d.layout.size, signed),
+                                None => return err!(Intrinsic(format!(
+                                    "simd_cast: {} does not fit into the target type in lane {}",
+                                    f, i,
+                                ))),
+                            };
+                            this.write_scalar(Scalar::from_uint(res, d.layout.size), d.into())?;
+                        }
+                        _ => {
+                            this.cast(lane, mir::CastKind::Misc, d.into())?;
+                        }
+                    }
+                }
+            }
+
+            "simd_reduce_add_ordered" | "simd_reduce_mul_ordered" |
+            "simd_reduce_add_unordered" | "simd_reduce_mul_unordered" |
+            "simd_reduce_and" | "simd_reduce_or" | "simd_reduce_xor" |
+            "simd_reduce_min" | "simd_reduce_max" => {
+                let lanes = this.simd_lanes(args[0])?;
+                let ordered = intrinsic_name.ends_with("_ordered");
+                let (mut acc, rest) = if ordered {
+                    (this.read_scalar(args[1])?.not_undef()?, &lanes[..])
+                } else {
+                    match lanes.split_first() {
+                        Some((&first, rest)) => (this.read_scalar(first)?.not_undef()?, rest),
+                        None => return err!(Intrinsic(format!("{} on an empty vector", intrinsic_name))),
+                    }
+                };
+                let layout = dest.layout;
+                for &lane in rest {
+                    let val = this.read_scalar(lane)?.not_undef()?;
+                    acc = match intrinsic_name {
+                        "simd_reduce_min" | "simd_reduce_max" => {
+                            let min = intrinsic_name == "simd_reduce_min";
+                            if lane_kind(layout) == LaneKind::Float {
+                                float_min_max(acc, val, layout.size, min)?
+                            } else {
+                                let (lt, _) = this.binary_op(mir::BinOp::Lt, val, layout, acc, layout)?;
+                                if lt.to_bool()? == min { val } else { acc }
+                            }
+                        }
+                        _ => {
+                            let op = match intrinsic_name {
+                                "simd_reduce_add_ordered" | "simd_reduce_add_unordered" => mir::BinOp::Add,
+                                "simd_reduce_mul_ordered" | "simd_reduce_mul_unordered" => mir::BinOp::Mul,
+                                "simd_reduce_and" => mir::BinOp::BitAnd,
+                                "simd_reduce_or" => mir::BinOp::BitOr,
+                                "simd_reduce_xor" => mir::BinOp::BitXor,
+                                _ => bug!(),
+                            };
+                            // Integer reductions wrap around.
+                            this.binary_op(op, acc, layout, val, layout)?.0
+                        }
+                    };
+                }
+                this.write_scalar(acc, dest)?;
+            }
+            "simd_reduce_all" | "simd_reduce_any" => {
+                let lanes = this.simd_lanes(args[0])?;
+                let all = intrinsic_name == "simd_reduce_all";
+                let mut res = all;
+                for (i, &lane) in lanes.iter().enumerate() {
+                    // Check every lane, even if the result is already known.
+                    let set = this.read_mask_lane(lane, intrinsic_name, i)?;
+                    res = if all { res && set } else { res || set };
+                }
+                this.write_scalar(Scalar::from_bool(res), dest)?;
+            }
+
+            "simd_gather" => {
+                // fn simd_gather<T, P, M>(values: T, pointers: P, mask: M) -> T
+                let values = this.simd_lanes(args[0])?;
+                let ptrs = this.simd_lanes(args[1])?;
+                let mask = this.simd_lanes(args[2])?;
+                let dest = this.simd_dest_lanes(dest)?;
+                for (i, &d) in dest.iter().enumerate() {
+                    if this.read_mask_lane(mask[i], intrinsic_name, i)? {
+                        let ptr = this.read_scalar(ptrs[i])?.not_undef()?;
+                        let place = this.simd_lane_place(ptr, d.layout)?;
+                        this.copy_op(place.into(), d.into())?;
+                    } else {
+                        this.copy_op(values[i], d.into())?;
+                    }
+                }
+            }
+            "simd_scatter" => {
+                // fn simd_scatter<T, P, M>(values: T, pointers: P, mask: M)
+                let values = this.simd_lanes(args[0])?;
+                let ptrs = this.simd_lanes(args[1])?;
+                let mask = this.simd_lanes(args[2])?;
+                for (i, &value) in values.iter().enumerate() {
+                    if this.read_mask_lane(mask[i], intrinsic_name, i)? {
+                        let ptr = this.read_scalar(ptrs[i])?.not_undef()?;
+                        let place = this.simd_lane_place(ptr, value.layout)?;
+                        this.copy_op(value, place.into())?;
+                    }
+                }
+            }
+            "simd_masked_load" => {
+                // fn simd_masked_load<M, P, T>(mask: M, pointer: P, values: T) -> T
+                let mask = this.simd_lanes(args[0])?;
+                let ptr = this.read_scalar(args[1])?.not_undef()?;
+                let values = this.simd_lanes(args[2])?;
+                let dest = this.simd_dest_lanes(dest)?;
+                for (i, &d) in dest.iter().enumerate() {
+                    if this.read_mask_lane(mask[i], intrinsic_name, i)? {
+                        let lane_ptr = ptr.ptr_offset(d.layout.size * i as u64, this)?;
+                        let place = this.simd_lane_place(lane_ptr, d.layout)?;
+                        this.copy_op(place.into(), d.into())?;
+                    } else {
+                        this.copy_op(values[i], d.into())?;
+                    }
+                }
+            }
+            "simd_masked_store" => {
+                // fn simd_masked_store<M, P, T>(mask: M, pointer: P, values: T)
+                let mask = this.simd_lanes(args[0])?;
+                let ptr = this.read_scalar(args[1])?.not_undef()?;
+                let values = this.simd_lanes(args[2])?;
+                for (i, &value) in values.iter().enumerate() {
+                    if this.read_mask_lane(mask[i], intrinsic_name, i)? {
+                        let lane_ptr = ptr.ptr_offset(value.layout.size * i as u64, this)?;
+                        let place = this.simd_lane_place(lane_ptr, value.layout)?;
+                        this.copy_op(value, place.into())?;
+                    }
+                }
+            }
+
+            name => return err!(Unimplemented(format!("unimplemented intrinsic: {}", name))),
+        }
+        Ok(())
+    }
+
+    /// The lanes of a SIMD vector.
+    fn simd_lanes(&self, vector: OpTy<'tcx, Borrow>) -> EvalResult<'tcx, Vec<OpTy<'tcx, Borrow>>> {
+        let this = self.eval_context_ref();
+        if !vector.layout.ty.is_simd() {
+            return err!(Intrinsic(format!("expected a SIMD vector, got {}", vector.layout.ty)));
+        }
+        (0..vector.layout.fields.count() as u64)
+            .map(|i| this.operand_field(vector, i))
+            .collect()
+    }
+
+    /// The lanes of a SIMD vector that is to be written.
+    fn simd_dest_lanes(&mut self, dest: PlaceTy<'tcx, Borrow>) -> EvalResult<'tcx, Vec<MPlaceTy<'tcx, Borrow>>> {
+        let this = self.eval_context_mut();
+        if !dest.layout.ty.is_simd() {
+            return err!(Intrinsic(format!("expected a SIMD vector, got {}", dest.layout.ty)));
+        }
+        let dest = this.force_allocation(dest)?;
+        (0..dest.layout.fields.count() as u64)
+            .map(|i| this.mplace_field(dest, i))
+            .collect()
+    }
+
+    /// Read the lanes of an integer vector.  Signed lanes are sign-extended.
+    fn read_int_lanes(&self, vector: OpTy<'tcx, Borrow>) -> EvalResult<'tcx, Vec<i128>> {
+        let this = self.eval_context_ref();
+        this.simd_lanes(vector)?.into_iter().map(|lane| {
+            let bits = this.read_scalar(lane)?.to_bits(lane.layout.size)?;
+            Ok(match lane_kind(lane.layout) {
+                LaneKind::Int { signed: true } => sign_extend(bits, lane.layout.size),
+                _ => bits as i128,
+            })
+        }).collect()
+    }
+
+    /// Write the lanes of an integer vector.  The values are truncated to the lane size.
+    fn write_int_lanes(&mut self, values: &[i128], dest: PlaceTy<'tcx, Borrow>) -> EvalResult<'tcx> {
+        let this = self.eval_context_mut();
+        let dest = this.simd_dest_lanes(dest)?;
+        assert_eq!(values.len(), dest.len());
+        for (&value, &d) in values.iter().zip(&dest) {
+            let size = d.layout.size;
+            let bits = value as u128 & (u128::max_value() >> (128 - size.bits()));
+            this.write_scalar(Scalar::from_uint(bits, size), d.into())?;
+        }
+        Ok(())
+    }
+
+    /// Read the lanes of a float vector, as `f64`.
+    fn read_float_lanes(&self, vector: OpTy<'tcx, Borrow>) -> EvalResult<'tcx, Vec<f64>> {
+        let this = self.eval_context_ref();
+        this.simd_lanes(vector)?.into_iter().map(|lane| {
+            let val = this.read_scalar(lane)?.not_undef()?;
+            Ok(match lane.layout.size.bytes() {
+                4 => val.to_f32()? as f64,
+                8 => val.to_f64()?,
+                _ => bug!("unsupported float size"),
+            })
+        }).collect()
+    }
+
+    /// Write the lanes of a float vector.  The values are rounded to the lane type.
+    fn write_float_lanes(&mut self, values: &[f64], dest: PlaceTy<'tcx, Borrow>) -> EvalResult<'tcx> {
+        let this = self.eval_context_mut();
+        let dest = this.simd_dest_lanes(dest)?;
+        assert_eq!(values.len(), dest.len());
+        for (&value, &d) in values.iter().zip(&dest) {
+            let value = match d.layout.size.bytes() {
+                4 => Scalar::from_f32(value as f32),
+                8 => Scalar::from_f64(value),
+                _ => bug!("unsupported float size"),
+            };
+            this.write_scalar(value, d.into())?;
+        }
+        Ok(())
+    }
+
+    /// Read a lane of a mask vector.  Such lanes must be all ones (true) or all zeros (false).
+    fn read_mask_lane(&self, lane: OpTy<'tcx, Borrow>, intrinsic_name: &str, index: usize) -> EvalResult<'tcx, bool> {
+        let this = self.eval_context_ref();
+        let size = lane.layout.size;
+        let bits = this.read_scalar(lane)?.to_bits(size)?;
+        let all_ones = u128::max_value() >> (128 - size.bits());
+        if bits == 0 {
+            Ok(false)
+        } else if bits == all_ones {
+            Ok(true)
+        } else {
+            err!(Intrinsic(format!(
+                "{}: invalid mask value {:#x} in lane {}", intrinsic_name, bits, index,
+            )))
+        }
+    }
+
+    /// The place a pointer in a gather, scatter, masked load or masked store refers to.
+    /// The pointer has to be aligned for a lane.
+    fn simd_lane_place(
+        &self,
+        ptr: Scalar<Borrow>,
+        layout: TyLayout<'tcx>,
+    ) -> EvalResult<'tcx, MPlaceTy<'tcx, Borrow>> {
+        let this = self.eval_context_ref();
+        this.memory().check_align(ptr, layout.align.abi)?;
+        Ok(MPlaceTy::from_aligned_ptr(ptr.to_ptr()?, layout))
+    }
+}
+
+fn lane_kind(layout: TyLayout<'_>) -> LaneKind {
+    if layout.ty.is_floating_point() {
+        LaneKind::Float
+    } else if layout.ty.is_unsafe_ptr() || layout.ty.is_fn() {
+        LaneKind::Ptr
+    } else {
+        LaneKind::Int { signed: layout.ty.is_signed() }
+    }
+}
+
+/// The value of a mask lane of the given size.
+fn mask_lane(set: bool, size: Size) -> Scalar<Borrow> {
+    Scalar::from_int(if set { -1 } else { 0 }, size)
+}
+
+/// IEEE `minNum`/`maxNum`: if one operand is NaN, the other one is returned.
+fn float_min_max<'tcx>(l: Scalar<Borrow>, r: Scalar<Borrow>, size: Size, min: bool) -> EvalResult<'tcx, Scalar<Borrow>> {
+    Ok(match size.bytes() {
+        4 => {
+            let (l, r) = (l.to_f32()?, r.to_f32()?);
+            Scalar::from_f32(if min { l.min(r) } else { l.max(r) })
+        }
+        8 => {
+            let (l, r) = (l.to_f64()?, r.to_f64()?);
+            Scalar::from_f64(if min { l.min(r) } else { l.max(r) })
+        }
+        _ => bug!("unsupported float size"),
+    })
+}
+
+/// Convert `f` to an integer, rounding towards zero.  Returns `None` if the result does not
+/// fit, or `f` is NaN.  The result is the two's complement bit pattern.
+fn float_to_int(f: f64, size: Size, signed: bool) -> Option<u128> {
+    let f = f.trunc();
+    let bits = size.bits() as i32;
+    let (min, max) = if signed {
+        (-(2f64.powi(bits - 1)), 2f64.powi(bits - 1))
+    } else {
+        (0.0, 2f64.powi(bits))
+    };
+    // `max` itself is out of range.
+    if !(f >= min && f < max) {
+        return None;
+    }
+    let mask = u128::max_value() >> (128 - bits);
+    Some(if signed { (f as i128) as u128 & mask } else { f as u128 })
+}
+
+/// Convert `f` to an integer like `as` does: rounding towards zero, saturating at the
+/// bounds of the integer type, and mapping NaN to 0.
+fn saturate_float_to_int(f: f64, size: Size, signed: bool) -> u128 {
+    let bits = size.bits();
+    let mask = u128::max_value() >> (128 - bits);
+    if f.is_nan() {
+        0
+    } else if signed {
+        let (min, max) = (-(1i128 << (bits - 1)), (1i128 << (bits - 1)) - 1);
+        (if f < 0.0 { min } else { max }) as u128 & mask
+    } else if f < 0.0 {
+        0
+    } else {
+        mask
+    }
+}
+
+/// Interpret the low `size` bits of `bits` as a two's complement number.
+pub fn sign_extend(bits: u128, size: Size) -> i128 {
+    let shift = 128 - size.bits();
+    ((bits << shift) as i128) >> shift
+}
+
+/// Clamp `value` to the range of an integer of the given size and signedness.
+pub fn saturate(value: i128, size: Size, signed: bool) -> i128 {
+    let bits = size.bits();
+    let (min, max) = if signed {
+        (-(1i128 << (bits - 1)), (1i128 << (bits - 1)) - 1)
+    } else {
+        (0, (1i128 << bits) - 1)
+    };
+    value.max(min).min(max)
+}
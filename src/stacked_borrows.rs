@@ -1,14 +1,18 @@
 use std::cell::RefCell;
+use std::cmp;
 use std::collections::HashSet;
+use std::mem;
 use std::rc::Rc;
 
 use rustc::ty::{self, layout::Size};
 use rustc::hir::{Mutability, MutMutable, MutImmutable};
+use syntax::source_map::Span;
 
 use crate::{
     EvalResult, EvalErrorKind, MiriEvalContext, HelpersEvalContextExt, Evaluator, MutValueVisitor,
     MemoryKind, MiriMemoryKind, RangeMap, AllocId, Allocation, AllocationExtra,
     Pointer, Immediate, ImmTy, PlaceTy, MPlaceTy,
+    Observers, MemoryEvent, MemoryEventKind, ObserverEvalContextExt, Watches,
 };
 
 pub type Timestamp = u64;
@@ -65,8 +69,9 @@ pub enum BorStackItem {
     FnBarrier(CallId)
 }
 
-/// Extra per-location state
-#[derive(Clone, Debug)]
+/// Extra per-location state.  Most locations of an allocation have the same stack, so they
+/// are interned: equal stacks share their storage, see `GlobalState::intern`.
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
 pub struct Stack {
     borrows: Vec<BorStackItem>, // used as a stack; never empty
     frozen_since: Option<Timestamp>, // virtual frozen "item" on top of the stack
@@ -77,6 +82,19 @@ impl Stack {
     pub fn is_frozen(&self) -> bool {
         self.frozen_since.is_some()
     }
+
+    /// A stack without items, which does not allocate.  Only ever a buffer to compute
+    /// stacks in, see `GlobalState::scratch`.
+    fn empty() -> Self {
+        Stack { borrows: Vec::new(), frozen_since: None }
+    }
+
+    /// Make this a copy of `other`, reusing our buffer.
+    fn copy_from(&mut self, other: &Stack) {
+        self.borrows.clear();
+        self.borrows.extend_from_slice(&other.borrows);
+        self.frozen_since = other.frozen_since;
+    }
 }
 
 /// What kind of reference is being used?
@@ -98,24 +116,51 @@ pub enum AccessKind {
     Dealloc,
 }
 
+/// How many interned stacks there are at least before we forget the unused ones
+const MIN_INTERNED_STACKS: usize = 1024;
+
 /// Extra global state in the memory, available to the memory access hooks
 #[derive(Debug)]
-pub struct BarrierTracking {
+pub struct GlobalState {
     next_id: CallId,
     active_calls: HashSet<CallId>,
+    /// The stacks of all locations, each of them once
+    interned: HashSet<Rc<Stack>>,
+    /// How many interned stacks there may be before we forget those no location uses any more
+    collect_at: usize,
+    /// Who to tell about accesses, see `observer.rs`
+    pub(crate) observers: Observers,
+    /// The statement or terminator being executed, for the observers
+    pub(crate) span: Option<Span>,
+    /// The memory whose writes to report, see `watch.rs`
+    pub(crate) watches: Watches,
+    /// The violation the program stopped with, as the allocation and tag of the pointer whose
+    /// check failed.  The error itself is a `MachineError` like those of the shims; this is
+    /// what tells undefined behavior apart from them.
+    pub(crate) violation: Option<(AllocId, Borrow)>,
+    /// Where `Stacks::update` computes the new stacks.  Most updates end up with a stack that
+    /// is already interned, so we keep the buffer around instead of allocating one each time.
+    scratch: Stack,
 }
-pub type MemoryState = Rc<RefCell<BarrierTracking>>;
+pub type MemoryState = Rc<RefCell<GlobalState>>;
 
-impl Default for BarrierTracking {
+impl Default for GlobalState {
     fn default() -> Self {
-        BarrierTracking {
+        GlobalState {
             next_id: 0,
             active_calls: HashSet::default(),
+            interned: HashSet::default(),
+            collect_at: MIN_INTERNED_STACKS,
+            observers: Observers::default(),
+            span: None,
+            watches: Watches::default(),
+            violation: None,
+            scratch: Stack::empty(),
         }
     }
 }
 
-impl BarrierTracking {
+impl GlobalState {
     pub fn new_call(&mut self) -> CallId {
         let id = self.next_id;
         trace!("new_call: Assigning ID {}", id);
@@ -131,6 +176,29 @@ impl BarrierTracking {
     fn is_active(&self, id: CallId) -> bool {
         self.active_calls.contains(&id)
     }
+
+    /// The shared copy of `stack`
+    fn intern(&mut self, stack: Stack) -> Rc<Stack> {
+        if let Some(interned) = self.interned.get(&stack) {
+            return Rc::clone(interned);
+        }
+        if self.interned.len() >= self.collect_at {
+            self.interned.retain(|stack| Rc::strong_count(stack) > 1);
+            self.collect_at = cmp::max(2 * self.interned.len(), MIN_INTERNED_STACKS);
+        }
+        let stack = Rc::new(stack);
+        self.interned.insert(Rc::clone(&stack));
+        stack
+    }
+
+    /// Like `intern`, but only takes the storage of `stack` if there is no shared copy yet,
+    /// leaving it empty.  Otherwise the caller can use its buffer again.
+    fn intern_scratch(&mut self, stack: &mut Stack) -> Rc<Stack> {
+        if let Some(interned) = self.interned.get(&*stack) {
+            return Rc::clone(interned);
+        }
+        self.intern(mem::replace(stack, Stack::empty()))
+    }
 }
 
 /// Extra global machine state
@@ -157,8 +225,8 @@ impl State {
 #[derive(Clone, Debug)]
 pub struct Stacks {
     // Even reading memory can have effects on the stack, so we need a `RefCell` here.
-    stacks: RefCell<RangeMap<Stack>>,
-    barrier_tracking: MemoryState,
+    stacks: RefCell<RangeMap<Rc<Stack>>>,
+    global: MemoryState,
 }
 
 /// Core per-location operations: deref, access, create.
@@ -230,7 +298,7 @@ impl<'tcx> Stack {
         &mut self,
         bor: Borrow,
         kind: AccessKind,
-        barrier_tracking: &BarrierTracking,
+        global: &GlobalState,
     ) -> EvalResult<'tcx> {
         // Check if we can match the frozen "item".
         // Not possible on writes!
@@ -248,7 +316,7 @@ impl<'tcx> Stack {
         // Pop the stack until we have something matching.
         while let Some(&itm) = self.borrows.last() {
             match (itm, bor) {
-                (BorStackItem::FnBarrier(call), _) if barrier_tracking.is_active(call) => {
+                (BorStackItem::FnBarrier(call), _) if global.is_active(call) => {
                     return err!(MachineError(format!(
                         "Stopping looking for borrow being accessed ({:?}) because of barrier ({})",
                         bor, call
@@ -279,7 +347,7 @@ impl<'tcx> Stack {
             if kind == AccessKind::Dealloc {
                 for &itm in self.borrows.iter().rev() {
                     match itm {
-                        BorStackItem::FnBarrier(call) if barrier_tracking.is_active(call) => {
+                        BorStackItem::FnBarrier(call) if global.is_active(call) => {
                             return err!(MachineError(format!(
                                 "Deallocating with active barrier ({})", call
                             )))
@@ -359,6 +427,42 @@ impl<'tcx> Stack {
 
 /// Higher-level per-location operations: deref, access, reborrow.
 impl<'tcx> Stacks {
+    /// Apply `f` to the stacks of the `size` bytes at `offset`, and intern the results.
+    /// Neighbouring locations that share a stack share the result, so that `f` runs
+    /// only once for them.
+    fn update(
+        &self,
+        offset: Size,
+        size: Size,
+        mut f: impl FnMut(&mut Stack, &GlobalState) -> EvalResult<'tcx>,
+    ) -> EvalResult<'tcx> {
+        let mut global = self.global.borrow_mut();
+        let mut stacks = self.stacks.borrow_mut();
+        // If `f` fails, the buffer is lost, which only costs us an allocation next time.
+        let mut scratch = mem::replace(&mut global.scratch, Stack::empty());
+        // The stack we updated last, and what it became
+        let mut last: Option<(Rc<Stack>, Rc<Stack>)> = None;
+        for stack in stacks.iter_mut(offset, size) {
+            if let Some((ref old, ref new)) = last {
+                if Rc::ptr_eq(old, stack) {
+                    *stack = Rc::clone(new);
+                    continue;
+                }
+            }
+            scratch.copy_from(stack);
+            f(&mut scratch, &global)?;
+            let new = if scratch == **stack {
+                // Most accesses leave the stack as it is.
+                Rc::clone(stack)
+            } else {
+                global.intern_scratch(&mut scratch)
+            };
+            last = Some((mem::replace(stack, Rc::clone(&new)), new));
+        }
+        global.scratch = scratch;
+        Ok(())
+    }
+
     /// Check that this stack is fine with being dereferenced
     fn deref(
         &self,
@@ -369,10 +473,10 @@ impl<'tcx> Stacks {
         trace!("deref for tag {:?} as {:?}: {:?}, size {}",
             ptr.tag, kind, ptr, size.bytes());
         let stacks = self.stacks.borrow();
-        for stack in stacks.iter(ptr.offset, size) {
-            stack.deref(ptr.tag, kind).map_err(EvalErrorKind::MachineError)?;
-        }
-        Ok(())
+        let res = stacks.iter(ptr.offset, size)
+            .try_for_each(|stack| stack.deref(ptr.tag, kind).map(|_| ()))
+            .map_err(|msg| EvalErrorKind::MachineError(msg).into());
+        self.note_failure(ptr, res)
     }
 
     /// `ptr` got used, reflect that in the stack.
@@ -383,15 +487,32 @@ impl<'tcx> Stacks {
         kind: AccessKind,
     ) -> EvalResult<'tcx> {
         trace!("{:?} access of tag {:?}: {:?}, size {}", kind, ptr.tag, ptr, size.bytes());
+        {
+            let mut global = self.global.borrow_mut();
+            if kind == AccessKind::Write && !global.watches.is_empty() {
+                global.watches.record_write(ptr.alloc_id, ptr.offset, size);
+            }
+            if !global.observers.is_empty() {
+                let kind = match kind {
+                    AccessKind::Read => MemoryEventKind::Read,
+                    AccessKind::Write => MemoryEventKind::Write,
+                    AccessKind::Dealloc => MemoryEventKind::Deallocate,
+                };
+                global.observers.notify(&MemoryEvent {
+                    kind,
+                    alloc_id: ptr.alloc_id,
+                    offset: ptr.offset,
+                    size,
+                    tag: ptr.tag,
+                    span: global.span,
+                });
+            }
+        }
         // Even reads can have a side-effect, by invalidating other references.
         // This is fundamentally necessary since `&mut` asserts that there
         // are no accesses through other references, not even reads.
-        let barrier_tracking = self.barrier_tracking.borrow();
-        let mut stacks = self.stacks.borrow_mut();
-        for stack in stacks.iter_mut(ptr.offset, size) {
-            stack.access(ptr.tag, kind, &*barrier_tracking)?;
-        }
-        Ok(())
+        let res = self.update(ptr.offset, size, |stack, global| stack.access(ptr.tag, kind, global));
+        self.note_failure(ptr, res)
     }
 
     /// Reborrow the given pointer to the new tag for the given kind of reference.
@@ -420,9 +541,7 @@ impl<'tcx> Stacks {
             // [2]: <https://lists.llvm.org/pipermail/llvm-dev/2018-July/124555.html>
             barrier = None;
         }
-        let barrier_tracking = self.barrier_tracking.borrow();
-        let mut stacks = self.stacks.borrow_mut();
-        for stack in stacks.iter_mut(ptr.offset, size) {
+        let res = self.update(ptr.offset, size, |stack, global| {
             // Access source `ptr`, create new ref.
             let ptr_idx = stack.deref(ptr.tag, new_kind).map_err(EvalErrorKind::MachineError)?;
             // If we can deref the new tag already, and if that tag lives higher on
@@ -441,7 +560,7 @@ impl<'tcx> Stacks {
             if bor_redundant {
                 assert!(new_bor.is_shared(), "A unique reborrow can never be redundant");
                 trace!("reborrow is redundant");
-                continue;
+                return Ok(());
             }
             // We need to do some actual work.
             let access_kind = if new_kind == RefKind::Unique {
@@ -449,13 +568,22 @@ impl<'tcx> Stacks {
             } else {
                 AccessKind::Read
             };
-            stack.access(ptr.tag, access_kind, &*barrier_tracking)?;
+            stack.access(ptr.tag, access_kind, global)?;
             if let Some(call) = barrier {
                 stack.barrier(call);
             }
             stack.create(new_bor, new_kind);
+            Ok(())
+        });
+        self.note_failure(ptr, res)
+    }
+
+    /// Pass on `res`, and if it is an error, record it as a violation by `ptr`.
+    fn note_failure(&self, ptr: Pointer<Borrow>, res: EvalResult<'tcx>) -> EvalResult<'tcx> {
+        if res.is_err() {
+            self.global.borrow_mut().violation = Some((ptr.alloc_id, ptr.tag));
         }
-        Ok(())
+        res
     }
 }
 
@@ -463,13 +591,13 @@ impl<'tcx> Stacks {
 impl AllocationExtra<Borrow, MemoryState> for Stacks {
     #[inline(always)]
     fn memory_allocated<'tcx>(size: Size, extra: &MemoryState) -> Self {
-        let stack = Stack {
+        let stack = extra.borrow_mut().intern(Stack {
             borrows: vec![BorStackItem::Shr],
             frozen_since: None,
-        };
+        });
         Stacks {
             stacks: RefCell::new(RangeMap::new(size, stack)),
-            barrier_tracking: Rc::clone(extra),
+            global: Rc::clone(extra),
         }
     }
 
@@ -502,17 +630,32 @@ impl AllocationExtra<Borrow, MemoryState> for Stacks {
 }
 
 impl<'tcx> Stacks {
+    /// The stacks of the locations, with the ranges of locations that have them, for dumps
+    /// of the state.  The items are listed bottom to top.
+    pub(crate) fn describe(&self) -> Vec<(u64, u64, String)> {
+        self.stacks.borrow().iter_all()
+            .map(|(start, end, stack)| {
+                let mut items = format!("{:?}", stack.borrows);
+                if let Some(since) = stack.frozen_since {
+                    items.push_str(&format!(", frozen since {}", since));
+                }
+                (start, end, items)
+            })
+            .collect()
+    }
+
     /// Pushes the first item to the stacks.
     pub(crate) fn first_item(
         &mut self,
         itm: BorStackItem,
         size: Size
     ) {
-        for stack in self.stacks.get_mut().iter_mut(Size::ZERO, size) {
+        self.update(Size::ZERO, size, |stack, _| {
             assert!(stack.borrows.len() == 1);
             assert_eq!(stack.borrows.pop().unwrap(), BorStackItem::Shr);
             stack.borrows.push(itm);
-        }
+            Ok(())
+        }).expect("pushing the first item cannot fail");
     }
 }
 
@@ -546,6 +689,7 @@ trait EvalContextPrivExt<'a, 'mir, 'tcx: 'a+'mir>: crate::MiriEvalContextExt<'a,
             let kind = if new_bor.is_unique() { RefKind::Unique } else { RefKind::Raw };
             alloc.extra.reborrow(ptr, size, barrier, new_bor, kind)?;
         }
+        this.observe_memory(MemoryEventKind::Retag, Pointer::new_with_tag(ptr.alloc_id, ptr.offset, new_bor), size);
         Ok(())
     }
 
@@ -0,0 +1,192 @@
+//! `-Zmiri-state-dump=<dir>`: when the program is stopped by an error, write the state of the
+//! machine to `<dir>/<program>-<pid>.miri-state.json`, like a core dump, to be looked at after
+//! the fact.  That is the stack with the locals of every frame, and the memory: the live heap
+//! allocations, and whatever the locals point to, transitively.  Each allocation comes with
+//! its bytes, which of them are initialized, its pointers, and its Stacked Borrows stacks.
+//!
+//! The interpreter cannot list all of its allocations, so memory that nothing points to any
+//! more, other than the heap, is not in the dump.
+
+use std::collections::{BTreeSet, HashSet};
+use std::fmt::Write;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::process;
+
+use rustc::ty::layout::{HasDataLayout, Size};
+use syntax::ast::Mutability;
+
+use crate::*;
+
+impl<'a, 'mir, 'tcx> EvalContextExt<'a, 'mir, 'tcx> for crate::MiriEvalContext<'a, 'mir, 'tcx> {}
+pub trait EvalContextExt<'a, 'mir, 'tcx: 'a+'mir>: crate::MiriEvalContextExt<'a, 'mir, 'tcx> {
+    /// Write the state of the machine, which `error` stopped, to the directory of
+    /// `-Zmiri-state-dump`, and return the file it is in.
+    fn write_state_dump(&self, program: &str, error: &str) -> io::Result<Option<PathBuf>> {
+        let this = self.eval_context_ref();
+        let dir = match this.machine.state_dump {
+            Some(ref dir) => dir,
+            None => return Ok(None),
+        };
+        // The memory to dump, starting with the heap and the allocations of the locals
+        let mut allocs: Vec<AllocId> = this.machine.alloc_history.live_ids();
+        let mut frames = Vec::new();
+        for (idx, frame) in this.stack().iter().enumerate() {
+            let mut locals = Vec::new();
+            for (local, name, op) in this.frame_locals(idx) {
+                let alloc = match *op {
+                    Operand::Indirect(mplace) => mplace.ptr.to_ptr().ok().map(|ptr| ptr.alloc_id),
+                    Operand::Immediate(imm) => {
+                        allocs.extend(immediate_pointers(imm));
+                        None
+                    }
+                };
+                allocs.extend(alloc);
+                let name = match name {
+                    Some(name) => json_string(&name.as_str()),
+                    None => "null".to_owned(),
+                };
+                let value = match this.format_value(op) {
+                    Some(value) => json_string(&value),
+                    None => "null".to_owned(),
+                };
+                let alloc = match alloc {
+                    Some(id) => id.0.to_string(),
+                    None => "null".to_owned(),
+                };
+                locals.push(format!(
+                    "{{\"local\":{},\"name\":{},\"type\":{},\"value\":{},\"alloc\":{}}}",
+                    local.index(), name, json_string(&op.layout.ty.to_string()), value, alloc,
+                ));
+            }
+            let block = &frame.mir.basic_blocks()[frame.block];
+            let span = if frame.stmt < block.statements.len() {
+                block.statements[frame.stmt].source_info.span
+            } else {
+                block.terminator().source_info.span
+            };
+            frames.push(format!(
+                "{{\"function\":{},\"location\":{},\"locals\":[{}]}}",
+                json_string(&frame.instance.to_string()),
+                json_string(&this.debug_span(span)),
+                locals.join(","),
+            ));
+        }
+
+        // Follow the pointers in memory, ordered by id so that dumps are easy to compare.
+        let mut seen: HashSet<AllocId> = HashSet::new();
+        let mut dumped = BTreeSet::new();
+        while let Some(id) = allocs.pop() {
+            if !seen.insert(id) {
+                continue;
+            }
+            // Functions and freed memory have no bytes.
+            if let Ok(alloc) = this.memory().get(id) {
+                allocs.extend(alloc.relocations.iter().map(|&(_, (_, target))| target));
+                dumped.insert(id.0);
+            }
+        }
+        let memory: Vec<String> = dumped.into_iter()
+            .map(|id| this.dump_allocation(AllocId(id)))
+            .collect();
+
+        let dump = format!(
+            "{{\"program\":{},\"error\":{},\"stack\":[\n{}\n],\"memory\":[\n{}\n]}}\n",
+            json_string(program), json_string(error), frames.join(",\n"), memory.join(",\n"),
+        );
+        fs::create_dir_all(dir)?;
+        let path = dir.join(format!("{}-{}.miri-state.json", crate::helpers::program_file_stem(program), process::id()));
+        fs::write(&path, dump)?;
+        Ok(Some(path))
+    }
+
+    /// The allocation `id`, which exists, as a JSON object.  Its bytes are in hex, with `__`
+    /// for uninitialized bytes.
+    fn dump_allocation(&self, id: AllocId) -> String {
+        let this = self.eval_context_ref();
+        let alloc = this.memory().get(id).unwrap();
+        let mut bytes = String::with_capacity(alloc.bytes.len() * 2);
+        for (i, &byte) in alloc.bytes.iter().enumerate() {
+            if alloc.undef_mask.get(Size::from_bytes(i as u64)) {
+                write!(bytes, "{:02x}", byte).unwrap();
+            } else {
+                bytes.push_str("__");
+            }
+        }
+        let data_layout = this.data_layout();
+        let pointer_size = data_layout.pointer_size.bytes();
+        let pointers: Vec<String> = alloc.relocations.iter()
+            .map(|&(offset, (tag, target))| {
+                // The bytes of a pointer hold the offset into the allocation it points to.
+                let start = offset.bytes() as usize;
+                let bytes = &alloc.bytes[start..start + pointer_size as usize];
+                let target_offset = read_target_uint(data_layout.endian, bytes).unwrap();
+                format!(
+                    "{{\"offset\":{},\"alloc\":{},\"target_offset\":{},\"tag\":{}}}",
+                    offset.bytes(), target.0, target_offset, json_string(&format!("{:?}", tag)),
+                )
+            })
+            .collect();
+        let stacks: Vec<String> = alloc.extra.describe().into_iter()
+            .map(|(start, end, stack)| format!(
+                "{{\"start\":{},\"end\":{},\"stack\":{}}}", start, end, json_string(&stack),
+            ))
+            .collect();
+        format!(
+            "{{\"id\":{},\"size\":{},\"align\":{},\"mutable\":{},\"heap\":{},\
+             \"bytes\":\"{}\",\"pointers\":[{}],\"borrow_stacks\":[{}]}}",
+            id.0,
+            alloc.bytes.len(),
+            alloc.align.bytes(),
+            alloc.mutability == Mutability::Mutable,
+            this.machine.alloc_history.created(id).is_some(),
+            bytes,
+            pointers.join(","),
+            stacks.join(","),
+        )
+    }
+}
+
+/// The allocations that the pointers in `imm` point to
+fn immediate_pointers(imm: Immediate<Borrow>) -> Vec<AllocId> {
+    let scalars = match imm {
+        Immediate::Scalar(a) => vec![a],
+        Immediate::ScalarPair(a, b) => vec![a, b],
+    };
+    scalars.into_iter()
+        .filter_map(|scalar| match scalar {
+            ScalarMaybeUndef::Scalar(Scalar::Ptr(ptr)) => Some(ptr.alloc_id),
+            _ => None,
+        })
+        .collect()
+}
+
+/// `s` as a JSON string
+pub(crate) fn json_string(s: &str) -> String {
+    let mut json = String::with_capacity(s.len() + 2);
+    json.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            c if (c as u32) < 0x20 => write!(json, "\\u{:04x}", c as u32).unwrap(),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn json_strings() {
+        assert_eq!(json_string("main"), "\"main\"");
+        assert_eq!(json_string("a \"b\"\\c\n"), "\"a \\\"b\\\"\\\\c\\n\"");
+        assert_eq!(json_string("\t"), "\"\\u0009\"");
+    }
+}
@@ -0,0 +1,102 @@
+//! `-Zmiri-stats`: a summary of what the interpreter did, printed at the end of the run, so
+//! that users can tell why a program is slow under miri.
+//! `-Zmiri-shim-report=<N>`: the `N` foreign functions that were called most often, and how
+//! long their emulation took, so that users know which shims matter.
+
+use std::collections::HashMap;
+use std::fs;
+use std::time::Duration;
+
+use rustc::hir::def_id::DefId;
+
+use crate::*;
+
+#[derive(Clone, Debug, Default)]
+pub struct Stats {
+    /// Heap allocations created
+    pub(crate) allocations: u64,
+    /// Heap allocations freed
+    pub(crate) deallocations: u64,
+    /// Retags performed by Stacked Borrows
+    pub(crate) retags: u64,
+    /// Calls of foreign functions that miri emulates
+    pub(crate) shim_calls: u64,
+    /// Calls and emulation time per foreign function, if we report them
+    pub(crate) shims: Option<HashMap<DefId, (u64, Duration)>>,
+    /// How many foreign functions to report
+    shim_report: usize,
+}
+
+impl Stats {
+    pub fn new(shim_report: Option<usize>) -> Self {
+        Stats {
+            shims: shim_report.map(|_| HashMap::new()),
+            shim_report: shim_report.unwrap_or(0),
+            ..Stats::default()
+        }
+    }
+}
+
+impl<'a, 'mir, 'tcx> EvalContextExt<'a, 'mir, 'tcx> for crate::MiriEvalContext<'a, 'mir, 'tcx> {}
+pub trait EvalContextExt<'a, 'mir, 'tcx: 'a+'mir>: crate::MiriEvalContextExt<'a, 'mir, 'tcx> {
+    /// Count a call of the foreign function `def_id`, whose emulation took `elapsed`.
+    fn record_shim_call(&mut self, def_id: DefId, elapsed: Duration) {
+        let this = self.eval_context_mut();
+        if let Some(ref mut shims) = this.machine.stats.shims {
+            let entry = shims.entry(def_id).or_insert((0, Duration::from_secs(0)));
+            entry.0 += 1;
+            entry.1 += elapsed;
+        }
+    }
+
+    /// Print the foreign functions that were called most often.
+    fn report_shims(&self) {
+        let this = self.eval_context_ref();
+        let shims = match this.machine.stats.shims {
+            Some(ref shims) => shims,
+            None => return,
+        };
+        let mut shims: Vec<_> = shims.iter()
+            .map(|(&def_id, &(calls, time))| (this.tcx.item_path_str(def_id), calls, time))
+            .collect();
+        // Most calls first; the name makes the order deterministic.
+        shims.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        let mut report = format!("most called foreign functions ({} in total):", shims.len());
+        for (name, calls, time) in shims.into_iter().take(this.machine.stats.shim_report) {
+            let micros = time.as_secs() * 1_000_000 + u64::from(time.subsec_micros());
+            report.push_str(&format!("\n    {:>10} calls, {:>10} µs: `{}`", calls, micros, name));
+        }
+        this.tcx.sess.note_without_error(&report);
+    }
+
+    fn report_stats(&self) {
+        let this = self.eval_context_ref();
+        let stats = &this.machine.stats;
+        let peak_memory = match peak_memory() {
+            Some(kb) => format!("{} kB", kb),
+            None => "unknown".to_owned(),
+        };
+        this.tcx.sess.note_without_error(&format!(
+            "execution statistics:\n\
+             \x20   basic blocks (and terminators) executed: {}\n\
+             \x20   peak interpreter memory: {}\n\
+             \x20   heap allocations created: {}, freed: {}\n\
+             \x20   retags performed: {}\n\
+             \x20   shim calls: {}",
+            this.machine.progress.blocks(),
+            peak_memory,
+            stats.allocations,
+            stats.deallocations,
+            stats.retags,
+            stats.shim_calls,
+        ));
+    }
+}
+
+/// The peak resident memory of the interpreter in kB, if the host tells us.
+fn peak_memory() -> Option<u64> {
+    // Linux only, other hosts do not have a file for it.
+    let status = fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmHWM:"))?;
+    line["VmHWM:".len()..].trim().trim_end_matches("kB").trim().parse().ok()
+}
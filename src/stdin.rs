@@ -0,0 +1,53 @@
+//! The standard input of the interpreted program.  It is empty unless the user provides it,
+//! so that runs stay deterministic: `-Zmiri-stdin=<path>` reads it from a file,
+//! `-Zmiri-stdin-data=<text>` takes it from the command line, and `-Zmiri-stdin=-` passes
+//! through the standard input of miri itself.
+
+use std::cmp;
+use std::io::{self, Read};
+
+/// Where the standard input of the program comes from
+#[derive(Clone, Debug)]
+pub enum StdinSource {
+    Data(Vec<u8>),
+    /// The standard input of the host
+    Host,
+}
+
+impl Default for StdinSource {
+    fn default() -> Self {
+        StdinSource::Data(Vec::new())
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Stdin {
+    source: StdinSource,
+    /// How much of `Data` the program already read
+    pos: usize,
+}
+
+impl Stdin {
+    pub fn new(source: StdinSource) -> Self {
+        Stdin { source, pos: 0 }
+    }
+
+    /// Read up to `len` bytes.  Returns no bytes at the end of the input.
+    pub fn read(&mut self, len: usize) -> io::Result<Vec<u8>> {
+        match self.source {
+            StdinSource::Data(ref data) => {
+                let end = cmp::min(self.pos + len, data.len());
+                let bytes = data[self.pos..end].to_vec();
+                self.pos = end;
+                Ok(bytes)
+            }
+            StdinSource::Host => {
+                // Do not allocate huge buffers just because the program asked for much.
+                let mut bytes = vec![0; cmp::min(len, 1 << 16)];
+                let read = io::stdin().read(&mut bytes)?;
+                bytes.truncate(read);
+                Ok(bytes)
+            }
+        }
+    }
+}
@@ -0,0 +1,94 @@
+//! Suppressions for known findings, so that a code base can be cleaned up incrementally.
+//! A suppressions file has one rule per line, `<class>:<function>`, and `#` starts a comment:
+//!
+//! ```text
+//! # Known leak, tracked in #123
+//! memory_leak:mycrate::cache::Cache::insert
+//! undefined_behavior:mycrate::ffi
+//! ```
+//!
+//! The class is that of the error code (`undefined_behavior`, `invalid_value`,
+//! `program_error`, `unsupported`, `memory_leak`), or `*` for all of them.
+//! A rule matches if the function appears in the backtrace of the error, or in the
+//! backtrace that created the leaked allocation.  Like in sanitizer suppression lists, the
+//! function only has to be a substring of the path of a function in the backtrace.
+
+use crate::*;
+
+#[derive(Clone, Debug)]
+struct Rule {
+    class: String,
+    function: String,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Suppressions {
+    rules: Vec<Rule>,
+}
+
+impl Suppressions {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut rules = Vec::new();
+        for (line_number, line) in text.lines().enumerate() {
+            let line = line.splitn(2, '#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let mut parts = line.splitn(2, ':');
+            let class = parts.next().unwrap().trim();
+            let function = match parts.next() {
+                Some(function) if !function.trim().is_empty() => function.trim(),
+                _ => return Err(format!("line {}: expected `<class>:<function>`", line_number + 1)),
+            };
+            if class != "*" && ErrorClass::from_name(class).is_none() {
+                return Err(format!("line {}: unknown class `{}`", line_number + 1, class));
+            }
+            rules.push(Rule { class: class.to_owned(), function: function.to_owned() });
+        }
+        Ok(Suppressions { rules })
+    }
+
+    fn matches(&self, class: &str, function: &str) -> bool {
+        self.rules.iter().any(|rule| {
+            (rule.class == "*" || rule.class == class) && function.contains(&*rule.function)
+        })
+    }
+}
+
+impl<'a, 'mir, 'tcx> EvalContextExt<'a, 'mir, 'tcx> for crate::MiriEvalContext<'a, 'mir, 'tcx> {}
+pub trait EvalContextExt<'a, 'mir, 'tcx: 'a+'mir>: crate::MiriEvalContextExt<'a, 'mir, 'tcx> {
+    /// Whether a finding of the given class, with the given backtrace, is suppressed.
+    fn is_suppressed(&self, class: &str, frames: &[FrameInfo<'tcx>]) -> bool {
+        let this = self.eval_context_ref();
+        let suppressions = &this.machine.suppressions;
+        !suppressions.rules.is_empty() && frames.iter().any(|frame| {
+            suppressions.matches(class, &this.tcx.item_path_str(frame.instance.def_id()))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_and_match() {
+        let suppressions = Suppressions::parse("
+            # A comment
+            memory_leak:mycrate::cache  # trailing comment
+
+            *:mycrate::ffi::
+        ").unwrap();
+        assert!(suppressions.matches("memory_leak", "mycrate::cache::Cache::insert"));
+        assert!(!suppressions.matches("undefined_behavior", "mycrate::cache::Cache::insert"));
+        assert!(suppressions.matches("undefined_behavior", "mycrate::ffi::call"));
+        assert!(!suppressions.matches("memory_leak", "mycrate::main"));
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert!(Suppressions::parse("memory_leak").is_err());
+        assert!(Suppressions::parse("memory_leak:").is_err());
+        assert!(Suppressions::parse("race:foo").is_err());
+    }
+}
@@ -0,0 +1,65 @@
+//! Miri's notion of time.  The interpreted program cannot observe the host clock; instead,
+//! there is a virtual monotonic clock that only advances when the program waits.
+//! This keeps execution deterministic, and means that sleeping takes no real time.
+//! With `-Zmiri-clock`, the clock also advances as the program runs, still deterministically,
+//! so that code that measures elapsed time, or spins until some time passed, sees time pass.
+
+use std::time::Duration;
+
+/// When the virtual clock advances, besides when the program waits
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ClockMode {
+    /// Only when the program waits
+    Wait,
+    /// By this much for every basic block the program executes
+    PerBlock(Duration),
+    /// By this much whenever the program reads the clock
+    PerQuery(Duration),
+}
+
+impl Default for ClockMode {
+    fn default() -> Self {
+        ClockMode::Wait
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct VirtualClock {
+    /// Time elapsed since the program started.
+    elapsed: Duration,
+    mode: ClockMode,
+}
+
+impl VirtualClock {
+    pub fn new(mode: ClockMode) -> Self {
+        VirtualClock { elapsed: Duration::default(), mode }
+    }
+
+    /// The time elapsed since the program started.
+    pub fn now(&self) -> Duration {
+        self.elapsed
+    }
+
+    /// The time elapsed since the program started, for the program, which is reading the
+    /// clock.
+    pub fn query(&mut self) -> Duration {
+        let now = self.elapsed;
+        if let ClockMode::PerQuery(tick) = self.mode {
+            self.elapsed += tick;
+        }
+        now
+    }
+
+    /// Called before every terminator, i.e., once per basic block.
+    pub fn step(&mut self) {
+        if let ClockMode::PerBlock(tick) = self.mode {
+            self.elapsed += tick;
+        }
+    }
+
+    /// Let `duration` pass.
+    pub fn advance(&mut self, duration: Duration) {
+        trace!("Advancing virtual clock by {:?}", duration);
+        self.elapsed += duration;
+    }
+}
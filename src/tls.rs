@@ -5,7 +5,8 @@ use rustc::{ty, ty::layout::HasDataLayout, mir};
 
 use crate::{
     EvalResult, EvalErrorKind, StackPopCleanup,
-    MPlaceTy, Scalar, Borrow,
+    MPlaceTy, Scalar, Borrow, HelpersEvalContextExt, MissingFnsEvalContextExt,
+    ObserverEvalContextExt,
 };
 
 pub type TlsKey = u128;
@@ -23,6 +24,14 @@ pub struct TlsData<'tcx> {
 
     /// pthreads-style thread-local storage.
     pub(crate) keys: BTreeMap<TlsKey, TlsEntry<'tcx>>,
+
+    /// Dtors registered with `_tlv_atexit` on macOS, and their arguments.
+    macos_thread_dtors: Vec<(ty::Instance<'tcx>, Scalar<Borrow>)>,
+
+    /// The error code of the last failing Windows API call, see `GetLastError`.
+    /// This is thread-local state; it will become a map from thread IDs if we ever
+    /// support more than one thread.
+    last_error: u32,
 }
 
 impl<'tcx> Default for TlsData<'tcx> {
@@ -30,6 +39,8 @@ impl<'tcx> Default for TlsData<'tcx> {
         TlsData {
             next_key: 1, // start with 1 as we must not use 0 on Windows
             keys: Default::default(),
+            macos_thread_dtors: Vec::new(),
+            last_error: 0,
         }
     }
 }
@@ -53,6 +64,25 @@ impl<'tcx> TlsData<'tcx> {
         new_key
     }
 
+    /// Register a dtor to run at thread exit, see `_tlv_atexit`.
+    pub fn add_macos_thread_dtor(&mut self, dtor: ty::Instance<'tcx>, data: Scalar<Borrow>) {
+        trace!("New macOS TLS dtor {:?} on {:?}", dtor, data);
+        self.macos_thread_dtors.push((dtor, data));
+    }
+
+    fn pop_macos_thread_dtor(&mut self) -> Option<(ty::Instance<'tcx>, Scalar<Borrow>)> {
+        self.macos_thread_dtors.pop()
+    }
+
+    pub fn last_error(&self) -> u32 {
+        self.last_error
+    }
+
+    pub fn set_last_error(&mut self, err: u32) {
+        trace!("Last error set to {}", err);
+        self.last_error = err;
+    }
+
     pub fn delete_tls_key(&mut self, key: TlsKey) -> EvalResult<'tcx> {
         match self.keys.remove(&key) {
             Some(_) => {
@@ -133,36 +163,94 @@ impl<'a, 'mir, 'tcx> EvalContextExt<'a, 'mir, 'tcx> for crate::MiriEvalContext<'
 pub trait EvalContextExt<'a, 'mir, 'tcx: 'a+'mir>: crate::MiriEvalContextExt<'a, 'mir, 'tcx> {
     fn run_tls_dtors(&mut self) -> EvalResult<'tcx> {
         let this = self.eval_context_mut();
+        // macOS dtors registered with `_tlv_atexit` run first, most recently registered first.
+        // Dtors can register more dtors, so we check again after each one.
+        while let Some((instance, data)) = this.machine.tls.pop_macos_thread_dtor() {
+            trace!("Running macOS TLS dtor {:?} on {:?}", instance, data);
+            this.call_tls_dtor(instance, data)?;
+        }
+
         let mut dtor = this.machine.tls.fetch_tls_dtor(None, &*this.tcx);
         // FIXME: replace loop by some structure that works with stepping
         while let Some((instance, ptr, key)) = dtor {
             trace!("Running TLS dtor {:?} on {:?}", instance, ptr);
-            // TODO: Potentially, this has to support all the other possible instances?
-            // See eval_fn_call in interpret/terminator/mod.rs
-            let mir = this.load_mir(instance.def)?;
-            let ret_place = MPlaceTy::dangling(this.layout_of(this.tcx.mk_unit())?, this).into();
-            this.push_stack_frame(
-                instance,
-                mir.span,
-                mir,
-                Some(ret_place),
-                StackPopCleanup::None { cleanup: true },
-            )?;
-            let arg_local = this.frame().mir.args_iter().next().ok_or_else(
-                || EvalErrorKind::AbiViolation("TLS dtor does not take enough arguments.".to_owned()),
-            )?;
-            let dest = this.eval_place(&mir::Place::Local(arg_local))?;
-            this.write_scalar(ptr, dest)?;
-
-            // step until out of stackframes
-            this.run()?;
+            this.call_tls_dtor(instance, ptr)?;
 
             dtor = match this.machine.tls.fetch_tls_dtor(Some(key), &*this.tcx) {
                 dtor @ Some(_) => dtor,
                 None => this.machine.tls.fetch_tls_dtor(None, &*this.tcx),
             };
         }
-        // FIXME: On a windows target, call `unsafe extern "system" fn on_tls_callback`.
+        // On Windows, libstd runs its own TLS dtors from a callback placed in a special
+        // linker section.  We do not look for that section; we just call the one callback
+        // we know libstd puts there.
+        if this.tcx.tcx.sess.target.target.target_os.to_lowercase() == "windows" {
+            this.run_windows_tls_callback()?;
+        }
+        Ok(())
+    }
+
+    /// Call the dtor `instance` with `ptr` as its sole argument, and run it to completion.
+    fn call_tls_dtor(&mut self, instance: ty::Instance<'tcx>, ptr: Scalar<Borrow>) -> EvalResult<'tcx> {
+        let this = self.eval_context_mut();
+        // TODO: Potentially, this has to support all the other possible instances?
+        // See eval_fn_call in interpret/terminator/mod.rs
+        let mir = this.load_mir(instance.def)?;
+        let mir = this.strip_trivial_asm(instance.def, mir);
+        let ret_place = MPlaceTy::dangling(this.layout_of(this.tcx.mk_unit())?, this).into();
+        this.push_stack_frame(
+            instance,
+            mir.span,
+            mir,
+            Some(ret_place),
+            StackPopCleanup::None { cleanup: true },
+        )?;
+        let arg_local = this.frame().mir.args_iter().next().ok_or_else(
+            || EvalErrorKind::AbiViolation("TLS dtor does not take enough arguments.".to_owned()),
+        )?;
+        let dest = this.eval_place(&mir::Place::Local(arg_local))?;
+        this.write_scalar(ptr, dest)?;
+
+        // step until out of stackframes
+        while this.observed_step()? {}
+        Ok(())
+    }
+
+    fn run_windows_tls_callback(&mut self) -> EvalResult<'tcx> {
+        let this = self.eval_context_mut();
+        let callback = this.eval_path_scalar(
+            &["std", "sys", "windows", "thread_local", "p_thread_callback"]
+        )?.to_ptr()?;
+        let instance = this.memory().get_fn(callback)?;
+        trace!("Running Windows TLS callback {:?}", instance);
+        // The signature is `unsafe extern "system" fn(h: LPVOID, dwReason: DWORD, pv: LPVOID)`.
+        let reason = this.eval_path_scalar(
+            &["std", "sys", "windows", "c", "DLL_THREAD_DETACH"]
+        )?.not_undef()?;
+        let null = Scalar::ptr_null(&*this.tcx);
+
+        let mir = this.load_mir(instance.def)?;
+        let mir = this.strip_trivial_asm(instance.def, mir);
+        let ret_place = MPlaceTy::dangling(this.layout_of(this.tcx.mk_unit())?, this).into();
+        this.push_stack_frame(
+            instance,
+            mir.span,
+            mir,
+            Some(ret_place),
+            StackPopCleanup::None { cleanup: true },
+        )?;
+        let mut args = this.frame().mir.args_iter();
+        for &arg in &[null, reason, null] {
+            let arg_local = args.next().ok_or_else(
+                || EvalErrorKind::AbiViolation("TLS callback does not take enough arguments.".to_owned()),
+            )?;
+            let dest = this.eval_place(&mir::Place::Local(arg_local))?;
+            this.write_scalar(arg, dest)?;
+        }
+        assert!(args.next().is_none(), "TLS callback has more arguments than expected");
+
+        // step until out of stackframes
+        while this.observed_step()? {}
         Ok(())
     }
 }
@@ -0,0 +1,136 @@
+//! `-Zmiri-trace=<path>`: a timeline of the run in Chrome's trace event format, for
+//! `chrome://tracing`, Perfetto or speedscope.  The functions the program is in are spans,
+//! and allocations and deallocations on the heap are instant events.  Each run is its own
+//! thread of the trace, named after the program, so the tests of a crate end up side by
+//! side.  The runs append to the file, and the driver truncates it at startup; the array
+//! of events is not closed, which the format allows so that traces can be cut off anywhere.
+//!
+//! We see the calls before every terminator, which every function executes at least once
+//! before it returns, so no call is missed, but the times are those of basic blocks.
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::mem;
+use std::path::Path;
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+
+use rustc::ty;
+
+use crate::*;
+use crate::state_dump::json_string;
+
+/// How many runs this process traced, so that each gets its own thread
+static RUNS: AtomicUsize = AtomicUsize::new(0);
+
+pub(crate) struct Trace<'tcx> {
+    out: BufWriter<File>,
+    start: Instant,
+    tid: usize,
+    /// The functions that we told the trace we are in, outermost first
+    calls: Vec<ty::Instance<'tcx>>,
+    /// The first error writing the trace, which we report at the end
+    error: Option<io::Error>,
+}
+
+impl<'tcx> Trace<'tcx> {
+    pub(crate) fn new(path: &Path, program: &str) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let empty = file.metadata()?.len() == 0;
+        let mut trace = Trace {
+            out: BufWriter::new(file),
+            start: Instant::now(),
+            tid: RUNS.fetch_add(1, Ordering::Relaxed) + 1,
+            calls: Vec::new(),
+            error: None,
+        };
+        if empty {
+            trace.out.write_all(b"[\n")?;
+        }
+        let name = format!(
+            "{{\"name\":\"thread_name\",\"ph\":\"M\",\"pid\":{},\"tid\":{},\"args\":{{\"name\":{}}}}}",
+            process::id(), trace.tid, json_string(program),
+        );
+        trace.write(&name);
+        Ok(trace)
+    }
+
+    fn write(&mut self, event: &str) {
+        if self.error.is_none() {
+            if let Err(err) = writeln!(self.out, "{},", event) {
+                self.error = Some(err);
+            }
+        }
+    }
+
+    /// Add an event of type `phase` at the current time, with the JSON object `args`.
+    fn event(&mut self, phase: char, category: &str, name: &str, args: &str) {
+        let elapsed = self.start.elapsed();
+        // In microseconds, with the nanoseconds as fraction
+        let micros = elapsed.as_secs() * 1_000_000 + u64::from(elapsed.subsec_micros());
+        // Instant events are shown on their thread only.
+        let scope = if phase == 'i' { ",\"s\":\"t\"" } else { "" };
+        let event = format!(
+            "{{\"name\":{},\"cat\":\"{}\",\"ph\":\"{}\"{},\"ts\":{}.{:03},\"pid\":{},\"tid\":{},\"args\":{}}}",
+            json_string(name), category, phase, scope, micros, elapsed.subsec_nanos() % 1000,
+            process::id(), self.tid, args,
+        );
+        self.write(&event);
+    }
+
+    /// End the spans of the calls after the first `depth`, innermost first.
+    fn return_to(&mut self, depth: usize) {
+        while self.calls.len() > depth {
+            let instance = self.calls.pop().unwrap();
+            self.event('E', "call", &instance.to_string(), "{}");
+        }
+    }
+}
+
+impl<'a, 'mir, 'tcx> EvalContextExt<'a, 'mir, 'tcx> for crate::MiriEvalContext<'a, 'mir, 'tcx> {}
+pub trait EvalContextExt<'a, 'mir, 'tcx: 'a+'mir>: crate::MiriEvalContextExt<'a, 'mir, 'tcx> {
+    /// Called before every terminator: end the spans of the functions that returned since
+    /// the last time, and start those of the functions that were called.
+    fn trace_calls(&mut self) {
+        let this = self.eval_context_mut();
+        let mut trace = match this.machine.trace.take() {
+            Some(trace) => trace,
+            None => return,
+        };
+        let stack = this.stack();
+        let depth = trace.calls.iter().zip(stack)
+            .take_while(|&(&instance, frame)| instance == frame.instance)
+            .count();
+        trace.return_to(depth);
+        for frame in &stack[depth..] {
+            trace.event('B', "call", &frame.instance.to_string(), "{}");
+            trace.calls.push(frame.instance);
+        }
+        this.machine.trace = Some(trace);
+    }
+
+    /// Add an instant event for the heap allocation `id`, which has `size` bytes: `what` is
+    /// `allocate` or `deallocate`.
+    fn trace_allocation(&mut self, what: &str, id: AllocId, size: u64) {
+        let this = self.eval_context_mut();
+        if let Some(ref mut trace) = this.machine.trace {
+            let args = format!("{{\"alloc\":{},\"size\":{}}}", id.0, size);
+            trace.event('i', "memory", &format!("{} alloc{}", what, id.0), &args);
+        }
+    }
+
+    /// End the spans of the functions the run ended in, and write out the trace.
+    fn finish_trace(&mut self) -> io::Result<()> {
+        let this = self.eval_context_mut();
+        let mut trace = match this.machine.trace.take() {
+            Some(trace) => trace,
+            None => return Ok(()),
+        };
+        trace.return_to(0);
+        match mem::replace(&mut trace.error, None) {
+            Some(err) => Err(err),
+            None => trace.out.flush(),
+        }
+    }
+}
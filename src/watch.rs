@@ -0,0 +1,152 @@
+//! `-Zmiri-watch-alloc=<id>[@<start>..<end>]` and `miri_watch(ptr, len)`: find out who
+//! writes to some memory.  The memory access hooks note the writes to watched ranges, and after
+//! each statement we report them, with a backtrace.  If a debugger is attached, the program
+//! also stops there.
+
+use std::cmp;
+
+use rustc::ty::layout::Size;
+use syntax::errors::{DiagnosticBuilder, Level};
+use syntax::source_map::Span;
+
+use crate::*;
+use crate::debugger::Stop;
+
+/// Memory to watch, from `-Zmiri-watch-alloc`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Watch {
+    pub alloc_id: u64,
+    /// The offsets to watch, the end is exclusive.  All of the allocation if `None`.
+    pub range: Option<(u64, u64)>,
+}
+
+impl Watch {
+    /// Parse `<id>` or `<id>@<start>..<end>`, where the id may start with `alloc`.
+    pub fn parse(spec: &str) -> Result<Watch, String> {
+        let (id, range) = match spec.find('@') {
+            Some(split) => (&spec[..split], Some(&spec[split + 1..])),
+            None => (spec, None),
+        };
+        let alloc_id = id.trim_start_matches("alloc").parse()
+            .map_err(|_| format!("invalid allocation id `{}`", id))?;
+        let range = match range {
+            Some(range) => {
+                let split = range.find("..")
+                    .ok_or_else(|| format!("expected a range like `0..8`, got `{}`", range))?;
+                let parse = |offset: &str| offset.parse::<u64>()
+                    .map_err(|_| format!("invalid offset `{}`", offset));
+                let (start, end) = (parse(&range[..split])?, parse(&range[split + 2..])?);
+                if start >= end {
+                    return Err(format!("empty range `{}`", range));
+                }
+                Some((start, end))
+            }
+            None => None,
+        };
+        Ok(Watch { alloc_id, range })
+    }
+}
+
+/// The watched memory, and the writes to it that we did not report yet.  This lives in the
+/// global state of Stacked Borrows, which is all the access hooks have.
+#[derive(Debug, Default)]
+pub(crate) struct Watches {
+    ranges: Vec<(AllocId, u64, u64)>,
+    hits: Vec<(AllocId, u64, u64)>,
+}
+
+impl Watches {
+    pub(crate) fn new(watches: &[Watch]) -> Self {
+        let mut new = Watches::default();
+        for watch in watches {
+            let (start, end) = watch.range.unwrap_or((0, u64::max_value()));
+            new.add(AllocId(watch.alloc_id), start, end);
+        }
+        new
+    }
+
+    pub(crate) fn add(&mut self, alloc_id: AllocId, start: u64, end: u64) {
+        self.ranges.push((alloc_id, start, end));
+    }
+
+    #[inline(always)]
+    pub(crate) fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    /// Note the watched part of a write of `size` bytes at `offset` in `alloc_id`.
+    pub(crate) fn record_write(&mut self, alloc_id: AllocId, offset: Size, size: Size) {
+        let (start, end) = (offset.bytes(), offset.bytes() + size.bytes());
+        for &(id, watch_start, watch_end) in &self.ranges {
+            if id == alloc_id && start < watch_end && watch_start < end {
+                self.hits.push((id, cmp::max(start, watch_start), cmp::min(end, watch_end)));
+            }
+        }
+    }
+}
+
+impl<'a, 'mir, 'tcx> EvalContextExt<'a, 'mir, 'tcx> for crate::MiriEvalContext<'a, 'mir, 'tcx> {}
+pub trait EvalContextExt<'a, 'mir, 'tcx: 'a+'mir>: crate::MiriEvalContextExt<'a, 'mir, 'tcx> {
+    /// Whether any memory is watched
+    fn watching(&self) -> bool {
+        !self.eval_context_ref().memory().extra.borrow().watches.is_empty()
+    }
+
+    /// `miri_watch(ptr, len)`: watch the `len` bytes at `ptr`.
+    fn watch_memory(&mut self, ptr: Pointer<Borrow>, len: u64) {
+        let this = self.eval_context_mut();
+        let start = ptr.offset.bytes();
+        this.memory().extra.borrow_mut().watches.add(ptr.alloc_id, start, start.saturating_add(len));
+    }
+
+    /// Report the writes to watched memory of the statement that just ran, at `span`.
+    fn report_watch_hits(&mut self, span: Option<Span>) -> EvalResult<'tcx> {
+        let this = self.eval_context_mut();
+        let hits = ::std::mem::replace(&mut this.memory().extra.borrow_mut().watches.hits, Vec::new());
+        if hits.is_empty() {
+            return Ok(());
+        }
+        let ranges: Vec<String> = hits.iter()
+            .map(|&(id, start, end)| format!("alloc{}[{:#x}..{:#x}]", id.0, start, end))
+            .collect();
+        let msg = format!("watched memory was written: {}", ranges.join(", "));
+        let frames = this.generate_stacktrace(None);
+        let mut note = DiagnosticBuilder::new(this.tcx.sess.diagnostic(), Level::Note, &msg);
+        // Point at the statement if it is in local code, or else at the local call.
+        let in_local_code = frames.first().map_or(false, |frame| frame.instance.def_id().is_local());
+        let site = if in_local_code { span } else { local_call_site(&frames) };
+        if let Some(site) = site {
+            note.set_span(site);
+        }
+        this.note_stacktrace(&mut note, &frames);
+        note.emit();
+        if this.machine.debugger.is_some() {
+            this.debugger_wait(Stop::Watch(msg))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        assert_eq!(Watch::parse("5"), Ok(Watch { alloc_id: 5, range: None }));
+        assert_eq!(Watch::parse("alloc5@8..12"), Ok(Watch { alloc_id: 5, range: Some((8, 12)) }));
+        assert!(Watch::parse("alloc").is_err());
+        assert!(Watch::parse("5@8").is_err());
+        assert!(Watch::parse("5@8..8").is_err());
+    }
+
+    #[test]
+    fn hits() {
+        let mut watches = Watches::new(&[Watch { alloc_id: 5, range: Some((8, 12)) }]);
+        watches.record_write(AllocId(5), Size::from_bytes(0), Size::from_bytes(8));
+        watches.record_write(AllocId(6), Size::from_bytes(8), Size::from_bytes(4));
+        assert!(watches.hits.is_empty());
+        watches.record_write(AllocId(5), Size::from_bytes(10), Size::from_bytes(8));
+        assert_eq!(watches.hits, vec![(AllocId(5), 10, 12)]);
+    }
+}
@@ -0,0 +1,595 @@
+//! Emulation of the LLVM intrinsics that `core::arch::{x86, x86_64}` uses for the vendor
+//! intrinsics that do not map to a portable `simd_*` intrinsic.
+//! The vectors are `#[repr(simd)]` types, so we reuse the lane helpers of the `simd` module.
+//! Most operations work the same on 128-bit and 256-bit vectors; the ones that do not treat
+//! a 256-bit vector as two independent 128-bit halves, like the hardware does.
+
+use rustc::ty::layout::Size;
+
+use crate::*;
+use crate::simd::{saturate, sign_extend};
+
+impl<'a, 'mir, 'tcx> EvalContextExt<'a, 'mir, 'tcx> for crate::MiriEvalContext<'a, 'mir, 'tcx> {}
+pub trait EvalContextExt<'a, 'mir, 'tcx: 'a+'mir>: crate::MiriEvalContextExt<'a, 'mir, 'tcx> {
+    /// Emulate an `llvm.x86.*` intrinsic.  `link_name` is without the `llvm.x86.` prefix.
+    fn call_x86_intrinsic(
+        &mut self,
+        link_name: &str,
+        args: &[OpTy<'tcx, Borrow>],
+        dest: PlaceTy<'tcx, Borrow>,
+    ) -> EvalResult<'tcx> {
+        let this = self.eval_context_mut();
+        // The same operation exists for several extensions and vector widths, e.g.
+        // `sse2.pavg.b` and `avx2.pavg.b`, or `sse.max.ps` and `avx.max.ps.256`.
+        let op = unprefixed(link_name);
+        match op {
+            // Fences and hints.  There is only one thread, so there is nothing to order.
+            "pause" | "lfence" | "mfence" | "sfence" | "vzeroupper" | "vzeroall" => {}
+            "clflush" => {
+                // Flushing the cache has no observable effect, but the pointer must point
+                // to a live allocation.
+                let ptr = this.read_scalar(args[0])?.to_ptr()?;
+                this.memory().get(ptr.alloc_id)?;
+            }
+
+            // Extracting the sign bits
+            "pmovmskb" | "movmsk.pd" | "movmsk.ps" => {
+                let lanes = this.simd_lanes(args[0])?;
+                let mut mask = 0u32;
+                for (i, &lane) in lanes.iter().enumerate() {
+                    let size = lane.layout.size;
+                    let bits = this.read_scalar(lane)?.to_bits(size)?;
+                    if bits >> (size.bits() - 1) != 0 {
+                        mask |= 1 << i;
+                    }
+                }
+                this.write_scalar(Scalar::from_uint(mask, dest.layout.size), dest)?;
+            }
+
+            // Saturating packs: narrow both inputs and concatenate them, per 128-bit half.
+            "packsswb" | "packssdw" | "packuswb" | "packusdw" => {
+                let signed = op.starts_with("packss");
+                let left = this.read_int_lanes(args[0])?;
+                let right = this.read_int_lanes(args[1])?;
+                let lane_size = Size::from_bytes(dest.layout.size.bytes() / (left.len() * 2) as u64);
+                let per_half = left.len() / (args[0].layout.size.bytes() / 16) as usize;
+                let mut values = Vec::new();
+                for (left, right) in left.chunks(per_half).zip(right.chunks(per_half)) {
+                    values.extend(left.iter().chain(right).map(|&v| saturate(v, lane_size, signed)));
+                }
+                this.write_int_lanes(&values, dest)?;
+            }
+
+            // Lane-wise integer operations
+            "padds.b" | "padds.w" | "paddus.b" | "paddus.w" |
+            "psubs.b" | "psubs.w" | "psubus.b" | "psubus.w" |
+            "pavg.b" | "pavg.w" |
+            "pmaxs.w" | "pmaxu.b" | "pmins.w" | "pminu.b" |
+            "pmulh.w" | "pmulhu.w" => {
+                let left = this.read_int_lanes(args[0])?;
+                let right = this.read_int_lanes(args[1])?;
+                let lane_size = Size::from_bytes(dest.layout.size.bytes() / left.len() as u64);
+                let signed = !op.contains("us.") && !op.contains("u.") && !op.starts_with("pavg");
+                // The signedness is part of the operation, not of the lane types.
+                let mask = u128::max_value() >> (128 - lane_size.bits());
+                let normalize = |v: i128| if signed {
+                    sign_extend(v as u128 & mask, lane_size)
+                } else {
+                    (v as u128 & mask) as i128
+                };
+                let values: Vec<i128> = left.iter().zip(&right).map(|(&l, &r)| {
+                    let (l, r) = (normalize(l), normalize(r));
+                    match op {
+                        "padds.b" | "padds.w" | "paddus.b" | "paddus.w" => saturate(l + r, lane_size, signed),
+                        "psubs.b" | "psubs.w" | "psubus.b" | "psubus.w" => saturate(l - r, lane_size, signed),
+                        "pavg.b" | "pavg.w" => (l + r + 1) >> 1,
+                        "pmaxs.w" | "pmaxu.b" => l.max(r),
+                        "pmins.w" | "pminu.b" => l.min(r),
+                        "pmulh.w" | "pmulhu.w" => (l * r) >> lane_size.bits(),
+                        _ => bug!(),
+                    }
+                }).collect();
+                this.write_int_lanes(&values, dest)?;
+            }
+            "pmadd.wd" => {
+                // Multiply the 16-bit lanes, and add adjacent pairs of the 32-bit products.
+                let left = this.read_int_lanes(args[0])?;
+                let right = this.read_int_lanes(args[1])?;
+                let products: Vec<i128> = left.iter().zip(&right).map(|(&l, &r)| l * r).collect();
+                let values: Vec<i128> = products.chunks(2).map(|pair| pair[0] + pair[1]).collect();
+                this.write_int_lanes(&values, dest)?;
+            }
+            "pmulu.dq" | "pmul.dq" | "pmuldq" => {
+                // Multiply the low 32 bits of each 64-bit lane.
+                let left = this.read_int_lanes(args[0])?;
+                let right = this.read_int_lanes(args[1])?;
+                let low = |v: i128| if op == "pmulu.dq" {
+                    v & 0xFFFF_FFFF
+                } else {
+                    sign_extend(v as u128 & 0xFFFF_FFFF, Size::from_bytes(4))
+                };
+                let values: Vec<i128> = left.iter().zip(&right).map(|(&l, &r)| low(l) * low(r)).collect();
+                this.write_int_lanes(&values, dest)?;
+            }
+            "psad.bw" => {
+                // Sum of absolute differences of each group of 8 bytes, into a 64-bit lane.
+                let left = this.read_int_lanes(args[0])?;
+                let right = this.read_int_lanes(args[1])?;
+                let diffs: Vec<i128> = left.iter().zip(&right).map(|(&l, &r)| (l - r).abs()).collect();
+                let values: Vec<i128> = diffs.chunks(8).map(|group| group.iter().sum()).collect();
+                this.write_int_lanes(&values, dest)?;
+            }
+
+            // Shifts, by an immediate, by the low 64 bits of a vector, or by a vector of
+            // per-lane counts.  Shifting by the lane width or more is not UB: logical shifts
+            // produce 0, arithmetic shifts the sign.
+            "pslli.w" | "pslli.d" | "pslli.q" | "psrli.w" | "psrli.d" | "psrli.q" |
+            "psrai.w" | "psrai.d" |
+            "psll.w" | "psll.d" | "psll.q" | "psrl.w" | "psrl.d" | "psrl.q" |
+            "psra.w" | "psra.d" |
+            "psllv.d" | "psllv.q" | "psrlv.d" | "psrlv.q" | "psrav.d" => {
+                let values = this.read_int_lanes(args[0])?;
+                let lane_size = Size::from_bytes(dest.layout.size.bytes() / values.len() as u64);
+                let mask = u128::max_value() >> (128 - lane_size.bits());
+                let counts: Vec<u128> = if op.contains("v.") {
+                    this.read_int_lanes(args[1])?.into_iter().map(|count| count as u128 & mask).collect()
+                } else {
+                    let count = if op.contains("i.") {
+                        this.read_scalar(args[1])?.to_u32()? as u128
+                    } else {
+                        // The count is the low 64 bits of the second vector, which has the
+                        // same lanes as the first one.
+                        let lanes = this.read_int_lanes(args[1])?;
+                        let lanes_per_count = (64 / lane_size.bits()) as usize;
+                        lanes[..lanes_per_count].iter().enumerate().fold(0, |count, (i, &lane)| {
+                            count | (lane as u128 & mask) << (i as u64 * lane_size.bits())
+                        })
+                    };
+                    vec![count; values.len()]
+                };
+                let bits = lane_size.bits() as u128;
+                let values: Vec<i128> = values.into_iter().zip(counts).map(|(v, count)| {
+                    if op.starts_with("psll") {
+                        if count >= bits { 0 } else { ((v as u128) << count) as i128 }
+                    } else if op.starts_with("psrl") {
+                        if count >= bits { 0 } else { ((v as u128 & mask) >> count) as i128 }
+                    } else {
+                        // Arithmetic shifts operate on the sign-extended value.
+                        let v = sign_extend(v as u128 & mask, lane_size);
+                        v >> count.min(bits - 1)
+                    }
+                }).collect();
+                this.write_int_lanes(&values, dest)?;
+            }
+
+            // Float operations
+            "max.pd" | "min.pd" | "max.ps" | "min.ps" => {
+                let left = this.read_float_lanes(args[0])?;
+                let right = this.read_float_lanes(args[1])?;
+                // These return the second operand if either is NaN, or if both are zero.
+                let values: Vec<f64> = left.iter().zip(&right).map(|(&l, &r)| {
+                    let l_wins = if op.starts_with("max") { l > r } else { l < r };
+                    if l_wins { l } else { r }
+                }).collect();
+                this.write_float_lanes(&values, dest)?;
+            }
+            "sqrt.pd" | "sqrt.ps" | "rcp.ps" | "rsqrt.ps" => {
+                let values = this.read_float_lanes(args[0])?;
+                // The approximations are allowed to be more precise, so we just compute
+                // the exact value.
+                let values: Vec<f64> = values.into_iter().map(|v| match op {
+                    "sqrt.pd" | "sqrt.ps" => v.sqrt(),
+                    "rcp.ps" => 1.0 / v,
+                    "rsqrt.ps" => 1.0 / v.sqrt(),
+                    _ => bug!(),
+                }).collect();
+                this.write_float_lanes(&values, dest)?;
+            }
+            "round.ps" | "round.pd" => {
+                let values = this.read_float_lanes(args[0])?;
+                let mode = this.read_scalar(args[1])?.to_u32()?;
+                let values: Vec<f64> = values.into_iter().map(|v| {
+                    // Bit 2 selects the current rounding mode, which is always round-to-nearest.
+                    // Bit 3 only suppresses the precision exception.
+                    match if mode & 0b100 != 0 { 0 } else { mode & 0b11 } {
+                        0 => round_ties_even(v),
+                        1 => v.floor(),
+                        2 => v.ceil(),
+                        _ => v.trunc(),
+                    }
+                }).collect();
+                this.write_float_lanes(&values, dest)?;
+            }
+            "cmp.pd" | "cmp.ps" => {
+                let left = this.read_float_lanes(args[0])?;
+                let right = this.read_float_lanes(args[1])?;
+                let predicate = this.read_scalar(args[2])?.to_u8()?;
+                if predicate > 31 {
+                    return err!(Intrinsic(format!("{}: invalid comparison predicate {}", link_name, predicate)));
+                }
+                let dest = this.simd_dest_lanes(dest)?;
+                for ((&l, &r), &d) in left.iter().zip(&right).zip(&dest) {
+                    let unordered = l.is_nan() || r.is_nan();
+                    // Predicates 16 to 31 only differ from 0 to 15 in whether quiet NaNs
+                    // signal an exception, which we do not model.
+                    let res = match predicate & 0xF {
+                        0x0 => l == r,
+                        0x1 => l < r,
+                        0x2 => l <= r,
+                        0x3 => unordered,
+                        0x4 => l != r,
+                        0x5 => !(l < r),
+                        0x6 => !(l <= r),
+                        0x7 => !unordered,
+                        0x8 => unordered || l == r,
+                        0x9 => !(l >= r),
+                        0xA => !(l > r),
+                        0xB => false,
+                        0xC => !unordered && l != r,
+                        0xD => l >= r,
+                        0xE => l > r,
+                        _ => true,
+                    };
+                    // The result is a mask, which does not fit into a float lane as a value.
+                    let size = d.layout.size;
+                    let mask = if res { u128::max_value() >> (128 - size.bits()) } else { 0 };
+                    this.write_scalar(Scalar::from_uint(mask, size), d.into())?;
+                }
+            }
+            "cvtps2dq" | "cvttps2dq" | "cvtpd2dq" | "cvttpd2dq" |
+            "cvt.ps2dq" | "cvtt.ps2dq" | "cvt.pd2dq" | "cvtt.pd2dq" => {
+                let values = this.read_float_lanes(args[0])?;
+                let truncate = op.starts_with("cvtt");
+                let mut values: Vec<i128> = values.into_iter().map(|v| {
+                    let v = if truncate { v.trunc() } else { round_ties_even(v) };
+                    // Values that do not fit, and NaN, become the "integer indefinite" value.
+                    if v >= -2147483648.0 && v < 2147483648.0 {
+                        v as i128
+                    } else {
+                        i32::min_value() as i128
+                    }
+                }).collect();
+                // Converting two doubles fills the upper half of the result with zeros.
+                values.resize(dest.layout.fields.count(), 0);
+                this.write_int_lanes(&values, dest)?;
+            }
+
+            // Bit tests of whole vectors
+            "ptestz" | "ptestc" | "ptestnzc" => {
+                let left = this.read_int_lanes(args[0])?;
+                let right = this.read_int_lanes(args[1])?;
+                let zero = left.iter().zip(&right).all(|(&l, &r)| l & r == 0);
+                let carry = left.iter().zip(&right).all(|(&l, &r)| !l & r == 0);
+                let res = match op {
+                    "ptestz" => zero,
+                    "ptestc" => carry,
+                    _ => !zero && !carry,
+                };
+                this.write_scalar(Scalar::from_int(res as i32, dest.layout.size), dest)?;
+            }
+
+            // Shuffles with indices from a vector
+            "pshuf.b" => {
+                // Bytes are only picked from the same 128-bit half.
+                let values = this.read_int_lanes(args[0])?;
+                let indices = this.read_int_lanes(args[1])?;
+                let values: Vec<i128> = indices.iter().enumerate().map(|(i, &index)| {
+                    // If the high bit of the index is set, the result is 0.
+                    if index & 0x80 != 0 { 0 } else { values[i / 16 * 16 + (index & 0xF) as usize] }
+                }).collect();
+                this.write_int_lanes(&values, dest)?;
+            }
+            "permd" | "permps" => {
+                // Any 32-bit lane can go anywhere.
+                let values = this.simd_lanes(args[0])?;
+                let indices = this.read_int_lanes(args[1])?;
+                let dest = this.simd_dest_lanes(dest)?;
+                for (&index, &d) in indices.iter().zip(&dest) {
+                    this.copy_op(values[(index & 0x7) as usize], d.into())?;
+                }
+            }
+            "vpermilvar.ps" | "vpermilvar.pd" => {
+                // Lanes are only picked from the same 128-bit half.
+                let values = this.simd_lanes(args[0])?;
+                let indices = this.read_int_lanes(args[1])?;
+                let dest = this.simd_dest_lanes(dest)?;
+                let per_half = (16 / dest[0].layout.size.bytes()) as usize;
+                for (i, (&index, &d)) in indices.iter().zip(&dest).enumerate() {
+                    // For doubles, the selector is bit 1 of the index, not bit 0.
+                    let index = if op == "vpermilvar.pd" { (index >> 1) & 1 } else { index & 3 };
+                    this.copy_op(values[i / per_half * per_half + index as usize], d.into())?;
+                }
+            }
+            "vperm2i128" | "vperm2f128.ps" | "vperm2f128.pd" | "vperm2f128.si" => {
+                // Each 128-bit half of the result is any half of either input, or zero.
+                let left = this.simd_lanes(args[0])?;
+                let right = this.simd_lanes(args[1])?;
+                let imm = this.read_scalar(args[2])?.to_u8()?;
+                let dest = this.simd_dest_lanes(dest)?;
+                let per_half = dest.len() / 2;
+                for (half, dest) in dest.chunks(per_half).enumerate() {
+                    let control = imm >> (half * 4);
+                    let source = match control & 0b11 {
+                        0 => &left[..per_half],
+                        1 => &left[per_half..],
+                        2 => &right[..per_half],
+                        _ => &right[per_half..],
+                    };
+                    for (&src, &d) in source.iter().zip(dest) {
+                        if control & 0b1000 != 0 {
+                            this.write_scalar(Scalar::from_uint(0u8, d.layout.size), d.into())?;
+                        } else {
+                            this.copy_op(src, d.into())?;
+                        }
+                    }
+                }
+            }
+
+            // Bit manipulation (BMI1 and BMI2).  The counting instructions (`tzcnt`, `lzcnt`,
+            // `popcnt`) use the generic `cttz`, `ctlz` and `ctpop` intrinsics instead.
+            "bextr.32" | "bextr.64" | "bzhi.32" | "bzhi.64" | "pext.32" | "pext.64" | "pdep.32" | "pdep.64" => {
+                let size = dest.layout.size;
+                let bits = size.bits() as u128;
+                let value = this.read_scalar(args[0])?.to_bits(size)?;
+                let control = this.read_scalar(args[1])?.to_bits(size)?;
+                // The bits `0..n` of a `u128`, without overflowing for `n == 128`.
+                let low_bits = |n: u128| if n >= 128 { u128::max_value() } else { (1 << n) - 1 };
+                let res = match op {
+                    "bextr.32" | "bextr.64" => {
+                        // Extract `len` bits starting at `start`.
+                        let start = control & 0xFF;
+                        let len = (control >> 8) & 0xFF;
+                        if start >= bits { 0 } else { (value >> start) & low_bits(len) }
+                    }
+                    "bzhi.32" | "bzhi.64" => {
+                        // Zero the bits from `index` upwards.
+                        let index = control & 0xFF;
+                        if index >= bits { value } else { value & low_bits(index) }
+                    }
+                    "pext.32" | "pext.64" => {
+                        // Gather the bits of `value` selected by the mask into the low bits.
+                        let mut res = 0;
+                        let mut k = 0;
+                        for i in 0..bits {
+                            if control >> i & 1 != 0 {
+                                res |= (value >> i & 1) << k;
+                                k += 1;
+                            }
+                        }
+                        res
+                    }
+                    _ => {
+                        // Scatter the low bits of `value` to the positions selected by the mask.
+                        let mut res = 0;
+                        let mut k = 0;
+                        for i in 0..bits {
+                            if control >> i & 1 != 0 {
+                                res |= (value >> k & 1) << i;
+                                k += 1;
+                            }
+                        }
+                        res
+                    }
+                };
+                this.write_scalar(Scalar::from_uint(res & low_bits(bits), size), dest)?;
+            }
+
+            // AES rounds (AES-NI), on the whole 128-bit vector
+            "aesenc" | "aesenclast" | "aesdec" | "aesdeclast" | "aesimc" | "aeskeygenassist" => {
+                let state = lanes_to_u128(&this.read_int_lanes(args[0])?, args[0].layout.size);
+                let state = state.to_le_bytes();
+                let res = match op {
+                    "aesimc" => inv_mix_columns(state),
+                    "aeskeygenassist" => {
+                        let rcon = this.read_scalar(args[1])?.to_u8()? as u32;
+                        let word = |i: usize| u32::from_le_bytes([
+                            state[4 * i], state[4 * i + 1], state[4 * i + 2], state[4 * i + 3],
+                        ]);
+                        let sub_word = |w: u32| u32::from_le_bytes({
+                            let mut bytes = w.to_le_bytes();
+                            for b in &mut bytes {
+                                *b = sbox(*b);
+                            }
+                            bytes
+                        });
+                        let (x1, x3) = (sub_word(word(1)), sub_word(word(3)));
+                        let mut res = [0; 16];
+                        for (i, w) in [x1, x1.rotate_right(8) ^ rcon, x3, x3.rotate_right(8) ^ rcon].iter().enumerate() {
+                            res[4 * i..4 * i + 4].copy_from_slice(&w.to_le_bytes());
+                        }
+                        res
+                    }
+                    _ => {
+                        let key = lanes_to_u128(&this.read_int_lanes(args[1])?, args[1].layout.size);
+                        let state = match op {
+                            "aesenc" => mix_columns(sub_bytes(shift_rows(state))),
+                            "aesenclast" => sub_bytes(shift_rows(state)),
+                            "aesdec" => inv_mix_columns(inv_sub_bytes(inv_shift_rows(state))),
+                            _ => inv_sub_bytes(inv_shift_rows(state)),
+                        };
+                        (u128::from_le_bytes(state) ^ key).to_le_bytes()
+                    }
+                };
+                let lane_size = Size::from_bytes(16 / dest.layout.fields.count() as u64);
+                this.write_int_lanes(&u128_to_lanes(u128::from_le_bytes(res), lane_size), dest)?;
+            }
+            "pclmulqdq" => {
+                // Carry-less multiplication of one 64-bit half of each operand.
+                let left = lanes_to_u128(&this.read_int_lanes(args[0])?, args[0].layout.size);
+                let right = lanes_to_u128(&this.read_int_lanes(args[1])?, args[1].layout.size);
+                let imm = this.read_scalar(args[2])?.to_u8()?;
+                let left = (left >> (64 * (imm & 1) as u32)) as u64;
+                let right = (right >> (64 * (imm >> 4 & 1) as u32)) as u64;
+                let mut res = 0u128;
+                for i in 0..64 {
+                    if right >> i & 1 != 0 {
+                        res ^= (left as u128) << i;
+                    }
+                }
+                let lane_size = Size::from_bytes(16 / dest.layout.fields.count() as u64);
+                this.write_int_lanes(&u128_to_lanes(res, lane_size), dest)?;
+            }
+
+            // Gathers: `fn(src, base: *const i8, offsets, mask, scale: i8) -> T`
+            name if name.starts_with("gather.") => {
+                let tcx = &{this.tcx.tcx};
+                let src = this.simd_lanes(args[0])?;
+                let base = this.read_scalar(args[1])?.not_undef()?;
+                let offsets = this.read_int_lanes(args[2])?;
+                let mask = this.simd_lanes(args[3])?;
+                let scale = this.read_scalar(args[4])?.to_u8()? as i64;
+                let dest = this.simd_dest_lanes(dest)?;
+                for (i, &d) in dest.iter().enumerate() {
+                    let size = d.layout.size;
+                    if i >= offsets.len() {
+                        // With 64-bit offsets and 32-bit values, there are fewer offsets than
+                        // lanes.  The remaining lanes are zeroed.
+                        this.write_scalar(Scalar::from_uint(0u8, size), d.into())?;
+                        continue;
+                    }
+                    // Only the sign bit of the mask matters, and the mask may be a float vector.
+                    let mask_size = mask[i].layout.size;
+                    let mask_bits = this.read_scalar(mask[i])?.to_bits(mask_size)?;
+                    if mask_bits >> (mask_size.bits() - 1) != 0 {
+                        // The load does not have to be aligned, so we read the bytes directly.
+                        let offset = (offsets[i] as i64).wrapping_mul(scale);
+                        let ptr = base.to_ptr()?.signed_offset(offset, this)?;
+                        let val = this.memory().get(ptr.alloc_id)?.read_scalar(tcx, ptr, size)?;
+                        this.write_scalar(val, d.into())?;
+                    } else {
+                        this.copy_op(src[i], d.into())?;
+                    }
+                }
+            }
+
+            _ => return err!(Unimplemented(format!("can't call foreign function: llvm.x86.{}", link_name))),
+        }
+        Ok(())
+    }
+}
+
+/// Strip the instruction set extension and the vector width from the name of an intrinsic,
+/// e.g. `avx2.pmovmskb` and `sse2.pmovmskb.128` both become `pmovmskb`.
+fn unprefixed(link_name: &str) -> &str {
+    let name = match link_name.find('.') {
+        Some(dot) => &link_name[dot + 1..],
+        None => link_name,
+    };
+    name.trim_end_matches(".128").trim_end_matches(".256")
+}
+
+/// Combine the lanes of a 128-bit integer vector, with the first lane in the low bits.
+fn lanes_to_u128(lanes: &[i128], vector_size: Size) -> u128 {
+    let lane_bits = vector_size.bits() / lanes.len() as u64;
+    let mask = u128::max_value() >> (128 - lane_bits);
+    lanes.iter().enumerate().fold(0, |res, (i, &lane)| res | (lane as u128 & mask) << (i as u64 * lane_bits))
+}
+
+/// Split a 128-bit value into integer lanes, with the low bits in the first lane.
+fn u128_to_lanes(value: u128, lane_size: Size) -> Vec<i128> {
+    let lane_bits = lane_size.bits();
+    let mask = u128::max_value() >> (128 - lane_bits);
+    (0..128 / lane_bits).map(|i| (value >> (i * lane_bits) & mask) as i128).collect()
+}
+
+/// Multiplication in GF(2^8), modulo the AES polynomial.
+fn gf_mul(mut a: u8, mut b: u8) -> u8 {
+    let mut res = 0;
+    while b != 0 {
+        if b & 1 != 0 {
+            res ^= a;
+        }
+        a = (a << 1) ^ if a & 0x80 != 0 { 0x1B } else { 0 };
+        b >>= 1;
+    }
+    res
+}
+
+/// The AES S-box: the multiplicative inverse in GF(2^8), followed by an affine transformation.
+/// Computing it is slower than a table lookup, but we are not in a hurry.
+fn sbox(x: u8) -> u8 {
+    // x^254 is the inverse of x, and maps 0 to 0.
+    let mut inv = 1;
+    for _ in 0..254 {
+        inv = gf_mul(inv, x);
+    }
+    inv ^ inv.rotate_left(1) ^ inv.rotate_left(2) ^ inv.rotate_left(3) ^ inv.rotate_left(4) ^ 0x63
+}
+
+fn inv_sbox(y: u8) -> u8 {
+    let x = y.rotate_left(1) ^ y.rotate_left(3) ^ y.rotate_left(6) ^ 0x05;
+    let mut inv = 1;
+    for _ in 0..254 {
+        inv = gf_mul(inv, x);
+    }
+    inv
+}
+
+// The AES state is stored column by column: byte `i` is in row `i % 4` and column `i / 4`.
+
+fn sub_bytes(state: [u8; 16]) -> [u8; 16] {
+    let mut res = state;
+    for b in &mut res {
+        *b = sbox(*b);
+    }
+    res
+}
+
+fn inv_sub_bytes(state: [u8; 16]) -> [u8; 16] {
+    let mut res = state;
+    for b in &mut res {
+        *b = inv_sbox(*b);
+    }
+    res
+}
+
+/// Rotate row `r` to the left by `r` columns.
+fn shift_rows(state: [u8; 16]) -> [u8; 16] {
+    let mut res = [0; 16];
+    for (i, b) in res.iter_mut().enumerate() {
+        let (row, col) = (i % 4, i / 4);
+        *b = state[row + 4 * ((col + row) % 4)];
+    }
+    res
+}
+
+fn inv_shift_rows(state: [u8; 16]) -> [u8; 16] {
+    let mut res = [0; 16];
+    for (i, b) in res.iter_mut().enumerate() {
+        let (row, col) = (i % 4, i / 4);
+        *b = state[row + 4 * ((col + 4 - row) % 4)];
+    }
+    res
+}
+
+/// Multiply each column by a circulant matrix, given by its first row.
+fn mix_columns_with(state: [u8; 16], coefficients: [u8; 4]) -> [u8; 16] {
+    let mut res = [0; 16];
+    for col in 0..4 {
+        for row in 0..4 {
+            res[4 * col + row] = (0..4).fold(0, |acc, k| {
+                acc ^ gf_mul(coefficients[(k + 4 - row) % 4], state[4 * col + k])
+            });
+        }
+    }
+    res
+}
+
+fn mix_columns(state: [u8; 16]) -> [u8; 16] {
+    mix_columns_with(state, [2, 3, 1, 1])
+}
+
+fn inv_mix_columns(state: [u8; 16]) -> [u8; 16] {
+    mix_columns_with(state, [14, 11, 13, 9])
+}
+
+/// Round to the nearest integer, with ties going to the even one, like the default
+/// SSE rounding mode.
+pub fn round_ties_even(v: f64) -> f64 {
+    let r = v.round();
+    if (r - v).abs() == 0.5 && r % 2.0 != 0.0 {
+        r - v.signum()
+    } else {
+        r
+    }
+}
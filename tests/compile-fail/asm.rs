@@ -0,0 +1,10 @@
+#![feature(asm)]
+
+fn main() {
+    let x: u64 = 1;
+    let y: u64;
+    unsafe {
+        asm!("mov $1, $0" : "=r"(y) : "r"(x)); //~ ERROR cannot evaluate inline assembly
+    }
+    assert_eq!(x, y);
+}
@@ -0,0 +1,15 @@
+#![feature(core_intrinsics)]
+
+#[repr(C, align(16))]
+struct S {
+    a: u64,
+    b: u128, // only 8-aligned
+}
+
+fn main() {
+    let mut s = S { a: 0, b: 0 };
+    unsafe {
+        std::intrinsics::atomic_load(&mut s.b as *mut u128); //~ ERROR alignment 8, but alignment 16 is required
+    }
+    let _ = s.a;
+}
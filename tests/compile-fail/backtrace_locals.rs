@@ -0,0 +1,17 @@
+// compile-flags: -Zmiri-backtrace=full-locals
+#![feature(core_intrinsics)]
+
+//error-pattern: Division by 0 in unchecked_div
+//error-pattern: locals of `divide`: a = 7, b = 0, pair = (7, 0)
+//error-pattern: locals of `main`: x = 7, y = 0
+
+fn divide(a: u32, b: u32) -> u32 {
+    let pair = (a, b);
+    unsafe { std::intrinsics::unchecked_div(pair.0, pair.1) }
+}
+
+fn main() {
+    let x = 7;
+    let y = 0;
+    divide(x, y);
+}
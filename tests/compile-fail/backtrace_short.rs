@@ -0,0 +1,11 @@
+// Validation changes why we fail
+// compile-flags: -Zmiri-disable-validation -Zmiri-backtrace=short
+
+// error-pattern: tried to deallocate Stack memory
+// error-pattern: calls in the standard library (`-Zmiri-backtrace=full` shows them)
+
+fn main() {
+    let x = 0u8;
+    let v = unsafe { Vec::from_raw_parts(&x as *const u8 as *mut u8, 1, 1) };
+    drop(v);
+}
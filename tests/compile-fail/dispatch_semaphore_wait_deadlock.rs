@@ -0,0 +1,16 @@
+//ignore-linux: Uses macOS APIs
+//ignore-windows: Uses macOS APIs
+
+use std::os::raw::c_void;
+
+extern "C" {
+    fn dispatch_semaphore_create(value: isize) -> *mut c_void;
+    fn dispatch_semaphore_wait(sema: *mut c_void, timeout: u64) -> isize;
+}
+
+fn main() {
+    unsafe {
+        let sema = dispatch_semaphore_create(0);
+        dispatch_semaphore_wait(sema, !0); //~ ERROR deadlock
+    }
+}
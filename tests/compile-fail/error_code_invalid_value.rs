@@ -0,0 +1,6 @@
+// The human output shows the class of the error as its code.
+// error-pattern: error[miri::invalid_value]: constant evaluation error
+
+fn main() {
+    let _b = unsafe { std::mem::transmute::<u8, bool>(2) };
+}
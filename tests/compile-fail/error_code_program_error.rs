@@ -0,0 +1,7 @@
+// The human output shows the class of the error as its code.  A panic is the program's
+// own error, not undefined behavior.
+// error-pattern: error[miri::program_error]: constant evaluation error
+
+fn main() {
+    panic!("the program gives up");
+}
@@ -0,0 +1,11 @@
+// The human output shows the class of the error as its code.  Stacked Borrows violations
+// are undefined behavior.
+// error-pattern: error[miri::undefined_behavior]: constant evaluation error
+
+fn main() {
+    let mut x = 15;
+    let xraw = &mut x as *mut i32;
+    let xref = unsafe { &mut *xraw };
+    unsafe { *xraw = 16 };
+    *xref = 17;
+}
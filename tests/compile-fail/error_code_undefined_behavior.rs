@@ -0,0 +1,8 @@
+// The human output shows the class of the error as its code.
+// error-pattern: error[miri::undefined_behavior]: constant evaluation error
+
+fn main() {
+    let v: Vec<u8> = vec![1, 2];
+    let x = unsafe { *v.as_ptr().wrapping_offset(5) };
+    panic!("this should never print: {}", x);
+}
@@ -0,0 +1,9 @@
+// compile-flags: -Zmiri-disable-validation
+
+// Even when we do not track borrows, turning a reference into a raw pointer reads the
+// reference.
+
+fn main() {
+    let r: &i32 = unsafe { std::mem::uninitialized() };
+    let _p = r as *const i32; //~ ERROR attempted to read undefined bytes
+}
@@ -0,0 +1,10 @@
+//ignore-windows: No clock emulation on Windows
+// compile-flags: -Zmiri-isolation-error=clock=abort
+
+// error-pattern: reading the wall clock is not allowed in isolation
+
+use std::time::SystemTime;
+
+fn main() {
+    let _now = SystemTime::now();
+}
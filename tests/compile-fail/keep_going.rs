@@ -0,0 +1,21 @@
+// compile-flags: --test -Zmiri-keep-going
+// Both failing tests report their error, and the one in between still runs.
+// error-pattern: outside bounds of allocation
+// error-pattern: invalid use of NULL pointer
+// error-pattern: 2 of 3 tests failed:
+
+#[test]
+fn out_of_bounds() {
+    let v: Vec<u8> = vec![1, 2];
+    let x = unsafe { *v.as_ptr().wrapping_offset(5) };
+    panic!("this should never print: {}", x);
+}
+
+#[test]
+fn passes() {}
+
+#[test]
+fn null_deref() {
+    let x: i32 = unsafe { *std::ptr::null() };
+    panic!("this should never print: {}", x);
+}
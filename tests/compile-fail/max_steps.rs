@@ -0,0 +1,6 @@
+// compile-flags: -Zmiri-max-steps=100000
+
+fn main() {
+    let mut i = 0u64;
+    loop { i += 1; } //~ ERROR did not finish within 100000 basic blocks
+}
@@ -0,0 +1,14 @@
+#![feature(libc)]
+extern crate libc;
+
+fn main() {
+    let defined = [0u8; 4];
+    let undef: Vec<u8> = Vec::with_capacity(4);
+    unsafe {
+        libc::memcmp( //~ ERROR attempted to read undefined bytes
+            defined.as_ptr() as *const libc::c_void,
+            undef.as_ptr() as *const libc::c_void,
+            4,
+        );
+    }
+}
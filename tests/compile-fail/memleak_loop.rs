@@ -0,0 +1,14 @@
+//error-pattern: the evaluated program leaked memory
+//error-pattern: 10 allocations with 40 bytes in total were leaked, created here:
+
+// The ten leaks are reported together, as one allocation site.
+
+fn leak() {
+    std::mem::forget(Box::new(42));
+}
+
+fn main() {
+    for _ in 0..10 {
+        leak();
+    }
+}
@@ -1,6 +1,3 @@
-// ignore-windows: We do not check leaks on Windows
-// ignore-macos: We do not check leaks on macOS
-
 //error-pattern: the evaluated program leaked memory
 
 use std::rc::Rc;
@@ -0,0 +1,16 @@
+//ignore-linux: Uses macOS APIs
+//ignore-windows: Uses macOS APIs
+
+#[repr(C)]
+struct OsUnfairLock {
+    _os_unfair_lock_opaque: u32,
+}
+
+extern "C" {
+    fn os_unfair_lock_unlock(lock: *mut OsUnfairLock);
+}
+
+fn main() {
+    let mut lock = OsUnfairLock { _os_unfair_lock_opaque: 0 };
+    unsafe { os_unfair_lock_unlock(&mut lock); } //~ ERROR not held by the current thread
+}
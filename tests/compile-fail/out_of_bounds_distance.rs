@@ -0,0 +1,10 @@
+//error-pattern: outside bounds of allocation
+//error-pattern: the access ends 4 bytes past the end of alloc
+
+// The `u32` right after the two in the `Vec` is 4 bytes too far.
+
+fn main() {
+    let v: Vec<u32> = vec![1, 2];
+    let x = unsafe { *v.as_ptr().wrapping_offset(2) };
+    panic!("this should never print: {}", x);
+}
@@ -0,0 +1,19 @@
+// A violated precondition points at the call that violated it.
+
+extern "Rust" {
+    fn miri_precondition_failed(msg: *const u8, len: usize) -> !;
+}
+
+/// Safety: `idx` must be less than `data.len()`.
+unsafe fn get_unchecked(data: &[u8], idx: usize) -> u8 {
+    if idx >= data.len() {
+        let msg = "`idx` is in bounds";
+        miri_precondition_failed(msg.as_ptr(), msg.len());
+    }
+    *data.as_ptr().add(idx)
+}
+
+fn main() {
+    let data = [1, 2, 3];
+    unsafe { get_unchecked(&data, 3) }; //~ ERROR unsafe precondition violated: `idx` is in bounds
+}
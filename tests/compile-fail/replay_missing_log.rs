@@ -0,0 +1,5 @@
+// There is no replay log in this directory, so the program cannot start.
+// compile-flags: -Zmiri-replay=tests/compile-fail/no-replay-logs-here
+// error-pattern: error[miri::unsupported]: could not start the program: cannot read the replay log
+
+fn main() {}
@@ -0,0 +1,9 @@
+// compile-flags: -Zmiri-max-steps=100000 -Zmiri-report-progress=40000
+// error-pattern: progress: 40000 basic blocks executed
+// error-pattern: progress: 80000 basic blocks executed
+// error-pattern: the program did not finish within 100000 basic blocks
+
+fn main() {
+    let mut i = 0u64;
+    loop { i += 1; }
+}
@@ -0,0 +1,18 @@
+#![feature(repr_simd, platform_intrinsics)]
+#![allow(non_camel_case_types)]
+
+#[repr(simd)]
+#[derive(Copy, Clone)]
+struct i32x2(i32, i32);
+
+extern "platform-intrinsic" {
+    fn simd_select<M, T>(m: M, a: T, b: T) -> T;
+}
+
+fn main() {
+    unsafe {
+        let a = i32x2(1, 2);
+        let b = i32x2(3, 4);
+        simd_select(i32x2(-1, 1), a, b); //~ ERROR invalid mask value
+    }
+}
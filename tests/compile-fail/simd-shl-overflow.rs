@@ -0,0 +1,16 @@
+#![feature(repr_simd, platform_intrinsics)]
+#![allow(non_camel_case_types)]
+
+#[repr(simd)]
+#[derive(Copy, Clone)]
+struct i32x2(i32, i32);
+
+extern "platform-intrinsic" {
+    fn simd_shl<T>(x: T, y: T) -> T;
+}
+
+fn main() {
+    unsafe {
+        simd_shl(i32x2(1, 1), i32x2(1, 32)); //~ ERROR Overflowing shift
+    }
+}
@@ -0,0 +1,7 @@
+// compile-flags: --test -Zmiri-test-timeout=1
+
+#[test]
+fn spins() {
+    let mut i = 0u64;
+    loop { i += 1; } //~ ERROR did not finish within the timeout of 1 seconds
+}
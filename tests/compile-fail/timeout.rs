@@ -0,0 +1,6 @@
+// compile-flags: -Zmiri-timeout=1
+
+fn main() {
+    let mut i = 0u64;
+    loop { i += 1; } //~ ERROR did not finish within the timeout of 1 seconds
+}
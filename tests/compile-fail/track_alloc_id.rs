@@ -0,0 +1,16 @@
+// Allocation ids are hard to predict, so we track all heap allocations with the lower ids.
+// compile-flags: -Zmiri-track-alloc-id=0..100000
+// error-pattern: was created
+// error-pattern: track_alloc_id.rs:12:
+// error-pattern: was freed
+// error-pattern: track_alloc_id.rs:13:
+// error-pattern: invalid use of NULL pointer
+
+fn main() {
+    // Stack memory is never tracked.
+    let _x = [0u8; 4];
+    let b = Box::new(42);
+    drop(b);
+    let x: i32 = unsafe { *std::ptr::null() };
+    panic!("this should never print: {}", x);
+}
@@ -0,0 +1,14 @@
+// compile-flags: -Zmiri-track-alloc-site=track_alloc_site.rs:10
+// error-pattern: was created
+// error-pattern: track_alloc_site.rs:10:
+// error-pattern: was freed
+// error-pattern: track_alloc_site.rs:11:
+// error-pattern: invalid use of NULL pointer
+
+fn main() {
+    let _unrelated = Box::new(1);
+    let b = Box::new(42);
+    drop(b);
+    let x: i32 = unsafe { *std::ptr::null() };
+    panic!("this should never print: {}", x);
+}
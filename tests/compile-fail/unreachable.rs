@@ -0,0 +1,9 @@
+#![feature(core_intrinsics)]
+
+fn main() {
+    let x: Option<i32> = None;
+    let _val = match x {
+        Some(val) => val,
+        None => unsafe { std::intrinsics::unreachable() }, //~ ERROR entered unreachable code
+    };
+}
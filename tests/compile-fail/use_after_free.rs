@@ -0,0 +1,24 @@
+// compile-flags: -Zmiri-backtrace=short
+// The error also shows where the `Vec` buffer was allocated and where it was freed.
+
+fn make() -> Vec<u8> {
+    let mut v = Vec::with_capacity(3); //~ NOTE the allocation was created here
+    v.push(1);
+    v
+}
+
+fn main() {
+    let v = make(); //~ NOTE inside call to `make`
+    let p = v.as_ptr();
+    drop(v); //~ NOTE the allocation was freed here
+    let x = unsafe { *p }; //~ ERROR dangling pointer was dereferenced
+    //~| NOTE dangling pointer was dereferenced
+    //~| NOTE inside call to `main`
+    //~| NOTE the allocation was created here:
+    //~| NOTE calls in the standard library
+    //~| NOTE inside call to `main`
+    //~| NOTE the allocation was freed here:
+    //~| NOTE calls in the standard library
+    //~| NOTE inside call to `main`
+    panic!("this should never print: {}", x);
+}
@@ -0,0 +1,29 @@
+//ignore-linux: Uses Windows APIs
+//ignore-macos: Uses Windows APIs
+
+// error-pattern: use of closed handle
+
+use std::ptr;
+
+type HANDLE = *mut u8;
+
+extern "system" {
+    fn GetCurrentProcess() -> HANDLE;
+    fn GetCurrentThread() -> HANDLE;
+    fn DuplicateHandle(
+        source_process: HANDLE, source: HANDLE,
+        target_process: HANDLE, target: *mut HANDLE,
+        access: u32, inherit: i32, options: u32,
+    ) -> i32;
+    fn CloseHandle(handle: HANDLE) -> i32;
+}
+
+fn main() {
+    unsafe {
+        let process = GetCurrentProcess();
+        let mut thread = ptr::null_mut();
+        DuplicateHandle(process, GetCurrentThread(), process, &mut thread, 0, 0, 0);
+        CloseHandle(thread);
+        CloseHandle(thread);
+    }
+}
@@ -0,0 +1,33 @@
+//ignore-linux: Uses Windows APIs
+//ignore-macos: Uses Windows APIs
+
+// error-pattern: use of closed handle
+
+use std::ptr;
+
+type HANDLE = *mut u8;
+
+extern "system" {
+    fn GetCurrentProcess() -> HANDLE;
+    fn GetCurrentThread() -> HANDLE;
+    fn DuplicateHandle(
+        source_process: HANDLE, source: HANDLE,
+        target_process: HANDLE, target: *mut HANDLE,
+        access: u32, inherit: i32, options: u32,
+    ) -> i32;
+    fn CloseHandle(handle: HANDLE) -> i32;
+    fn WriteFile(
+        file: HANDLE, buffer: *const u8, len: u32,
+        written: *mut u32, overlapped: *mut u8,
+    ) -> i32;
+}
+
+fn main() {
+    unsafe {
+        let process = GetCurrentProcess();
+        let mut thread = ptr::null_mut();
+        DuplicateHandle(process, GetCurrentThread(), process, &mut thread, 0, 0, 0);
+        CloseHandle(thread);
+        WriteFile(thread, b"abc".as_ptr(), 3, ptr::null_mut(), ptr::null_mut());
+    }
+}
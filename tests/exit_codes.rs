@@ -0,0 +1,110 @@
+//! The exit code of miri tells which class of finding failed the run.
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process::{self, Command};
+
+fn miri_path() -> PathBuf {
+    match option_env!("MIRI_PATH") {
+        Some(path) => PathBuf::from(path),
+        None => PathBuf::from(concat!("target/", env!("PROFILE"), "/miri")),
+    }
+}
+
+/// Run `main` of `source` in miri, and return the exit code.
+fn exit_code(name: &str, source: &str) -> i32 {
+    let path = env::temp_dir().join(format!("miri-exit-code-{}-{}.rs", process::id(), name));
+    fs::write(&path, source).unwrap();
+    let status = Command::new(miri_path())
+        .arg(&path)
+        .args(&["--edition", "2018", "--crate-name", name])
+        .status()
+        .expect("could not run miri");
+    fs::remove_file(&path).unwrap();
+    status.code().expect("miri was killed")
+}
+
+#[test]
+fn undefined_behavior() {
+    let source = "
+        fn main() {
+            let x: i32 = unsafe { *std::ptr::null() };
+            println!(\"{}\", x);
+        }
+    ";
+    assert_eq!(exit_code("null", source), 2);
+}
+
+#[test]
+fn stacked_borrows_violation() {
+    let source = "
+        fn main() {
+            let mut x = 15;
+            let xraw = &mut x as *mut i32;
+            let xref = unsafe { &mut *xraw };
+            unsafe { *xraw = 16 };
+            *xref = 17;
+        }
+    ";
+    assert_eq!(exit_code("aliasing", source), 2);
+}
+
+#[test]
+fn invalid_value() {
+    let source = "
+        fn main() {
+            let _b = unsafe { std::mem::transmute::<u8, bool>(2) };
+        }
+    ";
+    assert_eq!(exit_code("invalid_bool", source), 3);
+}
+
+#[test]
+fn panic() {
+    let source = "
+        fn main() {
+            panic!(\"the program gives up\");
+        }
+    ";
+    assert_eq!(exit_code("panic", source), 4);
+}
+
+#[test]
+fn failed_assert() {
+    // The overflow check of the addition fails; the value is not known at compile time.
+    let source = "
+        fn add_one(x: u8) -> u8 {
+            x + 1
+        }
+
+        fn main() {
+            println!(\"{}\", add_one(254 + std::env::args().count() as u8));
+        }
+    ";
+    assert_eq!(exit_code("overflow", source), 4);
+}
+
+#[test]
+fn unsupported() {
+    let source = "
+        extern \"C\" {
+            fn miri_has_no_shim_for_this();
+        }
+
+        fn main() {
+            unsafe { miri_has_no_shim_for_this() };
+        }
+    ";
+    assert_eq!(exit_code("unsupported", source), 5);
+}
+
+#[test]
+fn memory_leak() {
+    let source = "
+        fn main() {
+            std::mem::forget(Box::new(42));
+        }
+    ";
+    assert_eq!(exit_code("leak", source), 6);
+}
@@ -0,0 +1,225 @@
+//! Using miri as a library, the way tools that embed it do: we compile a small crate, run
+//! one of its functions, and look at what the configuration gives back.
+
+#![feature(rustc_private)]
+
+extern crate getopts;
+extern crate miri;
+extern crate rustc;
+extern crate rustc_driver;
+extern crate syntax;
+
+use std::env;
+use std::fs;
+use std::process::{self, Command};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use rustc::session::Session;
+use rustc::ty;
+use rustc_driver::{Compilation, CompilerCalls};
+use rustc_driver::driver::CompileController;
+
+use miri::{
+    Borrow, ErrorClass, MemoryEvent, MemoryEventKind, MemoryObserver, MiriConfig, ReplayMode, Report,
+};
+
+fn sysroot() -> String {
+    if let Ok(sysroot) = env::var("MIRI_SYSROOT") {
+        return sysroot;
+    }
+    let out = Command::new("rustc").args(&["--print", "sysroot"]).output().unwrap();
+    String::from_utf8(out.stdout).unwrap().trim().to_owned()
+}
+
+struct RunFunction {
+    function: String,
+    config: MiriConfig,
+    failure: Arc<Mutex<Option<miri::ErrorClass>>>,
+}
+
+impl<'a> CompilerCalls<'a> for RunFunction {
+    fn build_controller(
+        self: Box<Self>,
+        _sess: &Session,
+        _matches: &getopts::Matches,
+    ) -> CompileController<'a> {
+        let this = *self;
+        let mut control = CompileController::basic();
+        control.after_analysis.callback = Box::new(move |state| {
+            state.session.abort_if_errors();
+            let tcx = state.tcx.unwrap();
+            let def_id = tcx.body_owners()
+                .find(|&did| match tcx.type_of(did).sty {
+                    ty::FnDef(..) => &*tcx.item_name(did).as_str() == this.function,
+                    _ => false,
+                })
+                .expect("there is no such function");
+            *this.failure.lock().unwrap() = miri::eval_main(tcx, def_id, this.config.clone());
+        });
+        control.after_analysis.stop = Compilation::Stop;
+        control
+    }
+}
+
+/// Run `function`, which takes no arguments, of the library `source`, and return the class
+/// of the finding that failed the run, if any.
+fn run(source: &str, function: &str, mut config: MiriConfig) -> Option<miri::ErrorClass> {
+    let path = env::temp_dir().join(format!("miri-library-{}-{}.rs", process::id(), function));
+    fs::write(&path, source).unwrap();
+    let mut args: Vec<String> = vec![
+        "miri".to_owned(),
+        path.display().to_string(),
+        "--crate-type=lib".to_owned(),
+        "--crate-name=library".to_owned(),
+        "--edition=2018".to_owned(),
+        "--sysroot".to_owned(),
+        sysroot(),
+    ];
+    args.splice(1..1, miri::miri_default_args().iter().map(ToString::to_string));
+    config.call = Some(miri::Call::new(Vec::new()));
+    let failure = Arc::new(Mutex::new(None));
+    let calls = RunFunction { function: function.to_owned(), config, failure: failure.clone() };
+    // The compiler needs a bigger stack than the test threads have.
+    thread::Builder::new().stack_size(32 << 20).spawn(move || {
+        syntax::with_globals(|| rustc_driver::run_compiler(&args, Box::new(calls), None, None));
+    }).unwrap().join().unwrap();
+    fs::remove_file(&path).unwrap();
+    let failure = failure.lock().unwrap().take();
+    failure
+}
+
+#[derive(Default)]
+struct Recorder(Mutex<Vec<MemoryEvent>>);
+
+impl MemoryObserver for Recorder {
+    fn observe(&self, event: &MemoryEvent) {
+        self.0.lock().unwrap().push(*event);
+    }
+}
+
+#[test]
+fn observer_events() {
+    let source = r#"
+        use std::alloc::{alloc, dealloc, Layout};
+
+        pub fn heap() {
+            unsafe {
+                let layout = Layout::from_size_align_unchecked(13, 1);
+                let p = alloc(layout);
+                *p.add(3) = 7;
+                let _x = *p.add(3);
+                dealloc(p, layout);
+            }
+        }
+    "#;
+    let recorder = Arc::new(Recorder::default());
+    let mut config = MiriConfig::default();
+    config.observers.add(recorder.clone());
+    assert!(run(source, "heap", config).is_none());
+
+    let events = recorder.0.lock().unwrap();
+    // Nothing else in the program has 13 bytes.
+    let heap = events.iter()
+        .find(|event| event.kind == MemoryEventKind::Allocate && event.size.bytes() == 13)
+        .expect("the allocation was not observed")
+        .alloc_id;
+    let seen: Vec<(MemoryEventKind, u64, u64)> = events.iter()
+        .filter(|event| event.alloc_id == heap)
+        .map(|event| (event.kind, event.offset.bytes(), event.size.bytes()))
+        .collect();
+    assert_eq!(seen, vec![
+        (MemoryEventKind::Allocate, 0, 13),
+        (MemoryEventKind::Write, 3, 1),
+        (MemoryEventKind::Read, 3, 1),
+        (MemoryEventKind::Deallocate, 0, 13),
+    ]);
+    assert!(events.iter().filter(|event| event.alloc_id == heap).all(|event| event.span.is_some()));
+}
+
+#[test]
+fn report_json() {
+    let source = r#"
+        pub fn out_of_bounds() {
+            let v = vec![1u8, 2, 3];
+            let _x = unsafe { *v.as_ptr().add(3) };
+        }
+
+        pub fn aliasing() {
+            let mut x = 15;
+            let xraw = &mut x as *mut i32;
+            let xref = unsafe { &mut *xraw };
+            unsafe { *xraw = 16 };
+            *xref = 17;
+        }
+    "#;
+    let path = env::temp_dir().join(format!("miri-library-{}-report.json", process::id()));
+    let report = Report::json(path.clone());
+    let mut config = MiriConfig::default();
+    config.report = Some(report.clone());
+    assert_eq!(run(source, "out_of_bounds", config.clone()), Some(ErrorClass::UndefinedBehavior));
+    assert_eq!(run(source, "aliasing", config), Some(ErrorClass::UndefinedBehavior));
+
+    let findings = report.findings();
+    assert_eq!(findings.len(), 2);
+    assert_eq!(findings[0].code.as_ref().map(|code| &**code), Some("miri::undefined_behavior"));
+    assert_eq!(findings[0].alloc_ids.len(), 1);
+    assert!(findings[0].tags.is_empty());
+    assert_eq!(findings[1].code.as_ref().map(|code| &**code), Some("miri::undefined_behavior"));
+    assert_eq!(findings[1].alloc_ids.len(), 1);
+    match findings[1].tags[..] {
+        [Borrow::Uniq(_)] => {}
+        ref tags => panic!("expected the tag of `xref`, got {:?}", tags),
+    }
+
+    report.write().unwrap();
+    let json = fs::read_to_string(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    let alloc_ids = format!("\"alloc_ids\":[{}],\"tags\":[]", findings[0].alloc_ids[0].0);
+    assert!(json.contains(&alloc_ids), "{} is not in {}", alloc_ids, json);
+    assert!(json.contains("\"tags\":[{\"kind\":\"unique\",\"time\":"), "no unique tag in {}", json);
+}
+
+#[test]
+fn record_and_replay() {
+    let source = r#"
+        pub fn env() {
+            assert_eq!(std::env::var("A NAME").unwrap(), "a value\nwith spaces");
+        }
+    "#;
+    let dir = env::temp_dir().join(format!("miri-library-{}-replay", process::id()));
+    let mut config = MiriConfig::default();
+    config.env_vars = vec![("A NAME".to_owned(), "a value\nwith spaces".to_owned())];
+    config.replay = Some(ReplayMode::Record(dir.clone()));
+    assert!(run(source, "env", config).is_none());
+
+    // The variable comes from the log now.
+    let mut config = MiriConfig::default();
+    config.replay = Some(ReplayMode::Replay(dir.clone()));
+    assert!(run(source, "env", config).is_none());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn in_memory_findings() {
+    let source = r#"
+        pub fn null() {
+            let _x: i32 = unsafe { *std::ptr::null() };
+        }
+    "#;
+    let report = Report::in_memory();
+    let mut config = MiriConfig::default();
+    config.report = Some(report.clone());
+    assert_eq!(run(source, "null", config), Some(ErrorClass::UndefinedBehavior));
+
+    let findings = report.findings();
+    assert_eq!(findings.len(), 1);
+    let finding = &findings[0];
+    assert!(finding.program.ends_with("null"), "unexpected program {}", finding.program);
+    assert_eq!(finding.code.as_ref().map(|code| &**code), Some("miri::undefined_behavior"));
+    assert!(finding.message.text.contains("invalid use of NULL pointer"), "{}", finding.message.text);
+    let location = finding.message.location.as_ref().expect("the error has no location");
+    assert_eq!(location.line, 3);
+    assert!(location.snippet.as_ref().map_or(false, |line| line.contains("*std::ptr::null()")));
+    assert!(finding.notes.iter().any(|note| note.text.starts_with("inside call to")));
+}
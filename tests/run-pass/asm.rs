@@ -0,0 +1,14 @@
+#![feature(asm)]
+
+fn black_box<T>(dummy: T) -> T {
+    unsafe { asm!("" : : "r"(&dummy) : "memory" : "volatile") }
+    dummy
+}
+
+fn main() {
+    assert_eq!(black_box(42), 42);
+    unsafe {
+        asm!("nop");
+        asm!("" ::: "memory" : "volatile");
+    }
+}
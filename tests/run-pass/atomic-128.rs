@@ -0,0 +1,36 @@
+#![feature(core_intrinsics)]
+
+use std::intrinsics::*;
+
+#[repr(align(16))]
+struct Aligned(u128);
+
+fn main() {
+    let mut x = Aligned(1 << 100);
+    let ptr = &mut x.0 as *mut u128;
+    unsafe {
+        assert_eq!(atomic_load(ptr), 1 << 100);
+        atomic_store(ptr, 5);
+        assert_eq!(atomic_xadd(ptr, !0 << 64), 5);
+        assert_eq!(atomic_load(ptr), (!0 << 64) + 5);
+
+        // A failing and a succeeding compare-exchange, like `cmpxchg16b`.
+        assert_eq!(atomic_cxchg(ptr, 0, 1), ((!0 << 64) + 5, false));
+        assert_eq!(atomic_cxchg(ptr, (!0 << 64) + 5, 7), ((!0 << 64) + 5, true));
+        assert_eq!(atomic_xchg(ptr, 1 << 127), 7);
+
+        assert_eq!(atomic_umax(ptr, 3), 1 << 127);
+        assert_eq!(atomic_umin(ptr, 3), 1 << 127);
+        assert_eq!(atomic_nand(ptr, !0), 3);
+        assert_eq!(atomic_load(ptr), !3);
+    }
+
+    let mut y = Aligned(0);
+    let ptr = &mut y.0 as *mut u128 as *mut i128;
+    unsafe {
+        atomic_store(ptr, -5);
+        assert_eq!(atomic_max(ptr, -10), -5);
+        assert_eq!(atomic_min(ptr, -10), -5);
+        assert_eq!(atomic_load(ptr), -10);
+    }
+}
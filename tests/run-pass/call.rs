@@ -0,0 +1,14 @@
+// compile-flags: -Zmiri-call=parse::checksum -Zmiri-call-args=tests/run-pass/call-args.json
+// Only `parse::checksum` runs, with the arguments from the JSON file.
+
+mod parse {
+    pub fn checksum(data: &[u8], name: &str, scale: i32) -> i64 {
+        assert_eq!(name, "h\u{e9}llo");
+        data.iter().map(|&b| b as i64).sum::<i64>() * scale as i64
+    }
+}
+
+fn main() {
+    parse::checksum(&[], "", 0);
+    panic!("miri should have called `parse::checksum` instead");
+}
@@ -1,4 +1,3 @@
-//ignore-msvc: Stdout not implemented on Windows
 use std::panic::{catch_unwind, AssertUnwindSafe};
 
 fn main() {
@@ -0,0 +1,31 @@
+use std::ptr;
+
+fn main() {
+    // Plain data is copied in bulk.
+    let src = vec![0u8; 1 << 16];
+    let mut dest = vec![1u8; 16];
+    dest.resize(1 << 16, 0);
+    dest.copy_from_slice(&src);
+    assert!(dest.iter().rev().take(16).all(|&b| b == 0));
+    assert_eq!(dest[0], 0);
+
+    // Overlapping copies within one allocation
+    let mut bytes = [1u8, 2, 3, 4, 5, 6, 7, 8];
+    unsafe { ptr::copy(bytes.as_ptr(), bytes.as_mut_ptr().offset(2), 6) };
+    assert_eq!(bytes, [1, 2, 1, 2, 3, 4, 5, 6]);
+
+    // Pointers keep working after a copy.
+    let (a, b) = (1, 2);
+    let refs = [&a, &b];
+    let mut copy = [&0; 2];
+    copy.copy_from_slice(&refs);
+    assert_eq!(*copy[0] + *copy[1], 3);
+
+    // As does uninitialized memory, like padding.
+    #[repr(C)]
+    struct Padded(u8, u16);
+    let padded = [Padded(1, 2), Padded(3, 4)];
+    let mut moved = [Padded(0, 0), Padded(0, 0)];
+    unsafe { ptr::copy_nonoverlapping(padded.as_ptr(), moved.as_mut_ptr(), 2) };
+    assert_eq!(moved[1].0 as u16 + moved[1].1, 7);
+}
@@ -0,0 +1,13 @@
+// compile-flags: -Zmiri-random-nans -Zmiri-float-imprecision -Zmiri-deterministic-floats
+
+fn main() {
+    // The other flags are overridden, so every run of an operation gives the same bits.
+    let nans: Vec<u64> = (0..16).map(|_| (-1.0f64).sqrt().to_bits()).collect();
+    assert!(f64::from_bits(nans[0]).is_nan());
+    assert!(nans.iter().all(|&n| n == nans[0]));
+
+    let sines: Vec<f64> = (0..16).map(|_| 1.0f64.sin()).collect();
+    assert!(sines.iter().all(|&s| s == sines[0]));
+    let powers: Vec<f32> = (0..16).map(|_| 1.5f32.powf(2.5)).collect();
+    assert!(powers.iter().all(|&p| p == powers[0]));
+}
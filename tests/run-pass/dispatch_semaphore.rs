@@ -0,0 +1,44 @@
+//ignore-linux: Uses macOS APIs
+//ignore-windows: Uses macOS APIs
+
+use std::os::raw::c_void;
+
+type DispatchTime = u64;
+const DISPATCH_TIME_NOW: DispatchTime = 0;
+const DISPATCH_TIME_FOREVER: DispatchTime = !0;
+
+extern "C" {
+    fn dispatch_time(when: DispatchTime, delta: i64) -> DispatchTime;
+    fn dispatch_semaphore_create(value: isize) -> *mut c_void;
+    fn dispatch_semaphore_wait(sema: *mut c_void, timeout: DispatchTime) -> isize;
+    fn dispatch_semaphore_signal(sema: *mut c_void) -> isize;
+    fn dispatch_retain(object: *mut c_void);
+    fn dispatch_release(object: *mut c_void);
+    fn mach_absolute_time() -> u64;
+}
+
+fn main() {
+    unsafe {
+        assert!(dispatch_semaphore_create(-1).is_null());
+
+        let sema = dispatch_semaphore_create(1);
+        assert!(!sema.is_null());
+        assert_eq!(dispatch_semaphore_wait(sema, DISPATCH_TIME_FOREVER), 0);
+        // The count is 0 now, so polling fails.
+        assert_ne!(dispatch_semaphore_wait(sema, DISPATCH_TIME_NOW), 0);
+
+        // Waiting with a timeout lets the time pass.
+        let before = mach_absolute_time();
+        assert_ne!(dispatch_semaphore_wait(sema, dispatch_time(DISPATCH_TIME_NOW, 1_000_000)), 0);
+        assert!(mach_absolute_time() - before >= 1_000_000);
+
+        dispatch_semaphore_signal(sema);
+        assert_eq!(dispatch_semaphore_wait(sema, DISPATCH_TIME_NOW), 0);
+        // Restore the initial value, so that the semaphore may be destroyed.
+        dispatch_semaphore_signal(sema);
+
+        dispatch_retain(sema);
+        dispatch_release(sema);
+        dispatch_release(sema);
+    }
+}
@@ -0,0 +1,10 @@
+// compile-flags: -Zmiri-env-set=MIRI_GREETING=hi -Zmiri-env-set=MIRI_GREETING=hello -Zmiri-env-forward=MIRI_NOT_SET_ON_THE_HOST
+
+use std::env;
+
+fn main() {
+    assert_eq!(env::var("MIRI_GREETING"), Ok("hello".to_owned()));
+    assert_eq!(env::var("MIRI_NOT_SET_ON_THE_HOST"), Err(env::VarError::NotPresent));
+    env::remove_var("MIRI_GREETING");
+    assert_eq!(env::var("MIRI_GREETING"), Err(env::VarError::NotPresent));
+}
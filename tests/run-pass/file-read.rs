@@ -0,0 +1,15 @@
+//ignore-windows: File access is only supported on Unix
+// compile-flags: -Zmiri-isolation-allow-path=tests/run-pass/*
+
+use std::fs::File;
+use std::io::{ErrorKind, Read};
+
+fn main() {
+    // This test reads itself.
+    let mut contents = String::new();
+    File::open("tests/run-pass/file-read.rs").unwrap().read_to_string(&mut contents).unwrap();
+    assert!(contents.starts_with("//ignore-windows"));
+
+    let err = File::open("tests/run-pass/does-not-exist.txt").unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::NotFound);
+}
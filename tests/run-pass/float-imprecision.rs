@@ -0,0 +1,20 @@
+// compile-flags: -Zmiri-float-imprecision -Zmiri-seed=7
+
+fn main() {
+    // The results are close, but not always the same.
+    let sines: Vec<f64> = (0..16).map(|_| 1.0f64.sin()).collect();
+    assert!(sines.iter().all(|&s| (s - 0.8414709848078965).abs() < 1e-15));
+    assert!(sines.iter().any(|&s| s != sines[0]));
+    let logs: Vec<f32> = (0..16).map(|_| 3.0f32.ln()).collect();
+    assert!(logs.iter().all(|&l| (l - 1.0986123).abs() < 1e-6));
+    assert!(logs.iter().any(|&l| l != logs[0]));
+
+    // Integral results are exact.
+    assert_eq!(0.0f64.exp(), 1.0);
+    assert_eq!(2.0f64.powf(10.0), 1024.0);
+    assert_eq!(8.0f32.log2(), 3.0);
+    assert_eq!(0.0f32.sin(), 0.0);
+
+    // The correctly rounded operations are unaffected.
+    assert_eq!(2.0f64.sqrt(), std::f64::consts::SQRT_2);
+}
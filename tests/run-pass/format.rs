@@ -1,4 +1,3 @@
-//ignore-msvc: Stdout not implemented on Windows
 fn main() {
     println!("Hello {}", 13);
 }
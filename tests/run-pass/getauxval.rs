@@ -0,0 +1,16 @@
+//ignore-windows: Uses Linux APIs
+//ignore-macos: Uses Linux APIs
+
+extern "C" {
+    fn getauxval(ty: usize) -> usize;
+}
+
+const AT_PAGESZ: usize = 6;
+const AT_HWCAP: usize = 16;
+
+fn main() {
+    unsafe {
+        assert_eq!(getauxval(AT_PAGESZ), 4096);
+        assert_eq!(getauxval(AT_HWCAP), 0);
+    }
+}
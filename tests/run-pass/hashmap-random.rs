@@ -0,0 +1,14 @@
+//ignore-macos: std reads `/dev/urandom` there, which isolation does not allow
+// compile-flags: -Zmiri-host-rng
+
+use std::collections::HashMap;
+
+fn main() {
+    // `RandomState` gets its keys from `getrandom`.
+    let mut map = HashMap::new();
+    for i in 0..22 {
+        map.insert(i, i * 2);
+    }
+    assert_eq!(map.len(), 22);
+    assert_eq!(map[&10], 20);
+}
@@ -0,0 +1,11 @@
+// compile-flags: -Zmiri-warn-int2ptr
+
+fn main() {
+    let x = 42u8;
+    let addr = &x as *const u8 as usize;
+    // Warns, but does not fail.  Casting the same way again is not reported again.
+    for _ in 0..3 {
+        let p = addr as *const u8;
+        assert!(!p.is_null());
+    }
+}
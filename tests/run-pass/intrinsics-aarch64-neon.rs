@@ -0,0 +1,38 @@
+#![cfg_attr(target_arch = "aarch64", feature(stdsimd))]
+
+#[cfg(target_arch = "aarch64")]
+fn main() {
+    use std::arch::aarch64::*;
+    use std::mem::transmute;
+
+    unsafe {
+        let bytes: [u8; 16] = [3, 200, 1, 0, 7, 9, 255, 4, 2, 8, 6, 5, 10, 11, 12, 13];
+        let v: uint8x16_t = transmute(bytes);
+        assert_eq!(vmaxvq_u8(v), 255);
+        assert_eq!(vminvq_u8(v), 0);
+        // Signed reductions look at the same bits differently.
+        assert_eq!(vmaxvq_s8(transmute(v)), 13);
+        assert_eq!(vminvq_s8(transmute(v)), -56);
+
+        let max: [u8; 16] = transmute(vpmaxq_u8(v, v));
+        assert_eq!(&max[..8], &[200, 1, 9, 255, 8, 6, 11, 13]);
+        assert_eq!(&max[8..], &max[..8]);
+
+        let min: [f32; 4] = transmute(vpminq_f32(
+            transmute([1.0f32, 2.0, -1.0, 5.0]),
+            transmute([std::f32::NAN, 0.0, 3.0, 3.0]),
+        ));
+        assert_eq!(min[0], 1.0);
+        assert_eq!(min[1], -1.0);
+        assert!(min[2].is_nan());
+        assert_eq!(min[3], 3.0);
+
+        // Table lookups with out-of-range indices produce 0.
+        let table: uint8x8_t = transmute([10u8, 11, 12, 13, 14, 15, 16, 17]);
+        let looked_up: [u8; 8] = transmute(vtbl1_u8(table, transmute([7u8, 0, 8, 255, 1, 1, 2, 3])));
+        assert_eq!(looked_up, [17, 10, 0, 0, 11, 11, 12, 13]);
+    }
+}
+
+#[cfg(not(target_arch = "aarch64"))]
+fn main() {}
@@ -0,0 +1,57 @@
+#[cfg(target_arch = "x86_64")]
+fn main() {
+    unsafe {
+        test_aes();
+        test_pclmulqdq();
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "aes")]
+unsafe fn test_aes() {
+    use std::arch::x86_64::*;
+    use std::mem::transmute;
+
+    // The first round of the AES-128 example in FIPS-197, appendix B.
+    let input: __m128i = transmute(0x3243f6a8_885a308d_313198a2_e0370734u128.swap_bytes());
+    let key: __m128i = transmute(0x2b7e1516_28aed2a6_abf71588_09cf4f3cu128.swap_bytes());
+    let state = _mm_xor_si128(input, key);
+    let round_key: __m128i = transmute(0xa0fafe17_88542cb1_23a33939_2a6c7605u128.swap_bytes());
+    let round: u128 = transmute(_mm_aesenc_si128(state, round_key));
+    assert_eq!(round.swap_bytes(), 0xa49c7ff2_689f352b_6b5bea43_026a5049);
+
+    // `aesimc` undoes the `MixColumns` step.
+    let mixed = _mm_aesenc_si128(state, _mm_setzero_si128());
+    let unmixed: u128 = transmute(_mm_aesimc_si128(mixed));
+    assert_eq!(unmixed, transmute::<_, u128>(_mm_aesenclast_si128(state, _mm_setzero_si128())));
+
+    let last = _mm_aesenclast_si128(state, _mm_setzero_si128());
+    let undone: u128 = transmute(_mm_aesdeclast_si128(last, _mm_setzero_si128()));
+    assert_eq!(undone, transmute::<_, u128>(state));
+
+    // The key expansion for the second round key of the same example.
+    let assist: [u32; 4] = transmute(_mm_aeskeygenassist_si128(key, 0x01));
+    let key: [u32; 4] = transmute(key);
+    let temp = assist[3];
+    let w4 = key[0] ^ temp;
+    assert_eq!(w4.swap_bytes(), 0xa0fafe17);
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "pclmulqdq")]
+unsafe fn test_pclmulqdq() {
+    use std::arch::x86_64::*;
+    use std::mem::transmute;
+
+    let a = _mm_set_epi64x(0b101, 0b11);
+    let b = _mm_set_epi64x(!0, 0b11);
+    let low: u128 = transmute(_mm_clmulepi64_si128(a, b, 0x00));
+    assert_eq!(low, 0b101);
+    let high: u128 = transmute(_mm_clmulepi64_si128(a, b, 0x11));
+    assert_eq!(high, (!0u64 as u128) ^ ((!0u64 as u128) << 2));
+    let mixed: u128 = transmute(_mm_clmulepi64_si128(a, b, 0x01));
+    assert_eq!(mixed, 0b1111);
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+fn main() {}
@@ -0,0 +1,91 @@
+#[cfg(target_arch = "x86_64")]
+fn main() {
+    unsafe { test_avx2() }
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2")]
+unsafe fn test_avx2() {
+    use std::arch::x86_64::*;
+    use std::mem::transmute;
+
+    let mut bytes = [0u8; 32];
+    bytes[0] = 0x80;
+    bytes[17] = 0xFF;
+    bytes[31] = 0x90;
+    let v = _mm256_loadu_si256(bytes.as_ptr() as *const __m256i);
+    assert_eq!(_mm256_movemask_epi8(v), (1 << 31) | (1 << 17) | 1);
+
+    // Packing works on each 128-bit half separately.
+    let packed: [i16; 16] = transmute(_mm256_packs_epi32(
+        _mm256_setr_epi32(1, 2, 3, 4, 5, 6, 7, 100_000),
+        _mm256_setr_epi32(-1, -2, -3, -4, -5, -6, -7, -100_000),
+    ));
+    assert_eq!(packed, [1, 2, 3, 4, -1, -2, -3, -4, 5, 6, 7, 32767, -5, -6, -7, -32768]);
+
+    let shifted: [u32; 8] = transmute(_mm256_sllv_epi32(
+        _mm256_set1_epi32(1),
+        _mm256_setr_epi32(0, 1, 2, 3, 31, 32, 33, -1),
+    ));
+    assert_eq!(shifted, [1, 2, 4, 8, 1 << 31, 0, 0, 0]);
+    let shifted: [i32; 8] = transmute(_mm256_srav_epi32(_mm256_set1_epi32(-64), _mm256_setr_epi32(0, 1, 2, 3, 4, 5, 6, 40)));
+    assert_eq!(shifted, [-64, -32, -16, -8, -4, -2, -1, -1]);
+
+    let permuted: [i32; 8] = transmute(_mm256_permutevar8x32_epi32(
+        _mm256_setr_epi32(10, 11, 12, 13, 14, 15, 16, 17),
+        _mm256_setr_epi32(7, 6, 5, 4, 3, 2, 1, 8),
+    ));
+    assert_eq!(permuted, [17, 16, 15, 14, 13, 12, 11, 10]);
+
+    let halves: [i64; 4] = transmute(_mm256_permute2x128_si256(
+        _mm256_setr_epi64x(1, 2, 3, 4),
+        _mm256_setr_epi64x(5, 6, 7, 8),
+        0x83,
+    ));
+    assert_eq!(halves, [7, 8, 0, 0]);
+
+    // Byte shuffles do not cross the 128-bit halves.
+    let swizzled: [u8; 32] = transmute(_mm256_shuffle_epi8(v, _mm256_set1_epi8(1)));
+    assert_eq!(swizzled[0], 0);
+    assert_eq!(swizzled[16], 0xFF);
+
+    let values = [1i32, 2, 3, 4, 5, 6, 7, 8];
+    let gathered: [i32; 8] = transmute(_mm256_mask_i32gather_epi32(
+        _mm256_set1_epi32(-1),
+        values.as_ptr(),
+        _mm256_setr_epi32(7, 6, 5, 4, 3, 2, 1, 0),
+        _mm256_setr_epi32(-1, -1, 0, -1, 0, -1, -1, -1),
+        4,
+    ));
+    assert_eq!(gathered, [8, 7, -1, 5, -1, 3, 2, 1]);
+    let gathered: [i32; 4] = transmute(_mm256_i64gather_epi32(
+        values.as_ptr(),
+        _mm256_setr_epi64x(0, 1, 2, 3),
+        4,
+    ));
+    assert_eq!(gathered, [1, 2, 3, 4]);
+
+    let rounded: [f64; 4] = transmute(_mm256_round_pd(_mm256_setr_pd(1.5, 2.5, -1.5, 0.2), _MM_FROUND_TO_NEAREST_INT));
+    assert_eq!(rounded, [2.0, 2.0, -2.0, 0.0]);
+    let floored: [f32; 8] = transmute(_mm256_floor_ps(_mm256_set1_ps(-0.5)));
+    assert_eq!(floored, [-1.0; 8]);
+
+    let ge: [u64; 4] = transmute(_mm256_cmp_pd(
+        _mm256_setr_pd(1.0, 2.0, 3.0, std::f64::NAN),
+        _mm256_set1_pd(2.0),
+        _CMP_GE_OQ,
+    ));
+    assert_eq!(ge, [0, !0, !0, 0]);
+
+    let converted: [i32; 4] = transmute(_mm256_cvttpd_epi32(_mm256_setr_pd(1.9, -1.9, 1e100, 0.0)));
+    assert_eq!(converted, [1, -1, i32::min_value(), 0]);
+
+    assert_eq!(_mm256_testz_si256(v, _mm256_setzero_si256()), 1);
+    assert_eq!(_mm256_testc_si256(v, v), 1);
+    assert_eq!(_mm256_testnzc_si256(v, _mm256_set1_epi8(0x10)), 1);
+
+    _mm256_zeroupper();
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+fn main() {}
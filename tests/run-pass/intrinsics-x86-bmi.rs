@@ -0,0 +1,46 @@
+#[cfg(target_arch = "x86_64")]
+fn main() {
+    unsafe {
+        test_bmi1();
+        test_bmi2();
+        test_popcnt();
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "bmi1")]
+unsafe fn test_bmi1() {
+    use std::arch::x86_64::*;
+
+    assert_eq!(_bextr_u32(0b1011_0110, 1, 4), 0b1011);
+    assert_eq!(_bextr_u64(!0, 60, 10), 0b1111);
+    assert_eq!(_bextr_u64(!0, 64, 10), 0);
+    assert_eq!(_tzcnt_u64(0b1000), 3);
+    assert_eq!(_tzcnt_u64(0), 64);
+    assert_eq!(_tzcnt_u32(0), 32);
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "bmi2")]
+unsafe fn test_bmi2() {
+    use std::arch::x86_64::*;
+
+    assert_eq!(_pext_u64(0b1011_0110, 0b1111_0000), 0b1011);
+    assert_eq!(_pext_u32(0xFFFF_0000, 0x8000_0001), 0b10);
+    assert_eq!(_pdep_u64(0b1011, 0b1111_0000), 0b1011_0000);
+    assert_eq!(_pdep_u32(!0, 0x8000_0001), 0x8000_0001);
+    assert_eq!(_bzhi_u64(!0, 3), 0b111);
+    assert_eq!(_bzhi_u32(!0, 200), !0);
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "popcnt")]
+unsafe fn test_popcnt() {
+    use std::arch::x86_64::*;
+
+    assert_eq!(_popcnt64(-1), 64);
+    assert_eq!(_popcnt32(0b1011), 3);
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+fn main() {}
@@ -0,0 +1,53 @@
+#[cfg(target_arch = "x86_64")]
+fn main() {
+    use std::arch::x86_64::*;
+    use std::mem::transmute;
+
+    unsafe {
+        let bytes: [u8; 16] = [0, 0x80, 1, 0xFF, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 0x90];
+        let v = _mm_loadu_si128(bytes.as_ptr() as *const __m128i);
+        assert_eq!(_mm_movemask_epi8(v), 0b1000_0000_0000_1010);
+
+        let eq = _mm_cmpeq_epi8(v, _mm_set1_epi8(2));
+        assert_eq!(_mm_movemask_epi8(eq), 0b1_0000);
+
+        let sat: [u8; 16] = transmute(_mm_adds_epu8(v, _mm_set1_epi8(0x70)));
+        assert_eq!(sat[0], 0x70);
+        assert_eq!(sat[1], 0xF0);
+        assert_eq!(sat[3], 0xFF);
+
+        let packed: [i8; 16] = transmute(_mm_packs_epi16(
+            _mm_setr_epi16(1, -1, 200, -200, 127, -128, 0, 300),
+            _mm_set1_epi16(0),
+        ));
+        assert_eq!(&packed[..8], &[1, -1, 127, -128, 127, -128, 0, 127]);
+
+        let sad: [u64; 2] = transmute(_mm_sad_epu8(v, _mm_setzero_si128()));
+        assert_eq!(sad[0], 0x80 + 1 + 0xFF + 2 + 3 + 4 + 5);
+
+        let shifted: [i32; 4] = transmute(_mm_slli_epi32(_mm_setr_epi32(1, 2, 3, -1), 4));
+        assert_eq!(shifted, [16, 32, 48, -16]);
+        let shifted: [i16; 8] = transmute(_mm_sra_epi16(_mm_set1_epi16(-256), _mm_setr_epi32(4, 0, 0, 0)));
+        assert_eq!(shifted, [-16; 8]);
+        let shifted: [i16; 8] = transmute(_mm_srl_epi16(_mm_set1_epi16(-1), _mm_setr_epi32(16, 0, 0, 0)));
+        assert_eq!(shifted, [0; 8]);
+
+        let max: [f32; 4] = transmute(_mm_max_ps(_mm_setr_ps(1.0, 5.0, -1.0, 0.0), _mm_set1_ps(2.0)));
+        assert_eq!(max, [2.0, 5.0, 2.0, 2.0]);
+
+        let rounded: [i32; 4] = transmute(_mm_cvtps_epi32(_mm_setr_ps(0.5, 1.5, -2.5, 1e20)));
+        assert_eq!(rounded, [0, 2, -2, i32::min_value()]);
+
+        let lt: [u32; 4] = transmute(_mm_cmplt_ps(_mm_setr_ps(1.0, 3.0, std::f32::NAN, 0.0), _mm_set1_ps(2.0)));
+        assert_eq!(lt, [!0, 0, 0, !0]);
+
+        let swizzled: [u8; 16] = transmute(_mm_shuffle_epi8(
+            v,
+            _mm_setr_epi8(15, 0, 1, -1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0),
+        ));
+        assert_eq!(&swizzled[..4], &[0x90, 0, 0x80, 0]);
+    }
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+fn main() {}
@@ -0,0 +1,10 @@
+//ignore-windows: No clock emulation on Windows
+// compile-flags: -Zmiri-isolation-error=clock=hide
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+fn main() {
+    // Hiding the wall clock gives the virtual clock, which starts at the epoch.
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+    assert_eq!(now.as_secs(), 0);
+}
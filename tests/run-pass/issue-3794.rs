@@ -8,7 +8,6 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
-//ignore-msvc: Stdout not implemented on Windows
 #![feature(box_syntax)]
 
 trait T {
@@ -0,0 +1,69 @@
+//ignore-linux: Uses kqueue
+//ignore-windows: Uses kqueue
+
+#![feature(libc)]
+extern crate libc;
+
+use std::{mem, ptr};
+
+fn kevent(ident: usize, filter: i16, flags: u16, fflags: u32, data: isize) -> libc::kevent {
+    let mut event: libc::kevent = unsafe { mem::zeroed() };
+    event.ident = ident as _;
+    event.filter = filter as _;
+    event.flags = flags as _;
+    event.fflags = fflags as _;
+    event.data = data as _;
+    event
+}
+
+fn main() {
+    unsafe {
+        let kq = libc::kqueue();
+        assert!(kq >= 0);
+        let mut events: [libc::kevent; 4] = mem::zeroed();
+
+        // Register a user event, with a receipt.
+        let change = kevent(42, libc::EVFILT_USER, libc::EV_ADD | libc::EV_CLEAR | libc::EV_RECEIPT, 0, 0);
+        assert_eq!(libc::kevent(kq, &change, 1, events.as_mut_ptr(), 4, ptr::null()), 1);
+        assert_eq!(events[0].ident, 42);
+        assert!(events[0].flags & libc::EV_ERROR != 0);
+        assert_eq!(events[0].data, 0);
+
+        // Nothing is pending yet.
+        let zero = libc::timespec { tv_sec: 0, tv_nsec: 0 };
+        assert_eq!(libc::kevent(kq, ptr::null(), 0, events.as_mut_ptr(), 4, &zero), 0);
+
+        // Trigger it.  It is reported once, since it is `EV_CLEAR`.
+        let change = kevent(42, libc::EVFILT_USER, 0, libc::NOTE_TRIGGER, 0);
+        assert_eq!(libc::kevent(kq, &change, 1, events.as_mut_ptr(), 4, &zero), 1);
+        assert_eq!(events[0].ident, 42);
+        assert_eq!(events[0].filter, libc::EVFILT_USER);
+        assert_eq!(libc::kevent(kq, ptr::null(), 0, events.as_mut_ptr(), 4, &zero), 0);
+
+        // A periodic timer, in milliseconds.  Waiting without a timeout returns when it fires.
+        let change = kevent(7, libc::EVFILT_TIMER, libc::EV_ADD, 0, 10);
+        assert_eq!(libc::kevent(kq, &change, 1, events.as_mut_ptr(), 4, ptr::null()), 1);
+        assert_eq!(events[0].ident, 7);
+        assert_eq!(events[0].filter, libc::EVFILT_TIMER);
+        assert_eq!(events[0].data, 1);
+
+        // A short timeout expires before the timer fires again.
+        let short = libc::timespec { tv_sec: 0, tv_nsec: 5_000_000 };
+        assert_eq!(libc::kevent(kq, ptr::null(), 0, events.as_mut_ptr(), 4, &short), 0);
+
+        // Disabling and enabling the timer keeps its period.
+        let change = kevent(7, libc::EVFILT_TIMER, libc::EV_DISABLE, 0, 0);
+        assert_eq!(libc::kevent(kq, &change, 1, ptr::null_mut(), 0, ptr::null()), 0);
+        let change = kevent(7, libc::EVFILT_TIMER, libc::EV_ENABLE, 0, 0);
+        assert_eq!(libc::kevent(kq, &change, 1, ptr::null_mut(), 0, ptr::null()), 0);
+        assert_eq!(libc::kevent(kq, ptr::null(), 0, events.as_mut_ptr(), 4, ptr::null()), 1);
+        assert_eq!(events[0].ident, 7);
+
+        // Remove the timer.
+        let change = kevent(7, libc::EVFILT_TIMER, libc::EV_DELETE, 0, 0);
+        assert_eq!(libc::kevent(kq, &change, 1, ptr::null_mut(), 0, ptr::null()), 0);
+        assert_eq!(libc::kevent(kq, ptr::null(), 0, events.as_mut_ptr(), 4, &short), 0);
+
+        assert_eq!(libc::close(kq), 0);
+    }
+}
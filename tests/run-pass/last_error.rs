@@ -0,0 +1,16 @@
+//ignore-linux: Uses Windows APIs
+//ignore-macos: Uses Windows APIs
+
+use std::io;
+
+extern "system" {
+    fn SetLastError(err: u32);
+}
+
+fn main() {
+    unsafe { SetLastError(42); }
+    assert_eq!(io::Error::last_os_error().raw_os_error(), Some(42));
+    // Env var lookups set the error code if the variable is missing.
+    assert!(std::env::var_os("MIRI_NOT_SET").is_none());
+    assert_eq!(io::Error::last_os_error().raw_os_error(), Some(203));
+}
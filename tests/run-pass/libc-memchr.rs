@@ -0,0 +1,37 @@
+//ignore-windows: Uses POSIX APIs
+
+#![feature(libc)]
+extern crate libc;
+
+use std::ffi::CStr;
+
+fn memchr(haystack: *const u8, needle: u8, len: usize) -> Option<usize> {
+    let found = unsafe {
+        libc::memchr(haystack as *const libc::c_void, needle as libc::c_int, len)
+    };
+    if found.is_null() {
+        None
+    } else {
+        Some(found as usize - haystack as usize)
+    }
+}
+
+fn main() {
+    let data = *b"hello, world";
+    assert_eq!(memchr(data.as_ptr(), b'o', data.len()), Some(4));
+    assert_eq!(memchr(data.as_ptr(), b'x', data.len()), None);
+    assert_eq!(memchr(data.as_ptr(), b'h', 0), None);
+    // memchr stops at the first match, so the length may go past the end of the buffer.
+    assert_eq!(memchr(data.as_ptr(), b',', 1000), Some(5));
+
+    let undef: Vec<u8> = {
+        let mut v = Vec::with_capacity(8);
+        v.push(b'a');
+        v
+    };
+    // Only the bytes up to the match are read.
+    assert_eq!(memchr(undef.as_ptr(), b'a', 8), Some(0));
+
+    let s = CStr::from_bytes_with_nul(b"abc\0").unwrap();
+    assert_eq!(s.to_bytes(), b"abc");
+}
@@ -0,0 +1,25 @@
+//ignore-linux: Uses macOS symbol names
+//ignore-windows: Uses macOS symbol names
+
+#![feature(libc)]
+#![allow(unused_extern_crates)] // rustc bug https://github.com/rust-lang/rust/issues/56098
+
+extern crate libc;
+
+use std::{io, mem};
+
+extern {
+    // What `libc::fstat` links to on macOS
+    #[link_name = "fstat$INODE64"]
+    fn fstat_inode64(fd: libc::c_int, buf: *mut libc::stat) -> libc::c_int;
+}
+
+fn main() {
+    unsafe {
+        // The suffix is stripped, so this reaches the `fstat` shim, which does not support
+        // file metadata.
+        let mut buf: libc::stat = mem::zeroed();
+        assert_eq!(fstat_inode64(0, &mut buf), -1);
+        assert_eq!(io::Error::last_os_error().raw_os_error(), Some(libc::ENOSYS));
+    }
+}
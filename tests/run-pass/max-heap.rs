@@ -0,0 +1,21 @@
+// compile-flags: -Zmiri-max-heap=100000
+
+#![feature(try_reserve)]
+
+fn main() {
+    let mut v: Vec<u8> = Vec::new();
+    // More than the limit fails, and leaves the vector as it was.
+    assert!(v.try_reserve(1_000_000).is_err());
+    assert_eq!(v.capacity(), 0);
+    v.try_reserve(1000).unwrap();
+    v.extend_from_slice(&[1; 1000]);
+    // Growing past the limit fails as well.
+    assert!(v.try_reserve(1_000_000).is_err());
+    assert_eq!(v.len(), 1000);
+    // Freed memory can be allocated again.
+    drop(v);
+    for _ in 0..100 {
+        let v = vec![0u8; 50_000];
+        assert_eq!(v.len(), 50_000);
+    }
+}
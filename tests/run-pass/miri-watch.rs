@@ -0,0 +1,21 @@
+// Writes to watched memory only add notes, the program runs as usual.  The expected notes are
+// for the writes to data[5] and to data[4..8] in the loop, and none for the write to data[0].
+// compile-flags: -Zmiri-backtrace=short
+// normalize-stderr-test "alloc[0-9]+" -> "ALLOC"
+// normalize-stderr-test "inside call to `main` at .*" -> "inside call to `main`"
+// normalize-stderr-test "inside [0-9]+ calls" -> "inside N calls"
+
+extern "Rust" {
+    fn miri_watch(ptr: *const u8, len: usize);
+}
+
+fn main() {
+    let mut data = [0u8; 16];
+    unsafe { miri_watch(data.as_ptr().add(4), 4) };
+    data[0] = 1;
+    data[5] = 2;
+    for byte in data[3..9].iter_mut() {
+        *byte += 1;
+    }
+    assert_eq!(data[5], 3);
+}
@@ -0,0 +1,28 @@
+//ignore-linux: Uses macOS APIs
+//ignore-windows: Uses macOS APIs
+
+#[repr(C)]
+struct OsUnfairLock {
+    _os_unfair_lock_opaque: u32,
+}
+
+extern "C" {
+    fn os_unfair_lock_lock(lock: *mut OsUnfairLock);
+    fn os_unfair_lock_trylock(lock: *mut OsUnfairLock) -> bool;
+    fn os_unfair_lock_unlock(lock: *mut OsUnfairLock);
+    fn os_unfair_lock_assert_owner(lock: *const OsUnfairLock);
+    fn os_unfair_lock_assert_not_owner(lock: *const OsUnfairLock);
+}
+
+fn main() {
+    let mut lock = OsUnfairLock { _os_unfair_lock_opaque: 0 };
+    unsafe {
+        os_unfair_lock_lock(&mut lock);
+        os_unfair_lock_assert_owner(&lock);
+        assert!(!os_unfair_lock_trylock(&mut lock));
+        os_unfair_lock_unlock(&mut lock);
+        os_unfair_lock_assert_not_owner(&lock);
+        assert!(os_unfair_lock_trylock(&mut lock));
+        os_unfair_lock_unlock(&mut lock);
+    }
+}
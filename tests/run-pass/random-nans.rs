@@ -0,0 +1,20 @@
+// compile-flags: -Zmiri-random-nans -Zmiri-seed=42
+
+fn main() {
+    let nan = (-1.0f64).sqrt();
+    assert!(nan.is_nan());
+
+    // The payloads differ between operations, even with the same inputs.
+    let bits: Vec<u64> = (0..16).map(|_| (-1.0f64).sqrt().to_bits()).collect();
+    assert!(bits.iter().any(|&b| b != bits[0]));
+    let bits: Vec<u32> = (0..16).map(|_| (-1.0f32).powf(0.5).to_bits()).collect();
+    assert!(bits.iter().all(|&b| f32::from_bits(b).is_nan()));
+    assert!(bits.iter().any(|&b| b != bits[0]));
+
+    // `abs` keeps the payload.
+    assert_eq!(nan.abs().to_bits(), nan.to_bits() & !(1 << 63));
+
+    // Numbers are unaffected.
+    assert_eq!(4.0f64.sqrt(), 2.0);
+    assert_eq!(2.0f32.mul_add(3.0, 1.0), 7.0);
+}
@@ -0,0 +1,67 @@
+#![feature(repr_simd, platform_intrinsics)]
+#![allow(non_camel_case_types)]
+
+#[repr(simd)]
+#[derive(Copy, Clone, Debug, PartialEq)]
+struct i32x4(i32, i32, i32, i32);
+
+#[repr(simd)]
+#[derive(Copy, Clone, Debug, PartialEq)]
+struct f32x4(f32, f32, f32, f32);
+
+#[repr(simd)]
+#[derive(Copy, Clone, Debug, PartialEq)]
+struct ptrx4(*const i32, *const i32, *const i32, *const i32);
+
+extern "platform-intrinsic" {
+    fn simd_add<T>(x: T, y: T) -> T;
+    fn simd_mul<T>(x: T, y: T) -> T;
+    fn simd_shl<T>(x: T, y: T) -> T;
+    fn simd_div<T>(x: T, y: T) -> T;
+    fn simd_lt<T, U>(x: T, y: T) -> U;
+    fn simd_select<M, T>(m: M, a: T, b: T) -> T;
+    fn simd_extract<T, E>(x: T, idx: u32) -> E;
+    fn simd_insert<T, E>(x: T, idx: u32, val: E) -> T;
+    fn simd_shuffle4<T, U>(x: T, y: T, idx: [u32; 4]) -> U;
+    fn simd_cast<T, U>(x: T) -> U;
+    fn simd_reduce_add_unordered<T, U>(x: T) -> U;
+    fn simd_reduce_max<T, U>(x: T) -> U;
+    fn simd_reduce_all<T>(x: T) -> bool;
+    fn simd_reduce_any<T>(x: T) -> bool;
+    fn simd_gather<T, P, M>(values: T, pointers: P, mask: M) -> T;
+}
+
+fn main() {
+    unsafe {
+        let a = i32x4(1, 2, 3, 4);
+        let b = i32x4(10, 20, 30, 40);
+        assert_eq!(simd_add(a, b), i32x4(11, 22, 33, 44));
+        assert_eq!(simd_mul(a, i32x4(i32::max_value(), 1, 1, 1)), i32x4(i32::max_value(), 2, 3, 4));
+        assert_eq!(simd_shl(a, i32x4(1, 2, 3, 4)), i32x4(2, 8, 24, 64));
+        assert_eq!(simd_div(b, a), i32x4(10, 10, 10, 10));
+
+        let mask: i32x4 = simd_lt(a, i32x4(3, 3, 3, 3));
+        assert_eq!(mask, i32x4(-1, -1, 0, 0));
+        assert_eq!(simd_select(mask, a, b), i32x4(1, 2, 30, 40));
+        assert!(simd_reduce_any(mask));
+        assert!(!simd_reduce_all(mask));
+
+        assert_eq!(simd_extract::<_, i32>(b, 2), 30);
+        assert_eq!(simd_insert(a, 0, 7), i32x4(7, 2, 3, 4));
+        let shuffled: i32x4 = simd_shuffle4(a, b, [7, 0, 5, 2]);
+        assert_eq!(shuffled, i32x4(40, 1, 20, 3));
+
+        let f: f32x4 = simd_cast(a);
+        assert_eq!(f, f32x4(1.0, 2.0, 3.0, 4.0));
+        let back: i32x4 = simd_cast(f32x4(1.5, -2.5, 0.0, 100.9));
+        assert_eq!(back, i32x4(1, -2, 0, 100));
+
+        assert_eq!(simd_reduce_add_unordered::<_, i32>(a), 10);
+        assert_eq!(simd_reduce_max::<_, i32>(b), 40);
+
+        let data = [5, 6, 7, 8];
+        let ptrs = ptrx4(&data[3], &data[2], &data[1], &data[0]);
+        let gathered = simd_gather(i32x4(-1, -1, -1, -1), ptrs, i32x4(-1, 0, -1, 0));
+        assert_eq!(gathered, i32x4(8, -1, 6, -1));
+    }
+}
@@ -0,0 +1,9 @@
+// compile-flags: -Zmiri-stdin-data=hello,stdin
+
+use std::io::{self, Read};
+
+fn main() {
+    let mut input = String::new();
+    io::stdin().read_to_string(&mut input).unwrap();
+    assert_eq!(input, "hello,stdin");
+}
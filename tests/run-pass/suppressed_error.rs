@@ -0,0 +1,13 @@
+// compile-flags: -Zmiri-suppressions=tests/run-pass/suppressions.txt
+
+// The error is suppressed, so it does not fail the run, and is only mentioned in a note.
+// The program stops there all the same.
+
+fn deref_null_on_purpose() -> i32 {
+    unsafe { *std::ptr::null() }
+}
+
+fn main() {
+    let x = deref_null_on_purpose();
+    panic!("this should never print: {}", x);
+}
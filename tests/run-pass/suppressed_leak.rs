@@ -0,0 +1,11 @@
+// compile-flags: -Zmiri-suppressions=tests/run-pass/suppressions.txt
+
+// The leak is suppressed, so it does not fail the run, and is only counted in a note.
+
+fn leak_on_purpose() {
+    std::mem::forget(Box::new(42));
+}
+
+fn main() {
+    leak_on_purpose();
+}
@@ -0,0 +1,46 @@
+//ignore-linux: Uses sysctl
+//ignore-windows: Uses sysctl
+
+#![feature(libc)]
+extern crate libc;
+
+use std::{io, mem, ptr};
+
+fn main() {
+    unsafe {
+        // By MIB
+        let mut mib = [libc::CTL_HW, libc::HW_NCPU];
+        let mut ncpu: libc::c_int = 0;
+        let mut len = mem::size_of::<libc::c_int>();
+        assert_eq!(libc::sysctl(mib.as_mut_ptr(), 2, &mut ncpu as *mut _ as *mut _, &mut len, ptr::null_mut(), 0), 0);
+        assert_eq!(ncpu, 1);
+        assert_eq!(len, mem::size_of::<libc::c_int>());
+
+        // By name
+        let mut pagesize: libc::c_int = 0;
+        let mut len = mem::size_of::<libc::c_int>();
+        assert_eq!(libc::sysctlbyname(b"hw.pagesize\0".as_ptr() as *const _, &mut pagesize as *mut _ as *mut _, &mut len, ptr::null_mut(), 0), 0);
+        assert_eq!(pagesize as libc::c_long, libc::sysconf(libc::_SC_PAGESIZE));
+
+        // Strings: first query the length, then the value.
+        let name = b"kern.osrelease\0".as_ptr() as *const _;
+        let mut len = 0;
+        assert_eq!(libc::sysctlbyname(name, ptr::null_mut(), &mut len, ptr::null_mut(), 0), 0);
+        let mut buf = vec![0u8; len];
+        assert_eq!(libc::sysctlbyname(name, buf.as_mut_ptr() as *mut _, &mut len, ptr::null_mut(), 0), 0);
+        assert_eq!(buf.last(), Some(&0));
+        assert!(buf.len() > 1);
+
+        // A buffer that is too small is an error.
+        let mut small = [0u8; 1];
+        let mut len = 1;
+        assert_eq!(libc::sysctlbyname(name, small.as_mut_ptr() as *mut _, &mut len, ptr::null_mut(), 0), -1);
+        assert_eq!(io::Error::last_os_error().raw_os_error(), Some(libc::ENOMEM));
+
+        // The program cannot change the configuration.
+        let mut ncpu: libc::c_int = 4;
+        let new_len = mem::size_of::<libc::c_int>();
+        assert_eq!(libc::sysctl(mib.as_mut_ptr(), 2, ptr::null_mut(), ptr::null_mut(), &mut ncpu as *mut _ as *mut _, new_len), -1);
+        assert_eq!(io::Error::last_os_error().raw_os_error(), Some(libc::EPERM));
+    }
+}
@@ -0,0 +1,31 @@
+// compile-flags: --test -Zmiri-test-shard=1/3
+// normalize-stdout-test "\[[0-9a-f]+\]" -> ""
+
+// The second of three shards runs every third test, starting at the second one.  The tests of
+// the other shards fail if they run.
+
+#[test]
+fn test0() {
+    panic!("in shard 0");
+}
+
+#[test]
+fn test1() {}
+
+#[test]
+fn test2() {
+    panic!("in shard 2");
+}
+
+#[test]
+fn test3() {
+    panic!("in shard 0");
+}
+
+#[test]
+fn test4() {}
+
+#[test]
+fn test5() {
+    panic!("in shard 2");
+}
@@ -0,0 +1,9 @@
+use std::cell::RefCell;
+
+// If the TLS destructor does not run, the `Box` leaks and the test fails.
+thread_local!(static FOO: RefCell<Option<Box<u64>>> = RefCell::new(None));
+
+fn main() {
+    FOO.with(|foo| *foo.borrow_mut() = Some(Box::new(42)));
+    FOO.with(|foo| assert_eq!(**foo.borrow().as_ref().unwrap(), 42));
+}
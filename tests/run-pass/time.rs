@@ -0,0 +1,11 @@
+//ignore-windows: No clock emulation on Windows
+
+use std::time::{Duration, Instant};
+
+fn main() {
+    let start = Instant::now();
+    let elapsed = start.elapsed();
+    // The virtual clock only advances when the program waits.
+    assert!(elapsed < Duration::from_millis(1));
+    assert!(Instant::now() >= start);
+}
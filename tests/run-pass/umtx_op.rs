@@ -0,0 +1,45 @@
+//ignore-linux: Uses FreeBSD APIs
+//ignore-macos: Uses FreeBSD APIs
+//ignore-windows: Uses FreeBSD APIs
+
+#![feature(libc)]
+extern crate libc;
+
+use std::io;
+use std::mem;
+use std::ptr;
+use std::time::{Duration, Instant};
+
+extern "C" {
+    fn _umtx_op(
+        obj: *mut libc::c_void,
+        op: libc::c_int,
+        val: libc::c_ulong,
+        uaddr: *mut libc::c_void,
+        uaddr2: *mut libc::c_void,
+    ) -> libc::c_int;
+}
+
+const UMTX_OP_WAIT_UINT_PRIVATE: libc::c_int = 15;
+const UMTX_OP_WAKE_PRIVATE: libc::c_int = 16;
+
+fn main() {
+    let mut futex: u32 = 1;
+    let obj = &mut futex as *mut u32 as *mut libc::c_void;
+    unsafe {
+        // The value is not the expected one, so there is no waiting.
+        assert_eq!(_umtx_op(obj, UMTX_OP_WAIT_UINT_PRIVATE, 0, ptr::null_mut(), ptr::null_mut()), 0);
+
+        // Nobody can wake us up, so the wait times out, after the clock advanced by the timeout.
+        let timeout = libc::timespec { tv_sec: 0, tv_nsec: 50_000_000 };
+        let start = Instant::now();
+        let size = mem::size_of::<libc::timespec>() as *mut libc::c_void;
+        let res = _umtx_op(obj, UMTX_OP_WAIT_UINT_PRIVATE, 1, size, &timeout as *const _ as *mut _);
+        assert_eq!(res, -1);
+        assert_eq!(io::Error::last_os_error().raw_os_error(), Some(libc::ETIMEDOUT));
+        assert!(start.elapsed() >= Duration::from_millis(50));
+
+        // There is nobody to wake up.
+        assert_eq!(_umtx_op(obj, UMTX_OP_WAKE_PRIVATE, 1, ptr::null_mut(), ptr::null_mut()), 0);
+    }
+}
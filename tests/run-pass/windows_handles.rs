@@ -0,0 +1,40 @@
+//ignore-linux: Uses Windows APIs
+//ignore-macos: Uses Windows APIs
+
+use std::ptr;
+
+type HANDLE = *mut u8;
+
+extern "system" {
+    fn GetCurrentProcess() -> HANDLE;
+    fn GetCurrentThread() -> HANDLE;
+    fn DuplicateHandle(
+        source_process: HANDLE, source: HANDLE,
+        target_process: HANDLE, target: *mut HANDLE,
+        access: u32, inherit: i32, options: u32,
+    ) -> i32;
+    fn CloseHandle(handle: HANDLE) -> i32;
+    fn WriteFile(
+        file: HANDLE, buffer: *const u8, len: u32,
+        written: *mut u32, overlapped: *mut u8,
+    ) -> i32;
+}
+
+fn main() {
+    unsafe {
+        let process = GetCurrentProcess();
+        let mut thread = ptr::null_mut();
+        assert_ne!(DuplicateHandle(process, GetCurrentThread(), process, &mut thread, 0, 0, 0), 0);
+        let mut thread2 = ptr::null_mut();
+        assert_ne!(DuplicateHandle(process, thread, process, &mut thread2, 0, 0, 0), 0);
+        assert_ne!(thread, thread2);
+        // Output to handles other than the standard streams is ignored.
+        let mut written = 0;
+        assert_ne!(WriteFile(thread, b"abc".as_ptr(), 3, &mut written, ptr::null_mut()), 0);
+        assert_eq!(written, 3);
+        assert_ne!(CloseHandle(thread), 0);
+        assert_ne!(CloseHandle(thread2), 0);
+        // Closing pseudo-handles is a NOP.
+        assert_ne!(CloseHandle(process), 0);
+    }
+}
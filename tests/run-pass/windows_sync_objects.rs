@@ -0,0 +1,60 @@
+//ignore-linux: Uses Windows APIs
+//ignore-macos: Uses Windows APIs
+
+use std::ptr;
+
+type HANDLE = *mut u8;
+
+extern "system" {
+    fn CreateEventW(attrs: *mut u8, manual_reset: i32, initial_state: i32, name: *const u16) -> HANDLE;
+    fn CreateMutexW(attrs: *mut u8, initial_owner: i32, name: *const u16) -> HANDLE;
+    fn SetEvent(event: HANDLE) -> i32;
+    fn ResetEvent(event: HANDLE) -> i32;
+    fn ReleaseMutex(mutex: HANDLE) -> i32;
+    fn WaitForSingleObject(handle: HANDLE, timeout: u32) -> u32;
+    fn CloseHandle(handle: HANDLE) -> i32;
+    fn GetLastError() -> u32;
+}
+
+const WAIT_OBJECT_0: u32 = 0;
+const WAIT_TIMEOUT: u32 = 0x102;
+const ERROR_ALREADY_EXISTS: u32 = 183;
+
+fn main() {
+    unsafe {
+        // Auto-reset event
+        let event = CreateEventW(ptr::null_mut(), 0, 1, ptr::null());
+        assert!(!event.is_null());
+        assert_eq!(WaitForSingleObject(event, 0), WAIT_OBJECT_0);
+        assert_eq!(WaitForSingleObject(event, 0), WAIT_TIMEOUT);
+        assert_ne!(SetEvent(event), 0);
+        assert_eq!(WaitForSingleObject(event, 0), WAIT_OBJECT_0);
+        assert_ne!(CloseHandle(event), 0);
+
+        // Manual-reset event
+        let event = CreateEventW(ptr::null_mut(), 1, 1, ptr::null());
+        assert_eq!(WaitForSingleObject(event, 0), WAIT_OBJECT_0);
+        assert_eq!(WaitForSingleObject(event, 0), WAIT_OBJECT_0);
+        assert_ne!(ResetEvent(event), 0);
+        assert_eq!(WaitForSingleObject(event, 0), WAIT_TIMEOUT);
+        assert_ne!(CloseHandle(event), 0);
+
+        // Named objects are shared
+        let name: Vec<u16> = "miri-event\0".encode_utf16().collect();
+        let event1 = CreateEventW(ptr::null_mut(), 1, 0, name.as_ptr());
+        let event2 = CreateEventW(ptr::null_mut(), 1, 0, name.as_ptr());
+        assert_eq!(GetLastError(), ERROR_ALREADY_EXISTS);
+        assert_ne!(SetEvent(event1), 0);
+        assert_eq!(WaitForSingleObject(event2, 0), WAIT_OBJECT_0);
+        assert_ne!(CloseHandle(event1), 0);
+        assert_ne!(CloseHandle(event2), 0);
+
+        // Recursive mutex
+        let mutex = CreateMutexW(ptr::null_mut(), 1, ptr::null());
+        assert_eq!(WaitForSingleObject(mutex, 0), WAIT_OBJECT_0);
+        assert_ne!(ReleaseMutex(mutex), 0);
+        assert_ne!(ReleaseMutex(mutex), 0);
+        assert_eq!(ReleaseMutex(mutex), 0);
+        assert_ne!(CloseHandle(mutex), 0);
+    }
+}
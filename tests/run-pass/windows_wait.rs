@@ -0,0 +1,48 @@
+//ignore-linux: Uses Windows APIs
+//ignore-macos: Uses Windows APIs
+
+use std::ptr;
+use std::thread;
+use std::time::Duration;
+
+type HANDLE = *mut u8;
+
+extern "system" {
+    fn CreateEventW(attrs: *mut u8, manual_reset: i32, initial_state: i32, name: *const u16) -> HANDLE;
+    fn WaitForMultipleObjects(count: u32, handles: *const HANDLE, wait_all: i32, timeout: u32) -> u32;
+    fn SleepEx(millis: u32, alertable: i32) -> u32;
+    fn CloseHandle(handle: HANDLE) -> i32;
+    fn GetLastError() -> u32;
+}
+
+const WAIT_OBJECT_0: u32 = 0;
+const WAIT_TIMEOUT: u32 = 0x102;
+const WAIT_FAILED: u32 = 0xFFFFFFFF;
+const ERROR_INVALID_PARAMETER: u32 = 87;
+
+fn main() {
+    thread::sleep(Duration::from_millis(100));
+
+    unsafe {
+        assert_eq!(SleepEx(10, 1), 0);
+
+        let unsignaled = CreateEventW(ptr::null_mut(), 0, 0, ptr::null());
+        let signaled1 = CreateEventW(ptr::null_mut(), 0, 1, ptr::null());
+        let signaled2 = CreateEventW(ptr::null_mut(), 0, 1, ptr::null());
+
+        // Waiting for all objects does not acquire any of them if one is not signaled.
+        let handles = [unsignaled, signaled1, signaled2];
+        assert_eq!(WaitForMultipleObjects(3, handles.as_ptr(), 1, 50), WAIT_TIMEOUT);
+        // The lowest signaled index wins.
+        assert_eq!(WaitForMultipleObjects(3, handles.as_ptr(), 0, 50), WAIT_OBJECT_0 + 1);
+        assert_eq!(WaitForMultipleObjects(3, handles.as_ptr(), 0, 50), WAIT_OBJECT_0 + 2);
+        assert_eq!(WaitForMultipleObjects(3, handles.as_ptr(), 0, 50), WAIT_TIMEOUT);
+        // Too many handles fails before any of them is read.
+        assert_eq!(WaitForMultipleObjects(65, handles.as_ptr(), 0, 50), WAIT_FAILED);
+        assert_eq!(GetLastError(), ERROR_INVALID_PARAMETER);
+
+        for &handle in &handles {
+            assert_ne!(CloseHandle(handle), 0);
+        }
+    }
+}